@@ -0,0 +1,166 @@
+use std::{fmt, fs, io};
+use std::path::{Path, PathBuf};
+use crate::remote::{Computer, OsInfo};
+use crate::process_runner::create_report_path;
+
+/// Methods tried to acquire one artifact and the one that finally succeeded.
+/// Methods that ran out of time are kept apart from those that failed.
+pub struct ArtifactOutcome {
+    pub artifact: &'static str,
+    pub succeeded_with: Option<&'static str>,
+    pub failed_with: Vec<(&'static str, String)>,
+    pub timed_out_with: Vec<(&'static str, String)>,
+}
+
+/// Record of which connection method acquired each artifact from one target.
+pub struct AcquisitionSummary {
+    computer: Computer,
+    os: Option<OsInfo>,
+    artifacts: Vec<ArtifactOutcome>,
+}
+
+impl AcquisitionSummary {
+    pub fn new(computer: &Computer) -> AcquisitionSummary {
+        AcquisitionSummary {
+            computer: computer.clone(),
+            os: None,
+            artifacts: Vec::new(),
+        }
+    }
+
+    /// Records the operating system found by the fingerprint of the target.
+    pub fn set_os(&mut self, os: OsInfo) {
+        self.os = Some(os);
+    }
+
+    /// Acquires the artifact with each acquirer in order, falling back to the next one only when the acquirer fails.
+    /// Single commands exiting non-zero are outcomes of the target, but an artifact without any successful command fails.
+    pub fn acquire_with_fallback<T, M, F>(
+        &mut self,
+        artifact: &'static str,
+        acquirers: Vec<T>,
+        method_name: M,
+        acquire: F,
+    ) where M: Fn(&T) -> &'static str,
+            F: Fn(&T) -> io::Result<()> {
+        if acquirers.is_empty() {
+            return;
+        }
+        let mut outcome = ArtifactOutcome {
+            artifact,
+            succeeded_with: None,
+            failed_with: Vec::new(),
+            timed_out_with: Vec::new(),
+        };
+        for acquirer in acquirers.iter() {
+            let method = method_name(acquirer);
+            info!("Acquiring {} from {} using method {}", artifact, self.computer.address, method);
+            match acquire(acquirer) {
+                Ok(()) => {
+                    outcome.succeeded_with = Some(method);
+                    break;
+                }
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    warn!("Acquiring {} from {} using method {} timed out: {}", artifact, self.computer.address, method, err);
+                    outcome.timed_out_with.push((method, err.to_string()));
+                }
+                Err(err) => {
+                    warn!("Acquiring {} from {} using method {} failed due to {}", artifact, self.computer.address, method, err);
+                    outcome.failed_with.push((method, err.to_string()));
+                }
+            }
+        }
+        if outcome.succeeded_with.is_none() {
+            error!("Cannot acquire {} from {} using any method", artifact, self.computer.address);
+        }
+        self.artifacts.push(outcome);
+    }
+
+    pub fn store(&self, store_directory: &Path) -> io::Result<PathBuf> {
+        let path = create_report_path(&self.computer, store_directory, "acquisition", "SUMMARY", "txt");
+        fs::write(&path, self.to_string())?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for AcquisitionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Acquisition summary of {} with user {}", self.computer.address, self.computer.domain_username())?;
+        if let Some(os) = &self.os {
+            writeln!(f, "  operating system: {}", os)?;
+        }
+        for outcome in self.artifacts.iter() {
+            match outcome.succeeded_with {
+                Some(method) => writeln!(f, "  {}: acquired using {}", outcome.artifact, method)?,
+                None if outcome.failed_with.is_empty() => writeln!(f, "  {}: timed out", outcome.artifact)?,
+                None => writeln!(f, "  {}: failed", outcome.artifact)?,
+            }
+            for (method, err) in outcome.failed_with.iter() {
+                writeln!(f, "    {} failed: {}", method, err)?;
+            }
+            for (method, err) in outcome.timed_out_with.iter() {
+                writeln!(f, "    {} timed out: {}", method, err)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::test_computer;
+
+    fn summary() -> AcquisitionSummary {
+        AcquisitionSummary::new(&test_computer())
+    }
+
+    #[test]
+    fn falls_back_only_after_failure() {
+        let mut summary = summary();
+        let attempted = std::cell::RefCell::new(Vec::new());
+        summary.acquire_with_fallback(
+            "registry",
+            vec![("PSEXEC", false), ("WINRM", true), ("WMI", true)],
+            |(method, _)| method,
+            |(method, works)| {
+                attempted.borrow_mut().push(*method);
+                if *works { Ok(()) } else { Err(io::Error::new(io::ErrorKind::Other, "broken pipe")) }
+            },
+        );
+        assert_eq!(*attempted.borrow(), vec!["PSEXEC", "WINRM"]);
+        let outcome = &summary.artifacts[0];
+        assert_eq!(outcome.succeeded_with, Some("WINRM"));
+        assert_eq!(outcome.failed_with, vec![("PSEXEC", "broken pipe".to_string())]);
+    }
+
+    #[test]
+    fn records_artifact_failed_with_every_method() {
+        let mut summary = summary();
+        summary.acquire_with_fallback(
+            "memory",
+            vec!["PSEXEC", "RDP"],
+            |method| method,
+            |_| Err(io::Error::new(io::ErrorKind::Other, "access denied")),
+        );
+        assert_eq!(summary.artifacts[0].succeeded_with, None);
+        assert!(summary.to_string().contains("memory: failed"));
+    }
+
+    #[test]
+    fn reports_timeouts_apart_from_failures() {
+        let mut summary = summary();
+        summary.acquire_with_fallback(
+            "registry",
+            vec!["PSEXEC", "WMI"],
+            |method| method,
+            |_| Err(io::Error::new(io::ErrorKind::TimedOut, "reg timed out after 300 s")),
+        );
+        let outcome = &summary.artifacts[0];
+        assert!(outcome.failed_with.is_empty());
+        assert_eq!(outcome.timed_out_with.len(), 2);
+        let report = summary.to_string();
+        assert!(report.contains("registry: timed out"));
+        assert!(report.contains("WMI timed out: reg timed out after 300 s"));
+    }
+}
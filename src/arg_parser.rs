@@ -1,4 +1,5 @@
 use clap::Clap;
+use crate::remote::{CredentialSource, ProxyJump, HostKeyPolicy, Escalation, RetryPolicy, ShareMap, ShadowHistory};
 
 #[derive(Clap, Clone)]
 #[clap(version = "1.1", author = "LIFARS LLC")]
@@ -7,7 +8,7 @@ pub struct Opts {
     short = "c",
     long = "computer",
     default_value = "127.0.0.1",
-    help = "Remote computer address/name. It may be also a path to a file with list of addresses/names (one per line in form 'address domain\\user password')."
+    help = "Remote computer address/name, SSH targets may be given as host:port. It may be also a path to a file with list of addresses/names (one per line in form 'address domain\\user password', optionally with `jump=` and `hostkey=` items, see --ssh-jump and --ssh-host-key)."
     )]
     pub computer: String,
 
@@ -33,6 +34,20 @@ pub struct Opts {
     )]
     pub password: Option<String>,
 
+    #[clap(
+    long = "credentials",
+    help = "Optional: Source of passwords not given by -p or in the computer list: `prompt` (default), \
+    `env:NAME` (environment variable), `file:PATH` (first line of a file) or `vault:PATH` (encrypted vault, \
+    its passphrase is read from GARGAMEL_VAULT_PASSPHRASE or prompted). Targets missing in the source are prompted for."
+    )]
+    pub credentials: Option<CredentialSource>,
+
+    #[clap(
+    long = "vault-add",
+    help = "Optional: Store the passwords of all targets in the vault given by --credentials vault:PATH and exit."
+    )]
+    pub vault_add: bool,
+
     #[clap(
     short = "o",
     long = "output",
@@ -62,7 +77,8 @@ pub struct Opts {
     help = "Optional: File with files names to be searched on a remote computer. \
     File names support also `*` and `?` wildcards on file names (but only single * is allowed yet \
     in parent directories). When placing here value 'EMBEDDED' then it will download the paths \
-    declared in 'src/embedded_search_list.rs'."
+    declared in 'src/embedded_search_list.rs'. With --ssh the value 'MACOS' (or 'EMBEDDED' on a target \
+    fingerprinted as macOS) acquires unified logs, launch agents and daemons, quarantine events and shell histories."
     )]
     pub search_files_path: Option<String>,
 
@@ -78,6 +94,35 @@ pub struct Opts {
     )]
     pub disable_evidence_download: bool,
 
+    #[clap(
+    long = "no-fingerprint",
+    help = "Disables detection of the target operating system (family, version, architecture, system drive and locale). \
+    Without it the command set is chosen by the connection method."
+    )]
+    pub no_fingerprint: bool,
+
+    #[clap(
+    long = "dry-run",
+    help = "Records every remote command, upload, download, delete, share creation and VSS operation \
+    the acquisition would perform without executing anything. The plan is stored as DRYRUN-plan-*.txt and .json \
+    in the output directory."
+    )]
+    pub dry_run: bool,
+
+    #[clap(
+    long = "record",
+    help = "Optional: Records command lines, outputs and transferred files of the session into a fixture \
+    directory per target under the given directory, e.g. to replay them later with --replay."
+    )]
+    pub record: Option<String>,
+
+    #[clap(
+    long = "replay",
+    help = "Optional: Serves a session recorded by --record from the given directory instead of connecting \
+    to the targets. The options must select the same methods and artifacts as the recorded session."
+    )]
+    pub replay: Option<String>,
+
     #[clap(
     long = "no-registry-search",
     help = "Disables target registry acquisition."
@@ -94,10 +139,24 @@ pub struct Opts {
     #[clap(
     short = "a",
     long = "all",
-    help = "Acquire evidence from Windows machine using all supported methods (PsExec, PsRemote, WMI, RDP)."
+    help = "Acquire evidence from Windows machine using all supported methods (PsExec, WinRM, WMI, RDP)."
     )]
     pub all: bool,
 
+    #[clap(
+    long = "auto",
+    help = "Probe every connection method supported on this host and acquire evidence from each target \
+    using the best working one. The capability report of each target is saved to the output directory."
+    )]
+    pub auto: bool,
+
+    #[clap(
+    long = "probe",
+    help = "Only check which connection methods reach each target, authenticate and have admin rights. \
+    The capability report of each target is saved to the output directory and nothing is acquired."
+    )]
+    pub probe: bool,
+
     #[clap(
     long = "wmi",
     help = "Acquire evidence from a remote Windows machine using WMI. \
@@ -126,19 +185,20 @@ pub struct Opts {
 
     #[clap(
     long = "psrem",
-    help = "Acquire evidence a remote from Windows machine using PowerShell."
+    help = "Acquire evidence from a remote Windows machine using PowerShell remoting (WinRM). \
+    Commands and file transfers are handled by Gargamel itself."
     )]
     pub psrem: bool,
 
     #[clap(
     long = "ssh",
-    help = "Acquire evidence from a remote Linux machine using SSH. Requires both plink.exe and pscp.exe in the current directory or in the path."
+    help = "Acquire evidence from a remote Linux machine using SSH. Files are transferred using SFTP."
     )]
     pub ssh: bool,
 
     #[clap(
     long = "local",
-    help = "Acquire evidence from a local Windows machine."
+    help = "Acquire evidence from the local machine. On Linux hosts only the evidence, custom commands and files are acquired."
     )]
     pub local: bool,
 
@@ -151,10 +211,18 @@ pub struct Opts {
 
     #[clap(
     long = "shadow",
-    help = "Optional: Acquire system files from shadow volume."
+    help = "Optional: Acquire files from shadow copies of their volumes, made when a volume is first used and deleted afterwards."
     )]
     pub shadow: bool,
 
+    #[clap(
+    long = "shadow-history",
+    help = "Optional: Acquire the files of --search also from the shadow copies already on the target, `all` of them \
+    or the one with the given ID, each into its own `shadow-copy-<ID>` directory. The shadow copies found are listed \
+    with their IDs and creation times in the VSS_HISTORY report. The shadow copies are left on the target."
+    )]
+    pub shadow_history: Option<ShadowHistory>,
+
     #[clap(
     long = "svi",
     help = "Optional: Acquire .lnk files from System Volume Information"
@@ -170,12 +238,98 @@ pub struct Opts {
     )]
     pub timeout: u64,
 
+    #[clap(
+    long = "ready-timeout",
+    help = "Optional: Upper bound in seconds of each wait for the target, e.g. until a file written by WMI is complete, \
+    7-Zip finished or a program released its file. The target is polled every `--ready-interval` so that the wait ends as soon as it is ready. \
+    Methods that cannot check the target (RDP) wait fixed times instead.",
+    default_value = "120",
+    )]
+    pub ready_timeout: u64,
+
+    #[clap(
+    long = "ready-interval",
+    help = "Optional: Time in milliseconds between two checks of the target while waiting for it, see `--ready-timeout`.",
+    default_value = "1000",
+    )]
+    pub ready_interval: u64,
+
+    #[clap(
+    long = "operation-timeout",
+    help = "Optional: Timeout in seconds of each remote command and transfer that has no timeout of its own. \
+    Timed out commands are killed on the target where the method allows it (SSH, WinRM and programs uploaded by Gargamel) \
+    and reported as timed out instead of failed."
+    )]
+    pub operation_timeout: Option<u64>,
+
+    #[clap(
+    long = "target-timeout",
+    help = "Optional: Time budget in seconds of the whole acquisition from one target. Operations are cut to the rest of the budget \
+    and no new one is started once it is spent, the artifacts left are reported as timed out."
+    )]
+    pub target_timeout: Option<u64>,
+
+    #[clap(
+    long = "retry",
+    help = "Optional: Retry uploads, downloads and deletes failing with transient errors like dropped connections \
+    and commands whose connection could not be made, e.g. `attempts=5,delay=2,max-delay=60,multiplier=2,jitter=0.2` \
+    (delays in seconds), or `file:PATH` with the same items one per line. Items not given default to 3 attempts \
+    2 seconds apart doubling up to 60 seconds with 20% jitter. Nothing is retried without it."
+    )]
+    pub retry: Option<RetryPolicy>,
+
+    #[clap(
+    long = "cleanup",
+    help = "Optional: Remove what interrupted runs left on the targets, e.g. uploaded programs, reports, 7z parts, \
+    VSS links, granted permissions and shares of this host, according to the footprint ledgers in the given case directory \
+    (the output directory of those runs). The methods that made the changes are used. No evidence is acquired."
+    )]
+    pub cleanup: Option<String>,
+
     #[clap(long = "key", help = "Optional: Name/path of a SSH private key file. (Linux target only)")]
     pub ssh_key: Option<String>,
 
+    #[clap(long = "ssh-jump", help = "Optional: Reach SSH targets through jump hosts given as comma separated \
+    user@host[:port] like OpenSSH ProxyJump, e.g. `admin@bastion,ops@10.1.0.5:2222`. \
+    Jump hosts are authenticated by the --key or the SSH agent only. A `jump=` item on a line of the computer list overrides it for that target, `jump=none` connects directly. (Linux target only)")]
+    pub ssh_jump: Option<ProxyJump>,
+
+    #[clap(long = "known-hosts", help = "Optional: OpenSSH known hosts file used to verify SSH host keys. \
+    Defaults to ~/.ssh/known_hosts. (Linux target only)")]
+    pub known_hosts: Option<String>,
+
+    #[clap(long = "ssh-host-key", help = "Optional: Expected SHA256 fingerprint of the SSH host key of the target, \
+    e.g. `SHA256:nThbg6kXUpJWGl7E1IGOCspRomTxdCARLviKw6E5SY8`. A `hostkey=` item on a line of the computer list pins it for that target. \
+    Connections to a host with a different key are refused. (Linux target only)")]
+    pub ssh_host_key: Option<String>,
+
+    #[clap(long = "ssh-host-key-policy", default_value = "tofu", help = "Optional: What to do with SSH hosts that are neither pinned \
+    nor in the known hosts file: `tofu` trusts them on first use and records their keys in ssh_known_hosts in the output directory, \
+    `strict` refuses them. Hosts with a different key than the known one are always refused. (Linux target only)")]
+    pub ssh_host_key_policy: HostKeyPolicy,
+
+    #[clap(long = "ssh-escalation", help = "Optional: How to run elevated commands on SSH targets: `root`, `sudo-nopasswd`, \
+    `sudo` (password on the standard input), `sudo-pty` (for requiretty), `doas-nopass`, `doas`, `su` (the target password is the root password) \
+    or `none`. Detected on each target in this order when not given, except for `su`, stopping at the first rejected password. \
+    (Linux target only)")]
+    pub ssh_escalation: Option<Escalation>,
+
     #[clap(long = "nla", help = "Optional: Use network level authentication when using RDP. (Windows targets only)")]
     pub nla: bool,
 
+    #[clap(long = "winrm-https", help = "Optional: Use HTTPS for --psrem (port 5986 by default).")]
+    pub winrm_https: bool,
+
+    #[clap(long = "winrm-port", help = "Optional: Custom WinRM port for --psrem.")]
+    pub winrm_port: Option<u16>,
+
+    #[clap(long = "winrm-basic", help = "Optional: Use Basic instead of NTLM authentication for --psrem. \
+    Over HTTP the target must also allow unencrypted WinRM traffic.")]
+    pub winrm_basic: bool,
+
+    #[clap(long = "winrm-insecure", help = "Optional: Do not verify the certificate of the target when using --winrm-https.")]
+    pub winrm_insecure: bool,
+
     #[clap(long = "no-7z", help = "Optional: Disable 7zip compression for registry & memory images.\
     This will significantly decrease the running time, but WMI and RDP connections will probably not work properly.
     (Windows targets only)")]
@@ -198,13 +352,15 @@ pub struct Opts {
 
     #[clap(
     long = "share",
-    help = "Optional: Use custom shared folder.",
+    help = "Optional: Shares the drives of the target are reached through by --psexec, e.g. `C=C$,D=Data`. \
+    A single letter is a drive of this host the share is mapped to, e.g. `C=Z`, and a share without a drive \
+    is the share of drive C. Other drives are reached through their administrative shares, e.g. `E$`.",
     )]
-    pub share: Option<String>,
+    pub share: Option<ShareMap>,
 
     #[clap(
     long = "reverse-share",
-    help = "Optional: Dangerous!. Reverse copy operations for --psexec and --psrem. By default, Gargamel creates temporary (or uses) shared folders to target's C drive. This option creates a temporary shared folder of the host's C drive and exposes it to the target.",
+    help = "Optional: Reverse copy operations for --psexec, --psrem, --wmi and --rdp. By default, Gargamel creates temporary (or uses) shared folders to target's C drive. This option creates a hidden, randomly named share of a staging directory of this host, which only the given user and the computer account of the target may use, and removes it afterwards.",
     )]
     pub reverse_share: bool
 }
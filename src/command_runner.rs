@@ -1,8 +1,9 @@
-use crate::remote::{Computer, Connector, Command, PsExec, PsRemote, Ssh, Rdp, Wmi, Local};
+use crate::remote::{Computer, Connector, Command, PsExec, WinRm, WinRmSettings, Ssh, SshSettings, Rdp, Wmi, Local, Decorate, ShareMap};
 use std::path::{Path, PathBuf};
 use std::fs::File;
 use crate::command_utils::parse_command;
 use std::time::Duration;
+use std::io;
 
 pub struct CommandRunner<'a> {
     local_store_directory: &'a Path,
@@ -15,7 +16,7 @@ impl<'a> CommandRunner<'a> {
         remote_computer: Computer,
         local_store_directory: &'a Path,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>
+        custom_share_folder: Option<ShareMap>
     ) -> CommandRunner<'a> {
         CommandRunner {
             local_store_directory,
@@ -31,7 +32,7 @@ impl<'a> CommandRunner<'a> {
     ) -> CommandRunner<'a> {
         CommandRunner {
             local_store_directory,
-            connector: Box::new(Wmi { computer: remote_computer, remote_temp_storage}),
+            connector: Box::new(Wmi::new(remote_computer, remote_temp_storage)),
             run_implicit: true,
         }
     }
@@ -51,11 +52,11 @@ impl<'a> CommandRunner<'a> {
         remote_computer: Computer,
         local_store_directory: &'a Path,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>
+        winrm: WinRmSettings,
     ) -> CommandRunner<'a> {
         CommandRunner {
             local_store_directory,
-            connector: Box::new(PsRemote::new(remote_computer, remote_temp_storage, custom_share_folder)),
+            connector: Box::new(WinRm::new(remote_computer, remote_temp_storage, winrm)),
             run_implicit: true,
         }
     }
@@ -80,11 +81,11 @@ impl<'a> CommandRunner<'a> {
     pub fn ssh(
         remote_computer: Computer,
         local_store_directory: &'a Path,
-        key_file: Option<PathBuf>,
+        settings: SshSettings,
     ) -> CommandRunner<'a> {
         CommandRunner {
             local_store_directory,
-            connector: Box::new(Ssh { key_file, computer: remote_computer }),
+            connector: Box::new(Ssh::new(remote_computer, settings)),
             run_implicit: false,
         }
     }
@@ -93,14 +94,17 @@ impl<'a> CommandRunner<'a> {
         &self,
         command_file: &Path,
         timeout: Option<Duration>
-    ) {
+    ) -> io::Result<()> {
         let file = match File::open(command_file) {
             Ok(file) => file,
             Err(err) => {
                 error!("{}", err);
-                return;
+                return Err(err);
             }
         };
+        let mut succeeded_commands = 0;
+        let mut failed_commands = Vec::new();
+        let mut timed_out_commands = Vec::new();
         let reader = std::io::BufReader::new(file);
         use std::io::BufRead;
         for one_command in reader.lines().filter_map(|item| item.ok()) {
@@ -150,12 +154,48 @@ impl<'a> CommandRunner<'a> {
                 &report_filename_prefix,
                 elevated,
             );
-            if let Err(err) = self.connector.connect_and_run_command(
+            match self.connector.connect_and_run_command(
                 remote_connection,
                 timeout
             ) {
-                error!("{}", err)
+                Ok(outcome) if outcome.timed_out => {
+                    error!("Custom command {} {}", report_filename_prefix, outcome);
+                    timed_out_commands.push(one_command);
+                }
+                Ok(outcome) if outcome.is_failure() => {
+                    error!("Custom command {} failed with {}", report_filename_prefix, outcome);
+                    failed_commands.push(one_command);
+                }
+                Ok(_) => succeeded_commands += 1,
+                // The connection failed, the commands are left to the next method.
+                Err(err) => {
+                    error!("{}", err);
+                    return Err(err);
+                }
             };
         }
+        // Commands that ran are outcomes of the target, running them again with another method would not change them.
+        if !failed_commands.is_empty() {
+            warn!("Custom commands failed: {}", failed_commands.join(", "));
+        }
+        if !timed_out_commands.is_empty() {
+            warn!("Custom commands timed out: {}", timed_out_commands.join(", "));
+        }
+        // Unless none of them succeeded, then the method likely does not work and the next one is tried.
+        if succeeded_commands == 0 && !(failed_commands.is_empty() && timed_out_commands.is_empty()) {
+            let kind = if failed_commands.is_empty() { io::ErrorKind::TimedOut } else { io::ErrorKind::Other };
+            return Err(io::Error::new(
+                kind,
+                format!("No custom command succeeded using {}", self.connector.connect_method_name()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Decorate for CommandRunner<'a> {
+    fn decorate(mut self, decorator: &dyn Fn(Box<dyn Connector>) -> Box<dyn Connector>) -> Self {
+        self.connector = decorator(self.connector);
+        self
     }
 }
@@ -1,6 +1,7 @@
 use std::path::{Path, PathBuf};
-use crate::remote::{Computer, Connector, PsExec, PsRemote, Rdp, Wmi, Compression, Local, RevShareConnector};
+use crate::remote::{Computer, Connector, PsExec, WinRm, WinRmSettings, Rdp, Wmi, Compression, Local, RevShareConnector, Decorate, ShareMap};
 use std::time::Duration;
+use std::io;
 use crate::large_evidence_acquirer::LargeEvidenceAcquirer;
 
 pub struct EventsAcquirer<'a> {
@@ -44,7 +45,7 @@ impl<'a> EventsAcquirer<'a> {
         remote_computer: Computer,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reverse: bool,
     ) -> EventsAcquirer {
         let connector = Box::new(PsExec::psexec32(remote_computer, remote_temp_storage, custom_share_folder));
@@ -61,7 +62,7 @@ impl<'a> EventsAcquirer<'a> {
         remote_computer: Computer,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reverse: bool,
     ) -> EventsAcquirer {
         let connector = Box::new(PsExec::psexec64(remote_computer, remote_temp_storage, custom_share_folder));
@@ -91,10 +92,10 @@ impl<'a> EventsAcquirer<'a> {
         remote_computer: Computer,
         _no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        winrm: WinRmSettings,
         reverse: bool,
     ) -> EventsAcquirer {
-        let connector = Box::new(PsRemote::new(remote_computer, remote_temp_storage, custom_share_folder));
+        let connector = Box::new(WinRm::new(remote_computer, remote_temp_storage, winrm));
         EventsAcquirer::new(
             store_directory,
             if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
@@ -109,12 +110,14 @@ impl<'a> EventsAcquirer<'a> {
         compress_timeout: Duration,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
+        reverse: bool,
     ) -> EventsAcquirer {
+        let connector = Box::new(Wmi::new(computer, remote_temp_storage));
         EventsAcquirer::new(
             store_directory,
-            Box::new(Wmi { computer, remote_temp_storage }),
+            if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
             Some(compress_timeout),
-            if no_7zip { Compression::No } else { Compression::YesSplit },
+            Compression::split_unless_reversed(no_7zip, reverse),
         )
     }
 
@@ -125,16 +128,22 @@ impl<'a> EventsAcquirer<'a> {
         nla: bool,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
+        reverse: bool,
     ) -> EventsAcquirer {
+        let connector = Box::new(Rdp { computer, nla, remote_temp_storage });
         EventsAcquirer::new(
             store_directory,
-            Box::new(Rdp { computer, nla, remote_temp_storage }),
+            if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
             Some(compress_timeout),
-            if no_7zip { Compression::No } else { Compression::YesSplit },
+            Compression::split_unless_reversed(no_7zip, reverse),
         )
     }
 
-    pub fn acquire(&self) {
+    pub fn method_name(&self) -> &'static str {
+        self.connector.connect_method_name()
+    }
+
+    pub fn acquire(&self) -> io::Result<()> {
         let lea = LargeEvidenceAcquirer {
             store_directory: self.store_directory,
             connector: self.connector.as_ref(),
@@ -144,14 +153,22 @@ impl<'a> EventsAcquirer<'a> {
             overwrite_switch: Some("/ow:true"),
         };
         let command = &self.system_event_logs_command;
-        lea.run(
+        let system = lea.run(
             command,
             "events-system",
         );
         let command = &self.application_event_logs_command;
-        lea.run(
+        let application = lea.run(
             command,
             "events-application",
         );
+        system.and(application)
     }
-}
\ No newline at end of file
+}
+
+impl<'a> Decorate for EventsAcquirer<'a> {
+    fn decorate(mut self, decorator: &dyn Fn(Box<dyn Connector>) -> Box<dyn Connector>) -> Self {
+        self.connector = decorator(self.connector);
+        self
+    }
+}
@@ -1,5 +1,7 @@
 use std::path::{Path, PathBuf};
-use crate::remote::{Computer, Connector, Command, PsExec, PsRemote, Ssh, Rdp, Wmi, Local, RevShareConnector};
+use crate::remote::{Computer, Connector, Command, PsExec, WinRm, WinRmSettings, Ssh, SshSettings, Rdp, Wmi, Local, RevShareConnector, OsFamily, OsInfo, Decorate, ShareMap};
+use crate::host::is_windows_host;
+use std::io;
 
 pub struct EvidenceAcquirer<'a> {
     store_directory: &'a Path,
@@ -17,31 +19,107 @@ impl<'a> EvidenceAcquirer<'a> {
         store_directory: &'a Path,
         remote_connector: Box<dyn Connector>,
     ) -> EvidenceAcquirer<'a> {
-        EvidenceAcquirer {
+        EvidenceAcquirer::for_os(
             store_directory,
-            connector: remote_connector,
-            firewall_state_command: Some(vec![
-                "netsh".to_string(),
-                "advfirewall".to_string(),
-                "show".to_string(),
-                "allprofiles".to_string(),
-                "state".to_string(),
-            ]),
-            network_state_command: Some(vec![
-                "ipconfig".to_string(),
-                "/all".to_string(),
-            ]),
-            logged_users_command: Some(vec![
-                "query".to_string(),
-                "user".to_string(),
-            ]),
-            running_processes_command: Some(vec![
-                "tasklist".to_string(),
-            ]),
-            active_network_connections_command: Some(vec![
-                "netstat".to_string(),
-                "-ano".to_string(),
-            ]),
+            remote_connector,
+            OsFamily::Windows,
+        )
+    }
+
+    /// Acquirer running the command set of the operating system family, Windows commands are used for unknown ones.
+    pub fn for_os(
+        store_directory: &'a Path,
+        connector: Box<dyn Connector>,
+        family: OsFamily,
+    ) -> EvidenceAcquirer<'a> {
+        match family {
+            OsFamily::Linux => EvidenceAcquirer {
+                store_directory,
+                connector,
+                firewall_state_command: Some(vec![
+                    "iptables".to_string(),
+                    "-L".to_string(),
+                ]),
+                network_state_command: Some(vec![
+                    "ifconfig".to_string(),
+                ]),
+                logged_users_command: Some(vec![
+                    "who".to_string(),
+                ]),
+                running_processes_command: Some(vec![
+                    "ps".to_string(),
+                    "aux".to_string(),
+                ]),
+                active_network_connections_command: Some(vec![
+                    "netstat".to_string(),
+                    "-natp".to_string(),
+                ]),
+            },
+            OsFamily::MacOs => EvidenceAcquirer {
+                store_directory,
+                connector,
+                firewall_state_command: Some(vec![
+                    "pfctl".to_string(),
+                    "-s".to_string(),
+                    "all".to_string(),
+                    ";".to_string(),
+                    "/usr/libexec/ApplicationFirewall/socketfilterfw".to_string(),
+                    "--getglobalstate".to_string(),
+                ]),
+                network_state_command: Some(vec![
+                    "ifconfig".to_string(),
+                    "-a".to_string(),
+                ]),
+                logged_users_command: Some(vec![
+                    "who".to_string(),
+                ]),
+                running_processes_command: Some(vec![
+                    "ps".to_string(),
+                    "aux".to_string(),
+                ]),
+                active_network_connections_command: Some(vec![
+                    "netstat".to_string(),
+                    "-anv".to_string(),
+                    ";".to_string(),
+                    "lsof".to_string(),
+                    "-nP".to_string(),
+                    "-i".to_string(),
+                ]),
+            },
+            _ => EvidenceAcquirer {
+                store_directory,
+                connector,
+                firewall_state_command: Some(vec![
+                    "netsh".to_string(),
+                    "advfirewall".to_string(),
+                    "show".to_string(),
+                    "allprofiles".to_string(),
+                    "state".to_string(),
+                ]),
+                network_state_command: Some(vec![
+                    "ipconfig".to_string(),
+                    "/all".to_string(),
+                ]),
+                logged_users_command: Some(vec![
+                    "query".to_string(),
+                    "user".to_string(),
+                ]),
+                running_processes_command: Some(vec![
+                    "tasklist".to_string(),
+                ]),
+                active_network_connections_command: Some(vec![
+                    "netstat".to_string(),
+                    "-ano".to_string(),
+                ]),
+            },
+        }
+    }
+
+    /// Switches to the command set of the operating system found by the fingerprint of the target.
+    pub fn with_os(self, os: &OsInfo) -> EvidenceAcquirer<'a> {
+        match os.family {
+            OsFamily::Windows | OsFamily::Linux | OsFamily::MacOs => EvidenceAcquirer::for_os(self.store_directory, self.connector, os.family),
+            _ => self,
         }
     }
 
@@ -50,9 +128,12 @@ impl<'a> EvidenceAcquirer<'a> {
         store_directory: &'a Path,
         temp_storage: PathBuf,
     ) -> EvidenceAcquirer<'a> {
-        EvidenceAcquirer::new(
+        let connector = Box::new(Local::new(username, temp_storage));
+        let family = if is_windows_host() { OsFamily::Windows } else { OsFamily::Linux };
+        EvidenceAcquirer::for_os(
             store_directory,
-            Box::new(Local::new(username, temp_storage)),
+            connector,
+            family,
         )
     }
 
@@ -60,7 +141,7 @@ impl<'a> EvidenceAcquirer<'a> {
         remote_computer: Computer,
         store_directory: &'a Path,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reversed: bool,
     ) -> EvidenceAcquirer<'a> {
         let connector = Box::new(PsExec::paexec(remote_computer, remote_temp_storage, custom_share_folder));
@@ -74,10 +155,10 @@ impl<'a> EvidenceAcquirer<'a> {
         remote_computer: Computer,
         store_directory: &'a Path,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        winrm: WinRmSettings,
         reversed: bool,
     ) -> EvidenceAcquirer<'a> {
-        let connector = Box::new(PsRemote::new(remote_computer, remote_temp_storage, custom_share_folder));
+        let connector = Box::new(WinRm::new(remote_computer, remote_temp_storage, winrm));
         EvidenceAcquirer::new(
             store_directory,
             if reversed { Box::new(RevShareConnector::new(connector)) } else { connector },
@@ -88,13 +169,12 @@ impl<'a> EvidenceAcquirer<'a> {
         remote_computer: Computer,
         store_directory: &'a Path,
         remote_temp_storage: PathBuf,
+        reversed: bool,
     ) -> EvidenceAcquirer<'a> {
+        let connector = Box::new(Wmi::new(remote_computer, remote_temp_storage));
         EvidenceAcquirer::new(
             store_directory,
-            Box::new(Wmi {
-                computer: remote_computer,
-                remote_temp_storage,
-            }),
+            if reversed { Box::new(RevShareConnector::new(connector)) } else { connector },
         )
     }
 
@@ -103,50 +183,39 @@ impl<'a> EvidenceAcquirer<'a> {
         store_directory: &'a Path,
         nla: bool,
         remote_temp_storage: PathBuf,
+        reversed: bool,
     ) -> EvidenceAcquirer<'a> {
+        let connector = Box::new(Rdp {
+            nla,
+            computer: remote_computer,
+            remote_temp_storage,
+        });
         EvidenceAcquirer::new(
             store_directory,
-            Box::new(Rdp {
-                nla,
-                computer: remote_computer,
-                remote_temp_storage,
-            }),
+            if reversed { Box::new(RevShareConnector::new(connector)) } else { connector },
         )
     }
 
     pub fn ssh(
         remote_computer: Computer,
         store_directory: &'a Path,
-        key_file: Option<PathBuf>,
+        settings: SshSettings,
     ) -> EvidenceAcquirer<'a> {
-        EvidenceAcquirer {
+        EvidenceAcquirer::for_os(
             store_directory,
-            connector: Box::new(Ssh { key_file, computer: remote_computer.clone() }),
-            firewall_state_command: Some(vec![
-                format!("echo {} | sudo -S iptables -L", remote_computer.password.unwrap_or_default()),
-            ]),
-            network_state_command: Some(vec![
-                "ifconfig".to_string(),
-            ]),
-            logged_users_command: Some(vec![
-                "who".to_string(),
-            ]),
-            running_processes_command: Some(vec![
-                "ps aux".to_string(),
-            ]),
-            active_network_connections_command: Some(vec![
-                "netstat -natp".to_string(),
-            ]),
-        }
+            Box::new(Ssh::new(remote_computer, settings)),
+            OsFamily::Linux,
+        )
     }
 
+    /// Tells whether the command succeeded, a failed connection is an error.
     fn run(
         &self,
         command: &[String],
         report_filename_prefix: &str,
-    ) {
+    ) -> io::Result<bool> {
         if command.is_empty() {
-            return;
+            return Ok(false);
         }
         let remote_connection = Command::new(
             command.to_vec(),
@@ -161,14 +230,25 @@ impl<'a> EvidenceAcquirer<'a> {
         );
 
         match self.connector.connect_and_run_command(remote_connection, None) {
-            Ok(_) => {}
-            Err(err) => { error!("Error running command {:?}. Cause: {}", command, err) }
+            // The command ran, so its exit is an outcome of the target and another method would not change it.
+            Ok(outcome) if outcome.is_failure() => {
+                warn!("Command {:?} failed with {}", command, outcome);
+                Ok(false)
+            }
+            Ok(outcome) => {
+                debug!("Command {:?} finished with {}", command, outcome);
+                Ok(true)
+            }
+            Err(err) => {
+                error!("Error running command {:?}. Cause: {}", command, err);
+                Err(err)
+            }
         }
     }
 
-    pub fn firewall_state(&self) {
+    pub fn firewall_state(&self) -> io::Result<bool> {
         match &self.firewall_state_command {
-            None => {}
+            None => Ok(false),
             Some(command) => {
                 self.run(
                     command,
@@ -178,9 +258,9 @@ impl<'a> EvidenceAcquirer<'a> {
         }
     }
 
-    pub fn network_state(&self) {
+    pub fn network_state(&self) -> io::Result<bool> {
         match &self.network_state_command {
-            None => {}
+            None => Ok(false),
             Some(command) => {
                 self.run(
                     command,
@@ -190,9 +270,9 @@ impl<'a> EvidenceAcquirer<'a> {
         }
     }
 
-    pub fn logged_users(&self) {
+    pub fn logged_users(&self) -> io::Result<bool> {
         match &self.logged_users_command {
-            None => {}
+            None => Ok(false),
             Some(command) => {
                 self.run(
                     command,
@@ -202,9 +282,9 @@ impl<'a> EvidenceAcquirer<'a> {
         }
     }
 
-    pub fn running_processes(&self) {
+    pub fn running_processes(&self) -> io::Result<bool> {
         match &self.running_processes_command {
-            None => {}
+            None => Ok(false),
             Some(command) => {
                 self.run(
                     command,
@@ -214,9 +294,9 @@ impl<'a> EvidenceAcquirer<'a> {
         }
     }
 
-    pub fn active_network_connections(&self) {
+    pub fn active_network_connections(&self) -> io::Result<bool> {
         match &self.active_network_connections_command {
-            None => {}
+            None => Ok(false),
             Some(command) => {
                 self.run(
                     command,
@@ -226,13 +306,57 @@ impl<'a> EvidenceAcquirer<'a> {
         }
     }
 
+    pub fn method_name(&self) -> &'static str {
+        self.connector.connect_method_name()
+    }
+
+    /// Fails when the connection failed or when no command succeeded, so that another method is tried.
     pub fn run_all(
         &self,
-    ) {
-        self.firewall_state();
-        self.network_state();
-        self.active_network_connections();
-        self.running_processes();
-        self.logged_users();
-    }
-}
\ No newline at end of file
+    ) -> io::Result<()> {
+        let firewall_state = self.firewall_state();
+        let network_state = self.network_state();
+        let active_network_connections = self.active_network_connections();
+        let running_processes = self.running_processes();
+        let logged_users = self.logged_users();
+        let succeeded = [firewall_state?, network_state?, active_network_connections?, running_processes?, logged_users?];
+        if succeeded.contains(&true) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("No evidence command succeeded using {}", self.method_name()),
+            ))
+        }
+    }
+}
+
+impl<'a> Decorate for EvidenceAcquirer<'a> {
+    fn decorate(mut self, decorator: &dyn Fn(Box<dyn Connector>) -> Box<dyn Connector>) -> Self {
+        self.connector = decorator(self.connector);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{replay_fixture, replay_store_directory};
+    use std::fs;
+
+    #[test]
+    fn replays_windows_evidence() {
+        let (replayer, fixture) = replay_fixture("evidence-psexec");
+        let store_directory = replay_store_directory();
+        let acquirer = EvidenceAcquirer::new(&store_directory, Box::new(replayer));
+        // `query user` exits with 1 when nobody is logged on, which is no reason to try another method.
+        assert!(acquirer.run_all().is_ok());
+        assert_eq!(fixture.remaining(), 0);
+        let reports = fs::read_dir(&store_directory).unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect::<Vec<String>>();
+        assert!(reports.iter().any(|report| report.contains("Domain Profile Settings")));
+        assert!(reports.iter().any(|report| report.contains("No User exists for *")));
+        let _ = fs::remove_dir_all(&store_directory);
+    }
+}
@@ -1,7 +1,7 @@
-use crate::remote::{RemoteFileCopier};
+use crate::remote::{RemoteFileCopier, Connector, ShadowCopier, ShadowHistory, list_shadow_copies};
 use std::path::Path;
 use std::io;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, BufRead};
 use crate::embedded_search_list::embedded_search_list;
 
@@ -86,3 +86,34 @@ pub fn download_file(
     }
     download_result
 }
+
+/// Downloads the files of the list from the existing shadow copies selected by `history`,
+/// each into its own `shadow-copy-<ID>` directory of the store directory.
+pub fn download_files_from_shadow_copies(file_list: &str,
+                                         local_store_directory: &Path,
+                                         connector: &dyn Connector,
+                                         downloader: Option<&dyn RemoteFileCopier>,
+                                         history: &ShadowHistory,
+                                         separate_stores: bool,
+) -> io::Result<()> {
+    let shadow_copies = list_shadow_copies(connector, local_store_directory)?
+        .into_iter()
+        .filter(|shadow_copy| history.selects(shadow_copy))
+        .collect::<Vec<_>>();
+    if shadow_copies.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No shadow copy to download files from on {}", connector.computer().address),
+        ));
+    }
+    for shadow_copy in shadow_copies {
+        let shadow_store_directory = local_store_directory.join(
+            format!("shadow-copy-{}", shadow_copy.id.trim_start_matches('{').trim_end_matches('}'))
+        );
+        fs::create_dir_all(&shadow_store_directory)?;
+        info!("Downloading specified files from shadow copy {} made {}", shadow_copy.id, shadow_copy.created);
+        let shadow_copier = ShadowCopier::existing(connector, local_store_directory, downloader, shadow_copy);
+        download_files(file_list, &shadow_store_directory, &shadow_copier, separate_stores)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::path::Path;
+use std::io;
+use std::iter;
+
+#[cfg(windows)]
+use crate::process_runner::run_process_blocking;
+
+/// Shell used on the host machine to launch the connection client programs.
+#[cfg(windows)]
+pub const HOST_SHELL: &str = "cmd.exe";
+
+#[cfg(not(windows))]
+pub const HOST_SHELL: &str = "sh";
+
+const SHELL_OPERATORS: [&str; 5] = [">", ">>", "|", "&&", ";"];
+
+pub fn is_windows_host() -> bool {
+    cfg!(windows)
+}
+
+/// Arguments making the `HOST_SHELL` run the given command line.
+pub fn shell_args(command: Vec<String>) -> Vec<String> {
+    if is_windows_host() {
+        iter::once("/c".to_string())
+            .chain(command.into_iter())
+            .collect()
+    } else {
+        let command_line = command.iter()
+            .map(|arg| quote_posix(arg))
+            .collect::<Vec<String>>()
+            .join(" ");
+        vec!["-c".to_string(), command_line]
+    }
+}
+
+/// Quotes an argument for a POSIX shell. Shell operators are left as they are.
+pub fn quote_posix(arg: &str) -> String {
+    let safe = !arg.is_empty() && arg.chars().all(|c|
+        c.is_ascii_alphanumeric() || "-_./=:@,+%".contains(c)
+    );
+    if safe || SHELL_OPERATORS.contains(&arg) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace("'", "'\\''"))
+    }
+}
+
+#[cfg(windows)]
+pub fn copy_path(source: &Path, target: &Path) -> io::Result<()> {
+    let args = vec![
+        "/y".to_string(),
+        "/i".to_string(),
+        "/c".to_string(),
+        "/H".to_string(),
+        "/S".to_string(),
+        source.to_string_lossy().to_string(),
+        target.to_string_lossy().to_string(),
+    ];
+    run_process_blocking(
+        "xcopy",
+        &args,
+    )
+}
+
+/// Copies a file or a directory into the `target` directory.
+/// Wildcards (`*`, `?`) are supported in the last component of the `source`.
+#[cfg(not(windows))]
+pub fn copy_path(source: &Path, target: &Path) -> io::Result<()> {
+    use wildmatch::WildMatch;
+
+    trace!("Copying {} to {}", source.display(), target.display());
+    std::fs::create_dir_all(target)?;
+    let file_name = source.file_name()
+        .map(|it| it.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !file_name.contains('*') && !file_name.contains('?') {
+        return copy_single_path(source, target);
+    }
+    let parent = source.parent().unwrap_or_else(|| Path::new("."));
+    let pattern = WildMatch::new(&file_name);
+    for entry in parent.read_dir()?.filter_map(|entry| entry.ok()) {
+        if pattern.matches(&entry.file_name().to_string_lossy()) {
+            if let Err(err) = copy_single_path(&entry.path(), target) {
+                error!("Cannot copy {} due to {}", entry.path().display(), err);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn copy_single_path(source: &Path, target: &Path) -> io::Result<()> {
+    if source.is_dir() {
+        let mut options = fs_extra::dir::CopyOptions::new();
+        options.overwrite = true;
+        fs_extra::dir::copy(source, target, &options)
+            .map(|_| ())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))
+    } else {
+        let file_name = source.file_name().ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file", source.display()),
+        ))?;
+        std::fs::copy(source, target.join(file_name)).map(|_| ())
+    }
+}
+
+#[cfg(windows)]
+pub fn delete_path(target: &Path) -> io::Result<()> {
+    let args = vec![
+        "/F".to_string(),
+        "/Q".to_string(),
+        target.to_string_lossy().to_string(),
+    ];
+    run_process_blocking(
+        "del",
+        &args,
+    )
+}
+
+#[cfg(not(windows))]
+pub fn delete_path(target: &Path) -> io::Result<()> {
+    trace!("Deleting {}", target.display());
+    std::fs::remove_file(target)
+}
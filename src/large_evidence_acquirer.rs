@@ -1,8 +1,8 @@
 use crate::process_runner::create_report_path;
-use std::path::Path;
-use crate::remote::{Connector, Compression, SevenZipCompressCopier, RemoteFileCopier, Command};
+use std::path::{Path, PathBuf};
+use crate::remote::{Connector, Compression, SevenZipCompressCopier, RemoteFileCopier, Command, Readiness, Footprint, record_footprint};
 use std::time::Duration;
-use std::thread;
+use std::io;
 
 pub(crate) struct LargeEvidenceAcquirer<'a> {
     pub(crate) store_directory: &'a Path,
@@ -19,9 +19,9 @@ impl<'a> LargeEvidenceAcquirer<'a> {
         &self,
         command: &[String],
         report_filename_prefix: &str
-    ) {
+    ) -> io::Result<()> {
         if command.is_empty() {
-            return;
+            return Ok(());
         }
         let report_path = create_report_path(
             self.connector.computer(),
@@ -52,17 +52,35 @@ impl<'a> LargeEvidenceAcquirer<'a> {
               report_filename_prefix.replace("-", " ")
         );
 
-        match self.connector.connect_and_run_command(remote_connection, None) {
-            Ok(_) => {}
+        // The command may create the report even when it fails.
+        record_footprint(self.connector.connect_method_name(), Footprint::RemoteFile(PathBuf::from(&remote_report_path)));
+        let command_result = match self.connector.connect_and_run_command(remote_connection, None) {
+            Ok(outcome) if outcome.is_failure() => {
+                error!(
+                    "Command to acquire {} failed with {}",
+                    report_filename_prefix,
+                    outcome
+                );
+                Err(io::Error::new(
+                    outcome.error_kind(),
+                    format!("Command to acquire {} failed with {}", report_filename_prefix, outcome),
+                ))
+            }
+            // The command may leave the report to a process still writing it, e.g. when run by WMI.
+            Ok(outcome) if !outcome.has_finished() => {
+                Readiness::new(self.connector).wait_for_complete_file(Path::new(&remote_report_path), Duration::from_secs(10));
+                Ok(())
+            }
+            Ok(_) => Ok(()),
             Err(err) => {
                 error!(
                     "Error running command to acquire {}. Cause: {}",
                     report_filename_prefix,
                     err
-                )
+                );
+                Err(err)
             }
-        }
-        thread::sleep(Duration::from_millis(10_000));
+        };
 
         let _compression_split_copier = SevenZipCompressCopier::new(self.connector, true, self.compress_timeout.clone(), true);
         let _compression_copier = SevenZipCompressCopier::new(self.connector, false, self.compress_timeout.clone(), true);
@@ -72,19 +90,16 @@ impl<'a> LargeEvidenceAcquirer<'a> {
             Compression::YesSplit => &_compression_split_copier as &dyn RemoteFileCopier,
         };
 
-        match copier.copy_from_remote(Path::new(&remote_report_path), report_path.parent().unwrap()) {
-            Ok(_) => {}
-            Err(err) => {
-                error!("Cannot download {} report from {} using method {} due to {}",
-                       report_filename_prefix,
-                       self.connector.computer().address,
-                       self.connector.connect_method_name(),
-                       err
-                )
-            }
+        let download_result = copier.copy_from_remote(Path::new(&remote_report_path), report_path.parent().unwrap());
+        if let Err(err) = &download_result {
+            error!("Cannot download {} report from {} using method {} due to {}",
+                   report_filename_prefix,
+                   self.connector.computer().address,
+                   self.connector.connect_method_name(),
+                   err
+            )
         }
-        thread::sleep(Duration::from_secs(2));
-        match copier.delete_remote_file(Path::new(&remote_report_path)) {
+        match Readiness::new(self.connector).delete_when_released(copier, Path::new(&remote_report_path), Duration::from_secs(2)) {
             Ok(_) => {}
             Err(err) => {
                 error!("Cannot delete remote file {} using method {} due to: {}",
@@ -94,5 +109,33 @@ impl<'a> LargeEvidenceAcquirer<'a> {
                 )
             }
         }
+        command_result.and(download_result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{replay_fixture, replay_store_directory};
+    use std::fs;
+
+    #[test]
+    fn replays_saved_hive_download() {
+        let (replayer, fixture) = replay_fixture("large-evidence-psexec");
+        let store_directory = replay_store_directory();
+        let acquirer = LargeEvidenceAcquirer {
+            store_directory: &store_directory,
+            connector: &replayer,
+            compress_timeout: None,
+            compression: Compression::No,
+            report_extension: "hiv",
+            overwrite_switch: Some("/y"),
+        };
+        let command = ["reg".to_string(), "save".to_string(), "HKLM\\SAM".to_string()];
+        acquirer.run(&command, "sam").unwrap();
+        assert_eq!(fixture.remaining(), 0);
+        let hive = fs::read(store_directory.join("PSEXEC-sam-10-0-0-1-admin.hiv")).unwrap();
+        assert!(hive.starts_with(b"regf"));
+        let _ = fs::remove_dir_all(&store_directory);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,105 @@
+use crate::remote::{Connector, Command, Decorate};
+use std::path::{Path, PathBuf};
+use std::io;
+use uuid::Uuid;
+
+/// Value of `--search-files` selecting the macOS artifact profile.
+pub const MACOS_PROFILE: &str = "MACOS";
+
+/// Artifacts of the macOS profile, globs are expanded by the shell of the target.
+const MACOS_ARTIFACTS: [&str; 12] = [
+    "/Library/LaunchAgents",
+    "/Library/LaunchDaemons",
+    "/Users/*/Library/LaunchAgents",
+    "/Users/*/Library/Preferences/com.apple.LaunchServices.QuarantineEventsV2",
+    "/Users/*/.bash_history",
+    "/Users/*/.zsh_history",
+    "/Users/*/.zsh_sessions",
+    "/Users/*/.sh_history",
+    "/var/root/.bash_history",
+    "/var/root/.zsh_history",
+    "/var/root/.zsh_sessions",
+    "/private/var/log/install.log",
+];
+
+/// Acquires the macOS artifact profile: unified logs collected by `log collect`, launch agents and daemons,
+/// quarantine events and shell histories. Everything is packed by an elevated `tar` on the target,
+/// so that files of other users are readable, and downloaded as a single archive.
+pub struct MacOsArtifactsAcquirer<'a> {
+    store_directory: &'a Path,
+    pub connector: Box<dyn Connector>,
+}
+
+impl<'a> MacOsArtifactsAcquirer<'a> {
+    pub fn new(
+        store_directory: &'a Path,
+        connector: Box<dyn Connector>,
+    ) -> MacOsArtifactsAcquirer<'a> {
+        MacOsArtifactsAcquirer {
+            store_directory,
+            connector,
+        }
+    }
+
+    pub fn method_name(&self) -> &'static str {
+        self.connector.connect_method_name()
+    }
+
+    pub fn acquire(&self) -> io::Result<()> {
+        let staging = PathBuf::from(format!("{}/gargamel-{}", self.connector.remote_temp_storage().display(), Uuid::new_v4()));
+        let staging = staging.to_string_lossy().to_string();
+        // Only the connecting user can enter the staging directory while it holds the collected files.
+        self.run(vec!["mkdir".to_string(), "-m".to_string(), "700".to_string(), staging.clone()], false)?;
+        let result = self.collect_and_download(&staging);
+        if let Err(err) = self.run(vec!["rm".to_string(), "-rf".to_string(), staging.clone()], true) {
+            warn!("Cannot remove {} from {} due to {}", staging, self.connector.computer().address, err);
+        }
+        result
+    }
+
+    fn collect_and_download(&self, staging: &str) -> io::Result<()> {
+        let log_archive = format!("{}/unified.logarchive", staging);
+        info!("{}: Collecting unified logs", self.method_name());
+        if let Err(err) = self.run(vec!["log".to_string(), "collect".to_string(), "--output".to_string(), log_archive.clone()], true) {
+            warn!("Cannot collect unified logs from {} due to {}", self.connector.computer().address, err);
+        }
+
+        let archive = format!("{}/macos-artifacts.tar.gz", staging);
+        let mut tar = vec![
+            "tar".to_string(),
+            "-czf".to_string(),
+            archive.clone(),
+            log_archive,
+        ];
+        tar.extend(MACOS_ARTIFACTS.iter().map(|artifact| artifact.to_string()));
+        tar.push("2>/dev/null".to_string());
+        info!("{}: Packing macOS artifacts", self.method_name());
+        // Missing artifacts make tar fail, the archive with the rest is still created.
+        if let Err(err) = self.run(tar, true) {
+            debug!("Packing macOS artifacts on {} reported {}", self.connector.computer().address, err);
+        }
+
+        self.connector.copier().copy_from_remote(Path::new(&archive), self.store_directory)
+    }
+
+    fn run(&self, command: Vec<String>, elevated: bool) -> io::Result<()> {
+        let outcome = self.connector.connect_and_run_command(
+            Command::new(command.clone(), None, "", elevated),
+            None,
+        )?;
+        if outcome.is_failure() {
+            return Err(io::Error::new(
+                outcome.error_kind(),
+                format!("Command {:?} failed with {}", command, outcome),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Decorate for MacOsArtifactsAcquirer<'a> {
+    fn decorate(mut self, decorator: &dyn Fn(Box<dyn Connector>) -> Box<dyn Connector>) -> Self {
+        self.connector = decorator(self.connector);
+        self
+    }
+}
@@ -13,17 +13,22 @@ extern crate simplelog;
 
 use clap::derive::Clap;
 use crate::evidence_acquirer::EvidenceAcquirer;
-use std::path::{Path, PathBuf};
-use crate::remote::{Computer, Rdp, Wmi, Ssh, RemoteFileCopier, ReDownloader, PsExec, PsRemote, Local, Connector, RevShareConnector, SevenZipCompressCopier, ShadowCopier};
+use std::path::Path;
+use std::rc::Rc;
+use std::panic::{self, AssertUnwindSafe};
+use crate::remote::{Computer, Rdp, Wmi, Ssh, RemoteFileCopier, ReDownloader, PsExec, WinRm, WinRmSettings, Local, Connector, SshSettings, RevShareConnector, SevenZipCompressCopier, ShadowCopier, CapabilityReport, CredentialSource, Vault, vault_passphrase, OsInfo, OsFamily, DEFAULT_SYSTEM_DRIVE, DryRun, DryRunPlan, Decorate, Recording, Recorder, Fixture, Replayer, set_ready_timeout, set_operation_timeout, set_target_budget, TargetBudget, Retrying, RetryPolicy, set_retry_policy, FootprintLedger};
+use crate::macos_acquirer::{MacOsArtifactsAcquirer, MACOS_PROFILE};
 use crate::memory_acquirer::MemoryAcquirer;
 use crate::command_runner::CommandRunner;
-use crate::file_acquirer::download_files;
+use crate::file_acquirer::{download_files, download_files_from_shadow_copies};
 use crate::registry_acquirer::RegistryAcquirer;
 use std::time::Duration;
 use crate::events_acquirer::EventsAcquirer;
 use rayon::prelude::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
 use crate::svi_data_acquirer::SystemVolumeInformationAcquirer;
+use crate::host::is_windows_host;
+use crate::acquisition_summary::AcquisitionSummary;
 
 mod process_runner;
 mod evidence_acquirer;
@@ -40,6 +45,9 @@ mod registry_acquirer;
 mod command_runner;
 mod svi_data_acquirer;
 mod embedded_search_list;
+mod host;
+mod acquisition_summary;
+mod macos_acquirer;
 
 fn setup_logger() {
     CombinedLogger::init(
@@ -54,11 +62,34 @@ fn main() -> Result<(), io::Error> {
     setup_logger();
     print_logo();
 
-    let opts: Opts = Opts::parse();
+    let opts = Opts::parse();
+    // A replayed session runs no program on this host, so the methods of a session recorded on Windows stay available.
+    let opts: Opts = if opts.replay.is_some() { opts } else { restrict_to_host_platform(opts) };
     create_dir_all(&opts.local_store_directory)?;
+    set_ready_timeout(Duration::from_secs(opts.ready_timeout), Duration::from_millis(opts.ready_interval));
+    if let Some(timeout) = opts.operation_timeout {
+        set_operation_timeout(Duration::from_secs(timeout));
+    }
+    if let Some(budget) = opts.target_timeout {
+        set_target_budget(Duration::from_secs(budget));
+    }
+    if let Some(policy) = &opts.retry {
+        set_retry_policy(policy.clone());
+    }
     debug!("Parsing remote computers.");
     let remote_computers: Vec<Computer> = opts.clone().into();
     trace!("Will connect to {} computers", remote_computers.len());
+    if opts.vault_add {
+        return add_to_vault(&opts, &remote_computers);
+    }
+    if let Some(case) = &opts.cleanup {
+        for remote_computer in remote_computers.iter() {
+            if let Err(err) = clean_up_case(&opts, remote_computer, Path::new(case)) {
+                error!("Cannot clean up {} due to {}", remote_computer.address, err);
+            }
+        }
+        return Ok(());
+    }
     let opts = Opts {
         password: remote_computers[0].password.clone(),
         domain: remote_computers[0].domain.clone(),
@@ -67,6 +98,8 @@ fn main() -> Result<(), io::Error> {
         ..opts
     };
 
+    // A target is handled from start to end by one thread, also by rayon with --par,
+    // so what belongs to the current target (footprint ledger, deadline) is kept in thread-locals.
     if opts.par {
         remote_computers.par_iter()
             .map(|remote_computer| handle_remote_computer(&opts, &remote_computer))
@@ -79,23 +112,208 @@ fn main() -> Result<(), io::Error> {
     Ok(())
 }
 
+/// Stores the passwords of the targets in the vault given by `--credentials vault:PATH`.
+fn add_to_vault(opts: &Opts, remote_computers: &[Computer]) -> io::Result<()> {
+    let path = match &opts.credentials {
+        Some(CredentialSource::Vault(path)) => path,
+        _ => return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--vault-add requires --credentials vault:PATH",
+        )),
+    };
+    let mut vault = Vault::open(path, vault_passphrase())?;
+    let mut stored = 0;
+    for computer in remote_computers.iter() {
+        if let Some(password) = &computer.password {
+            vault.insert(format!("{}@{}", computer.username, computer.address), password.clone());
+            stored += 1;
+        }
+    }
+    vault.save()?;
+    info!("Stored passwords of {} targets in {}", stored, vault);
+    Ok(())
+}
+
+/// Windows connection methods and artifacts depend on Windows-only programs
+/// (PsExec, WMImplant, SharpRDP, `net`, `reg`, `wevtutil`, winpmem),
+/// so they are turned off when Gargamel is not launched from Windows.
+/// PowerShell remoting (`--psrem`) is implemented natively and stays available.
+fn restrict_to_host_platform(opts: Opts) -> Opts {
+    if is_windows_host() {
+        return opts;
+    }
+    let windows_switches = [
+        (opts.psexec64, "--psexec"),
+        (opts.psexec32, "--psexec32"),
+        (opts.wmi, "--wmi"),
+        (opts.rdp, "--rdp"),
+        (opts.all, "--all"),
+        (opts.reverse_share, "--reverse-share"),
+    ];
+    for (enabled, switch) in windows_switches.iter() {
+        if *enabled {
+            warn!("{} is supported only on Windows hosts and will be ignored.", switch);
+        }
+    }
+    let local = opts.local || opts.computer == "127.0.0.1" || opts.computer == "localhost";
+    if local {
+        debug!("Local acquisition of Windows event logs, registry, memory and shadow copies is supported only on Windows hosts.");
+    }
+    Opts {
+        psexec64: false,
+        psexec32: false,
+        wmi: false,
+        rdp: false,
+        all: false,
+        reverse_share: false,
+        disable_event_download: opts.disable_event_download || local,
+        disable_registry_download: opts.disable_registry_download || local,
+        image_memory: opts.image_memory && !local,
+        acquire_svi_data: opts.acquire_svi_data && !local,
+        shadow: opts.shadow && !local,
+        ..opts
+    }
+}
+
 fn handle_remote_computer(opts: &Opts, remote_computer: &Computer) -> Result<(), io::Error> {
     info!("Connecting to {} with user {}", remote_computer.address, remote_computer.domain_username());
-    let local_store_directory_owned = dunce::canonicalize(Path::new(&opts.local_store_directory)).unwrap();
-    let local_store_directory = local_store_directory_owned.as_path();
+    let budget = TargetBudget::start();
+    let local_store_directory = dunce::canonicalize(Path::new(&opts.local_store_directory)).unwrap();
+    // A dry run and a replay change nothing on the target.
+    let ledger = if opts.dry_run || opts.replay.is_some() {
+        None
+    } else {
+        Some(FootprintLedger::open(&local_store_directory, remote_computer)?)
+    };
+    if let Some(ledger) = &ledger {
+        // A reverse share left open by a run that crashed is not kept open during this one.
+        ledger.clean_up_host();
+    }
+    let active_ledger = ledger.as_ref().map(|ledger| ledger.activate());
+    // What the acquisition left on the target is removed even when it panics.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        acquire_from_remote_computer(opts, remote_computer, &local_store_directory, &budget)
+    }));
+    drop(active_ledger);
+    if let Some(ledger) = &ledger {
+        clean_up_remote_computer(opts, remote_computer, ledger);
+        store_footprint_report(remote_computer, ledger, &local_store_directory);
+    }
+    match result {
+        Ok(result) => result,
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+/// Removes what interrupted runs left on the target according to its footprint ledger in the case directory.
+fn clean_up_case(opts: &Opts, remote_computer: &Computer, case: &Path) -> io::Result<()> {
+    if !FootprintLedger::path(case, remote_computer).exists() {
+        info!("No footprint ledger of {} in {}", remote_computer.address, case.display());
+        return Ok(());
+    }
+    let ledger = FootprintLedger::open(case, remote_computer)?;
+    if clean_up_remote_computer(opts, remote_computer, &ledger) == 0 {
+        info!("Nothing is left on {}", remote_computer.address);
+    }
+    store_footprint_report(remote_computer, &ledger, case);
+    Ok(())
+}
+
+/// Documents every change made on the target in the case output.
+fn store_footprint_report(remote_computer: &Computer, ledger: &FootprintLedger, store_directory: &Path) {
+    match ledger.report(remote_computer).store(store_directory) {
+        Ok(path) => info!("Footprint report of {} stored in {}", remote_computer.address, path.display()),
+        Err(err) => error!("Cannot store footprint report of {} due to {}", remote_computer.address, err),
+    }
+}
+
+/// Reverts the pending footprints of the target using the methods that made them.
+/// Returns how many footprints are left.
+fn clean_up_remote_computer(opts: &Opts, remote_computer: &Computer, ledger: &Rc<FootprintLedger>) -> usize {
+    if ledger.pending().is_empty() {
+        return 0;
+    }
+    let methods = ledger.pending_methods();
+    let uses = |method: &str| methods.iter().any(|it| it == method);
+    let cleanup_opts = Opts {
+        psexec64: uses("PSEXEC"),
+        psexec32: false,
+        psrem: uses("WINRM"),
+        wmi: uses("WMI"),
+        rdp: uses("RDP"),
+        ssh: uses("SSH"),
+        local: uses("LOCAL"),
+        all: false,
+        ..opts.clone()
+    };
+    let local = remote_computer.address == "127.0.0.1" || remote_computer.address == "localhost";
+    let remote_temp_storage = Path::new(&opts.remote_store_directory);
+    let connectors = create_connectors(&cleanup_opts, remote_computer, remote_temp_storage, true, local, false)
+        .into_iter()
+        .map(|connector| match &opts.retry {
+            Some(policy) => Box::new(Retrying::new(connector, policy.clone())),
+            None => connector,
+        })
+        .collect::<Vec<Box<dyn Connector>>>();
+    info!("Removing what the acquisition left on {}", remote_computer.address);
+    ledger.clean_up(&connectors)
+}
+
+fn acquire_from_remote_computer(
+    opts: &Opts,
+    remote_computer: &Computer,
+    local_store_directory: &Path,
+    budget: &TargetBudget,
+) -> Result<(), io::Error> {
     let remote_temp_storage = Path::new(&opts.remote_store_directory);
-    let key_file = opts.ssh_key.clone().map(|it| PathBuf::from(it));
     let local = opts.computer == "127.0.0.1" || opts.computer == "localhost";
 
+    let reverse_share_planned = opts.dry_run && opts.reverse_share;
+    let decorators = Decorators::new(opts, remote_computer)?;
+    let planned_opts;
+    let opts = if decorators.plan.is_some() || decorators.fixture.is_some() {
+        if opts.probe || opts.auto {
+            warn!("Dry run and replay do not probe {}, which would connect to it, only the selected methods are used", remote_computer.address);
+        }
+        if let (Some(plan), true) = (&decorators.plan, opts.reverse_share) {
+            plan.record_reverse_share(true);
+        }
+        // The reverse share is created on this host, so it is only recorded in the plan and a replay reads the target directly.
+        planned_opts = Opts {
+            probe: false,
+            auto: false,
+            reverse_share: false,
+            ..opts.clone()
+        };
+        &planned_opts
+    } else {
+        opts
+    };
+
+    let selected_opts;
+    let opts = if (opts.probe || opts.auto) && !local {
+        let report = probe_remote_computer(opts, remote_computer, local_store_directory, remote_temp_storage);
+        if opts.probe {
+            return Ok(());
+        }
+        selected_opts = select_method(opts, &report).ok_or_else(|| io::Error::new(
+            io::ErrorKind::Other,
+            format!("No connection method works for {}", remote_computer.address),
+        ))?;
+        &selected_opts
+    } else {
+        opts
+    };
+
     if let Some(remote_file) = &opts.re_download {
-        let connectors = create_connectors(
+        let connectors = decorators.connectors(create_connectors(
             &opts,
             &remote_computer,
             remote_temp_storage,
             true,
             local,
             opts.reverse_share,
-        );
+        ));
         let remote_file = Path::new(&remote_file);
         for connector in connectors {
             let _compress_copier = SevenZipCompressCopier::new(connector.as_ref(), false, None, false);
@@ -122,159 +340,388 @@ fn handle_remote_computer(opts: &Opts, remote_computer: &Computer) -> Result<(),
         }
     }
 
+    let mut summary = AcquisitionSummary::new(remote_computer);
+    let os = if opts.no_fingerprint {
+        None
+    } else {
+        fingerprint_remote_computer(opts, remote_computer, local_store_directory, remote_temp_storage, local, &decorators)
+    };
+    if let Some(os) = &os {
+        summary.set_os(os.clone());
+    }
+
     if !opts.disable_evidence_download && !opts.disable_predefined_download {
-        let evidence_acquirers = create_evidence_acquirers(
+        let evidence_acquirers = decorators.acquirers(create_evidence_acquirers(
             &remote_computer,
             local_store_directory,
             &opts,
-            key_file.as_ref().map(|it| it.to_path_buf()),
             remote_temp_storage,
             local,
+        ));
+        let evidence_acquirers = evidence_acquirers.into_iter()
+            .map(|acquirer| match &os {
+                Some(os) => acquirer.with_os(os),
+                None => acquirer,
+            })
+            .collect();
+        summary.acquire_with_fallback(
+            "evidence",
+            evidence_acquirers,
+            |acquirer| acquirer.method_name(),
+            |acquirer| acquirer.run_all(),
         );
-        for acquirer in evidence_acquirers {
-            acquirer.run_all();
-        }
     }
 
     if !opts.disable_event_download && !opts.disable_predefined_download {
-        let event_acquirers = create_events_acquirers(
+        let event_acquirers = decorators.acquirers(create_events_acquirers(
             &remote_computer,
             local_store_directory,
             &opts,
             remote_temp_storage,
             local,
+        ));
+        summary.acquire_with_fallback(
+            "events",
+            event_acquirers,
+            |acquirer| acquirer.method_name(),
+            |acquirer| acquirer.acquire(),
         );
-        for acquirer in event_acquirers {
-            acquirer.acquire();
-        }
     }
 
     if let Some(custom_commands_path) = &opts.custom_command_path {
-        let command_runners = create_command_runners(
+        let command_runners = decorators.acquirers(create_command_runners(
             &remote_computer,
             local_store_directory,
             &opts,
-            key_file.as_ref().map(|it| it.to_path_buf()),
             remote_temp_storage,
             local,
-        );
-        for command_runner in command_runners {
-            info!("Running commands using method {}", command_runner.connector.connect_method_name());
-            command_runner.run_commands(
+        ));
+        summary.acquire_with_fallback(
+            "custom commands",
+            command_runners,
+            |command_runner| command_runner.connector.connect_method_name(),
+            |command_runner| command_runner.run_commands(
                 Path::new(custom_commands_path),
                 Some(Duration::from_secs(opts.timeout)),
-            );
-        }
+            ),
+        );
     }
     if !opts.disable_registry_download && !opts.disable_predefined_download {
-        let registry_acquirers = create_registry_acquirers(
+        let registry_acquirers = decorators.acquirers(create_registry_acquirers(
             &remote_computer,
             local_store_directory,
             &opts,
             remote_temp_storage,
             local,
+        ));
+        summary.acquire_with_fallback(
+            "registry",
+            registry_acquirers,
+            |acquirer| acquirer.method_name(),
+            |acquirer| acquirer.acquire(),
         );
-        for acquirer in registry_acquirers {
-            acquirer.acquire();
-        }
     }
     if let Some(search_files_path) = &opts.search_files_path {
         if opts.ssh {
-            let remote_copier = Ssh {
-                computer: remote_computer.clone(),
-                key_file: key_file.as_ref().map(|it| it.clone()),
-            };
-            download_files(
-                search_files_path,
-                local_store_directory,
-                &remote_copier,
-                opts.no_compression,
-            )?;
+            let remote_copier = decorators.decorate(Box::new(Ssh::new(
+                remote_computer.clone(),
+                SshSettings::from(opts),
+            )));
+            let macos = os.as_ref().map(|os| os.family == OsFamily::MacOs).unwrap_or(false);
+            if search_files_path == MACOS_PROFILE || (search_files_path == "EMBEDDED" && macos) {
+                summary.acquire_with_fallback(
+                    "files",
+                    vec![MacOsArtifactsAcquirer::new(local_store_directory, remote_copier)],
+                    |acquirer| acquirer.method_name(),
+                    |acquirer| acquirer.acquire(),
+                );
+            } else {
+                summary.acquire_with_fallback(
+                    "files",
+                    vec![remote_copier],
+                    |connector| connector.connect_method_name(),
+                    |connector| download_files(
+                        search_files_path,
+                        local_store_directory,
+                        connector.copier(),
+                        opts.no_compression,
+                    ),
+                );
+            }
         } else {
-            let connectors = create_connectors(
+            let connectors = decorators.connectors(create_connectors(
                 &opts,
                 &remote_computer,
                 remote_temp_storage,
                 true,
                 local,
                 opts.reverse_share,
+            ));
+            summary.acquire_with_fallback(
+                "files",
+                connectors,
+                |connector| connector.connect_method_name(),
+                |connector| {
+                    let _compress_copier = SevenZipCompressCopier::new(connector.as_ref(), false, None, false);
+                    let mut _shadow_copier = ShadowCopier::new(connector.as_ref(), local_store_directory, None);
+                    let compression = !opts.no_compression;
+                    let shadow = opts.shadow;
+                    let copier = if compression && shadow {
+                        _shadow_copier.copier_impl = &_compress_copier;
+                        &_shadow_copier as &dyn RemoteFileCopier
+                    } else if compression {
+                        &_compress_copier as &dyn RemoteFileCopier
+                    } else if shadow {
+                        &_shadow_copier as &dyn RemoteFileCopier
+                    } else {
+                        connector.copier()
+                    };
+                    info!("Downloading specified files using {}", copier.method_name());
+                    download_files(
+                        search_files_path,
+                        local_store_directory,
+                        copier,
+                        opts.no_compression,
+                    )?;
+                    info!("Files in {} successfully transferred.", search_files_path);
+                    Ok(())
+                },
             );
-            for connector in connectors.into_iter() {
-                let _compress_copier = SevenZipCompressCopier::new(connector.as_ref(), false, None, false);
-                let mut _shadow_copier = ShadowCopier::new(connector.as_ref(), local_store_directory, None);
-                let compression = !opts.no_compression;
-                let shadow = opts.shadow;
-                let copier = if compression && shadow {
-                    _shadow_copier.copier_impl = &_compress_copier;
-                    &_shadow_copier as &dyn RemoteFileCopier
-                } else if compression {
-                    &_compress_copier as &dyn RemoteFileCopier
-                } else if shadow {
-                    &_shadow_copier as &dyn RemoteFileCopier
-                } else {
-                    connector.copier()
-                };
-                info!("Downloading specified files using {}", copier.method_name());
-                let result = download_files(
-                    search_files_path,
-                    local_store_directory,
-                    copier,
-                    opts.no_compression,
+            if let Some(shadow_history) = &opts.shadow_history {
+                let connectors = decorators.connectors(create_connectors(
+                    &opts,
+                    &remote_computer,
+                    remote_temp_storage,
+                    true,
+                    local,
+                    opts.reverse_share,
+                ));
+                summary.acquire_with_fallback(
+                    "files from shadow copies",
+                    connectors,
+                    |connector| connector.connect_method_name(),
+                    |connector| {
+                        let _compress_copier = SevenZipCompressCopier::new(connector.as_ref(), false, None, false);
+                        let downloader = if opts.no_compression { None } else { Some(&_compress_copier as &dyn RemoteFileCopier) };
+                        download_files_from_shadow_copies(
+                            search_files_path,
+                            local_store_directory,
+                            connector.as_ref(),
+                            downloader,
+                            shadow_history,
+                            opts.no_compression,
+                        )
+                    },
                 );
-                match result {
-                    Err(err) => error!("{}", err),
-                    Ok(_) => {
-                        info!("Files in {} successfully transferred.", search_files_path);
-                        break;
-                    }
-                };
             }
         }
     }
     if opts.image_memory {
-        let memory_acquirers = create_memory_acquirers(
+        let memory_acquirers = decorators.acquirers(create_memory_acquirers(
             &remote_computer,
             local_store_directory,
             &opts,
             remote_temp_storage,
             local,
+        ));
+        summary.acquire_with_fallback(
+            "memory",
+            memory_acquirers,
+            |acquirer| acquirer.connector.connect_method_name(),
+            |acquirer| acquirer.image_memory(),
         );
-        for acquirer in memory_acquirers {
-            info!("Running memory acquirer using method {}", acquirer.connector.connect_method_name());
-            let image_res = acquirer.image_memory();
-            match image_res {
-                Err(err) => error!("{}", err),
-                Ok(_) => break
-            };
-        }
     }
 
     if opts.acquire_svi_data {
-        let svi_acquirers = create_svi_acquirers(
+        let system_drive = os.as_ref().map_or_else(|| DEFAULT_SYSTEM_DRIVE.to_string(), |os| os.windows_system_drive());
+        let svi_acquirers = decorators.acquirers(create_svi_acquirers(
             &remote_computer,
             local_store_directory,
             &opts,
             remote_temp_storage,
             local,
+        ));
+        summary.acquire_with_fallback(
+            "system volume information",
+            svi_acquirers,
+            |acquirer| acquirer.connector.connect_method_name(),
+            |acquirer| acquirer.download_data(&system_drive),
         );
-        for acquirer in svi_acquirers {
-            info!("Running svi acquirer using method {}", acquirer.connector.connect_method_name());
-            let svi_res = acquirer.download_data();
-            match svi_res {
-                Err(err) => error!("{}", err),
-                Ok(_) => break
-            };
+    }
+
+    if let Some(plan) = &decorators.plan {
+        if reverse_share_planned {
+            plan.record_reverse_share(false);
+        }
+        info!("{}", plan);
+        match plan.store(local_store_directory) {
+            Ok((text_path, json_path)) => info!("Dry run plan of {} stored in {} and {}", remote_computer.address, text_path.display(), json_path.display()),
+            Err(err) => error!("Cannot store dry run plan of {} due to {}", remote_computer.address, err),
+        }
+        return Ok(());
+    }
+
+    if let Some(fixture) = &decorators.fixture {
+        if fixture.remaining() > 0 {
+            warn!("Replay of {} left {} recorded interactions unused", remote_computer.address, fixture.remaining());
         }
     }
 
+    if budget.is_spent() {
+        warn!("Time budget of {} was spent before the acquisition finished", remote_computer.address);
+    }
+    info!("{}", summary);
+    if let Err(err) = summary.store(local_store_directory) {
+        error!("Cannot store acquisition summary of {} due to {}", remote_computer.address, err);
+    }
     Ok(())
 }
 
+/// Connector decorators of one target selected by `--dry-run`, `--record` and `--replay`,
+/// connectors reaching the target also retry transient failures with `--retry`.
+struct Decorators {
+    plan: Option<Rc<DryRunPlan>>,
+    recording: Option<Rc<Recording>>,
+    fixture: Option<Rc<Fixture>>,
+    retry: Option<RetryPolicy>,
+}
+
+impl Decorators {
+    fn new(opts: &Opts, computer: &Computer) -> io::Result<Decorators> {
+        if opts.dry_run as u8 + opts.record.is_some() as u8 + opts.replay.is_some() as u8 > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--dry-run, --record and --replay cannot be combined",
+            ));
+        }
+        // Each target has its own fixture, named like the reports of the target.
+        let fixture_directory = |root: &str| Path::new(root).join(computer.address.replace(|c: char| !c.is_ascii_alphanumeric(), "-"));
+        let recording = match &opts.record {
+            Some(root) => Some(Rc::new(Recording::create(&fixture_directory(root))?)),
+            None => None,
+        };
+        let fixture = match &opts.replay {
+            Some(root) => Some(Rc::new(Fixture::open(&fixture_directory(root))?)),
+            None => None,
+        };
+        Ok(Decorators {
+            plan: if opts.dry_run { Some(Rc::new(DryRunPlan::new(computer))) } else { None },
+            recording,
+            fixture,
+            retry: opts.retry.clone(),
+        })
+    }
+
+    fn decorate(&self, connector: Box<dyn Connector>) -> Box<dyn Connector> {
+        // Only operations on the target are retried, a recording keeps just their final results.
+        let connector: Box<dyn Connector> = match (&self.fixture, &self.plan) {
+            (Some(fixture), _) => Box::new(Replayer::new(connector, fixture.clone())),
+            (None, None) => match &self.retry {
+                Some(policy) => Box::new(Retrying::new(connector, policy.clone())),
+                None => connector,
+            },
+            (None, Some(_)) => connector,
+        };
+        let connector: Box<dyn Connector> = match &self.recording {
+            Some(recording) => Box::new(Recorder::new(connector, recording.clone())),
+            None => connector,
+        };
+        DryRun::wrap(connector, self.plan.as_ref())
+    }
+
+    fn connectors(&self, connectors: Vec<Box<dyn Connector>>) -> Vec<Box<dyn Connector>> {
+        connectors.into_iter()
+            .map(|connector| self.decorate(connector))
+            .collect()
+    }
+
+    fn acquirers<T: Decorate>(&self, acquirers: Vec<T>) -> Vec<T> {
+        acquirers.into_iter()
+            .map(|acquirer| acquirer.decorate(&|connector| self.decorate(connector)))
+            .collect()
+    }
+}
+
+/// Probes every method available on this host and stores the capability report of the target.
+fn probe_remote_computer(
+    opts: &Opts,
+    computer: &Computer,
+    local_store_directory: &Path,
+    remote_temp_storage: &Path,
+) -> CapabilityReport {
+    let probed_opts = Opts {
+        psexec64: is_windows_host(),
+        psexec32: false,
+        wmi: is_windows_host(),
+        rdp: is_windows_host(),
+        psrem: true,
+        ssh: true,
+        all: false,
+        local: false,
+        ..opts.clone()
+    };
+    let connectors = create_connectors(&probed_opts, computer, remote_temp_storage, true, false, false);
+    let report = CapabilityReport::probe(computer, &connectors);
+    info!("{}", report);
+    if let Err(err) = report.store(local_store_directory) {
+        error!("Cannot store capability report of {} due to {}", computer.address, err);
+    }
+    report
+}
+
+/// Detects the operating system of the target using the enabled methods.
+/// The first method reporting more than the family wins, otherwise the family implied by the first method is used.
+fn fingerprint_remote_computer(
+    opts: &Opts,
+    computer: &Computer,
+    local_store_directory: &Path,
+    remote_temp_storage: &Path,
+    local: bool,
+    decorators: &Decorators,
+) -> Option<OsInfo> {
+    let connectors = decorators.connectors(create_connectors(opts, computer, remote_temp_storage, true, local, false));
+    let mut fallback = None;
+    for connector in connectors.iter() {
+        debug!("Fingerprinting {} using method {}", computer.address, connector.connect_method_name());
+        let os = connector.fingerprint(local_store_directory);
+        if os.is_detailed() {
+            info!("Operating system of {} is {}", computer.address, os);
+            if !os.has_english_output() {
+                warn!("{} uses locale {}, command output parsed in English, e.g. of vssadmin, may not be understood",
+                      computer.address, os.locale.as_deref().unwrap_or_default());
+            }
+            return Some(os);
+        }
+        fallback = fallback.or(Some(os));
+    }
+    match &fallback {
+        Some(os) => warn!("Cannot fingerprint {}, assuming {}", computer.address, os.family),
+        None => warn!("Cannot fingerprint {}, no method is enabled", computer.address),
+    }
+    fallback
+}
+
+/// Options enabling only the best method found by the probe.
+fn select_method(opts: &Opts, report: &CapabilityReport) -> Option<Opts> {
+    let method = report.best_method()?.method;
+    info!("Using method {} for {}", method, report.computer.address);
+    Some(Opts {
+        psexec64: method == "PSEXEC",
+        psexec32: false,
+        psrem: method == "WINRM",
+        wmi: method == "WMI",
+        rdp: method == "RDP",
+        ssh: method == "SSH",
+        all: false,
+        local: false,
+        ..opts.clone()
+    })
+}
+
 fn create_evidence_acquirers<'a>(
     computer: &'a Computer,
     local_store_directory: &'a Path,
     opts: &Opts,
-    key_file: Option<PathBuf>,
     remote_temp_storage: &Path,
     local: bool,
 ) -> Vec<EvidenceAcquirer<'a>> {
@@ -300,6 +747,7 @@ fn create_evidence_acquirers<'a>(
                 computer.clone(),
                 local_store_directory,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             ),
         );
     }
@@ -309,7 +757,7 @@ fn create_evidence_acquirers<'a>(
                 computer.clone(),
                 local_store_directory,
                 remote_temp_storage.to_path_buf(),
-                opts.share.clone(),
+                WinRmSettings::from(opts),
                 opts.reverse_share,
             )
         );
@@ -319,7 +767,7 @@ fn create_evidence_acquirers<'a>(
             EvidenceAcquirer::ssh(
                 computer.clone(),
                 local_store_directory,
-                key_file,
+                SshSettings::from(opts),
             )
         )
     }
@@ -330,6 +778,7 @@ fn create_evidence_acquirers<'a>(
                 local_store_directory,
                 opts.nla,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             ),
         )
     }
@@ -388,7 +837,7 @@ fn create_memory_acquirers<'a>(
                 local_store_directory,
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
-                opts.share.clone(),
+                WinRmSettings::from(opts),
                 opts.reverse_share,
             )
         );
@@ -403,6 +852,7 @@ fn create_memory_acquirers<'a>(
                 Duration::from_secs(opts.timeout),
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             )
         );
     }
@@ -415,6 +865,7 @@ fn create_memory_acquirers<'a>(
                 Duration::from_secs(opts.timeout),
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             )
         );
     }
@@ -469,7 +920,7 @@ fn create_svi_acquirers<'a>(
                 local_store_directory,
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
-                opts.share.clone(),
+                WinRmSettings::from(opts),
                 opts.reverse_share,
             )
         );
@@ -484,6 +935,7 @@ fn create_svi_acquirers<'a>(
                 Duration::from_secs(opts.timeout),
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             )
         );
     }
@@ -496,6 +948,7 @@ fn create_svi_acquirers<'a>(
                 Duration::from_secs(opts.timeout),
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             )
         );
     }
@@ -511,7 +964,6 @@ fn create_command_runners<'a>(
     computer: &'a Computer,
     local_store_directory: &'a Path,
     opts: &Opts,
-    key_file: Option<PathBuf>,
     remote_temp_storage: &Path,
     local: bool,
 ) -> Vec<CommandRunner<'a>> {
@@ -539,7 +991,7 @@ fn create_command_runners<'a>(
                 computer.clone(),
                 local_store_directory,
                 remote_temp_storage.to_path_buf(),
-                opts.share.clone(),
+                WinRmSettings::from(opts),
             )
         );
     }
@@ -548,7 +1000,7 @@ fn create_command_runners<'a>(
             CommandRunner::ssh(
                 computer.clone(),
                 local_store_directory,
-                key_file,
+                SshSettings::from(opts),
             )
         )
     }
@@ -626,7 +1078,7 @@ fn create_registry_acquirers<'a>(
                 computer.clone(),
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
-                opts.share.clone(),
+                WinRmSettings::from(opts),
                 opts.reverse_share,
             ),
         );
@@ -639,6 +1091,7 @@ fn create_registry_acquirers<'a>(
                 Duration::from_secs(opts.timeout),
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             ),
         );
     }
@@ -651,6 +1104,7 @@ fn create_registry_acquirers<'a>(
                 opts.nla,
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             ),
         )
     }
@@ -707,7 +1161,7 @@ fn create_events_acquirers<'a>(
                 computer.clone(),
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
-                opts.share.clone(),
+                WinRmSettings::from(opts),
                 opts.reverse_share,
             ),
         );
@@ -720,6 +1174,7 @@ fn create_events_acquirers<'a>(
                 Duration::from_secs(opts.timeout),
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             ),
         );
     }
@@ -732,6 +1187,7 @@ fn create_events_acquirers<'a>(
                 opts.nla,
                 opts.no_compression,
                 remote_temp_storage.to_path_buf(),
+                opts.reverse_share,
             ),
         )
     }
@@ -774,34 +1230,29 @@ fn create_connectors(
         copiers.push(copier);
     }
     if opts.psrem || opts.all {
-        let _copier = Box::new(PsRemote::new(computer.clone(), remote_temp_storage.to_path_buf(), opts.share.clone()));
+        let _copier = Box::new(WinRm::new(computer.clone(), remote_temp_storage.to_path_buf(), WinRmSettings::from(opts)));
         let copier: Box<dyn Connector> = if reverse_share { Box::new(RevShareConnector::new(_copier)) } else { _copier };
         copiers.push(copier);
     }
     if opts.rdp || opts.all {
-        let copier = Box::new(Rdp {
+        let _copier = Box::new(Rdp {
             computer: computer.clone(),
             nla: opts.nla,
             remote_temp_storage: remote_temp_storage.to_path_buf(),
         });
-        copiers.push(
-            copier
-        );
+        let copier: Box<dyn Connector> = if reverse_share { Box::new(RevShareConnector::new(_copier)) } else { _copier };
+        copiers.push(copier);
     }
     if opts.wmi || opts.all {
-        let copier = Box::new(Wmi {
-            computer: computer.clone(),
-            remote_temp_storage: remote_temp_storage.to_path_buf(),
-        });
-        copiers.push(
-            copier
-        );
+        let _copier = Box::new(Wmi::new(computer.clone(), remote_temp_storage.to_path_buf()));
+        let copier: Box<dyn Connector> = if reverse_share { Box::new(RevShareConnector::new(_copier)) } else { _copier };
+        copiers.push(copier);
     }
     if opts.ssh && allowed_ssh {
-        copiers.push(Box::new(Ssh {
-            computer: computer.clone(),
-            key_file: opts.ssh_key.clone().map(|key_file| PathBuf::from(key_file)),
-        }));
+        copiers.push(Box::new(Ssh::new(
+            computer.clone(),
+            SshSettings::from(opts),
+        )));
     }
     if opts.local {
         let copier = Box::new(Local::new(computer.username.clone(), remote_temp_storage.to_path_buf()));
@@ -1,4 +1,4 @@
-use crate::remote::{Connector, Computer, Command, PsExec, PsRemote, Rdp, Wmi, SevenZipCompressCopier, RemoteFileCopier, Compression, Local, RevShareConnector};
+use crate::remote::{Connector, Computer, Command, PsExec, WinRm, WinRmSettings, Rdp, Wmi, SevenZipCompressCopier, RemoteFileCopier, Compression, Local, RevShareConnector, Decorate, Footprint, record_footprint, footprint_removed, ShareMap};
 use std::path::{Path, PathBuf};
 use std::{io, thread};
 use std::time::Duration;
@@ -18,7 +18,7 @@ impl<'a> MemoryAcquirer<'a> {
         local_store_directory: &'a Path,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reverse: bool,
     ) -> MemoryAcquirer<'a> {
         let connector = Box::new(PsExec::psexec32(remote_computer, remote_temp_storage, custom_share_folder));
@@ -50,7 +50,7 @@ impl<'a> MemoryAcquirer<'a> {
         local_store_directory: &'a Path,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reverse: bool,
     ) -> MemoryAcquirer<'a> {
         let connector = Box::new(PsExec::psexec64(remote_computer, remote_temp_storage, custom_share_folder));
@@ -68,10 +68,10 @@ impl<'a> MemoryAcquirer<'a> {
         local_store_directory: &'a Path,
         _no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        winrm: WinRmSettings,
         reverse: bool,
     ) -> MemoryAcquirer<'a> {
-        let connector = Box::new(PsRemote::new(remote_computer, remote_temp_storage, custom_share_folder));
+        let connector = Box::new(WinRm::new(remote_computer, remote_temp_storage, winrm));
         MemoryAcquirer {
             local_store_directory,
             connector: if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
@@ -88,13 +88,15 @@ impl<'a> MemoryAcquirer<'a> {
         compress_timeout: Duration,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
+        reverse: bool,
     ) -> MemoryAcquirer<'a> {
+        let connector = Box::new(Wmi::new(remote_computer.clone(), remote_temp_storage));
         MemoryAcquirer {
             local_store_directory,
-            connector: Box::new(Wmi { computer: remote_computer.clone(), remote_temp_storage }),
+            connector: if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
             image_timeout: Some(timeout),
             compress_timeout: Some(compress_timeout),
-            compression: if no_7zip { Compression::No } else { Compression::YesSplit },
+            compression: Compression::split_unless_reversed(no_7zip, reverse),
         }
     }
 
@@ -106,17 +108,19 @@ impl<'a> MemoryAcquirer<'a> {
         compress_timeout: Duration,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
+        reverse: bool,
     ) -> MemoryAcquirer<'a> {
+        let connector = Box::new(Rdp {
+            nla,
+            computer: remote_computer.clone(),
+            remote_temp_storage,
+        });
         MemoryAcquirer {
             local_store_directory,
-            connector: Box::new(Rdp {
-                nla,
-                computer: remote_computer.clone(),
-                remote_temp_storage,
-            }),
+            connector: if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
             image_timeout: Some(image_timeout),
             compress_timeout: Some(compress_timeout),
-            compression: if no_7zip { Compression::No } else { Compression::YesSplit },
+            compression: Compression::split_unless_reversed(no_7zip, reverse),
         }
     }
 
@@ -147,10 +151,22 @@ impl<'a> MemoryAcquirer<'a> {
             report_filename_prefix: "mem-ack-log",
             elevated: true,
         };
-        self.connector.connect_and_run_local_program_in_current_directory(
+        let outcome = self.connector.connect_and_run_local_program_in_current_directory(
             connection,
             self.image_timeout,
         )?;
+        record_footprint(self.connector.connect_method_name(), Footprint::RemoteFile(target_name.clone()));
+        // An interrupted image is incomplete, so it is deleted instead of downloaded.
+        if outcome.timed_out {
+            match self.connector.copier().delete_remote_file(&target_name) {
+                Ok(_) => footprint_removed(&Footprint::RemoteFile(target_name.clone())),
+                Err(err) => error!("Cannot delete incomplete memory image {} due to {}", target_name.display(), err),
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Memory image of {} {}", self.connector.computer().address, outcome),
+            ));
+        }
         let _copier = self.connector.copier();
         let _compression_split_copier = SevenZipCompressCopier::new(self.connector.as_ref(), true, self.compress_timeout, false);
         let _compression_copier = SevenZipCompressCopier::new(self.connector.as_ref(), false, self.compress_timeout, false);
@@ -159,25 +175,23 @@ impl<'a> MemoryAcquirer<'a> {
             Compression::Yes => &_compression_copier as &dyn RemoteFileCopier,
             Compression::YesSplit => &_compression_split_copier as &dyn RemoteFileCopier,
         };
-        match copier.copy_from_remote(
+        let download_result = copier.copy_from_remote(
             &target_name,
             &local_store_directory,
             // &self.local_store_directory.join(target_name.file_name().unwrap()),
-        ) {
-            Ok(_) => {}
-            Err(err) => {
-                error!("Cannot download {} report from {} using method {} due to {}",
-                       target_name.display(),
-                       self.connector.computer().address,
-                       self.connector.connect_method_name(),
-                       err
-                )
-            }
+        );
+        if let Err(err) = &download_result {
+            error!("Cannot download {} report from {} using method {} due to {}",
+                   target_name.display(),
+                   self.connector.computer().address,
+                   self.connector.connect_method_name(),
+                   err
+            )
         }
         thread::sleep(Duration::from_millis(1000));
         let winpem_path = self.connector.remote_temp_storage().join(winpmem);
         match copier.delete_remote_file(&winpem_path) {
-            Ok(_) => {}
+            Ok(_) => footprint_removed(&Footprint::RemoteFile(winpem_path.clone())),
             Err(err) => {
                 error!("Cannot delete remote file {} using method {} due to {}",
                        winpem_path.display(),
@@ -188,7 +202,7 @@ impl<'a> MemoryAcquirer<'a> {
         };
         thread::sleep(Duration::from_millis(1000));
         match copier.delete_remote_file(&target_name) {
-            Ok(_) => {}
+            Ok(_) => footprint_removed(&Footprint::RemoteFile(target_name.clone())),
             Err(err) => {
                 error!("Cannot delete remote file {} using method {} due to {}",
                        target_name.display(),
@@ -197,6 +211,13 @@ impl<'a> MemoryAcquirer<'a> {
                 )
             }
         };
-        Ok(())
+        download_result
     }
-}
\ No newline at end of file
+}
+
+impl<'a> Decorate for MemoryAcquirer<'a> {
+    fn decorate(mut self, decorator: &dyn Fn(Box<dyn Connector>) -> Box<dyn Connector>) -> Self {
+        self.connector = decorator(self.connector);
+        self
+    }
+}
@@ -1,19 +1,27 @@
 use std::process::{Command, Stdio};
 use std::ops::Not;
 use std::path::{Path, PathBuf};
-use std::io::Result;
-use crate::remote::Computer;
+use std::io::{Result, Read};
+use crate::remote::{Computer, redact, timed_out_error};
 use std::fs::File;
 use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use wait_timeout::ChildExt;
 
 extern crate dunce;
 
+/// Arguments with secrets masked for logging.
+fn redacted(command_args: &[String]) -> Vec<String> {
+    command_args.iter().map(|arg| redact(arg)).collect()
+}
+
+/// Runs the process until it exits. Processes reaching the target are limited by the remote connectors.
 pub fn run_process_blocking(
     command_name: &str,
     command_args: &[String],
 ) -> Result<()> {
-    trace!("Starting process {}, with args: {:?}", command_name, command_args);
+    trace!("Starting process {}, with args: {:?}", command_name, redacted(command_args));
     let mut command = Command::new(command_name);
     // command.stdout(Stdio::null());
     if command_args.is_empty().not() {
@@ -25,34 +33,81 @@ pub fn run_process_blocking(
     Ok(())
 }
 
-pub fn run_piped_processes_blocking(
-    command_name_first: &str,
-    command_args_first: &[String],
-    command_name_second: &str,
-    command_args_second: &[String],
-) -> Result<()> {
-    let mut first = Command::new(command_name_first);
-    if !command_args_first.is_empty() {
-        first.args(command_args_first);
+/// Exit status and captured output of a finished process.
+pub struct ProcessOutput {
+    pub exit_status: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub timed_out: bool,
+}
+
+/// Runs the process and captures its standard output and error output.
+/// When the `timeout` is reached the process is killed and the output captured so far is returned.
+pub fn run_process_capturing(
+    command_name: &str,
+    command_args: &[String],
+    timeout: Option<Duration>,
+) -> Result<ProcessOutput> {
+    trace!("Starting process {}, with args: {:?}", command_name, redacted(command_args));
+    let mut command = Command::new(command_name);
+    if command_args.is_empty().not() {
+        command.args(command_args);
     }
-    trace!("Starting process \"{}\" with params {:?}", command_name_first, command_args_first);
-    let mut first = first.stdout(Stdio::piped())
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .spawn()?;
-
-    if let Some(first_output) = first.stdout.take() {
-        let mut second = Command::new(command_name_second);
-        second.stdin(first_output);
-        if !command_args_second.is_empty() {
-            second.args(command_args_second);
+    let (stdout, stdout_reader) = read_in_background(child.stdout.take());
+    let (stderr, stderr_reader) = read_in_background(child.stderr.take());
+    let (exit_status, timed_out) = match timeout {
+        None => (child.wait()?.code(), false),
+        Some(timeout) => match child.wait_timeout(timeout)? {
+            Some(status) => (status.code(), false),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                trace!("Process \"{} {}\" reached time out", command_name, redact(&command_args.join(" ")));
+                (None, true)
+            }
         }
-        trace!("Starting process {} with params {:?}", command_name_second, command_args_second);
-        let output = second.output()?;
-        trace!("Command {} output: {}", command_name_second, String::from_utf8_lossy(&output.stdout));
-        trace!("Command {} error: {}", command_name_second, String::from_utf8_lossy(&output.stderr));
-    } else {
-        trace!("Child not invoked")
+    };
+    // Grandchildren of a killed process may keep the pipes open, so the readers are not awaited then.
+    if !timed_out {
+        let _ = stdout_reader.join();
+        let _ = stderr_reader.join();
     }
-    Ok(())
+    let stdout = stdout.lock().map(|it| it.clone()).unwrap_or_default();
+    let stderr = stderr.lock().map(|it| it.clone()).unwrap_or_default();
+    trace!("Command {} output: {}", command_name, String::from_utf8_lossy(&stdout));
+    trace!("Command {} error: {}", command_name, String::from_utf8_lossy(&stderr));
+    Ok(ProcessOutput {
+        exit_status,
+        stdout,
+        stderr,
+        timed_out,
+    })
+}
+
+fn read_in_background<R: Read + Send + 'static>(
+    source: Option<R>
+) -> (Arc<Mutex<Vec<u8>>>, thread::JoinHandle<()>) {
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let buffer_writer = buffer.clone();
+    let reader = thread::spawn(move || {
+        if let Some(mut source) = source {
+            let mut chunk = [0u8; 8 * 1024];
+            while let Ok(read) = source.read(&mut chunk) {
+                if read == 0 {
+                    break;
+                }
+                if let Ok(mut buffer) = buffer_writer.lock() {
+                    buffer.extend_from_slice(&chunk[..read]);
+                }
+            }
+        }
+    });
+    (buffer, reader)
 }
 
 pub fn run_process_blocking_maybe_timed(
@@ -66,30 +121,28 @@ pub fn run_process_blocking_maybe_timed(
     }
 }
 
+/// Runs the process and kills it when it does not exit in time, which is reported as a `TimedOut` error.
 pub fn run_process_blocking_timed(
     command_name: &str,
     command_args: &[String],
     wait_for: Duration,
 ) -> Result<()> {
-    trace!("Starting process {}, with args: {:?} and timeout of {} seconds", command_name, command_args, wait_for.as_secs());
+    trace!("Starting process {}, with args: {:?} and timeout of {} seconds", command_name, redacted(command_args), wait_for.as_secs());
     let mut command = Command::new(command_name);
     if command_args.is_empty().not() {
         command.args(command_args);
     }
     let mut child = command.spawn()?;
     match child.wait_timeout(wait_for)? {
-        Some(_) => {}
+        Some(_) => Ok(()),
         None => {
             // child hasn't exited yet
-            match child.kill() {
-                Ok(_) => {}
-                Err(_) => {}
-            }
-
-            trace!("Process \"{} {}\" reached time out", command_name, command_args.join(" "));
+            let _ = child.kill();
+            let _ = child.wait();
+            trace!("Process \"{} {}\" reached time out", command_name, redact(&command_args.join(" ")));
+            Err(timed_out_error(command_name, wait_for))
         }
-    };
-    Ok(())
+    }
 }
 
 
@@ -1,6 +1,7 @@
 use std::path::{Path, PathBuf};
-use crate::remote::{Computer, Connector, PsExec, PsRemote, Rdp, Wmi, Compression, Local, RevShareConnector};
+use crate::remote::{Computer, Connector, PsExec, WinRm, WinRmSettings, Rdp, Wmi, Compression, Local, RevShareConnector, Decorate, ShareMap};
 use std::time::Duration;
+use std::io;
 use crate::large_evidence_acquirer::LargeEvidenceAcquirer;
 
 pub struct RegistryAcquirer<'a> {
@@ -62,7 +63,7 @@ impl<'a> RegistryAcquirer<'a> {
         computer: Computer,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reverse: bool,
     ) -> RegistryAcquirer {
         let connector = Box::new(PsExec::psexec32(computer, remote_temp_storage, custom_share_folder));
@@ -92,7 +93,7 @@ impl<'a> RegistryAcquirer<'a> {
         computer: Computer,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reverse: bool,
     ) -> RegistryAcquirer {
         let connector = Box::new(PsExec::psexec64(computer, remote_temp_storage, custom_share_folder));
@@ -109,10 +110,10 @@ impl<'a> RegistryAcquirer<'a> {
         computer: Computer,
         _no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        winrm: WinRmSettings,
         reverse: bool,
     ) -> RegistryAcquirer {
-        let connector = Box::new(PsRemote::new(computer, remote_temp_storage, custom_share_folder));
+        let connector = Box::new(WinRm::new(computer, remote_temp_storage, winrm));
         RegistryAcquirer::new(
             store_directory,
             if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
@@ -127,12 +128,14 @@ impl<'a> RegistryAcquirer<'a> {
         compress_timeout: Duration,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
+        reverse: bool,
     ) -> RegistryAcquirer {
+        let connector = Box::new(Wmi::new(computer, remote_temp_storage));
         RegistryAcquirer::new(
             store_directory,
-            Box::new(Wmi { computer, remote_temp_storage }),
+            if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
             Some(compress_timeout),
-            if no_7zip { Compression::No } else { Compression::YesSplit },
+            Compression::split_unless_reversed(no_7zip, reverse),
         )
     }
 
@@ -143,16 +146,22 @@ impl<'a> RegistryAcquirer<'a> {
         nla: bool,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
+        reverse: bool,
     ) -> RegistryAcquirer {
+        let connector = Box::new(Rdp { computer, nla, remote_temp_storage });
         RegistryAcquirer::new(
             store_directory,
-            Box::new(Rdp { computer, nla, remote_temp_storage }),
+            if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
             Some(compress_timeout),
-            if no_7zip { Compression::No } else { Compression::YesSplit },
+            Compression::split_unless_reversed(no_7zip, reverse),
         )
     }
 
-    pub fn acquire(&self) {
+    pub fn method_name(&self) -> &'static str {
+        self.connector.connect_method_name()
+    }
+
+    pub fn acquire(&self) -> io::Result<()> {
         let command = &self.registry_hklm_command;
         let lea = LargeEvidenceAcquirer {
             store_directory: self.store_directory,
@@ -162,29 +171,37 @@ impl<'a> RegistryAcquirer<'a> {
             report_extension: "txt",
             overwrite_switch: Some("/y"),
         };
-        lea.run(
+        let hklm = lea.run(
             command,
             "registry-hklm",
         );
         let command = &self.registry_hku_command;
-        lea.run(
+        let hku = lea.run(
             command,
             "registry-hku",
         );
         let command = &self.registry_hkcu_command;
-        lea.run(
+        let hkcu = lea.run(
             command,
             "registry-hkcu",
         );
         let command = &self.registry_hkcr_command;
-        lea.run(
+        let hkcr = lea.run(
             command,
             "registry-hkcr",
         );
         let command = &self.registry_hkcc_command;
-        lea.run(
+        let hkcc = lea.run(
             command,
             "registry-hkcc",
         );
+        hklm.and(hku).and(hkcu).and(hkcr).and(hkcc)
     }
-}
\ No newline at end of file
+}
+
+impl<'a> Decorate for RegistryAcquirer<'a> {
+    fn decorate(mut self, decorator: &dyn Fn(Box<dyn Connector>) -> Box<dyn Connector>) -> Self {
+        self.connector = decorator(self.connector);
+        self
+    }
+}
@@ -1,8 +1,8 @@
-use crate::remote::{Connector, Command, Local, FileCopier, RemoteFileCopier, Computer, file_is_empty, path_to_part, copy_from_remote_wildcards};
+use crate::remote::{Connector, Command, Local, FileCopier, RemoteFileCopier, Computer, Readiness, Footprint, path_to_part, copy_from_remote_wildcards, download_part, record_footprint, footprint_removed};
 use std::path::{Path, PathBuf};
-use std::{io, thread};
+use std::io;
 use std::time::Duration;
-use std::io::{Error};
+use std::io::{Error, ErrorKind};
 use uuid::Uuid;
 use crate::utils::path_join_to_string_ntfs;
 
@@ -14,6 +14,17 @@ pub enum Compression {
     YesSplit,
 }
 
+impl Compression {
+    /// Archives downloaded by WMI or RDP are split into parts, unless the target pushes them through the reverse share in one copy.
+    pub fn split_unless_reversed(no_7zip: bool, reverse: bool) -> Compression {
+        match (no_7zip, reverse) {
+            (true, _) => Compression::No,
+            (false, true) => Compression::Yes,
+            (false, false) => Compression::YesSplit,
+        }
+    }
+}
+
 pub trait Archiver {
     fn compress(&self, path: &Path, split: bool) -> PathBuf;
     fn uncompress(&self, path: &Path) -> io::Result<()>;
@@ -81,6 +92,7 @@ impl<'a> Archiver for SevenZipArchiver<'a> {
         ) {
             debug!("{}", err)
         }
+        record_footprint(self.connector.connect_method_name(), archive_footprint(&path_string_7z, split));
         // if split {
         // //  already deleted by 7zip itself
         // } else {
@@ -113,6 +125,15 @@ impl<'a> Archiver for SevenZipArchiver<'a> {
     }
 }
 
+/// The archive, or all its parts when it is split.
+fn archive_footprint(archive: &Path, split: bool) -> Footprint {
+    if split {
+        Footprint::RemoteFile(PathBuf::from(format!("{}.*", archive.to_string_lossy())))
+    } else {
+        Footprint::RemoteFile(archive.to_path_buf())
+    }
+}
+
 pub struct SevenZipCompressCopier<'a> {
     archiver: SevenZipArchiver<'a>,
     split: bool,
@@ -150,28 +171,38 @@ impl<'a> RemoteFileCopier for SevenZipCompressCopier<'a> {
     fn copy_to_remote(&self, source: &Path, target: &Path) -> Result<(), Error> {
         trace!("Copying {} to {} using compression", source.display(), &self.archiver.connector.computer().address);
         let remote_copier_impl = self.archiver.connector.copier();
+        let readiness = Readiness::new(self.archiver.connector);
         let local = Local::new_default(self.archiver.connector.computer().username.clone());
         let local_archiver = SevenZipArchiver::local(&local);
 
         let archived_source = local_archiver.compress(source, self.split);
-        let wait_time_s = Duration::from_secs(1);
+        let method = self.archiver.connector.connect_method_name();
         let wait_time_l = Duration::from_secs(10);
         if self.split {
             let mut i = 1;
             let mut part = path_to_part(&archived_source, i);
+            let mut local_parts_deleted = true;
             while part.exists() {
                 if let Err(err) = remote_copier_impl.copy_to_remote(&part, target) {
-                    debug!("{}", err)
+                    debug!("{}", err);
+                    local_parts_deleted = false;
                 } else {
-                    std::thread::sleep(wait_time_s.clone());
+                    record_footprint(method, Footprint::RemoteFile(target.join(part.file_name().unwrap())));
                     if let Err(err) = local.delete_file(&part) {
                         debug!("{}", err);
+                        local_parts_deleted = false;
                     }
                 }
                 i += 1;
                 part = path_to_part(&archived_source, i);
             }
-            std::thread::sleep(wait_time_l.clone());
+            if local_parts_deleted {
+                footprint_removed(&archive_footprint(&archived_source, true));
+            }
+            i -= 1;
+            if i > 0 {
+                readiness.wait_for_complete_file(&target.join(path_to_part(&archived_source, i).file_name().unwrap()), wait_time_l);
+            }
             if let Err(err) = self.archiver.uncompress(
                 &target.join(
                     path_to_part(&archived_source, 1).file_name().unwrap()
@@ -179,38 +210,32 @@ impl<'a> RemoteFileCopier for SevenZipCompressCopier<'a> {
             ) {
                 debug!("{}", err);
             } else {
-                i -= 1;
-                let mut remote_part = target.join(
-                    path_to_part(&archived_source, 1).file_name().unwrap());
-
                 while i > 0 {
-                    if let Err(err) = remote_copier_impl.delete_remote_file(&remote_part) {
+                    let remote_part = target.join(path_to_part(&archived_source, i).file_name().unwrap());
+                    if let Err(err) = readiness.delete_when_released(remote_copier_impl, &remote_part, wait_time_l) {
                         debug!("{}", err);
                     }
-                    std::thread::sleep(wait_time_l.clone());
                     i -= 1;
-                    remote_part = target.join(path_to_part(&archived_source, 1).file_name().unwrap());
                 }
             }
         } else {
             if let Err(err) = remote_copier_impl.copy_to_remote(&archived_source, target) {
                 debug!("{}", err);
             } else {
-                if let Err(err) = local.delete_file(&archived_source) {
-                    debug!("{}", err)
+                record_footprint(method, Footprint::RemoteFile(target.join(archived_source.file_name().unwrap())));
+                match local.delete_file(&archived_source) {
+                    Ok(()) => footprint_removed(&archive_footprint(&archived_source, false)),
+                    Err(err) => debug!("{}", err),
                 }
             }
-            std::thread::sleep(wait_time_l.clone());
             let target_archived = &target.join(
                 archived_source.file_name().unwrap()
             );
+            readiness.wait_for_complete_file(target_archived, wait_time_l);
             if let Err(err) = self.archiver.uncompress(&target_archived) {
                 debug!("{}", err)
-            } else {
-                std::thread::sleep(wait_time_l.clone());
-                if let Err(err) = remote_copier_impl.delete_remote_file(&target_archived) {
-                    debug!("{}", err);
-                }
+            } else if let Err(err) = readiness.delete_when_released(remote_copier_impl, target_archived, wait_time_l) {
+                debug!("{}", err);
             }
         }
 
@@ -242,8 +267,9 @@ impl SevenZipCompressCopier<'_> {
             );
 
         let wait_time_s = Duration::from_secs(10);
-        let wait_time_l = Duration::from_secs(30);
-        thread::sleep(wait_time_s.clone());
+        let readiness = Readiness::new(self.archiver.connector);
+        // The archive is complete once 7-Zip exits, which happens asynchronously e.g. with WMI.
+        readiness.wait_for_process_exit("7za.exe", wait_time_s);
 
         let remote_copier_impl = self.archiver.connector.copier();
 
@@ -251,52 +277,42 @@ impl SevenZipCompressCopier<'_> {
         let local_archiver = SevenZipArchiver::local(&local);
 
         if self.split {
-            self.copy_from_remote_splitted(target, wait_time_s, wait_time_l, &archived_source, remote_copier_impl, &local, &local_archiver)
+            self.copy_from_remote_splitted(target, wait_time_s, &archived_source, remote_copier_impl, &readiness, &local, &local_archiver)
         } else {
-            self.copy_from_remote_whole(target, wait_time_s, &archived_source, remote_copier_impl, &local, &local_archiver)
+            self.copy_from_remote_whole(target, wait_time_s, &archived_source, remote_copier_impl, &readiness, &local, &local_archiver)
         }
         Ok(())
     }
     fn copy_from_remote_splitted(&self,
                                  target: &Path,
                                  wait_time_s: Duration,
-                                 wait_time_l: Duration,
                                  archived_source: &Path,
                                  remote_copier_impl: &dyn RemoteFileCopier,
+                                 readiness: &Readiness<'_, dyn Connector + '_>,
                                  local: &Local,
                                  local_archiver: &SevenZipArchiver,
     ) {
-        let mut unsuccessful_trials = 0;
         let mut i = 0;
+        let mut all_parts_deleted = true;
         loop {
             i += 1;
             let part = path_to_part(archived_source, i);
             trace!("Copying {} from {} using compression", part.display(), &self.archiver.connector.computer().address);
-            if let Err(err) = remote_copier_impl.copy_from_remote(&part, target) {
+            // A part that does not exist follows the last one.
+            if let Err(err) = download_part(remote_copier_impl, Some(self.archiver.connector), &part, target) {
                 debug!("{}", err);
-            }
-            let target_downloaded = target.join(part.file_name().unwrap());
-
-            if file_is_empty(&target_downloaded) {
-                unsuccessful_trials += 1;
+                all_parts_deleted &= err.kind() == ErrorKind::NotFound;
                 i -= 1;
-                if unsuccessful_trials == 2 {
-                    break;
-                }
-                debug!("File download may ended with errors. Waiting {} seconds before retry.", wait_time_l.as_secs());
-                thread::sleep(wait_time_l);
-            } else {
-                unsuccessful_trials = 0;
+                break;
             }
-
-            thread::sleep(wait_time_s.clone());
-
-            if unsuccessful_trials == 0 {
-                if let Err(err) = remote_copier_impl.delete_remote_file(&part) {
-                    debug!("{}", err);
-                }
+            if let Err(err) = readiness.delete_when_released(remote_copier_impl, &part, wait_time_s) {
+                debug!("{}", err);
+                all_parts_deleted = false;
             }
         }
+        if all_parts_deleted {
+            footprint_removed(&archive_footprint(archived_source, true));
+        }
         if self.uncompress_downloaded {
             let target_downloaded_without_part_suffix = target.join(archived_source.file_name().unwrap());
             if let Err(err) = local_archiver.uncompress(&path_to_part(&target_downloaded_without_part_suffix, 1)) {
@@ -319,27 +335,45 @@ impl SevenZipCompressCopier<'_> {
                               wait_time_s: Duration,
                               archived_source: &Path,
                               remote_copier_impl: &dyn RemoteFileCopier,
+                              readiness: &Readiness<'_, dyn Connector + '_>,
                               local: &Local,
                               local_archiver: &SevenZipArchiver,
     ) {
         if let Err(err) = remote_copier_impl.copy_from_remote(archived_source, target) {
             debug!("{}", err);
-        } else {
-            thread::sleep(wait_time_s.clone());
-            if let Err(err) = remote_copier_impl.delete_remote_file(archived_source) {
-                debug!("{}", err);
-            }
+        } else if let Err(err) = readiness.delete_when_released(remote_copier_impl, archived_source, wait_time_s) {
+            debug!("{}", err);
         }
         if self.uncompress_downloaded {
             let target_downloaded = target.join(archived_source.file_name().unwrap());
             if let Err(err) = local_archiver.uncompress(&target_downloaded) {
                 debug!("{}", err);
-            } else {
-                thread::sleep(wait_time_s.clone());
-                if let Err(err) = local.delete_file(&target_downloaded) {
-                    debug!("{}", err);
-                }
+            } else if let Err(err) = local.delete_file(&target_downloaded) {
+                debug!("{}", err);
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{replay_fixture, replay_store_directory, file_is_empty};
+    use std::fs;
+
+    #[test]
+    fn replays_compressed_download() {
+        let (replayer, fixture) = replay_fixture("compress-download-psexec");
+        let store_directory = replay_store_directory();
+        let copier = SevenZipCompressCopier::new(&replayer, false, None, false);
+        copier.copy_from_remote(Path::new("C:\\Windows\\System32\\drivers\\etc\\hosts"), &store_directory).unwrap();
+        assert_eq!(fixture.remaining(), 0);
+        let archives = fs::read_dir(&store_directory).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with(".7z"))
+            .collect::<Vec<PathBuf>>();
+        assert_eq!(archives.len(), 1);
+        assert!(!file_is_empty(&archives[0]));
+        let _ = fs::remove_dir_all(&store_directory);
+    }
+}
@@ -1,14 +1,15 @@
 use std::io::{Result, BufReader, BufRead};
-use crate::process_runner::{run_process_blocking, create_report_path, run_process_blocking_timed};
-use std::{iter, thread, io};
+use crate::process_runner::{create_report_path, run_process_capturing, ProcessOutput};
+use std::{thread, io, fmt, fs};
 use std::path::{Path, PathBuf};
 use crate::arg_parser::Opts;
-use std::time::Duration;
-use crate::remote::{RemoteFileCopier, Local};
+use std::time::{Duration, SystemTime};
+use crate::remote::{RemoteFileCopier, Local, ProbeResult, probe_with_command, CredentialProvider, register_secret, ProxyJump, OsInfo, fingerprint_windows, Readiness, file_size_windows, process_running_windows, operation_timeout, terminate_windows, Footprint, record_footprint, record_footprint_with_detail, record_upload, footprint_removed, UNREACHABLE_OS_ERRORS};
 use std::fs::File;
+use std::sync::Once;
 use uuid::Uuid;
-use rpassword::read_password;
 use username::get_user_name;
+use crate::host::{HOST_SHELL, shell_args};
 
 #[derive(Clone)]
 pub struct Computer {
@@ -16,6 +17,8 @@ pub struct Computer {
     pub username: String,
     pub domain: Option<String>,
     pub password: Option<String>,
+    pub ssh_jump: Option<ProxyJump>,
+    pub ssh_host_key: Option<String>,
 }
 
 impl Computer {
@@ -29,6 +32,19 @@ impl Computer {
     }
 }
 
+/// Computer 10.0.0.1 with user admin and no password, the target of tests.
+#[cfg(test)]
+pub fn test_computer() -> Computer {
+    Computer {
+        address: "10.0.0.1".to_string(),
+        username: "admin".to_string(),
+        domain: None,
+        password: None,
+        ssh_jump: None,
+        ssh_host_key: None,
+    }
+}
+
 pub struct Command<'a> {
     pub command: Vec<String>,
     pub report_store_directory: Option<&'a Path>,
@@ -36,6 +52,20 @@ pub struct Command<'a> {
     pub elevated: bool,
 }
 
+static PASSWORD_WARNING: Once = Once::new();
+
+/// The `-p` password, it is visible in the process list of the host.
+fn password_from_command_line(password: &str) -> Option<String> {
+    if password.trim().is_empty() {
+        return None;
+    }
+    PASSWORD_WARNING.call_once(|| warn!(
+        "The password given by -p is visible to other users of this host, consider using --credentials instead."
+    ));
+    register_secret(password);
+    Some(password.to_string())
+}
+
 impl From<Opts> for Computer {
     fn from(opts: Opts) -> Self {
         let local_mode = opts.local || opts.computer == "127.0.0.1" || opts.computer == "localhost";
@@ -64,40 +94,70 @@ impl From<Opts> for Computer {
             return Local::new(username, PathBuf::from(opts.remote_store_directory)).computer().clone();
         }
         let password = match &opts.password {
-            Some(password) => if password.is_empty() {
-                None
-            } else {
-                Some(password.clone())
-            },
-            None => {
-                println!("Password: ");
-                let password = read_password().ok();
-                password
-            }
+            Some(password) => password_from_command_line(password),
+            None => CredentialProvider::new(opts.credentials.clone()).password(&opts.computer, &username),
         };
         Computer {
             address: opts.computer,
             username,
             domain,
             password,
+            ssh_jump: opts.ssh_jump,
+            ssh_host_key: opts.ssh_host_key,
         }
     }
 }
 
+/// Keys of the `key=value` items accepted anywhere after the address on a computer list line.
+const LINE_OPTIONS: [&str; 2] = ["jump", "hostkey"];
+
+/// Splits a computer list line into the positional items and the `key=value` options.
+fn split_line_options(line: &str) -> (Vec<String>, Vec<(String, String)>) {
+    let mut items = Vec::new();
+    let mut options = Vec::new();
+    for (index, item) in line.split(" ").enumerate() {
+        let option = item.find('=')
+            .map(|separator| (&item[..separator], &item[separator + 1..]))
+            .filter(|(key, _)| index > 0 && LINE_OPTIONS.contains(key));
+        match option {
+            Some((key, value)) => options.push((key.to_string(), value.to_string())),
+            None => items.push(item.to_string()),
+        }
+    }
+    (items, options)
+}
+
+fn line_option<'a>(options: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    options.iter()
+        .find(|(option_key, _)| option_key == key)
+        .map(|(_, value)| value.as_str())
+}
+
 impl Into<Vec<Computer>> for Opts {
     fn into(self) -> Vec<Computer> {
         let file = File::open(&self.computer);
         match file {
             Ok(file) => {
+                let credentials = CredentialProvider::new(self.credentials.clone());
                 BufReader::new(file)
                     .lines()
                     .filter_map(|line| line.ok())
                     .filter(|line| !line.trim().is_empty())
                     .filter_map(|line| {
-                        let splitted = line
-                            .split(" ")
-                            .map(|item| item.to_string())
-                            .collect::<Vec<String>>();
+                        let (splitted, options) = split_line_options(&line);
+                        let ssh_jump = match line_option(&options, "jump") {
+                            None => self.ssh_jump.clone(),
+                            Some(spec) => match spec.parse() {
+                                Ok(ssh_jump) => Some(ssh_jump),
+                                Err(err) => {
+                                    error!("Skipping computer list line of {} due to {}", splitted.get(0).cloned().unwrap_or_default(), err);
+                                    return None;
+                                }
+                            },
+                        };
+                        let ssh_host_key = line_option(&options, "hostkey")
+                            .map(|host_key| host_key.to_string())
+                            .or_else(|| self.ssh_host_key.clone());
                         let address = splitted.get(0).cloned();
                         let domain_username = splitted.get(1).cloned();
                         let password = splitted.get(2).cloned();
@@ -139,29 +199,22 @@ impl Into<Vec<Computer>> for Opts {
                                 }
                             };
                             let password = match password {
-                                None => match self.password.clone() {
-                                    Some(password) => if password.trim().is_empty() {
-                                        None
-                                    } else {
-                                        Some(password)
-                                    },
-                                    None => {
-                                        println!("Password for {}: ", address);
-                                        let password_user = read_password().ok();
-                                        if password_user.is_none() || password_user.as_ref().unwrap().trim().is_empty() {
-                                            None
-                                        } else {
-                                            Some(password_user.unwrap())
-                                        }
-                                    }
+                                None => match &self.password {
+                                    Some(password) => password_from_command_line(password),
+                                    None => credentials.password(&address, &username),
                                 },
-                                Some(password) => Some(password)
+                                Some(password) => {
+                                    register_secret(&password);
+                                    Some(password)
+                                }
                             };
                             Computer {
                                 address: address.clone(),
                                 username,
                                 domain,
                                 password,
+                                ssh_jump,
+                                ssh_host_key,
                             }
                         })
                     })
@@ -188,6 +241,104 @@ impl<'a> Command<'a> {
     }
 }
 
+/// Outcome of a command run on a remote computer.
+/// The exit status is `None` when the connection method cannot report it or when the command timed out.
+pub struct CommandOutcome {
+    pub exit_status: Option<i32>,
+    pub stdout: Option<PathBuf>,
+    pub stderr: Option<PathBuf>,
+    pub started: SystemTime,
+    pub finished: SystemTime,
+    pub timed_out: bool,
+}
+
+impl CommandOutcome {
+    /// Stores the captured output as reports in the `report_store_directory`.
+    /// Standard error output is stored only when the command wrote something to it.
+    pub fn store(
+        computer: &Computer,
+        method_name: &str,
+        report_store_directory: Option<&Path>,
+        report_filename_prefix: &str,
+        output: ProcessOutput,
+        started: SystemTime,
+    ) -> Result<CommandOutcome> {
+        let finished = SystemTime::now();
+        let (stdout, stderr) = match report_store_directory {
+            None => (None, None),
+            Some(store_directory) => {
+                let stdout_path = create_report_path(
+                    computer,
+                    store_directory,
+                    report_filename_prefix,
+                    method_name,
+                    "txt",
+                );
+                fs::write(&stdout_path, &output.stdout)?;
+                let stderr_path = if output.stderr.is_empty() {
+                    None
+                } else {
+                    let stderr_path = create_report_path(
+                        computer,
+                        store_directory,
+                        &format!("{}-stderr", report_filename_prefix),
+                        method_name,
+                        "txt",
+                    );
+                    fs::write(&stderr_path, &output.stderr)?;
+                    Some(stderr_path)
+                };
+                (Some(stdout_path), stderr_path)
+            }
+        };
+        Ok(CommandOutcome {
+            exit_status: output.exit_status,
+            stdout,
+            stderr,
+            started,
+            finished,
+            timed_out: output.timed_out,
+        })
+    }
+
+    /// Whether the command is known to have finished, RDP e.g. returns once the command started in the session.
+    pub fn has_finished(&self) -> bool {
+        !self.timed_out && self.exit_status.is_some()
+    }
+
+    pub fn is_failure(&self) -> bool {
+        self.timed_out || self.exit_status.map(|status| status != 0).unwrap_or(false)
+    }
+
+    /// Kind of the error reported for a failed outcome, timeouts are told apart from other failures.
+    pub fn error_kind(&self) -> io::ErrorKind {
+        if self.timed_out {
+            io::ErrorKind::TimedOut
+        } else {
+            io::ErrorKind::Other
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.finished.duration_since(self.started).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for CommandOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.duration().as_secs_f32();
+        match (self.timed_out, self.exit_status) {
+            (true, _) => write!(f, "timed out after {:.1} s", seconds),
+            (false, Some(status)) => write!(f, "exit status {} after {:.1} s", status, seconds),
+            (false, None) => write!(f, "unknown exit status after {:.1} s", seconds),
+        }?;
+        match &self.stderr {
+            None => Ok(()),
+            Some(stderr) => write!(f, " (error output in {})", stderr.display()),
+        }
+    }
+}
+
 pub trait Connector {
     fn connect_method_name(&self) -> &'static str;
 
@@ -197,6 +348,31 @@ pub trait Connector {
 
     fn remote_temp_storage(&self) -> &Path;
 
+    /// Checks whether the method reaches the target, authenticates and has admin rights.
+    fn probe(&self) -> ProbeResult {
+        probe_with_command(self, None, vec!["net".to_string(), "session".to_string()], false)
+    }
+
+    /// Detects the operating system of the target, Windows methods only reach Windows targets.
+    fn fingerprint(&self, store_directory: &Path) -> OsInfo {
+        fingerprint_windows(self, store_directory)
+    }
+
+    /// Size of a file on the target, `None` while it does not exist.
+    fn remote_file_size(&self, path: &Path) -> Result<Option<u64>> {
+        file_size_windows(self, path)
+    }
+
+    /// Whether a process of the program runs on the target.
+    fn remote_process_running(&self, program: &str) -> Result<bool> {
+        process_running_windows(self, program)
+    }
+
+    /// Kills the processes of the program left running on the target after a timeout.
+    fn terminate_remote_process(&self, program: &str) -> Result<()> {
+        terminate_windows(self, program)
+    }
+
     fn mkdir(&self, path: &Path) {
         let command = Command::new(
             vec![
@@ -218,7 +394,7 @@ pub trait Connector {
         &self,
         command_to_run: Command<'_>,
         timeout: Option<Duration>,
-    ) -> Result<Option<PathBuf>> {
+    ) -> Result<CommandOutcome> {
         let mut command = command_to_run.command;
         command[0] = std::env::current_dir().unwrap()
             .join(Path::new(&command[0]).file_name().unwrap())
@@ -237,17 +413,17 @@ pub trait Connector {
         &self,
         command_to_run: Command<'_>,
         timeout: Option<Duration>,
-    ) -> Result<Option<PathBuf>> {
+    ) -> Result<CommandOutcome> {
         let local_program_path = Path::new(command_to_run.command.first().unwrap());
         let remote_storage = self.remote_temp_storage();
         let copier = self.copier();
         copier.copy_to_remote(&local_program_path, &remote_storage)?;
-        thread::sleep(Duration::from_millis(20_000));
         let remote_program_path = remote_storage.join(local_program_path
             .file_name()
             .expect(&format!("Must specify file instead of {}", local_program_path.display())
             )
         );
+        record_upload(self.connect_method_name(), &remote_program_path, local_program_path);
         let mut command = command_to_run.command;
         command[0] = remote_program_path.to_string_lossy().to_string();
         let command_to_run = Command {
@@ -255,8 +431,10 @@ pub trait Connector {
             ..command_to_run
         };
         let result = self.connect_and_run_command(command_to_run, timeout)?;
-        thread::sleep(Duration::from_millis(10_000));
-        copier.delete_remote_file(&remote_program_path)?;
+        if result.timed_out {
+            terminate_timed_out_program(self, &remote_program_path);
+        }
+        Readiness::new(self).delete_when_released(copier, &remote_program_path, Duration::from_secs(10))?;
         Ok(result)
     }
 
@@ -264,64 +442,18 @@ pub trait Connector {
         &self,
         command_to_run: Command<'_>,
         timeout: Option<Duration>,
-    ) -> Result<Option<PathBuf>> {
-        debug!("Trying to run command {:?} on {}",
-               command_to_run.command,
-               &self.computer().address
-        );
-        let output_file_path = match command_to_run.report_store_directory {
-            None => None,
-            Some(store_directory) => {
-                let file_path = create_report_path(
-                    self.computer(),
-                    store_directory,
-                    &command_to_run.report_filename_prefix,
-                    self.connect_method_name(),
-                    "txt",
-                );
-                Some(file_path.to_str().unwrap().to_string())
-            }
-        };
-
-        let processed_command = self.prepare_command(
-            command_to_run.command,
-            output_file_path.as_deref(),
-            command_to_run.elevated,
-        );
-
-        let prepared_command = self.prepare_remote_process(processed_command);
-        match timeout {
-            None =>
-                run_process_blocking(
-                    "cmd.exe",
-                    &prepared_command,
-                ),
-            Some(timeout) =>
-                run_process_blocking_timed(
-                    "cmd.exe",
-                    &prepared_command,
-                    timeout.clone(),
-                ),
-        }?;
-        Ok(output_file_path.map(|it| PathBuf::from(it)))
+    ) -> Result<CommandOutcome> {
+        run_command_from_host(self, command_to_run, timeout)
     }
 
     fn prepare_remote_process(&self,
-                              // pre_command: Vec<String>,
                               processed_command: Vec<String>,
-                              // post_command: Vec<String>,
     ) -> Vec<String> {
-        let all_args = iter::once("/c".to_string())
-            // .chain(pre_command.into_iter())
-            .chain(processed_command.into_iter())
-            // .chain(post_command.into_iter())
-            .collect();
-        all_args
+        shell_args(processed_command)
     }
 
     fn prepare_command(&self,
                        command: Vec<String>,
-                       output_file_path: Option<&str>,
                        elevated: bool,
     ) -> Vec<String>;
 
@@ -341,24 +473,25 @@ pub trait Connector {
             &prefix,
             true,
         );
-        if let Err(err) = self.connect_and_run_command(command, Some(Duration::from_secs(10))) {
-            error!("{}", err);
-        }
-        let result_file_name = match store_directory.read_dir() {
-            Ok(dir_entry_iter) => {
-                dir_entry_iter
-                    .filter_map(|item| item.ok())
-                    .map(|item| item.file_name())
-                    .find(|item| item.to_string_lossy().contains(&prefix))
+        let outcome = match self.connect_and_run_command(command, Some(Duration::from_secs(10))) {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                error!("{}", err);
+                debug!("Remote path {} has dirs []", path.display());
+                return vec![];
             }
-            _ => None,
         };
-        if result_file_name.is_none() {
-            error!("Cannot find dir result file");
-            debug!("Remote path {} has dirs []", path.display());
-            return vec![];
+        if let Some(stderr_path) = &outcome.stderr {
+            let _ = std::fs::remove_file(stderr_path);
         }
-        let result_file_path = store_directory.join(&result_file_name.unwrap());
+        let result_file_path = match outcome.stdout {
+            Some(result_file_path) => result_file_path,
+            None => {
+                error!("Cannot find dir result file");
+                debug!("Remote path {} has dirs []", path.display());
+                return vec![];
+            }
+        };
         let result_file = File::open(&result_file_path);
         if result_file.is_err() {
             error!("Cannot open file {} due to {}", result_file_path.display(), result_file.err().unwrap());
@@ -377,8 +510,35 @@ pub trait Connector {
         result
     }
 
+    /// Saves the ACL of the path before granting full control, so that releasing restores it exactly.
     fn acquire_perms(&self, path: &Path) {
         debug!("Acquiring ownership");
+        let saved_acl = saved_acl_path(self.remote_temp_storage(), path);
+        let save_acl = Command {
+            command: vec![
+                "cmd.exe".to_string(),
+                "/c".to_string(),
+                "icacls.exe".to_string(),
+                path.to_string_lossy().to_string(),
+                "/save".to_string(),
+                saved_acl.to_string_lossy().to_string(),
+            ],
+            report_store_directory: None,
+            report_filename_prefix: "SAVE_ACL_VSI",
+            elevated: true,
+        };
+        match self.connect_and_run_command(save_acl, None) {
+            // The saved ACL is part of the grant footprint, reverting the grant restores and deletes it.
+            Ok(outcome) if !outcome.is_failure() => {}
+            Ok(outcome) => {
+                warn!("Cannot save ACL of {}, access is not granted: {}", path.display(), outcome);
+                return;
+            }
+            Err(err) => {
+                warn!("Cannot save ACL of {}, access is not granted: {}", path.display(), err);
+                return;
+            }
+        }
         let grant_svi = Command {
             command: vec![
                 "cmd.exe".to_string(),
@@ -393,37 +553,156 @@ pub trait Connector {
             elevated: true,
         };
 
-        if let Err(err) = self.connect_and_run_command(
+        match self.connect_and_run_command(
             grant_svi,
             None,
         ) {
-            warn!("Cannot acquire ownership: {}", err)
+            Ok(outcome) if !outcome.is_failure() => record_footprint_with_detail(
+                self.connect_method_name(),
+                Footprint::Grant(path.to_path_buf()),
+                &self.computer().domain_username(),
+            ),
+            Ok(_) => {}
+            Err(err) => warn!("Cannot acquire ownership: {}", err),
         }
         thread::sleep(Duration::from_secs(5));
     }
 
+    /// Restores the ACL saved by `acquire_perms`, removing the grant would also remove grants the user had before.
     fn release_perms(&self, path: &Path) {
         thread::sleep(Duration::from_secs(5));
         debug!("Releasing ownership");
-        let grant_svi = Command {
+        let saved_acl = saved_acl_path(self.remote_temp_storage(), path);
+        // The saved ACL names the path relative to its parent, which it is restored in.
+        let path = path.to_string_lossy();
+        let parent = match path.rfind(|c: char| c == '\\' || c == '/') {
+            Some(index) if path[..index].ends_with(':') => format!("{}\\", &path[..index]),
+            Some(index) => path[..index].to_string(),
+            None => ".".to_string(),
+        };
+        let restore_acl = Command {
             command: vec![
                 "cmd.exe".to_string(),
                 "/c".to_string(),
                 "icacls.exe".to_string(),
-                path.to_string_lossy().to_string(),
-                "/deny".to_string(),
-                format!("{}:F", self.computer().username)
+                parent,
+                "/restore".to_string(),
+                saved_acl.to_string_lossy().to_string(),
             ],
             report_store_directory: None,
-            report_filename_prefix: "DENY_VSI",
+            report_filename_prefix: "RESTORE_ACL_VSI",
             elevated: true,
         };
 
-        if let Err(err) = self.connect_and_run_command(
-            grant_svi,
+        match self.connect_and_run_command(
+            restore_acl,
             None,
         ) {
-            warn!("Cannot release ownership: {}", err)
+            Ok(outcome) if !outcome.is_failure() => footprint_removed(&Footprint::Grant(PathBuf::from(path.as_ref()))),
+            Ok(outcome) => {
+                warn!("Cannot restore ACL of {}: {}", path, outcome);
+                return;
+            }
+            Err(err) => {
+                warn!("Cannot release ownership: {}", err);
+                return;
+            }
+        }
+        if let Err(err) = self.copier().delete_remote_file(&saved_acl) {
+            warn!("Cannot delete saved ACL {} due to {}", saved_acl.display(), err);
+            record_footprint(self.connect_method_name(), Footprint::RemoteFile(saved_acl));
         }
     }
-}
\ No newline at end of file
+}
+
+/// File in the remote temporary storage the ACL of the path is saved to while access to it is granted.
+/// It is named after the path, so that a later `--cleanup` finds it as well.
+fn saved_acl_path(remote_temp_storage: &Path, path: &Path) -> PathBuf {
+    let name = path.to_string_lossy().replace(|c: char| c == '\\' || c == '/' || c == ':' || c == ' ', "-");
+    remote_temp_storage.join(format!("GARGAMEL_ACL_{}.txt", name))
+}
+
+/// Messages of the clients on this host (PsExec, PAExec, WMImplant) that could not reach or log on to the target.
+const CLIENT_CONNECTION_FAILURES: [(&str, io::ErrorKind); 8] = [
+    ("The user name or password is incorrect", io::ErrorKind::PermissionDenied),
+    ("Logon failure", io::ErrorKind::PermissionDenied),
+    ("unknown user name or bad password", io::ErrorKind::PermissionDenied),
+    ("The network path was not found", io::ErrorKind::NotConnected),
+    ("The RPC server is unavailable", io::ErrorKind::NotConnected),
+    ("Couldn't access", io::ErrorKind::Other),
+    ("Failed to connect to", io::ErrorKind::Other),
+    ("Could not connect to remote PAExec service", io::ErrorKind::Other),
+];
+
+/// Error of a failed client that reported it could not reach or log on to the target, so the command did not run.
+/// The client exits with its own error code then, which is no outcome of the target.
+pub fn client_connection_error(output: &ProcessOutput) -> Option<io::Error> {
+    if output.timed_out || output.exit_status.unwrap_or(0) == 0 {
+        return None;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // PsExec exits with the Windows error code when it cannot reach the target, so the command was not submitted.
+    if let Some(code) = output.exit_status.filter(|code| UNREACHABLE_OS_ERRORS.contains(code)) {
+        return Some(io::Error::new(
+            io::ErrorKind::NotConnected,
+            format!("Client cannot reach the target, exit status {}: {}", code, stderr.trim()),
+        ));
+    }
+    CLIENT_CONNECTION_FAILURES.iter()
+        .find(|(message, _)| stderr.contains(message))
+        .map(|(_, kind)| io::Error::new(*kind, format!(
+            "Client failed with exit status {}: {}",
+            output.exit_status.unwrap_or_default(),
+            stderr.trim(),
+        )))
+}
+
+/// Runs the command by the client program of the method on this host, e.g. PsExec.
+pub fn run_command_from_host<C: Connector + ?Sized>(
+    connector: &C,
+    command_to_run: Command<'_>,
+    timeout: Option<Duration>,
+) -> Result<CommandOutcome> {
+    debug!("Trying to run command {:?} on {}",
+           command_to_run.command,
+           &connector.computer().address
+    );
+    let processed_command = connector.prepare_command(
+        command_to_run.command,
+        command_to_run.elevated,
+    );
+
+    let prepared_command = connector.prepare_remote_process(processed_command);
+    let timeout = operation_timeout(timeout)?;
+    let started = SystemTime::now();
+    let output = run_process_capturing(
+        HOST_SHELL,
+        &prepared_command,
+        timeout,
+    )?;
+    if let Some(err) = client_connection_error(&output) {
+        return Err(err);
+    }
+    CommandOutcome::store(
+        connector.computer(),
+        connector.connect_method_name(),
+        command_to_run.report_store_directory,
+        command_to_run.report_filename_prefix,
+        output,
+        started,
+    )
+}
+
+/// Killing the local client of a timed out program does not stop it on the target, so it is killed there.
+pub fn terminate_timed_out_program<C: Connector + ?Sized>(connector: &C, program_path: &Path) {
+    let program = program_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    warn!("{} timed out on {}, terminating it", program, connector.computer().address);
+    if let Err(err) = connector.terminate_remote_process(&program) {
+        warn!("Cannot terminate {} on {} due to {}, it may still run there", program, connector.computer().address, err);
+    }
+}
+
+/// Acquirers owning their connector, which `--dry-run`, `--record` and `--replay` decorate after the acquirer is created.
+pub trait Decorate: Sized {
+    fn decorate(self, decorator: &dyn Fn(Box<dyn Connector>) -> Box<dyn Connector>) -> Self;
+}
@@ -1 +1,5 @@
-pub const DEFAULT_REMOTE_PATH_STORAGE: &str = "C:\\";
\ No newline at end of file
+#[cfg(windows)]
+pub const DEFAULT_REMOTE_PATH_STORAGE: &str = "C:\\";
+
+#[cfg(not(windows))]
+pub const DEFAULT_REMOTE_PATH_STORAGE: &str = "/tmp";
@@ -1,9 +1,10 @@
 use std::path::{Path, PathBuf, Component};
-use crate::remote::{Computer, Connector, Command};
+use crate::remote::{Computer, Connector, Command, CommandOutcome};
 use std::io;
-use crate::process_runner::run_process_blocking;
 use std::env::temp_dir;
+use std::str::FromStr;
 use wildmatch::WildMatch;
+use crate::host;
 
 pub trait FileCopier {
     fn copy_file(
@@ -27,35 +28,15 @@ impl FileCopier for Cmd {
         source: &Path,
         target: &Path,
     ) -> io::Result<()> {
-        let args = vec![
-            "/y".to_string(),
-            "/i".to_string(),
-            "/c".to_string(),
-            "/H".to_string(),
-            "/S".to_string(),
-            source.to_string_lossy().to_string(),
-            target.to_string_lossy().to_string(),
-        ];
-        run_process_blocking(
-            "xcopy",
-            &args,
-        )
+        host::copy_path(source, target)
     }
 
     fn delete_file(&self, target: &Path) -> io::Result<()> {
-        let args = vec![
-            "/F".to_string(),
-            "/Q".to_string(),
-            target.to_string_lossy().to_string(),
-        ];
-        run_process_blocking(
-            "del",
-            &args,
-        )
+        host::delete_path(target)
     }
 
     fn method_name(&self) -> &'static str {
-        "XCopy"
+        if host::is_windows_host() { "XCopy" } else { "Copy" }
     }
 }
 
@@ -67,36 +48,72 @@ impl RemoteCmd<'_> {
     pub fn new<'a>(connector: &'a dyn Connector) -> RemoteCmd {
         RemoteCmd { connector }
     }
-}
 
-impl FileCopier for RemoteCmd<'_> {
-    fn copy_file(
+    /// Copies by `xcopy` on the target, the outcome tells whether the method waited for it to finish.
+    pub fn copy(
         &self,
         source: &Path,
         target: &Path,
-    ) -> io::Result<()> {
+    ) -> io::Result<CommandOutcome> {
+        self.connector.connect_and_run_command(
+            Command::new(xcopy_command(source, target), None, "", true),
+            None,
+        )
+    }
+
+    /// Copies by `xcopy` on the target, which then writes the `marker` file once the copy succeeded,
+    /// so that the copy can be awaited with methods that return before their commands finish.
+    pub fn copy_and_mark(
+        &self,
+        source: &Path,
+        target: &Path,
+        marker: &Path,
+    ) -> io::Result<CommandOutcome> {
+        let command_line = format!("{} && echo done > {}", xcopy_command(source, target).join(" "), marker.display());
         self.connector.connect_and_run_command(
             Command::new(
                 vec![
-                    "xcopy".to_string(),
-                    "/y".to_string(),
-                    "/i".to_string(),
+                    "cmd.exe".to_string(),
                     "/c".to_string(),
-                    "/H".to_string(),
-                    "/S".to_string(),
-                    source.to_string_lossy().to_string(),
-                    target.to_string_lossy().to_string(),
+                    format!("\"{}\"", command_line),
                 ],
                 None,
                 "",
                 true,
             ),
             None,
-        ).map(|_| ())
+        )
+    }
+}
+
+fn xcopy_command(source: &Path, target: &Path) -> Vec<String> {
+    vec![
+        "xcopy".to_string(),
+        "/y".to_string(),
+        "/i".to_string(),
+        "/c".to_string(),
+        "/H".to_string(),
+        "/S".to_string(),
+        source.to_string_lossy().to_string(),
+        target.to_string_lossy().to_string(),
+    ]
+}
+
+impl FileCopier for RemoteCmd<'_> {
+    fn copy_file(
+        &self,
+        source: &Path,
+        target: &Path,
+    ) -> io::Result<()> {
+        let outcome = self.copy(source, target)?;
+        if outcome.is_failure() {
+            return Err(io::Error::new(outcome.error_kind(), format!("Copying {} failed with {}", source.display(), outcome)));
+        }
+        Ok(())
     }
 
     fn delete_file(&self, target: &Path) -> io::Result<()> {
-        self.connector.connect_and_run_command(
+        let outcome = self.connector.connect_and_run_command(
             Command::new(
                 vec![
                     "cmd.exe".to_string(),
@@ -111,7 +128,11 @@ impl FileCopier for RemoteCmd<'_> {
                 true,
             ),
             None,
-        ).map(|_| ())
+        )?;
+        if outcome.is_failure() {
+            return Err(io::Error::new(outcome.error_kind(), format!("Deleting {} failed with {}", target.display(), outcome)));
+        }
+        Ok(())
     }
 
     fn method_name(&self) -> &'static str {
@@ -260,62 +281,84 @@ pub fn copy_from_remote_wildcards<F>(
     }
 }
 
-pub struct WindowsRemoteFileHandler {
-    computer: Computer,
-    copier_impl: Box<dyn FileCopier>,
-    pub custom_share_folder: Option<String>,
+/// Shares the drives of a Windows target are reached through, given by `--share`.
+/// Drives without a share of their own are reached through their administrative share, e.g. `D$`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShareMap {
+    shares: Vec<(char, String)>,
 }
 
-impl Drop for WindowsRemoteFileHandler {
-    fn drop(&mut self) {
-        if self.custom_share_folder.is_none() {
-            run_process_blocking(
-                "NET",
-                &[
-                    "USE".to_string(),
-                    format!("\\\\{}", self.computer.address),
-                    // format!("\\\\{}", self.computer.address),
-                    "/D".to_string()
-                ],
-            ).expect(&format!(
-                "Cannot drop connection using \"net use\" to {}", self.computer.address
-            ));
+impl ShareMap {
+    /// Share the drive is reached through, a single letter is a drive of this host the share is mapped to.
+    pub fn share(&self, drive: char) -> Option<&str> {
+        let drive = drive.to_ascii_uppercase();
+        self.shares.iter()
+            .find(|(mapped, _)| *mapped == drive)
+            .map(|(_, share)| share.as_str())
+    }
+
+    /// Path on the target in the form reachable from this host, e.g. `D:\Logs` as `\\address\Data\Logs`.
+    pub fn remote_form(&self, address: &str, path: &Path) -> PathBuf {
+        let path = path.to_string_lossy();
+        let mut chars = path.chars();
+        let drive = match (chars.next(), chars.next()) {
+            (Some(drive), Some(':')) if drive.is_ascii_alphabetic() => drive.to_ascii_uppercase(),
+            _ => return PathBuf::from(format!("\\\\{}\\{}", address, path)),
+        };
+        let rest = &path[2..];
+        PathBuf::from(match self.share(drive) {
+            Some(share) if is_drive_letter(share) => format!("{}:{}", share, rest),
+            Some(share) => format!("\\\\{}\\{}{}", address, share, rest),
+            None => format!("\\\\{}\\{}${}", address, drive, rest),
+        })
+    }
+}
+
+impl FromStr for ShareMap {
+    type Err = String;
+
+    /// Parses comma separated `DRIVE=SHARE` items, e.g. `C=C$,D=Data`.
+    /// A share without a drive is the share of drive C, as given before drives could be mapped.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut map = ShareMap::default();
+        for item in spec.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()) {
+            let (drive, share) = match item.split_once('=') {
+                None => ("C", item),
+                Some((drive, share)) => (drive.trim().trim_end_matches(':'), share.trim()),
+            };
+            if !is_drive_letter(drive) {
+                return Err(format!("Share item {} does not start with a drive letter", item));
+            }
+            if share.is_empty() || share.contains(|c| c == '\\' || c == '/') {
+                return Err(format!("Share item {} does not name a share", item));
+            }
+            let drive = drive.chars().next().unwrap().to_ascii_uppercase();
+            if map.share(drive).is_some() {
+                return Err(format!("Drive {} is given more than once", drive));
+            }
+            map.shares.push((drive, share.to_string()));
         }
+        Ok(map)
     }
 }
 
+fn is_drive_letter(value: &str) -> bool {
+    value.len() == 1 && value.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+pub struct WindowsRemoteFileHandler {
+    computer: Computer,
+    copier_impl: Box<dyn FileCopier>,
+    pub custom_share_folder: Option<ShareMap>,
+}
+
 impl WindowsRemoteFileHandler {
     pub fn new(
         computer: Computer,
         copier_impl: Box<dyn FileCopier>,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
     ) -> WindowsRemoteFileHandler {
-        let no_custom_share_folder = custom_share_folder.is_none();
-        let result = WindowsRemoteFileHandler { computer, copier_impl, custom_share_folder };
-        if no_custom_share_folder {
-            result.open_connection();
-        }
-        result
-    }
-
-    fn open_connection(
-        &self
-    ) {
-        let mut args = vec![
-            "USE".to_string(),
-            format!("\\\\{}", self.computer.address),
-        ];
-        let username = self.computer.domain_username();
-        args.push(format!("/u:{}", username));
-        if let Some(password) = &self.computer.password {
-            args.push(password.clone());
-        }
-        run_process_blocking(
-            "NET",
-            &args,
-        ).expect(&format!(
-            "Cannot establish connection using \"net use\" to {}", &self.computer.address
-        ));
+        WindowsRemoteFileHandler { computer, copier_impl, custom_share_folder }
     }
 }
 
@@ -332,31 +375,29 @@ impl RemoteFileCopier for WindowsRemoteFileHandler {
         &self,
         path: &Path,
     ) -> PathBuf {
-        match self.custom_share_folder.as_ref() {
-            None => {
-                self.open_connection();
-                PathBuf::from(format!(
-                    "\\\\{}\\{}",
-                    self.remote_computer().address,
-                    path.to_str().unwrap().replacen(":", "$", 1)
-                ))
-            }
-
-            Some(custom_share) => {
-                if custom_share.len() == 1 {
-                    PathBuf::from(
-                        path.to_str().unwrap().replace("C", custom_share)
-                    )
-                } else {
-                    PathBuf::from(format!(
-                        "\\\\{}\\{}",
-                        self.remote_computer().address,
-                        path.to_str().unwrap().replace("C:", custom_share)
-                    ))
-                }
-            }
-        }
+        self.custom_share_folder
+            .as_ref()
+            .unwrap_or(&ShareMap::default())
+            .remote_form(&self.remote_computer().address, path)
     }
 }
 
-pub const GARGAMEL_SHARED_FOLDER_NAME: &str = "GargamelShare";
\ No newline at end of file
+pub const GARGAMEL_SHARED_FOLDER_NAME: &str = "GargamelShare";
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_drives_to_shares() {
+        let shares = "C=C$, d:=Data,E=Z".parse::<ShareMap>().unwrap();
+        let remote_form = |path: &str| shares.remote_form("10.0.0.1", Path::new(path)).to_string_lossy().to_string();
+        assert_eq!(remote_form("C:\\Users\\Carl\\NTUSER.DAT"), "\\\\10.0.0.1\\C$\\Users\\Carl\\NTUSER.DAT");
+        assert_eq!(remote_form("D:\\Logs\\CBS.log"), "\\\\10.0.0.1\\Data\\Logs\\CBS.log");
+        assert_eq!(remote_form("E:\\Case"), "Z:\\Case");
+        assert_eq!(remote_form("F:\\Case"), "\\\\10.0.0.1\\F$\\Case");
+        let legacy = "Evidence".parse::<ShareMap>().unwrap();
+        assert_eq!(legacy.remote_form("host", Path::new("C:\\Windows")), PathBuf::from("\\\\host\\Evidence\\Windows"));
+        assert!("C=C$,C=Data".parse::<ShareMap>().is_err());
+        assert!("CD=Data".parse::<ShareMap>().is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use crate::remote::Computer;
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::{env, fmt, fs, io};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use aes_gcm::aead::Aead;
+use rand::RngCore;
+use rpassword::read_password;
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Environment variable with the passphrase of the credential vault.
+pub const VAULT_PASSPHRASE_ENV: &str = "GARGAMEL_VAULT_PASSPHRASE";
+
+const VAULT_MAGIC: &[u8; 8] = b"GRGVAULT";
+const VAULT_KDF_ROUNDS: u32 = 310_000;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const HEADER_LENGTH: usize = VAULT_MAGIC.len() + 4 + SALT_LENGTH + NONCE_LENGTH;
+
+/// Secrets of the targets, masked in everything Gargamel logs.
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static VAULT_PASSPHRASE: OnceLock<String> = OnceLock::new();
+static PROGRAMS_GIVEN_SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Where the passwords not given on the command line or in the computer list come from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CredentialSource {
+    Prompt,
+    Env(String),
+    File(PathBuf),
+    Vault(PathBuf),
+}
+
+impl FromStr for CredentialSource {
+    type Err = String;
+
+    /// Parses `prompt`, `env:NAME`, `file:PATH` or `vault:PATH`.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = match spec.find(':') {
+            Some(index) => (&spec[..index], Some(&spec[index + 1..])),
+            None => (spec, None),
+        };
+        match (kind, value) {
+            ("prompt", None) => Ok(CredentialSource::Prompt),
+            ("env", Some(name)) if !name.is_empty() => Ok(CredentialSource::Env(name.to_string())),
+            ("file", Some(path)) if !path.is_empty() => Ok(CredentialSource::File(PathBuf::from(path))),
+            ("vault", Some(path)) if !path.is_empty() => Ok(CredentialSource::Vault(PathBuf::from(path))),
+            _ => Err(format!("Unknown credential source {}, expected prompt, env:NAME, file:PATH or vault:PATH", spec)),
+        }
+    }
+}
+
+/// Resolves passwords of the targets from a `CredentialSource`.
+/// Every source falls back to a prompt when it has no password for a target.
+pub struct CredentialProvider {
+    source: CredentialSource,
+    vault: Option<Vault>,
+}
+
+impl CredentialProvider {
+    pub fn new(source: Option<CredentialSource>) -> CredentialProvider {
+        let source = source.unwrap_or(CredentialSource::Prompt);
+        let vault = match &source {
+            CredentialSource::Vault(path) => match Vault::open(path, vault_passphrase()) {
+                Ok(vault) => Some(vault),
+                Err(err) => {
+                    error!("Cannot open credential vault {} due to {}", path.display(), err);
+                    None
+                }
+            },
+            _ => None,
+        };
+        CredentialProvider { source, vault }
+    }
+
+    /// Password of the `username` on the target, `None` stands for an empty password.
+    pub fn password(&self, address: &str, username: &str) -> Option<String> {
+        let password = match &self.source {
+            CredentialSource::Prompt => None,
+            CredentialSource::Env(name) => match env::var(name) {
+                Ok(password) => Some(password),
+                Err(err) => {
+                    warn!("Cannot read password from environment variable {} due to {}", name, err);
+                    None
+                }
+            },
+            CredentialSource::File(path) => match fs::read_to_string(path) {
+                Ok(content) => Some(content.lines().next().unwrap_or_default().to_string()),
+                Err(err) => {
+                    warn!("Cannot read password from {} due to {}", path.display(), err);
+                    None
+                }
+            },
+            CredentialSource::Vault(path) => {
+                let password = self.vault.as_ref().and_then(|vault| vault.get(username, address));
+                if password.is_none() {
+                    warn!("Credential vault {} has no password of {} on {}", path.display(), username, address);
+                }
+                password.map(|password| password.to_string())
+            }
+        };
+        let password = password.or_else(|| {
+            println!("Password for {}: ", address);
+            read_password().ok()
+        });
+        match password {
+            Some(password) if !password.is_empty() => {
+                register_secret(&password);
+                Some(password)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Passphrase of the credential vault, read once from `VAULT_PASSPHRASE_ENV` or prompted.
+pub fn vault_passphrase() -> &'static str {
+    VAULT_PASSPHRASE.get_or_init(|| match env::var(VAULT_PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            println!("Credential vault passphrase: ");
+            read_password().unwrap_or_default()
+        }
+    })
+}
+
+/// Makes `redact` mask the secret.
+pub fn register_secret(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    if let Ok(mut secrets) = SECRETS.lock() {
+        if !secrets.iter().any(|it| it == secret) {
+            secrets.push(secret.to_string());
+        }
+    }
+}
+
+/// Replaces all registered secrets in the text with `*****`.
+pub fn redact(text: &str) -> String {
+    let secrets = match SECRETS.lock() {
+        Ok(secrets) => secrets,
+        Err(_) => return text.to_string(),
+    };
+    secrets.iter().fold(text.to_string(), |text, secret| text.replace(secret.as_str(), "*****"))
+}
+
+/// Warns once per program that cannot read secrets from anywhere but its command line,
+/// where they are visible to other users of this host.
+pub fn warn_secret_on_command_line(program: &str) {
+    if let Ok(mut programs) = PROGRAMS_GIVEN_SECRETS.lock() {
+        if !programs.iter().any(|it| it == program) {
+            programs.push(program.to_string());
+            warn!("{} accepts passwords only on its command line, where they are visible to other users of this host.", program);
+        }
+    }
+}
+
+/// Creates a file readable only by the current user.
+/// On Windows the file inherits the ACL of its directory, the user's temp directory is private.
+fn create_private_file(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Temporary file with a secret for programs that can read it from a file instead of the command line.
+/// The file is overwritten and removed when dropped.
+pub struct SecretFile {
+    path: PathBuf,
+    length: usize,
+}
+
+impl SecretFile {
+    pub fn new(secret: &str) -> io::Result<SecretFile> {
+        let path = env::temp_dir().join(format!("gargamel-{}", Uuid::new_v4()));
+        let mut file = create_private_file(&path)?;
+        let secret_file = SecretFile { path, length: secret.len() };
+        file.write_all(secret.as_bytes())?;
+        Ok(secret_file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        if let Ok(mut file) = OpenOptions::new().write(true).open(&self.path) {
+            let _ = file.write_all(&vec![0u8; self.length]);
+        }
+        if let Err(err) = fs::remove_file(&self.path) {
+            debug!("Cannot remove secret file {} due to {}", self.path.display(), err);
+        }
+    }
+}
+
+/// Password of a target stored in a `SecretFile` on the first use.
+pub struct PasswordFile {
+    password: Option<String>,
+    file: RefCell<Option<SecretFile>>,
+}
+
+impl PasswordFile {
+    pub fn new(computer: &Computer) -> PasswordFile {
+        PasswordFile {
+            password: computer.password.clone(),
+            file: RefCell::new(None),
+        }
+    }
+
+    /// Path of the file with the password, `None` when the target has no password.
+    pub fn path(&self) -> io::Result<Option<PathBuf>> {
+        let password = match &self.password {
+            None => return Ok(None),
+            Some(password) => password,
+        };
+        if let Some(file) = self.file.borrow().as_ref() {
+            return Ok(Some(file.path().to_path_buf()));
+        }
+        let file = SecretFile::new(password)?;
+        let path = file.path().to_path_buf();
+        *self.file.borrow_mut() = Some(file);
+        Ok(Some(path))
+    }
+}
+
+/// Passwords encrypted with AES-256-GCM under a key derived from a passphrase with PBKDF2-SHA256.
+/// Entries are looked up by `user@address`, then `address` and finally `*`.
+pub struct Vault {
+    path: PathBuf,
+    passphrase: String,
+    entries: Vec<(String, String)>,
+}
+
+impl Vault {
+    /// Opens the vault, a missing file is an empty vault.
+    pub fn open(path: &Path, passphrase: &str) -> io::Result<Vault> {
+        let entries = match fs::read(path) {
+            Ok(content) => parse_entries(&open_sealed(&content, passphrase)?)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Vault {
+            path: path.to_path_buf(),
+            passphrase: passphrase.to_string(),
+            entries,
+        })
+    }
+
+    pub fn get(&self, username: &str, address: &str) -> Option<&str> {
+        let keys = [format!("{}@{}", username, address), address.to_string(), "*".to_string()];
+        keys.iter()
+            .filter_map(|key| self.entries.iter().find(|(entry_key, _)| entry_key == key))
+            .map(|(_, secret)| secret.as_str())
+            .next()
+    }
+
+    pub fn insert(&mut self, key: String, secret: String) {
+        self.entries.retain(|(entry_key, _)| *entry_key != key);
+        self.entries.push((key, secret));
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let plaintext = self.entries.iter()
+            .map(|(key, secret)| format!("{}\t{}\n", key, base64::encode(secret)))
+            .collect::<String>();
+        let sealed = seal(plaintext.as_bytes(), &self.passphrase, VAULT_KDF_ROUNDS)?;
+        create_private_file(&self.path)?.write_all(&sealed)
+    }
+}
+
+impl fmt::Display for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "credential vault {} with {} entries", self.path.display(), self.entries.len())
+    }
+}
+
+fn vault_key(passphrase: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, rounds, &mut key);
+    key
+}
+
+fn seal(plaintext: &[u8], passphrase: &str, rounds: u32) -> io::Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let cipher = Aes256Gcm::new_from_slice(&vault_key(passphrase, &salt, rounds))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid vault key"))?;
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Cannot encrypt the vault"))?;
+    let mut sealed = Vec::with_capacity(HEADER_LENGTH + ciphertext.len());
+    sealed.extend_from_slice(VAULT_MAGIC);
+    sealed.extend_from_slice(&rounds.to_le_bytes());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+fn open_sealed(sealed: &[u8], passphrase: &str) -> io::Result<Vec<u8>> {
+    if sealed.len() < HEADER_LENGTH || &sealed[..VAULT_MAGIC.len()] != VAULT_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a Gargamel credential vault"));
+    }
+    let mut rounds = [0u8; 4];
+    rounds.copy_from_slice(&sealed[VAULT_MAGIC.len()..VAULT_MAGIC.len() + 4]);
+    let salt_start = VAULT_MAGIC.len() + 4;
+    let nonce_start = salt_start + SALT_LENGTH;
+    let key = vault_key(passphrase, &sealed[salt_start..nonce_start], u32::from_le_bytes(rounds));
+    let cipher = Aes256Gcm::new_from_slice(&key)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid vault key"))?;
+    cipher.decrypt(Nonce::from_slice(&sealed[nonce_start..HEADER_LENGTH]), &sealed[HEADER_LENGTH..])
+        .map_err(|_| io::Error::new(io::ErrorKind::PermissionDenied, "Wrong vault passphrase or corrupted vault"))
+}
+
+fn parse_entries(plaintext: &[u8]) -> io::Result<Vec<(String, String)>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Corrupted vault entry");
+    String::from_utf8_lossy(plaintext)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (key, secret) = line.split_once('\t').ok_or_else(invalid)?;
+            let secret = base64::decode(secret).map_err(|_| invalid())?;
+            Ok((key.to_string(), String::from_utf8(secret).map_err(|_| invalid())?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_credential_sources() {
+        assert_eq!("prompt".parse(), Ok(CredentialSource::Prompt));
+        assert_eq!("env:TARGET_PASSWORD".parse(), Ok(CredentialSource::Env("TARGET_PASSWORD".to_string())));
+        assert_eq!("vault:C:\\case\\vault".parse(), Ok(CredentialSource::Vault(PathBuf::from("C:\\case\\vault"))));
+        assert!("file:".parse::<CredentialSource>().is_err());
+        assert!("keyring".parse::<CredentialSource>().is_err());
+    }
+
+    #[test]
+    fn sealed_vault_opens_only_with_its_passphrase() {
+        let entries = "admin@10.0.0.1\tc2VjcmV0\n".as_bytes();
+        let sealed = seal(entries, "correct horse", 10).unwrap();
+        assert_eq!(open_sealed(&sealed, "correct horse").unwrap(), entries);
+        assert_eq!(open_sealed(&sealed, "wrong").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            parse_entries(entries).unwrap(),
+            vec![("admin@10.0.0.1".to_string(), "secret".to_string())]
+        );
+    }
+
+    #[test]
+    fn vault_prefers_the_most_specific_entry() {
+        let mut vault = Vault { path: PathBuf::new(), passphrase: String::new(), entries: Vec::new() };
+        vault.insert("*".to_string(), "default".to_string());
+        vault.insert("10.0.0.1".to_string(), "host".to_string());
+        vault.insert("admin@10.0.0.1".to_string(), "user".to_string());
+        assert_eq!(vault.get("admin", "10.0.0.1"), Some("user"));
+        assert_eq!(vault.get("backup", "10.0.0.1"), Some("host"));
+        assert_eq!(vault.get("admin", "10.0.0.2"), Some("default"));
+    }
+
+    #[test]
+    fn redacts_registered_secrets() {
+        register_secret("Pa$$w0rd");
+        assert_eq!(redact("paexec \\\\host -u admin -p Pa$$w0rd cmd"), "paexec \\\\host -u admin -p ***** cmd");
+    }
+}
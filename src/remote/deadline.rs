@@ -0,0 +1,128 @@
+use crate::remote::{Connector, Command};
+use std::cell::Cell;
+use std::io;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(30);
+
+static OPERATION_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+static TARGET_BUDGET: OnceLock<Duration> = OnceLock::new();
+
+thread_local! {
+    // Deadline of the target of this thread, see `main` for why it is thread-local.
+    static TARGET_DEADLINE: Cell<Option<Instant>> = Cell::new(None);
+}
+
+/// Sets the upper bound of remote operations that do not set their own timeout, given by `--operation-timeout`.
+pub fn set_operation_timeout(timeout: Duration) {
+    let _ = OPERATION_TIMEOUT.set(timeout);
+}
+
+/// Sets the time budget of the acquisition from one target, given by `--target-timeout`.
+pub fn set_target_budget(budget: Duration) {
+    let _ = TARGET_BUDGET.set(budget);
+}
+
+/// Time budget of the target handled by the current thread, it ends when the guard is dropped.
+pub struct TargetBudget {
+    previous: Option<Instant>,
+}
+
+impl TargetBudget {
+    /// Starts the budget given by `--target-timeout`, targets are unlimited without it.
+    pub fn start() -> TargetBudget {
+        let deadline = TARGET_BUDGET.get().map(|budget| Instant::now() + *budget);
+        TargetBudget {
+            previous: TARGET_DEADLINE.with(|it| it.replace(deadline)),
+        }
+    }
+
+    /// Whether the budget of the target is spent.
+    pub fn is_spent(&self) -> bool {
+        TARGET_DEADLINE.with(|it| it.get())
+            .map(|deadline| Instant::now() >= deadline)
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for TargetBudget {
+    fn drop(&mut self) {
+        TARGET_DEADLINE.with(|it| it.set(self.previous));
+    }
+}
+
+/// Runs `f` without the budget of the target, e.g. to clean up the target after the budget was spent.
+pub fn beyond_budget<T, F: FnOnce() -> T>(f: F) -> T {
+    let previous = TARGET_DEADLINE.with(|it| it.replace(None));
+    let result = f();
+    TARGET_DEADLINE.with(|it| it.set(previous));
+    result
+}
+
+/// Timeout of a remote operation: the `requested` one or the one given by `--operation-timeout`,
+/// cut to what is left of the budget of the target.
+/// Fails with `TimedOut` when the budget is already spent, so no further operation is started.
+pub fn operation_timeout(requested: Option<Duration>) -> io::Result<Option<Duration>> {
+    let timeout = requested.or_else(|| OPERATION_TIMEOUT.get().cloned());
+    let remaining = match TARGET_DEADLINE.with(|it| it.get()) {
+        None => return Ok(timeout),
+        Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+    };
+    if remaining == Duration::from_secs(0) {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "Time budget of the target is spent"));
+    }
+    Ok(Some(timeout.map(|timeout| timeout.min(remaining)).unwrap_or(remaining)))
+}
+
+/// Error of an operation that did not finish in time, told apart from failures by its `TimedOut` kind.
+pub fn timed_out_error(what: &str, timeout: Duration) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("{} timed out after {} s", what, timeout.as_secs()))
+}
+
+/// Kills the processes of the program left running on a Windows target after a timeout.
+pub fn terminate_windows<C: Connector + ?Sized>(connector: &C, program: &str) -> io::Result<()> {
+    let taskkill = Command::new(
+        vec![
+            "taskkill".to_string(),
+            "/F".to_string(),
+            "/T".to_string(),
+            "/IM".to_string(),
+            program.to_string(),
+        ],
+        None,
+        "",
+        true,
+    );
+    beyond_budget(|| connector.connect_and_run_command(taskkill, Some(TERMINATE_TIMEOUT))).map(|_| ())
+}
+
+/// Kills the processes of the program left running on a POSIX target after a timeout.
+pub fn terminate_posix<C: Connector + ?Sized>(connector: &C, program: &str) -> io::Result<()> {
+    let pkill = Command::new(
+        vec!["pkill".to_string(), "-KILL".to_string(), "-x".to_string(), program.to_string()],
+        None,
+        "",
+        true,
+    );
+    beyond_budget(|| connector.connect_and_run_command(pkill, Some(TERMINATE_TIMEOUT))).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_timeouts_to_target_budget() {
+        TARGET_DEADLINE.with(|it| it.set(Some(Instant::now() + Duration::from_secs(60))));
+        let timeout = operation_timeout(Some(Duration::from_secs(300))).unwrap().unwrap();
+        assert!(timeout <= Duration::from_secs(60) && timeout > Duration::from_secs(50));
+        assert_eq!(operation_timeout(Some(Duration::from_secs(10))).unwrap(), Some(Duration::from_secs(10)));
+        assert!(operation_timeout(None).unwrap().unwrap() <= Duration::from_secs(60));
+
+        TARGET_DEADLINE.with(|it| it.set(Some(Instant::now())));
+        assert_eq!(operation_timeout(Some(Duration::from_secs(10))).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(beyond_budget(|| operation_timeout(Some(Duration::from_secs(10))).unwrap()), Some(Duration::from_secs(10)));
+        TARGET_DEADLINE.with(|it| it.set(None));
+    }
+}
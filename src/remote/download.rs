@@ -1,8 +1,12 @@
 use std::path::Path;
-use crate::remote::{RemoteFileCopier, file_is_empty, path_to_part};
-use std::thread;
+use crate::remote::{RemoteFileCopier, Connector, Readiness, RetryPolicy, file_is_empty, path_to_part};
+use std::io;
+use std::io::ErrorKind;
 use std::time::Duration;
 
+/// Wait for a part that exists but came out empty when its size cannot be checked.
+const INCOMPLETE_PART_WAIT: Duration = Duration::from_secs(30);
+
 pub struct ReDownloader<'a> {
     pub copier: &'a dyn RemoteFileCopier,
     pub target_dir: &'a Path,
@@ -36,37 +40,19 @@ impl<'a> ReDownloader<'a> {
 
     fn download_as_splitted(&self, remote_path: &Path) -> bool {
         let extension = remote_path.extension().unwrap().to_string_lossy();
-        let wait_time_s = Duration::from_secs(10);
 
         let mut i = extension.parse::<usize>().unwrap() - 1;
         let archived_remote_file = remote_path.parent().unwrap().join(remote_path.file_stem().unwrap());
-        let mut unsuccessful_trials = 0;
         loop {
             i += 1;
             let part = path_to_part(&archived_remote_file, i);
             trace!("Copying {} from {}", part.display(), &self.copier.remote_computer().address);
-            if let Err(err) = self.copier.copy_from_remote(&part, self.target_dir) {
+            if let Err(err) = download_part(self.copier, None, &part, self.target_dir) {
                 debug!("{}", err);
+                break;
             }
-            let target_downloaded = self.target_dir.join(part.file_name().unwrap());
-
-            if file_is_empty(&target_downloaded) {
-                unsuccessful_trials += 1;
-                i -= 1;
-                if unsuccessful_trials == 2 {
-                    break;
-                }
-                debug!("File download may ended with errors. Waiting 30 seconds before retry.");
-                thread::sleep(Duration::from_secs(30));
-            } else {
-                unsuccessful_trials = 0;
-            }
-
-            thread::sleep(wait_time_s.clone());
-            if unsuccessful_trials == 0 {
-                if let Err(err) = self.copier.delete_remote_file(&part) {
-                    debug!("{}", err);
-                }
+            if let Err(err) = self.copier.delete_remote_file(&part) {
+                debug!("{}", err);
             }
         }
         true
@@ -101,3 +87,59 @@ impl<'a> ReDownloader<'a> {
         !result
     }
 }
+
+/// Downloads one part of a split archive, retrying it by the retry policy while it comes out empty,
+/// e.g. because 7-Zip still writes it. Transient failures are already retried by the connector.
+/// Fails with `NotFound` when the `connector` tells that the part does not exist, i.e. the previous one was the last.
+pub fn download_part(
+    copier: &dyn RemoteFileCopier,
+    connector: Option<&dyn Connector>,
+    part: &Path,
+    target: &Path,
+) -> io::Result<()> {
+    let target_downloaded = target.join(part.file_name().unwrap());
+    let what = format!("Download of {}", part.display());
+    RetryPolicy::current().run_when(&what, &|err| err.kind() == ErrorKind::UnexpectedEof, || {
+        let downloaded = copier.copy_from_remote(part, target);
+        if !file_is_empty(&target_downloaded) {
+            return Ok(());
+        }
+        let size = match connector {
+            Some(connector) => connector.remote_file_size(part),
+            None => Err(io::Error::new(ErrorKind::Unsupported, "Size of the part cannot be checked")),
+        };
+        match (size, downloaded) {
+            (Ok(None), _) => Err(io::Error::new(ErrorKind::NotFound, format!("{} does not exist", part.display()))),
+            (Ok(Some(_)), _) => {
+                if let Some(connector) = connector {
+                    Readiness::new(connector).wait_for_complete_file(part, INCOMPLETE_PART_WAIT);
+                }
+                Err(io::Error::new(ErrorKind::UnexpectedEof, format!("{} came out empty", what)))
+            }
+            (Err(_), Err(err)) => Err(err),
+            (Err(_), Ok(())) => Err(io::Error::new(ErrorKind::UnexpectedEof, format!("{} came out empty", what))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{Connector, replay_fixture, replay_store_directory};
+
+    #[test]
+    fn falls_back_to_compressed_file() {
+        let (replayer, fixture) = replay_fixture("re-download-psexec");
+        let store_directory = replay_store_directory();
+        let re_downloader = ReDownloader {
+            copier: replayer.copier(),
+            target_dir: &store_directory,
+        };
+        let remote_file = Path::new("C:\\Users\\Public\\memory.aff4");
+        assert!(re_downloader.retry_download(remote_file));
+        assert_eq!(fixture.remaining(), 0);
+        let compressed = Path::new("C:\\Users\\Public\\memory.aff4.7z");
+        assert!(!file_is_empty(&store_directory.join(compressed.file_name().unwrap())));
+        let _ = std::fs::remove_dir_all(&store_directory);
+    }
+}
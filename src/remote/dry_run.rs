@@ -0,0 +1,424 @@
+use crate::remote::{Computer, Connector, Command, CommandOutcome, FileCopier, RemoteFileCopier, ProbeResult, Access, OsInfo, OsFamily, redact, GARGAMEL_SHARED_FOLDER_NAME, reverse_share_accounts};
+use crate::process_runner::create_report_path;
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+use std::{env, fmt, fs, io};
+
+/// One operation `--dry-run` would perform on the target or on its behalf.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlannedOperation {
+    Command { method: &'static str, command: Vec<String>, elevated: bool, timeout: Option<Duration> },
+    Vss { method: &'static str, command: Vec<String> },
+    Upload { method: &'static str, source: PathBuf, target: PathBuf },
+    Download { method: &'static str, source: PathBuf, target: PathBuf },
+    Delete { method: &'static str, path: PathBuf },
+    Mkdir { method: &'static str, path: PathBuf },
+    ListDirs { method: &'static str, path: PathBuf },
+    Permissions { method: &'static str, path: PathBuf, granted: bool },
+    Share { command: Vec<String> },
+    Probe { method: &'static str },
+    Fingerprint { method: &'static str },
+}
+
+impl PlannedOperation {
+    fn kind(&self) -> &'static str {
+        match self {
+            PlannedOperation::Command { .. } => "command",
+            PlannedOperation::Vss { .. } => "vss",
+            PlannedOperation::Upload { .. } => "upload",
+            PlannedOperation::Download { .. } => "download",
+            PlannedOperation::Delete { .. } => "delete",
+            PlannedOperation::Mkdir { .. } => "mkdir",
+            PlannedOperation::ListDirs { .. } => "list-dirs",
+            PlannedOperation::Permissions { .. } => "permissions",
+            PlannedOperation::Share { .. } => "share",
+            PlannedOperation::Probe { .. } => "probe",
+            PlannedOperation::Fingerprint { .. } => "fingerprint",
+        }
+    }
+
+    fn method(&self) -> &'static str {
+        match self {
+            PlannedOperation::Command { method, .. }
+            | PlannedOperation::Vss { method, .. }
+            | PlannedOperation::Upload { method, .. }
+            | PlannedOperation::Download { method, .. }
+            | PlannedOperation::Delete { method, .. }
+            | PlannedOperation::Mkdir { method, .. }
+            | PlannedOperation::ListDirs { method, .. }
+            | PlannedOperation::Permissions { method, .. }
+            | PlannedOperation::Probe { method }
+            | PlannedOperation::Fingerprint { method } => method,
+            PlannedOperation::Share { .. } => "HOST",
+        }
+    }
+
+    /// Fields of the operation besides its kind and method, secrets are redacted.
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let path = |path: &Path| redact(&path.to_string_lossy());
+        let command_line = |command: &[String]| command.iter()
+            .map(|arg| redact(arg))
+            .collect::<Vec<String>>()
+            .join(" ");
+        match self {
+            PlannedOperation::Command { command, elevated, timeout, .. } => {
+                let mut fields = vec![("command", command_line(command)), ("elevated", elevated.to_string())];
+                if let Some(timeout) = timeout {
+                    fields.push(("timeout_s", timeout.as_secs().to_string()));
+                }
+                fields
+            }
+            PlannedOperation::Vss { command, .. }
+            | PlannedOperation::Share { command } => vec![("command", command_line(command))],
+            PlannedOperation::Upload { source, target, .. }
+            | PlannedOperation::Download { source, target, .. } => vec![("source", path(source)), ("target", path(target))],
+            PlannedOperation::Delete { path: target, .. }
+            | PlannedOperation::Mkdir { path: target, .. }
+            | PlannedOperation::ListDirs { path: target, .. } => vec![("path", path(target))],
+            PlannedOperation::Permissions { path: target, granted, .. } => vec![
+                ("path", path(target)),
+                ("action", if *granted { "grant" } else { "restore ACL" }.to_string()),
+            ],
+            PlannedOperation::Probe { .. } | PlannedOperation::Fingerprint { .. } => vec![],
+        }
+    }
+}
+
+impl fmt::Display for PlannedOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.method(), self.kind())?;
+        for (name, value) in self.fields() {
+            match name {
+                "command" | "path" => write!(f, " {}", value)?,
+                "elevated" if value == "true" => write!(f, " (elevated)")?,
+                "elevated" => {}
+                _ => write!(f, " {}={}", name, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Operations recorded for one target by `--dry-run`, in the order the acquisition would perform them.
+pub struct DryRunPlan {
+    computer: Computer,
+    operations: RefCell<Vec<PlannedOperation>>,
+}
+
+impl DryRunPlan {
+    pub fn new(computer: &Computer) -> DryRunPlan {
+        DryRunPlan {
+            computer: computer.clone(),
+            operations: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, operation: PlannedOperation) {
+        debug!("Dry run on {}: {}", self.computer.address, operation);
+        self.operations.borrow_mut().push(operation);
+    }
+
+    /// Records the `net share` commands `--reverse-share` runs on this host around the acquisition.
+    /// The random part of the share name is only known once the share is created.
+    pub fn record_reverse_share(&self, created: bool) {
+        let name = format!("{}-<random>", GARGAMEL_SHARED_FOLDER_NAME);
+        let command = if created {
+            let mut command = vec![
+                "NET".to_string(),
+                "share".to_string(),
+                format!("{}$={}", name, env::temp_dir().join(&name).display()),
+            ];
+            command.extend(reverse_share_accounts(&self.computer).iter().map(|account| format!("/GRANT:{},CHANGE", account)));
+            command
+        } else {
+            vec!["NET".to_string(), "share".to_string(), "/Y".to_string(), "/D".to_string(), format!("{}$", name)]
+        };
+        self.record(PlannedOperation::Share { command });
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"computer\":{},\"user\":{},\"operations\":[",
+            json_string(&self.computer.address),
+            json_string(&self.computer.domain_username()),
+        );
+        for (index, operation) in self.operations.borrow().iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let _ = write!(json, "{{\"kind\":{},\"method\":{}", json_string(operation.kind()), json_string(operation.method()));
+            for (name, value) in operation.fields() {
+                match name {
+                    "elevated" | "timeout_s" => { let _ = write!(json, ",\"{}\":{}", name, value); }
+                    _ => { let _ = write!(json, ",\"{}\":{}", name, json_string(&value)); }
+                }
+            }
+            json.push('}');
+        }
+        json.push_str("]}\n");
+        json
+    }
+
+    /// Stores the plan as text and JSON reports in the output directory.
+    pub fn store(&self, store_directory: &Path) -> io::Result<(PathBuf, PathBuf)> {
+        let text_path = create_report_path(&self.computer, store_directory, "plan", "DRYRUN", "txt");
+        fs::write(&text_path, self.to_string())?;
+        let json_path = create_report_path(&self.computer, store_directory, "plan", "DRYRUN", "json");
+        fs::write(&json_path, self.to_json())?;
+        Ok((text_path, json_path))
+    }
+}
+
+impl fmt::Display for DryRunPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Dry run plan for {} with user {}", self.computer.address, self.computer.domain_username())?;
+        writeln!(f, "  Every artifact is planned with its first method, the next ones run only when it fails.")?;
+        for (index, operation) in self.operations.borrow().iter().enumerate() {
+            writeln!(f, "  {}. {}", index + 1, operation)?;
+        }
+        Ok(())
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(json, "\\u{:04x}", c as u32); }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Whether the command creates, lists or removes volume shadow copies.
+fn is_vss_command(command: &[String]) -> bool {
+    command.iter().any(|arg| {
+        let arg = arg.to_lowercase();
+        arg.contains("win32_shadowcopy") || arg == "vssadmin"
+    })
+}
+
+/// Connector decorator of `--dry-run`, it records every operation into the plan instead of performing it.
+/// Commands succeed without output, so steps parsing the output of a previous command are planned only up to it.
+pub struct DryRun {
+    connector_impl: Box<dyn Connector>,
+    plan: Rc<DryRunPlan>,
+}
+
+impl DryRun {
+    pub fn new(connector_impl: Box<dyn Connector>, plan: Rc<DryRunPlan>) -> DryRun {
+        DryRun { connector_impl, plan }
+    }
+
+    /// Decorates the connector when a plan is being recorded.
+    pub fn wrap(connector: Box<dyn Connector>, plan: Option<&Rc<DryRunPlan>>) -> Box<dyn Connector> {
+        match plan {
+            Some(plan) => Box::new(DryRun::new(connector, plan.clone())),
+            None => connector,
+        }
+    }
+
+    fn method(&self) -> &'static str {
+        self.connector_impl.connect_method_name()
+    }
+}
+
+impl Connector for DryRun {
+    fn connect_method_name(&self) -> &'static str {
+        self.connector_impl.connect_method_name()
+    }
+
+    fn computer(&self) -> &Computer {
+        self.connector_impl.computer()
+    }
+
+    fn copier(&self) -> &dyn RemoteFileCopier {
+        self as &dyn RemoteFileCopier
+    }
+
+    fn remote_temp_storage(&self) -> &Path {
+        self.connector_impl.remote_temp_storage()
+    }
+
+    fn probe(&self) -> ProbeResult {
+        self.plan.record(PlannedOperation::Probe { method: self.method() });
+        ProbeResult::new(self.method(), Access::Unknown, Some("dry run".to_string()))
+    }
+
+    fn fingerprint(&self, _store_directory: &Path) -> OsInfo {
+        self.plan.record(PlannedOperation::Fingerprint { method: self.method() });
+        OsInfo::new(OsFamily::Unknown)
+    }
+
+    /// Planned files are taken as ready, checking them changes nothing on the target.
+    fn remote_file_size(&self, _path: &Path) -> io::Result<Option<u64>> {
+        Ok(Some(0))
+    }
+
+    fn remote_process_running(&self, _program: &str) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    fn mkdir(&self, path: &Path) {
+        self.plan.record(PlannedOperation::Mkdir { method: self.method(), path: path.to_path_buf() });
+    }
+
+    fn connect_and_run_local_program(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let local_program_path = PathBuf::from(command_to_run.command.first().cloned().unwrap_or_default());
+        let remote_program_path = self.remote_temp_storage().join(local_program_path.file_name().unwrap_or_default());
+        self.copy_to_remote(&local_program_path, self.remote_temp_storage())?;
+        let mut command = command_to_run.command;
+        if let Some(program) = command.first_mut() {
+            *program = remote_program_path.to_string_lossy().to_string();
+        }
+        let result = self.connect_and_run_command(Command { command, ..command_to_run }, timeout);
+        self.delete_remote_file(&remote_program_path)?;
+        result
+    }
+
+    fn connect_and_run_command(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let operation = if is_vss_command(&command_to_run.command) {
+            PlannedOperation::Vss { method: self.method(), command: command_to_run.command }
+        } else {
+            PlannedOperation::Command {
+                method: self.method(),
+                command: command_to_run.command,
+                elevated: command_to_run.elevated,
+                timeout,
+            }
+        };
+        self.plan.record(operation);
+        let now = SystemTime::now();
+        Ok(CommandOutcome {
+            exit_status: None,
+            stdout: None,
+            stderr: None,
+            started: now,
+            finished: now,
+            timed_out: false,
+        })
+    }
+
+    fn prepare_command(&self,
+                       command: Vec<String>,
+                       elevated: bool,
+    ) -> Vec<String> {
+        self.connector_impl.prepare_command(command, elevated)
+    }
+
+    fn list_dirs(&self, path: &Path, _store_directory: &Path) -> Vec<String> {
+        self.plan.record(PlannedOperation::ListDirs { method: self.method(), path: path.to_path_buf() });
+        vec![]
+    }
+
+    fn acquire_perms(&self, path: &Path) {
+        self.plan.record(PlannedOperation::Permissions { method: self.method(), path: path.to_path_buf(), granted: true });
+    }
+
+    fn release_perms(&self, path: &Path) {
+        self.plan.record(PlannedOperation::Permissions { method: self.method(), path: path.to_path_buf(), granted: false });
+    }
+}
+
+impl FileCopier for DryRun {
+    fn copy_file(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.plan.record(PlannedOperation::Upload {
+            method: FileCopier::method_name(self),
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn delete_file(&self, target: &Path) -> io::Result<()> {
+        self.plan.record(PlannedOperation::Delete { method: FileCopier::method_name(self), path: target.to_path_buf() });
+        Ok(())
+    }
+
+    fn method_name(&self) -> &'static str {
+        self.connector_impl.copier().method_name()
+    }
+}
+
+impl RemoteFileCopier for DryRun {
+    fn remote_computer(&self) -> &Computer {
+        self.connector_impl.computer()
+    }
+
+    fn copier_impl(&self) -> &dyn FileCopier {
+        self as &dyn FileCopier
+    }
+
+    fn path_to_remote_form(&self, path: &Path) -> PathBuf {
+        self.connector_impl.copier().path_to_remote_form(path)
+    }
+
+    fn copy_to_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.plan.record(PlannedOperation::Upload {
+            method: RemoteFileCopier::method_name(self),
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
+        self.plan.record(PlannedOperation::Delete { method: RemoteFileCopier::method_name(self), path: target.to_path_buf() });
+        Ok(())
+    }
+
+    fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.plan.record(PlannedOperation::Download {
+            method: RemoteFileCopier::method_name(self),
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::Local;
+
+    #[test]
+    fn records_operations_instead_of_running_them() {
+        let local = Local::new("analyst".to_string(), PathBuf::from("/tmp"));
+        let plan = Rc::new(DryRunPlan::new(local.computer()));
+        let dry_run = DryRun::new(Box::new(Local::new("analyst".to_string(), PathBuf::from("/tmp"))), plan.clone());
+        dry_run.connect_and_run_local_program(
+            Command::new(vec!["winpmem.exe".to_string(), "-o".to_string(), "mem \"1\".aff4".to_string()], None, "", true),
+            None,
+        ).unwrap();
+        dry_run.connect_and_run_command(
+            Command::new(vec!["vssadmin".to_string(), "list".to_string(), "shadows".to_string()], None, "", true),
+            None,
+        ).unwrap();
+        let kinds = plan.operations.borrow().iter().map(|operation| operation.kind()).collect::<Vec<&str>>();
+        assert_eq!(kinds, vec!["upload", "command", "delete", "vss"]);
+        assert!(plan.to_string().contains("2. [LOCAL] command /tmp/winpmem.exe -o mem \"1\".aff4 (elevated)"));
+        assert!(plan.to_json().contains(r#"{"kind":"command","method":"LOCAL","command":"/tmp/winpmem.exe -o mem \"1\".aff4","elevated":true}"#));
+    }
+}
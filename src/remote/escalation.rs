@@ -0,0 +1,163 @@
+use crate::host::quote_posix;
+use std::str::FromStr;
+use std::fmt;
+
+/// How elevated commands are run on a Linux target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Escalation {
+    /// The user is root, commands run as they are.
+    AlreadyRoot,
+    /// `sudo` allowed without a password (`NOPASSWD`).
+    SudoNoPassword,
+    /// `sudo -S` reading the password from the standard input.
+    SudoPassword,
+    /// `sudo -S` on a pseudo-terminal for targets with `requiretty`.
+    SudoPasswordPty,
+    /// `doas` allowed without a password (`nopass`).
+    DoasNoPassword,
+    /// `doas` reading the password from its terminal.
+    DoasPassword,
+    /// `su` reading the root password, which is the target password, from its terminal.
+    Su,
+    /// No way to elevate, commands run with the rights of the user.
+    Unavailable,
+}
+
+/// Modes tried in this order when detecting how to elevate.
+/// `su` is only used when given, the target password is rarely the root password.
+pub const ESCALATION_CANDIDATES: [Escalation; 5] = [
+    Escalation::SudoNoPassword,
+    Escalation::SudoPassword,
+    Escalation::SudoPasswordPty,
+    Escalation::DoasNoPassword,
+    Escalation::DoasPassword,
+];
+
+impl Escalation {
+    /// Command line running the `command_line` elevated.
+    pub fn wrap(&self, command_line: &str) -> String {
+        let quoted = quote_posix(command_line);
+        match self {
+            Escalation::AlreadyRoot | Escalation::Unavailable => command_line.to_string(),
+            Escalation::SudoNoPassword => format!("sudo -n sh -c {}", quoted),
+            Escalation::SudoPassword | Escalation::SudoPasswordPty => format!("sudo -S -p '' sh -c {}", quoted),
+            Escalation::DoasNoPassword => format!("doas -n sh -c {}", quoted),
+            Escalation::DoasPassword => format!("doas sh -c {}", quoted),
+            Escalation::Su => format!("su root -c {}", quoted),
+        }
+    }
+
+    pub fn needs_password(&self) -> bool {
+        match self {
+            Escalation::SudoPassword | Escalation::SudoPasswordPty | Escalation::DoasPassword | Escalation::Su => true,
+            _ => false,
+        }
+    }
+
+    pub fn needs_pty(&self) -> bool {
+        match self {
+            Escalation::SudoPasswordPty | Escalation::DoasPassword | Escalation::Su => true,
+            _ => false,
+        }
+    }
+
+    /// Programs reading the password from their terminal discard anything typed before their prompt.
+    pub fn waits_for_prompt(&self) -> bool {
+        match self {
+            Escalation::DoasPassword | Escalation::Su => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Escalation {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "root" => Ok(Escalation::AlreadyRoot),
+            "sudo-nopasswd" => Ok(Escalation::SudoNoPassword),
+            "sudo" => Ok(Escalation::SudoPassword),
+            "sudo-pty" => Ok(Escalation::SudoPasswordPty),
+            "doas-nopass" => Ok(Escalation::DoasNoPassword),
+            "doas" => Ok(Escalation::DoasPassword),
+            "su" => Ok(Escalation::Su),
+            "none" => Ok(Escalation::Unavailable),
+            _ => Err(format!(
+                "Unknown escalation {}, expected root, sudo-nopasswd, sudo, sudo-pty, doas-nopass, doas, su or none",
+                mode
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Escalation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Escalation::AlreadyRoot => "already root",
+            Escalation::SudoNoPassword => "sudo without password",
+            Escalation::SudoPassword => "sudo with password",
+            Escalation::SudoPasswordPty => "sudo with password on a terminal",
+            Escalation::DoasNoPassword => "doas without password",
+            Escalation::DoasPassword => "doas with password",
+            Escalation::Su => "su",
+            Escalation::Unavailable => "none",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Whether a failed check never got to the password, i.e. the program is missing or needs a terminal.
+/// Any other failure of a mode reading the password counts as a rejected password.
+pub fn refused_before_password(exit_status: Option<i32>, stderr: &[u8]) -> bool {
+    exit_status == Some(127) || String::from_utf8_lossy(stderr).to_lowercase().contains("tty")
+}
+
+/// Removes the password prompt answered during the command from its output.
+pub fn strip_prompt(output: &mut Vec<u8>, prompt_end: usize) {
+    let line_start = output[..prompt_end].iter()
+        .rposition(|byte| *byte == b'\n')
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    let after_prompt = output[prompt_end..].iter()
+        .position(|byte| *byte != b'\r' && *byte != b'\n')
+        .map(|index| prompt_end + index)
+        .unwrap_or(output.len());
+    output.drain(line_start..after_prompt);
+}
+
+/// Whether the output ends with a password prompt like `Password:` or `doas (user@host) password: `.
+pub fn ends_with_prompt(output: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(output);
+    let last_line = text.rsplit('\n').next().unwrap_or_default().trim_end();
+    last_line.ends_with(':') && last_line.to_lowercase().contains("password")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_commands_in_quoted_shell() {
+        assert_eq!(Escalation::SudoPassword.wrap("netstat -natp | head"), "sudo -S -p '' sh -c 'netstat -natp | head'");
+        assert_eq!(Escalation::Su.wrap("iptables -L"), "su root -c 'iptables -L'");
+        assert_eq!(Escalation::AlreadyRoot.wrap("iptables -L"), "iptables -L");
+    }
+
+    #[test]
+    fn detects_and_strips_password_prompts() {
+        assert!(ends_with_prompt(b"doas (jano@web) password: "));
+        assert!(ends_with_prompt(b"Password:"));
+        assert!(!ends_with_prompt(b"Chain INPUT (policy ACCEPT)\ntarget:"));
+        let mut output = b"Password: \r\nChain INPUT\n".to_vec();
+        strip_prompt(&mut output, 10);
+        assert_eq!(output, b"Chain INPUT\n");
+    }
+
+    #[test]
+    fn tells_rejected_password_apart() {
+        assert!(refused_before_password(Some(1), b"sudo: sorry, you must have a tty to run sudo\n"));
+        assert!(refused_before_password(Some(127), b"sh: doas: not found\n"));
+        assert!(!refused_before_password(Some(1), b"Sorry, try again.\nsudo: 1 incorrect password attempt\n"));
+    }
+}
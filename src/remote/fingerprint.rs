@@ -0,0 +1,291 @@
+use crate::remote::{Connector, Command};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use uuid::Uuid;
+
+const FINGERPRINT_COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// System drive of Windows targets that were not fingerprinted.
+pub const DEFAULT_SYSTEM_DRIVE: &str = "C:";
+
+/// Operating system family of a target, it decides which command set the acquirers use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OsFamily {
+    Windows,
+    Linux,
+    MacOs,
+    Unknown,
+}
+
+impl fmt::Display for OsFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            OsFamily::Windows => "Windows",
+            OsFamily::Linux => "Linux",
+            OsFamily::MacOs => "macOS",
+            OsFamily::Unknown => "unknown",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Operating system of a target found by the fingerprint after connecting.
+/// Values are kept as the target reports them, e.g. architecture `64-bit` on Windows and `x86_64` on Linux.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OsInfo {
+    pub family: OsFamily,
+    pub version: Option<String>,
+    pub architecture: Option<String>,
+    pub system_drive: Option<String>,
+    pub locale: Option<String>,
+}
+
+impl OsInfo {
+    pub fn new(family: OsFamily) -> OsInfo {
+        OsInfo {
+            family,
+            version: None,
+            architecture: None,
+            system_drive: None,
+            locale: None,
+        }
+    }
+
+    /// Whether anything beyond the family was found.
+    pub fn is_detailed(&self) -> bool {
+        self.version.is_some() || self.architecture.is_some() || self.locale.is_some()
+    }
+
+    /// The system drive of a Windows target like `D:`, `C:` when it is not known.
+    pub fn windows_system_drive(&self) -> String {
+        self.system_drive.as_deref()
+            .filter(|drive| drive.len() == 2 && drive.ends_with(':') && drive.starts_with(|c: char| c.is_ascii_alphabetic()))
+            .map(|drive| drive.to_ascii_uppercase())
+            .unwrap_or_else(|| DEFAULT_SYSTEM_DRIVE.to_string())
+    }
+
+    /// Whether commands print English output, which the parsers of e.g. `vssadmin` and `dir` expect.
+    /// Targets with an unknown locale are expected to.
+    pub fn has_english_output(&self) -> bool {
+        let locale = match self.locale.as_deref() {
+            None => return true,
+            Some(locale) => locale,
+        };
+        match self.family {
+            // Windows reports the LCID in hex, English is the primary language 0x09 of e.g. 0409 and 0809.
+            OsFamily::Windows => u32::from_str_radix(locale, 16).map_or(true, |lcid| lcid & 0x3FF == 0x09),
+            _ => locale.starts_with("en") || locale == "C" || locale.starts_with("C.") || locale == "POSIX",
+        }
+    }
+}
+
+impl fmt::Display for OsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.version.as_deref().unwrap_or(&self.family.to_string()))?;
+        if let Some(architecture) = &self.architecture {
+            write!(f, " ({})", architecture)?;
+        }
+        if let Some(system_drive) = &self.system_drive {
+            write!(f, ", system drive {}", system_drive)?;
+        }
+        if let Some(locale) = &self.locale {
+            write!(f, ", locale {}", locale)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fingerprints a Windows target using `wmic`, falling back to `ver` where `wmic` is no longer installed.
+pub fn fingerprint_windows<C: Connector + ?Sized>(connector: &C, store_directory: &Path) -> OsInfo {
+    let wmic = vec![
+        "wmic".to_string(),
+        "os".to_string(),
+        "get".to_string(),
+        "Caption,Version,OSArchitecture,SystemDrive,Locale".to_string(),
+        "/format:list".to_string(),
+    ];
+    if let Some(output) = run_for_output(connector, wmic, store_directory) {
+        let info = parse_wmic_os(&output);
+        if info.is_detailed() {
+            return info;
+        }
+    }
+    let ver = vec!["cmd.exe".to_string(), "/c".to_string(), "ver".to_string()];
+    let mut info = OsInfo::new(OsFamily::Windows);
+    info.version = run_for_output(connector, ver, store_directory)
+        .and_then(|output| first_line(&output));
+    info
+}
+
+/// Fingerprints a POSIX target using `uname`, `/etc/os-release` or `sw_vers` and `locale`.
+/// Targets where `uname` does not run are fingerprinted as Windows, e.g. Windows with OpenSSH.
+pub fn fingerprint_posix<C: Connector + ?Sized>(connector: &C, store_directory: &Path) -> OsInfo {
+    let uname = vec!["uname".to_string(), "-s".to_string(), "-r".to_string(), "-m".to_string()];
+    let mut info = match run_for_output(connector, uname, store_directory).and_then(|output| parse_uname(&output)) {
+        Some(info) => info,
+        None => return fingerprint_windows(connector, store_directory),
+    };
+    let release = match info.family {
+        OsFamily::Linux => run_for_output(connector, vec!["cat".to_string(), "/etc/os-release".to_string()], store_directory)
+            .and_then(|output| parse_os_release(&output)),
+        OsFamily::MacOs => run_for_output(connector, vec!["sw_vers".to_string(), "-productVersion".to_string()], store_directory)
+            .and_then(|output| first_line(&output))
+            .map(|version| format!("macOS {}", version)),
+        _ => None,
+    };
+    if release.is_some() {
+        info.version = release;
+    }
+    info.locale = run_for_output(connector, vec!["locale".to_string()], store_directory)
+        .and_then(|output| parse_locale(&output));
+    info
+}
+
+/// Runs an unelevated command and returns its standard output, the reports it creates are removed.
+fn run_for_output<C: Connector + ?Sized>(connector: &C, command: Vec<String>, store_directory: &Path) -> Option<String> {
+    let prefix = format!("--TEMP_FINGERPRINT_{}", Uuid::new_v4());
+    let command = Command::new(command, Some(store_directory), &prefix, false);
+    let outcome = match connector.connect_and_run_command(command, Some(FINGERPRINT_COMMAND_TIMEOUT)) {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            debug!("Fingerprint command failed on {} due to {}", connector.computer().address, err);
+            return None;
+        }
+    };
+    if let Some(stderr) = &outcome.stderr {
+        let _ = fs::remove_file(stderr);
+    }
+    let stdout = outcome.stdout.as_ref()?;
+    let output = fs::read(stdout).ok().map(|bytes| decode_output(&bytes));
+    let _ = fs::remove_file(stdout);
+    if outcome.is_failure() {
+        debug!("Fingerprint command on {} finished with {}", connector.computer().address, outcome);
+        return None;
+    }
+    output.filter(|output| !output.trim().is_empty())
+}
+
+/// Decodes command output, `wmic` writes UTF-16 when its output is not a console.
+fn decode_output(bytes: &[u8]) -> String {
+    let utf16 = bytes.len() >= 2 && (bytes.starts_with(&[0xFF, 0xFE]) || bytes[1] == 0);
+    if utf16 {
+        let units = bytes.chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .skip_while(|unit| *unit == 0xFEFF)
+            .collect::<Vec<u16>>();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+fn first_line(output: &str) -> Option<String> {
+    output.lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string())
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim().trim_matches('"');
+    if value.is_empty() { None } else { Some(value.to_string()) }
+}
+
+/// Parses `wmic os get ... /format:list` output of `Key=Value` lines.
+fn parse_wmic_os(output: &str) -> OsInfo {
+    let mut info = OsInfo::new(OsFamily::Windows);
+    let mut caption = None;
+    let mut build = None;
+    for line in output.lines() {
+        let (key, value) = match line.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let value = non_empty(value);
+        match key.trim() {
+            "Caption" => caption = value,
+            "Version" => build = value,
+            "OSArchitecture" => info.architecture = value,
+            "SystemDrive" => info.system_drive = value,
+            "Locale" => info.locale = value,
+            _ => {}
+        }
+    }
+    info.version = match (caption, build) {
+        (Some(caption), Some(build)) => Some(format!("{} {}", caption, build)),
+        (caption, build) => caption.or(build),
+    };
+    info
+}
+
+/// Parses `uname -s -r -m` output like `Linux 5.15.0-91-generic x86_64`.
+fn parse_uname(output: &str) -> Option<OsInfo> {
+    let line = first_line(output)?;
+    let fields = line.split_whitespace().collect::<Vec<&str>>();
+    let family = match fields.first()? {
+        &"Linux" => OsFamily::Linux,
+        &"Darwin" => OsFamily::MacOs,
+        _ => OsFamily::Unknown,
+    };
+    let mut info = OsInfo::new(family);
+    info.system_drive = Some("/".to_string());
+    if fields.len() >= 3 {
+        info.version = Some(format!("{} {}", fields[0], fields[1]));
+        info.architecture = Some(fields[fields.len() - 1].to_string());
+    } else {
+        info.version = Some(line.to_string());
+    }
+    Some(info)
+}
+
+/// Reads `PRETTY_NAME`, or `NAME` and `VERSION`, from `/etc/os-release`.
+fn parse_os_release(output: &str) -> Option<String> {
+    let value = |key: &str| output.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+        .and_then(non_empty);
+    value("PRETTY_NAME").or_else(|| match (value("NAME"), value("VERSION")) {
+        (Some(name), Some(version)) => Some(format!("{} {}", name, version)),
+        (name, _) => name,
+    })
+}
+
+/// Reads the effective locale from `locale` output, `LC_ALL` overrides `LANG`.
+fn parse_locale(output: &str) -> Option<String> {
+    let value = |key: &str| output.lines()
+        .find_map(|line| line.trim().strip_prefix(key)?.strip_prefix('='))
+        .and_then(non_empty);
+    value("LC_ALL").or_else(|| value("LANG")).or_else(|| value("LC_CTYPE"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_wmic_output_in_utf16() {
+        let text = "\r\r\nCaption=Microsoft Windows 10 Pro\r\r\nLocale=0409\r\r\nOSArchitecture=64-bit\r\r\nSystemDrive=C:\r\r\nVersion=10.0.19045\r\r\n";
+        let bytes = [0xFF, 0xFE].iter().cloned()
+            .chain(text.encode_utf16().flat_map(|unit| unit.to_le_bytes().to_vec()))
+            .collect::<Vec<u8>>();
+        let info = parse_wmic_os(&decode_output(&bytes));
+        assert_eq!(info.family, OsFamily::Windows);
+        assert_eq!(info.version.as_deref(), Some("Microsoft Windows 10 Pro 10.0.19045"));
+        assert_eq!(info.to_string(), "Microsoft Windows 10 Pro 10.0.19045 (64-bit), system drive C:, locale 0409");
+        assert!(info.has_english_output());
+        let german = parse_wmic_os("Locale=0407\r\nSystemDrive=d:\r\n");
+        assert_eq!(german.windows_system_drive(), "D:");
+        assert!(!german.has_english_output());
+    }
+
+    #[test]
+    fn parses_posix_fingerprint() {
+        let info = parse_uname("Darwin 23.1.0 arm64\n").unwrap();
+        assert_eq!((info.family, info.architecture.as_deref()), (OsFamily::MacOs, Some("arm64")));
+        assert_eq!(parse_uname("Linux 5.15.0-91-generic x86_64").unwrap().family, OsFamily::Linux);
+        let os_release = "NAME=\"Ubuntu\"\nVERSION=\"22.04.3 LTS (Jammy Jellyfish)\"\nPRETTY_NAME=\"Ubuntu 22.04.3 LTS\"\n";
+        assert_eq!(parse_os_release(os_release).as_deref(), Some("Ubuntu 22.04.3 LTS"));
+        assert_eq!(parse_locale("LANG=en_US.UTF-8\nLC_CTYPE=\"en_US.UTF-8\"\nLC_ALL=\n").as_deref(), Some("en_US.UTF-8"));
+    }
+}
@@ -0,0 +1,659 @@
+use crate::remote::{Connector, Computer, Command, beyond_budget, delete_shadow_copy, delete_shadow_snapshot, remove_host_share};
+use crate::process_runner::create_report_path;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+use wildmatch::WildMatch;
+
+/// Method name of footprints made on this host on behalf of the target.
+pub const HOST_METHOD: &str = "HOST";
+
+thread_local! {
+    // Ledger of the target of this thread, see `main` for why it is thread-local.
+    static CURRENT_LEDGER: RefCell<Option<Rc<FootprintLedger>>> = RefCell::new(None);
+}
+
+/// Change made on the target, or on this host on behalf of the target, that the cleanup reverts.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Footprint {
+    /// File written onto the target, e.g. an uploaded program, a report or 7z parts matched by `*`.
+    RemoteFile(PathBuf),
+    /// Directory link to a shadow copy made by `mklink /d`.
+    VssLink(PathBuf),
+    /// Full control of the path granted to the user by `icacls`, its ACL saved before is restored on removal.
+    Grant(PathBuf),
+    /// Share of this host created by `net share` for the target.
+    Share(String),
+    /// Service installed on the target by PsExec or PAExec, `*` stands for a part of the name not known in advance.
+    Service(String),
+    /// Shadow copy made by VSS, given by its ID.
+    Snapshot(String),
+}
+
+impl Footprint {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Footprint::RemoteFile(_) => "file",
+            Footprint::VssLink(_) => "vss-link",
+            Footprint::Grant(_) => "grant",
+            Footprint::Share(_) => "share",
+            Footprint::Service(_) => "service",
+            Footprint::Snapshot(_) => "vss-snapshot",
+        }
+    }
+
+    /// The path or the name of what was changed.
+    pub fn subject(&self) -> String {
+        match self {
+            Footprint::RemoteFile(path)
+            | Footprint::VssLink(path)
+            | Footprint::Grant(path) => path.to_string_lossy().to_string(),
+            Footprint::Share(name)
+            | Footprint::Service(name)
+            | Footprint::Snapshot(name) => name.clone(),
+        }
+    }
+
+    fn parse(kind: &str, subject: &str) -> Option<Footprint> {
+        match kind {
+            "file" => Some(Footprint::RemoteFile(PathBuf::from(subject))),
+            "vss-link" => Some(Footprint::VssLink(PathBuf::from(subject))),
+            "grant" => Some(Footprint::Grant(PathBuf::from(subject))),
+            "share" => Some(Footprint::Share(subject.to_string())),
+            "service" => Some(Footprint::Service(subject.to_string())),
+            "vss-snapshot" => Some(Footprint::Snapshot(subject.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// One footprint with the method that made it, when it was made and when it was reverted.
+/// The detail tells more about it, e.g. the hash of an uploaded program or the account granted access.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FootprintEntry {
+    pub footprint: Footprint,
+    pub method: String,
+    pub detail: Option<String>,
+    pub created: SystemTime,
+    pub removed: Option<SystemTime>,
+}
+
+/// Footprints of one target, every change is appended to a file in the case directory
+/// so that leftovers of an interrupted run are known to the next run and to `--cleanup`.
+pub struct FootprintLedger {
+    path: PathBuf,
+    entries: RefCell<Vec<FootprintEntry>>,
+}
+
+/// Keeps the ledger current for the thread of the target, the previous one is restored on drop.
+pub struct ActiveLedger {
+    previous: Option<Rc<FootprintLedger>>,
+}
+
+impl Drop for ActiveLedger {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT_LEDGER.with(|it| *it.borrow_mut() = previous);
+    }
+}
+
+impl FootprintLedger {
+    /// Opens the ledger of the target in the case directory, entries left by previous runs are kept.
+    pub fn open(store_directory: &Path, computer: &Computer) -> io::Result<Rc<FootprintLedger>> {
+        let path = FootprintLedger::path(store_directory, computer);
+        let mut entries = Vec::new();
+        match fs::read_to_string(&path) {
+            Ok(content) => content.lines().for_each(|line| apply_event(&mut entries, line)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        };
+        Ok(Rc::new(FootprintLedger {
+            path,
+            entries: RefCell::new(entries),
+        }))
+    }
+
+    /// Ledger file of the target, named like its reports.
+    pub fn path(store_directory: &Path, computer: &Computer) -> PathBuf {
+        store_directory.join(format!(
+            "FOOTPRINT-ledger-{}-{}.txt",
+            computer.address.replace(".", "-"),
+            computer.username.replace(" ", ""),
+        ))
+    }
+
+    /// Makes this ledger record the footprints made by the current thread.
+    pub fn activate(self: &Rc<Self>) -> ActiveLedger {
+        ActiveLedger {
+            previous: CURRENT_LEDGER.with(|it| it.replace(Some(self.clone()))),
+        }
+    }
+
+    /// Footprints not reverted yet, in the order they were made.
+    pub fn pending(&self) -> Vec<FootprintEntry> {
+        self.entries.borrow().iter()
+            .filter(|entry| entry.removed.is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Methods needed to revert the pending footprints.
+    pub fn pending_methods(&self) -> Vec<String> {
+        let mut methods = Vec::<String>::new();
+        for entry in self.pending() {
+            if entry.method != HOST_METHOD && !methods.contains(&entry.method) {
+                methods.push(entry.method);
+            }
+        }
+        methods
+    }
+
+    fn record(&self, method: &str, footprint: Footprint, detail: Option<String>) {
+        trace!("Recording footprint {} {} made by {}", footprint.kind(), footprint.subject(), method);
+        let entry = FootprintEntry {
+            footprint,
+            method: method.to_string(),
+            detail,
+            created: SystemTime::now(),
+            removed: None,
+        };
+        self.append(&format_made(&entry));
+        self.entries.borrow_mut().push(entry);
+    }
+
+    fn mark_removed(&self, footprint: &Footprint) {
+        let removed = SystemTime::now();
+        if mark_removed_in(&mut self.entries.borrow_mut(), footprint, removed) {
+            self.append(&format_removed(footprint, removed));
+        }
+    }
+
+    fn is_pending(&self, footprint: &Footprint) -> bool {
+        self.entries.borrow().iter().any(|entry| entry.removed.is_none() && &entry.footprint == footprint)
+    }
+
+    fn check_removed(&self, footprint: &Footprint) -> io::Result<()> {
+        if self.is_pending(footprint) {
+            return Err(io::Error::new(io::ErrorKind::Other, "the command reverting it failed"));
+        }
+        Ok(())
+    }
+
+    /// Appends one event to the ledger file, a line cut short by a crash is skipped when the ledger is read.
+    fn append(&self, line: &str) {
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(err) = result {
+            error!("Cannot store footprint ledger {} due to {}", self.path.display(), err);
+        }
+    }
+
+    /// Reverts the pending footprints in reverse order, each using the connector of the method that made it.
+    /// Returns how many footprints are left on the target.
+    pub fn clean_up(self: &Rc<Self>, connectors: &[Box<dyn Connector>]) -> usize {
+        self.revert(connectors, |_| true);
+        let left = self.pending();
+        for entry in left.iter() {
+            warn!("{} {} made by {} is left, run --cleanup to remove it", entry.footprint.kind(), entry.footprint.subject(), entry.method);
+        }
+        left.len()
+    }
+
+    /// Reverts the pending footprints made on this host, e.g. the reverse share of a run that crashed.
+    pub fn clean_up_host(self: &Rc<Self>) {
+        self.revert(&[], |entry| entry.method == HOST_METHOD);
+    }
+
+    fn revert(self: &Rc<Self>, connectors: &[Box<dyn Connector>], selected: impl Fn(&FootprintEntry) -> bool) {
+        let _active = self.activate();
+        // Cleanup must run even when the time budget of the target is spent.
+        beyond_budget(|| {
+            for entry in self.pending().into_iter().rev().filter(|entry| selected(entry)) {
+                // Reverting a footprint may revert an earlier one as well, e.g. removing a VSS link releases its grant.
+                if !self.is_pending(&entry.footprint) {
+                    continue;
+                }
+                let footprint = &entry.footprint;
+                info!("Removing {} {} left by {}", footprint.kind(), footprint.subject(), entry.method);
+                let connector = connectors.iter().find(|connector| connector.connect_method_name() == entry.method);
+                let result = match (footprint, connector) {
+                    (Footprint::Share(name), _) => remove_host_share(name, entry.detail.as_deref().map(Path::new))
+                        .map(|_| footprint_removed(footprint)),
+                    (_, None) => Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Method {} is not available", entry.method),
+                    )),
+                    (Footprint::RemoteFile(path), Some(connector)) => delete_remote_file(connector.as_ref(), path)
+                        .map(|_| footprint_removed(footprint)),
+                    // These mark the footprint removed themselves once the command succeeded.
+                    (Footprint::VssLink(path), Some(connector)) => {
+                        delete_shadow_copy(connector.as_ref(), path);
+                        self.check_removed(footprint)
+                    }
+                    (Footprint::Grant(path), Some(connector)) => {
+                        connector.release_perms(path);
+                        self.check_removed(footprint)
+                    }
+                    (Footprint::Snapshot(id), Some(connector)) => {
+                        delete_shadow_snapshot(connector.as_ref(), id);
+                        self.check_removed(footprint)
+                    }
+                    (Footprint::Service(name), Some(connector)) => delete_service(connector.as_ref(), name)
+                        .map(|_| footprint_removed(footprint)),
+                };
+                if let Err(err) = result {
+                    warn!("Cannot remove {} {} due to {}", footprint.kind(), footprint.subject(), err);
+                }
+            }
+        });
+    }
+
+    /// Report of all footprints the ledger knows of, including those of previous runs.
+    pub fn report<'a>(&self, computer: &'a Computer) -> FootprintReport<'a> {
+        FootprintReport {
+            computer,
+            entries: self.entries.borrow().clone(),
+        }
+    }
+}
+
+/// Deletes the file and checks that it is gone, `del` e.g. succeeds even when the file is locked.
+fn delete_remote_file(connector: &dyn Connector, path: &Path) -> io::Result<()> {
+    connector.copier().delete_remote_file(path)?;
+    match connector.remote_file_size(path)? {
+        None => Ok(()),
+        Some(_) => Err(io::Error::new(io::ErrorKind::Other, format!("{} still exists", path.display()))),
+    }
+}
+
+/// Removes a service left by PsExec, e.g. after its client was killed on a timeout.
+/// The name of a PAExec service holds the process id of its client, e.g. `PAExec-*-HOST`,
+/// so the services matching it are looked up on the target.
+fn delete_service(connector: &dyn Connector, name: &str) -> io::Result<()> {
+    let names = if name.contains('*') {
+        find_services(connector, name)?
+    } else {
+        vec![name.to_string()]
+    };
+    for name in names.iter() {
+        let command = Command::new(
+            vec!["cmd.exe".to_string(), "/c".to_string(), "sc.exe".to_string(), "delete".to_string(), name.to_string()],
+            None,
+            "",
+            true,
+        );
+        let outcome = connector.connect_and_run_command(command, Some(Duration::from_secs(20)))?;
+        // The service may have been removed in the meantime by PsExec itself.
+        if outcome.is_failure() && outcome.exit_status != Some(SERVICE_DOES_NOT_EXIST) {
+            return Err(io::Error::new(outcome.error_kind(), format!("sc.exe delete {} failed with {}", name, outcome)));
+        }
+    }
+    Ok(())
+}
+
+/// Exit status of `sc.exe` for a service that does not exist.
+const SERVICE_DOES_NOT_EXIST: i32 = 1060;
+
+/// Names of the services on the target matching the `pattern` with `*` wildcards.
+fn find_services(connector: &dyn Connector, pattern: &str) -> io::Result<Vec<String>> {
+    let store_directory = std::env::temp_dir();
+    let prefix = format!("--TEMP_SERVICES_{}", Uuid::new_v4());
+    let command = Command::new(
+        vec![
+            "cmd.exe".to_string(),
+            "/c".to_string(),
+            "sc.exe".to_string(),
+            "query".to_string(),
+            "type=".to_string(),
+            "service".to_string(),
+            "state=".to_string(),
+            "all".to_string(),
+        ],
+        Some(&store_directory),
+        &prefix,
+        true,
+    );
+    let outcome = connector.connect_and_run_command(command, Some(Duration::from_secs(20)))?;
+    if let Some(stderr) = &outcome.stderr {
+        let _ = fs::remove_file(stderr);
+    }
+    let output = match &outcome.stdout {
+        Some(stdout) => {
+            let output = fs::read(stdout).map(|bytes| String::from_utf8_lossy(&bytes).to_string());
+            let _ = fs::remove_file(stdout);
+            output?
+        }
+        None => return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} does not return the services of the target", connector.connect_method_name()),
+        )),
+    };
+    if outcome.is_failure() {
+        return Err(io::Error::new(outcome.error_kind(), format!("sc.exe query failed with {}", outcome)));
+    }
+    Ok(parse_service_names(&output, pattern))
+}
+
+/// Reads the names matching the `pattern` from the `SERVICE_NAME: ...` lines of `sc.exe query`.
+fn parse_service_names(output: &str, pattern: &str) -> Vec<String> {
+    let pattern = WildMatch::new(&pattern.to_ascii_lowercase());
+    output.lines()
+        .filter_map(|line| line.trim().strip_prefix("SERVICE_NAME:"))
+        .map(|name| name.trim().to_string())
+        .filter(|name| pattern.matches(&name.to_ascii_lowercase()))
+        .collect()
+}
+
+/// Records the footprint made by the method in the ledger of the current target, if there is one.
+pub fn record_footprint(method: &str, footprint: Footprint) {
+    CURRENT_LEDGER.with(|it| if let Some(ledger) = it.borrow().as_ref() {
+        ledger.record(method, footprint, None)
+    })
+}
+
+/// Records the footprint together with a detail about it.
+pub fn record_footprint_with_detail(method: &str, footprint: Footprint, detail: &str) {
+    CURRENT_LEDGER.with(|it| if let Some(ledger) = it.borrow().as_ref() {
+        ledger.record(method, footprint, Some(detail.to_string()))
+    })
+}
+
+/// Records the program uploaded from `source` to `path` on the target with the SHA-256 hash of the program.
+pub fn record_upload(method: &str, path: &Path, source: &Path) {
+    CURRENT_LEDGER.with(|it| if let Some(ledger) = it.borrow().as_ref() {
+        let detail = match sha256_of_file(source) {
+            Ok(hash) => Some(format!("sha256 {}", hash)),
+            Err(err) => {
+                warn!("Cannot hash {} due to {}", source.display(), err);
+                None
+            }
+        };
+        ledger.record(method, Footprint::RemoteFile(path.to_path_buf()), detail)
+    })
+}
+
+fn sha256_of_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Marks the footprint reverted in the ledger of the current target.
+pub fn footprint_removed(footprint: &Footprint) {
+    CURRENT_LEDGER.with(|it| if let Some(ledger) = it.borrow().as_ref() {
+        ledger.mark_removed(footprint)
+    })
+}
+
+fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+fn from_unix_millis(millis: &str) -> Option<SystemTime> {
+    millis.parse::<u64>().ok().map(|millis| UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+/// Time in UTC like `2026-10-18 09:12:03 UTC`.
+fn format_utc(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, time_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+    // Civil date of the days since 1970-01-01 by the algorithm of Howard Hinnant.
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, time_of_day / 3600, time_of_day % 3600 / 60, time_of_day % 60,
+    )
+}
+
+/// One tab separated line: created, removed or `-`, method, kind, detail or `-` and subject.
+/// Marks the latest pending entry of the footprint removed, tells whether there was one.
+fn mark_removed_in(entries: &mut [FootprintEntry], footprint: &Footprint, removed: SystemTime) -> bool {
+    entries.iter_mut()
+        .rev()
+        .find(|entry| entry.removed.is_none() && &entry.footprint == footprint)
+        .map(|entry| entry.removed = Some(removed))
+        .is_some()
+}
+
+/// Event of a footprint made: `made`, time, method, kind, detail and subject separated by tabs.
+fn format_made(entry: &FootprintEntry) -> String {
+    format!(
+        "made\t{}\t{}\t{}\t{}\t{}\n",
+        unix_millis(entry.created),
+        entry.method,
+        entry.footprint.kind(),
+        entry.detail.as_deref().unwrap_or("-"),
+        entry.footprint.subject(),
+    )
+}
+
+/// Event of a footprint removed: `removed`, time, kind and subject separated by tabs.
+fn format_removed(footprint: &Footprint, removed: SystemTime) -> String {
+    format!("removed\t{}\t{}\t{}\n", unix_millis(removed), footprint.kind(), footprint.subject())
+}
+
+/// Replays one event of the ledger file onto the entries, unknown or incomplete lines are skipped.
+fn apply_event(entries: &mut Vec<FootprintEntry>, line: &str) {
+    let applied = match line.split('\t').next() {
+        Some("made") => parse_made(line).map(|entry| entries.push(entry)),
+        Some("removed") => parse_removed(line).map(|(footprint, removed)| {
+            mark_removed_in(entries, &footprint, removed);
+        }),
+        _ => None,
+    };
+    if applied.is_none() {
+        warn!("Skipping invalid footprint ledger line {}", line);
+    }
+}
+
+fn parse_made(line: &str) -> Option<FootprintEntry> {
+    let mut fields = line.splitn(6, '\t').skip(1);
+    let created = from_unix_millis(fields.next()?)?;
+    let method = fields.next()?.to_string();
+    let kind = fields.next()?;
+    let detail = match fields.next()? {
+        "-" => None,
+        detail => Some(detail.to_string()),
+    };
+    let footprint = Footprint::parse(kind, fields.next()?)?;
+    Some(FootprintEntry { footprint, method, detail, created, removed: None })
+}
+
+fn parse_removed(line: &str) -> Option<(Footprint, SystemTime)> {
+    let mut fields = line.splitn(4, '\t').skip(1);
+    let removed = from_unix_millis(fields.next()?)?;
+    let kind = fields.next()?;
+    let footprint = Footprint::parse(kind, fields.next()?)?;
+    Some((footprint, removed))
+}
+
+/// Every change made on one target, and on this host for it, documented in the case output.
+pub struct FootprintReport<'a> {
+    computer: &'a Computer,
+    entries: Vec<FootprintEntry>,
+}
+
+impl FootprintReport<'_> {
+    pub fn store(&self, store_directory: &Path) -> io::Result<PathBuf> {
+        let path = create_report_path(self.computer, store_directory, "report", "FOOTPRINT", "txt");
+        fs::write(&path, self.to_string())?;
+        Ok(path)
+    }
+
+    fn section(&self, f: &mut fmt::Formatter<'_>, title: &str, belongs: fn(&FootprintEntry) -> bool) -> fmt::Result {
+        writeln!(f, "  {}:", title)?;
+        let entries = self.entries.iter().filter(|entry| belongs(entry)).collect::<Vec<&FootprintEntry>>();
+        if entries.is_empty() {
+            return writeln!(f, "    none");
+        }
+        for entry in entries {
+            writeln!(f, "    {}", describe(entry))?;
+        }
+        Ok(())
+    }
+
+    /// PsExec installs its service for every command, so the services are summed up by their name.
+    fn services(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  services created:")?;
+        let mut services = Vec::<(&str, &str, Vec<&FootprintEntry>)>::new();
+        for entry in self.entries.iter() {
+            if let Footprint::Service(name) = &entry.footprint {
+                match services.iter_mut().find(|(service, method, _)| service == name && *method == entry.method) {
+                    Some((_, _, entries)) => entries.push(entry),
+                    None => services.push((name, &entry.method, vec![entry])),
+                }
+            }
+        }
+        if services.is_empty() {
+            return writeln!(f, "    none");
+        }
+        for (name, method, entries) in services {
+            let present = entries.iter().filter(|entry| entry.removed.is_none()).count();
+            let last = entries.iter().filter_map(|entry| entry.removed).max();
+            write!(f, "    {} by {}: installed {} times from {}", name, method, entries.len(), format_utc(entries[0].created))?;
+            if let Some(last) = last {
+                write!(f, ", last removed {}", format_utc(last))?;
+            }
+            if present > 0 {
+                write!(f, ", {} still present", present)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_upload(entry: &FootprintEntry) -> bool {
+    matches!(entry.footprint, Footprint::RemoteFile(_)) && entry.detail.as_deref().map(|detail| detail.starts_with("sha256 ")).unwrap_or(false)
+}
+
+fn describe(entry: &FootprintEntry) -> String {
+    let removal = match entry.removed {
+        Some(removed) => format!("removed {}", format_utc(removed)),
+        None => "still present".to_string(),
+    };
+    let created = format_utc(entry.created);
+    match (&entry.footprint, &entry.detail) {
+        (Footprint::Grant(path), account) => format!(
+            "{}: full control granted to {} by {} {}, {}",
+            path.display(),
+            account.as_deref().unwrap_or("the user"),
+            entry.method,
+            created,
+            match entry.removed {
+                Some(removed) => format!("ACL restored {}", format_utc(removed)),
+                None => "still granted".to_string(),
+            },
+        ),
+        (footprint, Some(detail)) if is_upload(entry) => format!("{} ({}) by {}: uploaded {}, {}", footprint.subject(), detail, entry.method, created, removal),
+        (footprint, Some(detail)) => format!("{} ({}) by {}: created {}, {}", footprint.subject(), detail, entry.method, created, removal),
+        (footprint, None) => format!("{} by {}: created {}, {}", footprint.subject(), entry.method, created, removal),
+    }
+}
+
+impl fmt::Display for FootprintReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Footprint of {} with user {}", self.computer.address, self.computer.domain_username())?;
+        self.section(f, "binaries uploaded", is_upload)?;
+        self.services(f)?;
+        self.section(f, "shares created on this host", |entry| matches!(entry.footprint, Footprint::Share(_)))?;
+        self.section(f, "VSS snapshots created", |entry| matches!(entry.footprint, Footprint::Snapshot(_)))?;
+        self.section(f, "VSS links created", |entry| matches!(entry.footprint, Footprint::VssLink(_)))?;
+        self.section(f, "ACL changes", |entry| matches!(entry.footprint, Footprint::Grant(_)))?;
+        self.section(f, "temporary files", |entry| matches!(entry.footprint, Footprint::RemoteFile(_)) && !is_upload(entry))?;
+        let left = self.entries.iter().filter(|entry| entry.removed.is_none()).count();
+        writeln!(f, "  left behind: {}", left)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use uuid::Uuid;
+    use crate::remote::test_computer;
+
+    #[test]
+    fn persists_footprints_and_their_removal() {
+        let store_directory = temp_dir().join(format!("gargamel-ledger-{}", Uuid::new_v4()));
+        fs::create_dir_all(&store_directory).unwrap();
+        let computer = test_computer();
+        let program = Footprint::RemoteFile(PathBuf::from("C:\\Users\\Public\\winpmem.exe"));
+        {
+            let ledger = FootprintLedger::open(&store_directory, &computer).unwrap();
+            let _active = ledger.activate();
+            record_footprint_with_detail("PSEXEC", Footprint::Grant(PathBuf::from("C:\\Users\\Public\\vss")), "CORP\\admin");
+            record_footprint("PSEXEC", program.clone());
+            record_footprint(HOST_METHOD, Footprint::Share("GargamelShare".to_string()));
+            footprint_removed(&program);
+        }
+        // Nothing is recorded without an active ledger.
+        record_footprint("WMI", Footprint::RemoteFile(PathBuf::from("C:\\x")));
+
+        // Each change is appended as one line instead of rewriting the ledger.
+        let ledger_path = FootprintLedger::path(&store_directory, &computer);
+        assert_eq!(fs::read_to_string(&ledger_path).unwrap().lines().count(), 4);
+        let ledger = FootprintLedger::open(&store_directory, &computer).unwrap();
+        let pending = ledger.pending();
+        assert_eq!(ledger.entries.borrow().len(), 3);
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].footprint, Footprint::Grant(PathBuf::from("C:\\Users\\Public\\vss")));
+        assert_eq!(pending[0].detail.as_deref(), Some("CORP\\admin"));
+        assert_eq!(pending[1].footprint, Footprint::Share("GargamelShare".to_string()));
+        assert_eq!(ledger.pending_methods(), vec!["PSEXEC".to_string()]);
+        let _ = fs::remove_dir_all(&store_directory);
+    }
+
+    #[test]
+    fn reports_footprints_by_kind() {
+        let computer = test_computer();
+        let at = |seconds: u64| UNIX_EPOCH + Duration::from_secs(seconds);
+        let entry = |footprint: Footprint, detail: Option<&str>, removed: Option<SystemTime>| FootprintEntry {
+            footprint,
+            method: "PSEXEC".to_string(),
+            detail: detail.map(|detail| detail.to_string()),
+            created: at(1700000000),
+            removed,
+        };
+        let report = FootprintReport {
+            computer: &computer,
+            entries: vec![
+                entry(Footprint::Service("PSEXESVC".to_string()), None, Some(at(1700000005))),
+                entry(Footprint::RemoteFile(PathBuf::from("C:\\Users\\Public\\winpmem.exe")), Some("sha256 ab12"), Some(at(1700000060))),
+                entry(Footprint::Service("PSEXESVC".to_string()), None, None),
+                entry(Footprint::RemoteFile(PathBuf::from("C:\\Users\\Public\\mem.aff4")), None, None),
+            ],
+        }.to_string();
+        assert!(report.contains("  binaries uploaded:\n    C:\\Users\\Public\\winpmem.exe (sha256 ab12) by PSEXEC: uploaded 2023-11-14 22:13:20 UTC, removed 2023-11-14 22:14:20 UTC\n"));
+        assert!(report.contains("    PSEXESVC by PSEXEC: installed 2 times from 2023-11-14 22:13:20 UTC, last removed 2023-11-14 22:13:25 UTC, 1 still present\n"));
+        assert!(report.contains("  shares created on this host:\n    none\n"));
+        assert!(report.contains("  temporary files:\n    C:\\Users\\Public\\mem.aff4 by PSEXEC: created 2023-11-14 22:13:20 UTC, still present\n"));
+        assert!(report.ends_with("  left behind: 2\n"));
+        assert_eq!(format_utc(at(951782400)), "2000-02-29 00:00:00 UTC");
+    }
+
+    #[test]
+    fn finds_paexec_services_of_this_host() {
+        let output = "\r\nSERVICE_NAME: PAExec-4312-FORENSIC01\r\nDISPLAY_NAME: PAExec-4312-FORENSIC01\r\n        TYPE               : 10  WIN32_OWN_PROCESS\r\n\r\n\
+            SERVICE_NAME: PAExec-77-OTHER\r\n\r\nSERVICE_NAME: Spooler\r\n";
+        assert_eq!(parse_service_names(output, "PAExec-*-forensic01"), vec!["PAExec-4312-FORENSIC01".to_string()]);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::remote::SSH_PORT;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::{fmt, io};
+use sha2::{Digest, Sha256};
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+
+/// Name of the file in the output directory where host keys trusted on first use are recorded.
+pub const RECORDED_HOSTS_FILE: &str = "ssh_known_hosts";
+
+static RECORDED_HOSTS_LOCK: Mutex<()> = Mutex::new(());
+
+/// What to do with SSH hosts that are neither pinned nor in any known hosts file.
+/// Hosts with a key different from the pinned or known one are always refused.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HostKeyPolicy {
+    /// Refuse unknown hosts.
+    Strict,
+    /// Trust unknown hosts on the first use and record their keys in the output directory.
+    Tofu,
+}
+
+impl FromStr for HostKeyPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "strict" => Ok(HostKeyPolicy::Strict),
+            "tofu" => Ok(HostKeyPolicy::Tofu),
+            _ => Err(format!("Unknown host key policy {}, expected strict or tofu", policy)),
+        }
+    }
+}
+
+impl fmt::Display for HostKeyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostKeyPolicy::Strict => write!(f, "strict"),
+            HostKeyPolicy::Tofu => write!(f, "tofu"),
+        }
+    }
+}
+
+/// Known hosts files and the policy used to verify the SSH host keys.
+#[derive(Clone)]
+pub struct HostKeyVerifier {
+    pub known_hosts: Option<PathBuf>,
+    pub recorded_hosts: PathBuf,
+    pub policy: HostKeyPolicy,
+}
+
+impl HostKeyVerifier {
+    /// Verifies the host key of the handshaken session against the `pinned` fingerprint if there is one,
+    /// otherwise against the known hosts files.
+    pub fn verify(&self, session: &Session, address: &str, port: u16, pinned: Option<&str>) -> io::Result<()> {
+        let key = session.host_key()
+            .map(|(key, _)| key.to_vec())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} sent no host key", address)))?;
+        let fingerprint = fingerprint(&key);
+        if let Some(pinned) = pinned {
+            return if fingerprints_match(pinned, &fingerprint) {
+                debug!("Host key {} of {} matches the pinned fingerprint", fingerprint, address);
+                Ok(())
+            } else {
+                Err(mismatch(address, &fingerprint))
+            };
+        }
+        match self.check_known_hosts(session, address, port, &key) {
+            CheckResult::Match => {
+                debug!("Host key {} of {} is known", fingerprint, address);
+                Ok(())
+            }
+            CheckResult::Mismatch => Err(mismatch(address, &fingerprint)),
+            // A key that could not be checked may be a changed one, so it is never trusted.
+            CheckResult::Failure => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Host key {} of {} cannot be checked against the known hosts, refusing to connect", fingerprint, address),
+            )),
+            CheckResult::NotFound => match self.policy {
+                HostKeyPolicy::Strict => Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("Host key {} of {} is not known, refusing to connect", fingerprint, address),
+                )),
+                HostKeyPolicy::Tofu => {
+                    self.record(address, port, &key)?;
+                    warn!("Trusting host key {} of {} on first use, it is recorded in {}",
+                          fingerprint, address, self.recorded_hosts.display());
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn check_known_hosts(&self, session: &Session, address: &str, port: u16, key: &[u8]) -> CheckResult {
+        let mut known_hosts = match session.known_hosts() {
+            Ok(known_hosts) => known_hosts,
+            Err(err) => {
+                warn!("Cannot check known hosts due to {}", err);
+                return CheckResult::Failure;
+            }
+        };
+        let files = self.known_hosts.iter().map(|it| it.as_path()).chain(Some(self.recorded_hosts.as_path()));
+        for file in files.filter(|file| file.exists()) {
+            if let Err(err) = known_hosts.read_file(file, KnownHostFileKind::OpenSSH) {
+                warn!("Cannot read known hosts from {} due to {}", file.display(), err);
+            }
+        }
+        known_hosts.check_port(address, port, key)
+    }
+
+    fn record(&self, address: &str, port: u16, key: &[u8]) -> io::Result<()> {
+        let _lock = RECORDED_HOSTS_LOCK.lock();
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.recorded_hosts)?;
+        file.write_all(known_hosts_line(address, port, key).as_bytes())
+    }
+}
+
+/// The default known hosts file of the current user.
+pub fn user_known_hosts() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| Path::new(&home).join(".ssh").join("known_hosts"))
+}
+
+/// Fingerprint of the key in the OpenSSH form, e.g. `SHA256:nThbg6kXUpJWGl7E1IGOCspRomTxdCARLviKw6E5SY8`.
+pub fn fingerprint(key: &[u8]) -> String {
+    format!("SHA256:{}", base64::encode_config(Sha256::digest(key), base64::STANDARD_NO_PAD))
+}
+
+/// Compares fingerprints regardless of the `SHA256:` prefix and base64 padding.
+fn fingerprints_match(expected: &str, actual: &str) -> bool {
+    let normalize = |fingerprint: &str| fingerprint.trim()
+        .trim_start_matches("SHA256:")
+        .trim_end_matches('=')
+        .to_string();
+    normalize(expected) == normalize(actual)
+}
+
+fn mismatch(address: &str, fingerprint: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("Host key {} of {} does not match the expected one, refusing to connect (possible man-in-the-middle attack)",
+                fingerprint, address),
+    )
+}
+
+/// Line of an OpenSSH known hosts file, the key type is the first string of the key blob.
+fn known_hosts_line(address: &str, port: u16, key: &[u8]) -> String {
+    let key_type = key.get(..4)
+        .map(|length| u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize)
+        .and_then(|length| key.get(4..4 + length))
+        .map(|key_type| String::from_utf8_lossy(key_type).to_string())
+        .unwrap_or_default();
+    let host = if port == SSH_PORT { address.to_string() } else { format!("[{}]:{}", address, port) };
+    format!("{} {} {}\n", host, key_type, base64::encode(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_blob() -> Vec<u8> {
+        let mut key = vec![0, 0, 0, 11];
+        key.extend_from_slice(b"ssh-ed25519");
+        key.extend_from_slice(&[0, 0, 0, 32]);
+        key.extend_from_slice(&[7u8; 32]);
+        key
+    }
+
+    #[test]
+    fn formats_known_hosts_lines() {
+        let key = key_blob();
+        let line = known_hosts_line("10.0.0.5", 22, &key);
+        assert_eq!(line, format!("10.0.0.5 ssh-ed25519 {}\n", base64::encode(&key)));
+        assert!(known_hosts_line("10.0.0.5", 2222, &key).starts_with("[10.0.0.5]:2222 ssh-ed25519 "));
+    }
+
+    #[test]
+    fn matches_fingerprints_in_openssh_form() {
+        let fingerprint = fingerprint(&key_blob());
+        assert!(fingerprint.starts_with("SHA256:") && !fingerprint.ends_with('='));
+        assert!(fingerprints_match(&fingerprint, &fingerprint));
+        assert!(fingerprints_match(&format!("{}=", fingerprint.trim_start_matches("SHA256:")), &fingerprint));
+        assert!(!fingerprints_match("SHA256:nThbg6kXUpJWGl7E1IGOCspRomTxdCARLviKw6E5SY8", &fingerprint));
+    }
+}
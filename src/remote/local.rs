@@ -1,4 +1,5 @@
-use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, Command, DEFAULT_REMOTE_PATH_STORAGE, Cmd, copy_from_local_wildcards};
+use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, Command, CommandOutcome, DEFAULT_REMOTE_PATH_STORAGE, Cmd, copy_from_local_wildcards, OsInfo, fingerprint_windows, fingerprint_posix, process_running_windows, process_running_posix};
+use crate::host::is_windows_host;
 use std::path::{Path, PathBuf};
 use std::io;
 use std::time::Duration;
@@ -16,6 +17,8 @@ impl Local {
                 username,
                 password: None,
                 domain: None,
+                ssh_jump: None,
+                ssh_host_key: None,
             },
             temp_storage
         }
@@ -43,28 +46,66 @@ impl Connector for Local {
         self.temp_storage.as_path()
     }
 
+    fn fingerprint(&self, store_directory: &Path) -> OsInfo {
+        if is_windows_host() {
+            fingerprint_windows(self, store_directory)
+        } else {
+            fingerprint_posix(self, store_directory)
+        }
+    }
+
+    fn remote_file_size(&self, path: &Path) -> io::Result<Option<u64>> {
+        match std::fs::metadata(path) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn remote_process_running(&self, program: &str) -> io::Result<bool> {
+        if is_windows_host() {
+            process_running_windows(self, program)
+        } else {
+            process_running_posix(self, program)
+        }
+    }
+
+    fn mkdir(&self, path: &Path) {
+        if let Err(err) = std::fs::create_dir_all(path) {
+            error!("{}", err);
+        }
+    }
+
+    fn list_dirs(&self, path: &Path, _store_directory: &Path) -> Vec<String> {
+        debug!("Listing dirs in local path {}", path.display());
+        let result = match path.read_dir() {
+            Ok(dir_entry_iter) => dir_entry_iter
+                .filter_map(|item| item.ok())
+                .filter(|item| item.path().is_dir())
+                .map(|item| item.file_name().to_string_lossy().to_string())
+                .collect(),
+            Err(err) => {
+                error!("Cannot list {} due to {}", path.display(), err);
+                vec![]
+            }
+        };
+        debug!("Local path {} has dirs {:?}", path.display(), result);
+        result
+    }
+
     fn connect_and_run_local_program(
         &self,
         command_to_run: Command<'_>,
         timeout: Option<Duration>
-    ) -> io::Result<Option<PathBuf>> {
+    ) -> io::Result<CommandOutcome> {
         self.connect_and_run_command(command_to_run, timeout)
     }
 
     fn prepare_command(&self,
                        command: Vec<String>,
-                       output_file_path: Option<&str>,
                        _elevated: bool,
     ) -> Vec<String> {
-        match output_file_path {
-            None => command,
-            Some(output_file_path) => {
-                let mut result : Vec<String> = command.into();
-                result.push(">".to_string());
-                result.push(output_file_path.to_string());
-                result
-            }
-        }
+        command
     }
 }
 
@@ -2,6 +2,10 @@ pub mod connector;
 
 pub use self::connector::*;
 
+pub mod credentials;
+
+pub use self::credentials::*;
+
 pub mod local;
 
 pub use self::local::*;
@@ -14,14 +18,64 @@ pub mod wmi;
 
 pub use self::wmi::*;
 
-pub mod psremote;
+pub mod winrm;
+
+pub use self::winrm::*;
+
+mod ntlm;
+
+mod wsman;
+
+pub use self::wsman::*;
+
+pub mod smb;
+
+pub use self::smb::*;
 
-pub use self::psremote::*;
+mod smb2;
+
+pub use self::smb2::SMB_PORT;
+
+pub mod probe;
+
+pub use self::probe::*;
 
 pub mod ssh;
 
 pub use self::ssh::*;
 
+mod proxy_jump;
+
+pub use self::proxy_jump::*;
+
+mod host_key;
+
+pub use self::host_key::*;
+
+mod escalation;
+
+pub use self::escalation::*;
+
+mod fingerprint;
+
+pub use self::fingerprint::*;
+
+mod readiness;
+
+pub use self::readiness::*;
+
+mod deadline;
+
+pub use self::deadline::*;
+
+mod retry;
+
+pub use self::retry::*;
+
+mod footprint;
+
+pub use self::footprint::*;
+
 pub mod rdp;
 
 pub use self::rdp::*;
@@ -46,6 +100,14 @@ pub use self::constants::*;
 
 mod reverse_share_connector;
 
+mod dry_run;
+
+pub use self::dry_run::*;
+
+mod replay;
+
+pub use self::replay::*;
+
 mod shadow_copier;
 
 pub use self::reverse_share_connector::*;
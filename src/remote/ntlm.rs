@@ -0,0 +1,438 @@
+use std::io;
+use std::io::ErrorKind;
+use std::time::{SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac};
+use md4::{Md4, Digest};
+use md5::Md5;
+use rand::RngCore;
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_SIGN: u32 = 0x0000_0010;
+const NEGOTIATE_SEAL: u32 = 0x0000_0020;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+const NEGOTIATE_KEY_EXCH: u32 = 0x4000_0000;
+const NEGOTIATE_56: u32 = 0x8000_0000;
+
+const NEGOTIATE_FLAGS: u32 = NEGOTIATE_UNICODE
+    | REQUEST_TARGET
+    | NEGOTIATE_SIGN
+    | NEGOTIATE_SEAL
+    | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSIONSECURITY
+    | NEGOTIATE_TARGET_INFO
+    | NEGOTIATE_128
+    | NEGOTIATE_KEY_EXCH
+    | NEGOTIATE_56;
+
+const AV_TIMESTAMP: u16 = 7;
+
+/// Seconds between 1601-01-01 (FILETIME epoch) and 1970-01-01.
+const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
+const CLIENT_SIGNING_MAGIC: &[u8] = b"session key to client-to-server signing key magic constant\0";
+const SERVER_SIGNING_MAGIC: &[u8] = b"session key to server-to-client signing key magic constant\0";
+const CLIENT_SEALING_MAGIC: &[u8] = b"session key to client-to-server sealing key magic constant\0";
+const SERVER_SEALING_MAGIC: &[u8] = b"session key to server-to-client sealing key magic constant\0";
+
+/// First message of the NTLM handshake.
+pub fn negotiate_message() -> Vec<u8> {
+    let mut message = Vec::with_capacity(32);
+    message.extend_from_slice(SIGNATURE);
+    message.extend_from_slice(&1u32.to_le_bytes());
+    message.extend_from_slice(&NEGOTIATE_FLAGS.to_le_bytes());
+    // Empty domain and workstation fields.
+    message.extend_from_slice(&[0u8; 16]);
+    message
+}
+
+pub struct ChallengeMessage {
+    pub flags: u32,
+    pub server_challenge: [u8; 8],
+    pub target_info: Vec<u8>,
+}
+
+impl ChallengeMessage {
+    pub fn parse(message: &[u8]) -> io::Result<ChallengeMessage> {
+        if message.len() < 48 || &message[..8] != SIGNATURE || u32_at(message, 8) != 2 {
+            return Err(invalid_data("Not a NTLM challenge message"));
+        }
+        let mut server_challenge = [0u8; 8];
+        server_challenge.copy_from_slice(&message[24..32]);
+        Ok(ChallengeMessage {
+            flags: u32_at(message, 20),
+            server_challenge,
+            target_info: field(message, 40)?.to_vec(),
+        })
+    }
+
+    fn timestamp(&self) -> Option<[u8; 8]> {
+        let mut position = 0;
+        while position + 4 <= self.target_info.len() {
+            let id = u16::from_le_bytes([self.target_info[position], self.target_info[position + 1]]);
+            let length = u16::from_le_bytes([self.target_info[position + 2], self.target_info[position + 3]]) as usize;
+            let value = self.target_info.get(position + 4..position + 4 + length)?;
+            if id == AV_TIMESTAMP && length == 8 {
+                let mut timestamp = [0u8; 8];
+                timestamp.copy_from_slice(value);
+                return Some(timestamp);
+            }
+            if id == 0 {
+                break;
+            }
+            position += 4 + length;
+        }
+        None
+    }
+}
+
+/// Last message of the NTLM handshake together with the negotiated session key.
+pub struct Authentication {
+    pub message: Vec<u8>,
+    pub session_key: [u8; 16],
+    pub flags: u32,
+}
+
+/// Answers the server challenge using NTLMv2.
+pub fn authenticate(
+    username: &str,
+    domain: &str,
+    password: &str,
+    challenge: &ChallengeMessage,
+) -> Authentication {
+    let mut client_challenge = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut client_challenge);
+    let mut random_session_key = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut random_session_key);
+    let workstation = gethostname::gethostname().to_string_lossy().to_uppercase();
+    authenticate_with(
+        username,
+        domain,
+        password,
+        &workstation,
+        challenge,
+        client_challenge,
+        random_session_key,
+        current_filetime(),
+    )
+}
+
+fn authenticate_with(
+    username: &str,
+    domain: &str,
+    password: &str,
+    workstation: &str,
+    challenge: &ChallengeMessage,
+    client_challenge: [u8; 8],
+    random_session_key: [u8; 16],
+    now: [u8; 8],
+) -> Authentication {
+    let response_key = nt_owf_v2(username, domain, password);
+    let server_timestamp = challenge.timestamp();
+
+    let mut temp = vec![1u8, 1, 0, 0, 0, 0, 0, 0];
+    temp.extend_from_slice(&server_timestamp.unwrap_or(now));
+    temp.extend_from_slice(&client_challenge);
+    temp.extend_from_slice(&[0u8; 4]);
+    temp.extend_from_slice(&challenge.target_info);
+    temp.extend_from_slice(&[0u8; 4]);
+
+    let nt_proof = hmac_md5(&response_key, &[&challenge.server_challenge, &temp]);
+    let mut nt_response = nt_proof.to_vec();
+    nt_response.extend_from_slice(&temp);
+
+    // LMv2 must be zeroed when the server sends a timestamp.
+    let lm_response = if server_timestamp.is_some() {
+        vec![0u8; 24]
+    } else {
+        let mut lm_response = hmac_md5(&response_key, &[&challenge.server_challenge, &client_challenge]).to_vec();
+        lm_response.extend_from_slice(&client_challenge);
+        lm_response
+    };
+
+    let key_exchange_key = hmac_md5(&response_key, &[&nt_proof]);
+    let flags = challenge.flags & NEGOTIATE_FLAGS;
+    let (session_key, encrypted_session_key) = if flags & NEGOTIATE_KEY_EXCH != 0 {
+        let mut encrypted = random_session_key;
+        Rc4::new(&key_exchange_key).apply(&mut encrypted);
+        (random_session_key, encrypted.to_vec())
+    } else {
+        (key_exchange_key, vec![])
+    };
+
+    let payload_fields: [Vec<u8>; 6] = [
+        lm_response,
+        nt_response,
+        utf16le(domain),
+        utf16le(username),
+        utf16le(workstation),
+        encrypted_session_key,
+    ];
+    let header_length = 64;
+    let mut header = Vec::with_capacity(header_length);
+    let mut payload = Vec::new();
+    header.extend_from_slice(SIGNATURE);
+    header.extend_from_slice(&3u32.to_le_bytes());
+    for value in payload_fields.iter() {
+        let offset = (header_length + payload.len()) as u32;
+        header.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        header.extend_from_slice(&offset.to_le_bytes());
+        payload.extend_from_slice(value);
+    }
+    header.extend_from_slice(&flags.to_le_bytes());
+    header.extend(payload);
+
+    Authentication {
+        message: header,
+        session_key,
+        flags,
+    }
+}
+
+/// Signing and sealing of messages exchanged after the NTLM handshake.
+pub struct SessionSecurity {
+    client_signing_key: [u8; 16],
+    server_signing_key: [u8; 16],
+    client_sealing: Rc4,
+    server_sealing: Rc4,
+    client_sequence: u32,
+    server_sequence: u32,
+    key_exchange: bool,
+}
+
+impl SessionSecurity {
+    pub fn new(authentication: &Authentication) -> SessionSecurity {
+        let key = &authentication.session_key;
+        let flags = authentication.flags;
+        let sealing_key = if flags & NEGOTIATE_128 != 0 {
+            &key[..]
+        } else if flags & NEGOTIATE_56 != 0 {
+            &key[..7]
+        } else {
+            &key[..5]
+        };
+        SessionSecurity {
+            client_signing_key: md5(&[key, CLIENT_SIGNING_MAGIC]),
+            server_signing_key: md5(&[key, SERVER_SIGNING_MAGIC]),
+            client_sealing: Rc4::new(&md5(&[sealing_key, CLIENT_SEALING_MAGIC])),
+            server_sealing: Rc4::new(&md5(&[sealing_key, SERVER_SEALING_MAGIC])),
+            client_sequence: 0,
+            server_sequence: 0,
+            key_exchange: flags & NEGOTIATE_KEY_EXCH != 0,
+        }
+    }
+
+    /// Encrypts a message sent to the server. Returns the sealed message and its signature.
+    pub fn seal(&mut self, message: &[u8]) -> (Vec<u8>, [u8; 16]) {
+        let mut sealed = message.to_vec();
+        self.client_sealing.apply(&mut sealed);
+        let signature = signature(
+            &self.client_signing_key,
+            &mut self.client_sealing,
+            self.client_sequence,
+            message,
+            self.key_exchange,
+        );
+        self.client_sequence += 1;
+        (sealed, signature)
+    }
+
+    /// Decrypts a message received from the server and verifies its signature.
+    pub fn unseal(&mut self, sealed: &[u8], received_signature: &[u8]) -> io::Result<Vec<u8>> {
+        let mut message = sealed.to_vec();
+        self.server_sealing.apply(&mut message);
+        let expected_signature = signature(
+            &self.server_signing_key,
+            &mut self.server_sealing,
+            self.server_sequence,
+            &message,
+            self.key_exchange,
+        );
+        self.server_sequence += 1;
+        if expected_signature[..] != *received_signature {
+            return Err(invalid_data("NTLM message signature does not match"));
+        }
+        Ok(message)
+    }
+}
+
+fn signature(
+    signing_key: &[u8; 16],
+    sealing: &mut Rc4,
+    sequence: u32,
+    message: &[u8],
+    key_exchange: bool,
+) -> [u8; 16] {
+    let sequence = sequence.to_le_bytes();
+    let mac = hmac_md5(signing_key, &[&sequence, message]);
+    let mut checksum = [0u8; 8];
+    checksum.copy_from_slice(&mac[..8]);
+    if key_exchange {
+        sealing.apply(&mut checksum);
+    }
+    let mut signature = [0u8; 16];
+    signature[..4].copy_from_slice(&1u32.to_le_bytes());
+    signature[4..12].copy_from_slice(&checksum);
+    signature[12..].copy_from_slice(&sequence);
+    signature
+}
+
+struct Rc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    fn new(key: &[u8]) -> Rc4 {
+        let mut state = [0u8; 256];
+        for (index, value) in state.iter_mut().enumerate() {
+            *value = index as u8;
+        }
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+            state.swap(i, j as usize);
+        }
+        Rc4 { state, i: 0, j: 0 }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.state[self.i as usize]);
+            self.state.swap(self.i as usize, self.j as usize);
+            let index = self.state[self.i as usize].wrapping_add(self.state[self.j as usize]);
+            *byte ^= self.state[index as usize];
+        }
+    }
+}
+
+fn nt_owf_v2(username: &str, domain: &str, password: &str) -> [u8; 16] {
+    let nt_hash = Md4::digest(&utf16le(password));
+    hmac_md5(&nt_hash, &[&utf16le(&format!("{}{}", username.to_uppercase(), domain))])
+}
+
+fn hmac_md5(key: &[u8], data: &[&[u8]]) -> [u8; 16] {
+    let mut mac = Hmac::<Md5>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in data {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn md5(data: &[&[u8]]) -> [u8; 16] {
+    let mut hasher = Md5::new();
+    for part in data {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+pub fn utf16le(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(|unit| unit.to_le_bytes().to_vec()).collect()
+}
+
+fn current_filetime() -> [u8; 8] {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let filetime = (since_epoch.as_secs() + FILETIME_UNIX_OFFSET) * 10_000_000
+        + since_epoch.subsec_nanos() as u64 / 100;
+    filetime.to_le_bytes()
+}
+
+fn u32_at(message: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&message[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Reads a variable length field described by a length/offset structure at `descriptor`.
+fn field(message: &[u8], descriptor: usize) -> io::Result<&[u8]> {
+    let length = u16::from_le_bytes([message[descriptor], message[descriptor + 1]]) as usize;
+    let offset = u32_at(message, descriptor + 4) as usize;
+    message.get(offset..offset + length)
+        .ok_or_else(|| invalid_data("NTLM message field out of bounds"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from [MS-NLMP] 4.2.4 (NTLMv2 authentication).
+    const SERVER_CHALLENGE: [u8; 8] = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+    const CLIENT_CHALLENGE: [u8; 8] = [0xaa; 8];
+    const RANDOM_SESSION_KEY: [u8; 16] = [0x55; 16];
+
+    fn challenge() -> ChallengeMessage {
+        let mut target_info = vec![0x02, 0x00, 0x0c, 0x00];
+        target_info.extend(utf16le("Domain"));
+        target_info.extend(&[0x01, 0x00, 0x0c, 0x00]);
+        target_info.extend(utf16le("Server"));
+        target_info.extend(&[0x00, 0x00, 0x00, 0x00]);
+        ChallengeMessage {
+            flags: 0xe28a8233,
+            server_challenge: SERVER_CHALLENGE,
+            target_info,
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn computes_nt_owf_v2() {
+        assert_eq!(hex(&nt_owf_v2("User", "Domain", "Password")), "0c868a403bfd7a93a3001ef22ef02e3f");
+    }
+
+    #[test]
+    fn computes_nt_proof_and_session_key() {
+        let authentication = authenticate_with(
+            "User",
+            "Domain",
+            "Password",
+            "COMPUTER",
+            &challenge(),
+            CLIENT_CHALLENGE,
+            RANDOM_SESSION_KEY,
+            [0u8; 8],
+        );
+        let nt_response = field(&authentication.message, 20).unwrap();
+        assert_eq!(hex(&nt_response[..16]), "68cd0ab851e51c96aabc927bebef6a1c");
+        let encrypted_session_key = field(&authentication.message, 52).unwrap();
+        assert_eq!(hex(encrypted_session_key), "c5dad2544fc9799094ce1ce90bc9d03e");
+        assert_eq!(authentication.session_key, RANDOM_SESSION_KEY);
+    }
+
+    #[test]
+    fn seals_and_unseals_messages() {
+        let authentication = Authentication {
+            message: vec![],
+            session_key: RANDOM_SESSION_KEY,
+            flags: 0xe28a8233,
+        };
+        let mut client = SessionSecurity::new(&authentication);
+        let (sealed, signature) = client.seal(&utf16le("Plaintext"));
+        assert_eq!(hex(&sealed), "54e50165bf1936dc996020c1811b0f06fb5f");
+        assert_eq!(hex(&signature), "010000007fb38ec5c55d497600000000");
+
+        // A server sealing with the swapped keys must be readable by the client.
+        let mut server = SessionSecurity::new(&authentication);
+        std::mem::swap(&mut server.client_signing_key, &mut server.server_signing_key);
+        std::mem::swap(&mut server.client_sealing, &mut server.server_sealing);
+        let (sealed, signature) = server.seal(b"response");
+        assert_eq!(client.unseal(&sealed, &signature).unwrap(), b"response");
+    }
+}
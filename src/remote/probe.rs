@@ -0,0 +1,235 @@
+use crate::remote::{Computer, Connector, Command, WINRM_HTTP_PORT, SSH_PORT};
+use crate::remote::smb2::{Smb2Client, SMB_PORT};
+use crate::process_runner::create_report_path;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+pub const RPC_PORT: u16 = 135;
+pub const RDP_PORT: u16 = 3389;
+
+/// Ports checked on every target together with the service expected behind them.
+pub const PROBED_PORTS: [(u16, &str); 5] = [
+    (SMB_PORT, "SMB"),
+    (RPC_PORT, "RPC"),
+    (WINRM_HTTP_PORT, "WinRM"),
+    (RDP_PORT, "RDP"),
+    (SSH_PORT, "SSH"),
+];
+
+const PORT_TIMEOUT: Duration = Duration::from_secs(3);
+const CHECK_COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What a connection method can do on a target, ordered from the worst to the best.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Unreachable,
+    Denied,
+    Unknown,
+    User,
+    Admin,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Access::Unreachable => "unreachable",
+            Access::Denied => "authentication failed",
+            Access::Unknown => "reachable, access not verified",
+            Access::User => "authenticated without admin rights",
+            Access::Admin => "authenticated with admin rights",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+pub struct ProbeResult {
+    pub method: &'static str,
+    pub access: Access,
+    pub detail: Option<String>,
+}
+
+impl ProbeResult {
+    pub fn new(method: &'static str, access: Access, detail: Option<String>) -> ProbeResult {
+        ProbeResult { method, access, detail }
+    }
+}
+
+pub fn is_port_open(address: &str, port: u16) -> bool {
+    let addresses = match (address, port).to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(err) => {
+            debug!("Cannot resolve {} due to {}", address, err);
+            return false;
+        }
+    };
+    addresses.into_iter().any(|address| TcpStream::connect_timeout(&address, PORT_TIMEOUT).is_ok())
+}
+
+/// Probes a method by running `command` on the target. Exit status 0 means admin rights.
+pub fn probe_with_command<C: Connector + ?Sized>(
+    connector: &C,
+    port: Option<u16>,
+    command: Vec<String>,
+    elevated: bool,
+) -> ProbeResult {
+    let method = connector.connect_method_name();
+    if let Some(port) = port {
+        if !is_port_open(&connector.computer().address, port) {
+            return ProbeResult::new(method, Access::Unreachable, Some(format!("port {} is closed", port)));
+        }
+    }
+    match connector.connect_and_run_command(
+        Command::new(command, None, "", elevated),
+        Some(CHECK_COMMAND_TIMEOUT),
+    ) {
+        Ok(outcome) => match outcome.exit_status {
+            Some(0) => ProbeResult::new(method, Access::Admin, None),
+            Some(_) => ProbeResult::new(method, Access::User, Some(outcome.to_string())),
+            None => ProbeResult::new(method, Access::Unknown, Some(outcome.to_string())),
+        },
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied =>
+            ProbeResult::new(method, Access::Denied, Some(err.to_string())),
+        Err(err) => ProbeResult::new(method, Access::Unknown, Some(err.to_string())),
+    }
+}
+
+/// Probes a method reachable on `port` by logging in over SMB, admin rights mean access to `ADMIN$`.
+/// Other methods than SMB itself are not tried, so their access stays unknown and SMB is only reported.
+pub fn probe_over_smb(method: &'static str, computer: &Computer, port: u16) -> ProbeResult {
+    if !is_port_open(&computer.address, port) {
+        return ProbeResult::new(method, Access::Unreachable, Some(format!("port {} is closed", port)));
+    }
+    if port != SMB_PORT && !is_port_open(&computer.address, SMB_PORT) {
+        return ProbeResult::new(method, Access::Unknown, Some("credentials not verified, SMB port is closed".to_string()));
+    }
+    let (access, detail) = smb_access(computer);
+    if port == SMB_PORT {
+        return ProbeResult::new(method, access, detail);
+    }
+    let checked = match detail {
+        None => format!("credentials checked over SMB only, {}", access),
+        Some(detail) => format!("credentials checked over SMB only, {}: {}", access, detail),
+    };
+    ProbeResult::new(method, Access::Unknown, Some(checked))
+}
+
+fn smb_access(computer: &Computer) -> (Access, Option<String>) {
+    let mut client = match Smb2Client::connect(&computer.address, SMB_PORT) {
+        Ok(client) => client,
+        Err(err) => return (Access::Unknown, Some(err.to_string())),
+    };
+    if let Err(err) = client.login(
+        &computer.username,
+        computer.domain.as_deref().unwrap_or_default(),
+        computer.password.as_deref().unwrap_or_default(),
+    ) {
+        let access = if err.kind() == io::ErrorKind::PermissionDenied { Access::Denied } else { Access::Unknown };
+        return (access, Some(err.to_string()));
+    }
+    let result = match client.tree_connect(&format!("\\\\{}\\ADMIN$", computer.address)) {
+        Ok(tree_id) => {
+            let _ = client.tree_disconnect(tree_id);
+            (Access::Admin, None)
+        }
+        Err(err) => (Access::User, Some(err.to_string())),
+    };
+    let _ = client.logoff();
+    result
+}
+
+/// Open ports and probe results of all connection methods on one target.
+pub struct CapabilityReport {
+    pub computer: Computer,
+    pub open_ports: Vec<(u16, &'static str, bool)>,
+    pub methods: Vec<ProbeResult>,
+}
+
+impl CapabilityReport {
+    pub fn probe(computer: &Computer, connectors: &[Box<dyn Connector>]) -> CapabilityReport {
+        let open_ports = PROBED_PORTS.iter()
+            .map(|(port, service)| (*port, *service, is_port_open(&computer.address, *port)))
+            .collect();
+        let methods = connectors.iter()
+            .map(|connector| {
+                debug!("Probing {} using method {}", computer.address, connector.connect_method_name());
+                connector.probe()
+            })
+            .collect();
+        CapabilityReport {
+            computer: computer.clone(),
+            open_ports,
+            methods,
+        }
+    }
+
+    /// The first method with the best access, methods that cannot authenticate are never chosen.
+    pub fn best_method(&self) -> Option<&ProbeResult> {
+        self.methods.iter()
+            .filter(|result| result.access > Access::Denied)
+            .fold(None, |best: Option<&ProbeResult>, result| match best {
+                Some(best) if best.access >= result.access => Some(best),
+                _ => Some(result),
+            })
+    }
+
+    pub fn store(&self, store_directory: &Path) -> io::Result<PathBuf> {
+        let path = create_report_path(&self.computer, store_directory, "capabilities", "PROBE", "txt");
+        fs::write(&path, self.to_string())?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for CapabilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Capabilities of {} with user {}", self.computer.address, self.computer.domain_username())?;
+        for (port, service, open) in self.open_ports.iter() {
+            writeln!(f, "  port {} ({}): {}", port, service, if *open { "open" } else { "closed" })?;
+        }
+        for result in self.methods.iter() {
+            match &result.detail {
+                None => writeln!(f, "  {}: {}", result.method, result.access)?,
+                Some(detail) => writeln!(f, "  {}: {} ({})", result.method, result.access, detail)?,
+            }
+        }
+        match self.best_method() {
+            None => writeln!(f, "  no usable method"),
+            Some(best) => writeln!(f, "  best method: {}", best.method),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::test_computer;
+
+    fn report(methods: Vec<ProbeResult>) -> CapabilityReport {
+        CapabilityReport {
+            computer: test_computer(),
+            open_ports: vec![],
+            methods,
+        }
+    }
+
+    #[test]
+    fn prefers_admin_access_then_earlier_method() {
+        let report = report(vec![
+            ProbeResult::new("PSEXEC", Access::Denied, None),
+            ProbeResult::new("WINRM", Access::User, None),
+            ProbeResult::new("RDP", Access::Admin, None),
+            ProbeResult::new("WMI", Access::Admin, None),
+        ]);
+        assert_eq!(report.best_method().unwrap().method, "RDP");
+    }
+
+    #[test]
+    fn never_selects_unusable_methods() {
+        let report = report(vec![
+            ProbeResult::new("PSEXEC", Access::Unreachable, None),
+            ProbeResult::new("SSH", Access::Denied, None),
+        ]);
+        assert!(report.best_method().is_none());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::remote::SSH_PORT;
+use std::io::{Read, Write, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use std::{fmt, io};
+use ssh2::{Channel, Session};
+
+/// SSH server the connection to a target goes through, given as `user@host[:port]`.
+/// The user is required, jump hosts never get the credentials of the target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JumpHost {
+    pub username: String,
+    pub address: String,
+    pub port: u16,
+}
+
+impl FromStr for JumpHost {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (username, host) = match spec.rfind('@') {
+            Some(index) => (&spec[..index], &spec[index + 1..]),
+            None => return Err(format!("Missing user in jump host {}, expected user@host[:port]", spec)),
+        };
+        let (address, port) = split_host_port(host)
+            .map_err(|_| format!("Invalid port in jump host {}", spec))?;
+        if address.is_empty() || username.is_empty() {
+            return Err(format!("Invalid jump host {}, expected user@host[:port]", spec));
+        }
+        Ok(JumpHost {
+            username: username.to_string(),
+            address: address.to_string(),
+            port: port.unwrap_or(SSH_PORT),
+        })
+    }
+}
+
+impl fmt::Display for JumpHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@", self.username)?;
+        if self.address.contains(':') {
+            write!(f, "[{}]:{}", self.address, self.port)
+        } else {
+            write!(f, "{}:{}", self.address, self.port)
+        }
+    }
+}
+
+/// Splits `host[:port]` or `[ipv6]:port`, a bare IPv6 address has no port.
+pub fn split_host_port(host: &str) -> Result<(&str, Option<u16>), String> {
+    let (address, port) = if host.starts_with('[') {
+        // IPv6 address with an optional port, e.g. `[fe80::1]:2222`
+        let end = host.find(']').ok_or_else(|| format!("Missing ] in {}", host))?;
+        (&host[1..end], host[end + 1..].strip_prefix(':'))
+    } else if host.matches(':').count() == 1 {
+        let index = host.find(':').unwrap_or_default();
+        (&host[..index], Some(&host[index + 1..]))
+    } else {
+        (host, None)
+    };
+    match port {
+        None => Ok((address, None)),
+        Some(port) => port.parse()
+            .map(|port| (address, Some(port)))
+            .map_err(|_| format!("Invalid port in {}", host)),
+    }
+}
+
+/// Jump hosts chained in order like OpenSSH `ProxyJump`, e.g. `admin@bastion,ops@10.1.0.5:2222`.
+/// `none` stands for a direct connection.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProxyJump {
+    pub hosts: Vec<JumpHost>,
+}
+
+impl FromStr for ProxyJump {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        if spec == "none" {
+            return Ok(ProxyJump::default());
+        }
+        let hosts = spec.split(',')
+            .map(|host| host.trim().parse())
+            .collect::<Result<Vec<JumpHost>, String>>()?;
+        Ok(ProxyJump { hosts })
+    }
+}
+
+impl fmt::Display for ProxyJump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.hosts.is_empty() {
+            return write!(f, "none");
+        }
+        let hosts = self.hosts.iter()
+            .map(|host| host.to_string())
+            .collect::<Vec<String>>();
+        write!(f, "{}", hosts.join(","))
+    }
+}
+
+/// Forwards a local TCP connection through an SSH session to `address:port`, like `ssh -W`.
+/// The next SSH session of the chain is opened over the returned local stream.
+pub struct Tunnel {
+    session: Session,
+    destination: String,
+    stop: Arc<AtomicBool>,
+    forwarder: Option<thread::JoinHandle<()>>,
+}
+
+impl Tunnel {
+    pub fn open(session: Session, address: &str, port: u16) -> io::Result<(Tunnel, TcpStream)> {
+        let destination = format!("{}:{}", address, port);
+        let channel = session.channel_direct_tcpip(address, port, None)?;
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let stream = TcpStream::connect(listener.local_addr()?)?;
+        let local = accept_from(&listener, &stream)?;
+        session.set_blocking(false);
+        let stop = Arc::new(AtomicBool::new(false));
+        let forwarder_stop = stop.clone();
+        let forwarder_destination = destination.clone();
+        let forwarder = thread::spawn(move || {
+            if let Err(err) = forward(local, channel, &forwarder_stop) {
+                debug!("Tunnel to {} closed due to {}", forwarder_destination, err);
+            }
+        });
+        let tunnel = Tunnel {
+            session,
+            destination,
+            stop,
+            forwarder: Some(forwarder),
+        };
+        Ok((tunnel, stream))
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(forwarder) = self.forwarder.take() {
+            let _ = forwarder.join();
+        }
+        self.session.set_blocking(true);
+        if let Err(err) = self.session.disconnect(None, "Gargamel finished", None) {
+            debug!("Cannot disconnect SSH session tunneling to {}: {}", self.destination, err);
+        }
+    }
+}
+
+/// Accepts the connection of `stream`, any other process connecting to the listener is refused.
+fn accept_from(listener: &TcpListener, stream: &TcpStream) -> io::Result<TcpStream> {
+    let expected_peer = stream.local_addr()?;
+    loop {
+        let (local, peer) = listener.accept()?;
+        if peer == expected_peer {
+            return Ok(local);
+        }
+        warn!("Refused unexpected connection from {} to a local SSH tunnel", peer);
+    }
+}
+
+fn is_would_block(err: &io::Error) -> bool {
+    err.kind() == ErrorKind::WouldBlock
+}
+
+/// Copies data both ways until either side closes or the tunnel is stopped.
+/// The session must be non-blocking so that a pending read does not hold it for the writes.
+fn forward(mut local: TcpStream, mut channel: Channel, stop: &AtomicBool) -> io::Result<()> {
+    local.set_nonblocking(true)?;
+    let mut buffer = [0u8; 32 * 1024];
+    let mut to_remote = Vec::<u8>::new();
+    let mut to_local = Vec::<u8>::new();
+    while !stop.load(Ordering::Relaxed) {
+        let mut progressed = false;
+        if to_remote.is_empty() {
+            match local.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => {
+                    to_remote.extend_from_slice(&buffer[..read]);
+                    progressed = true;
+                }
+                Err(err) if is_would_block(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if !to_remote.is_empty() {
+            match channel.write(&to_remote) {
+                Ok(written) => {
+                    to_remote.drain(..written);
+                    progressed = true;
+                }
+                Err(err) if is_would_block(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if to_local.is_empty() {
+            match channel.read(&mut buffer) {
+                Ok(0) if channel.eof() => break,
+                Ok(read) => {
+                    to_local.extend_from_slice(&buffer[..read]);
+                    progressed = read > 0;
+                }
+                Err(err) if is_would_block(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if !to_local.is_empty() {
+            match local.write(&to_local) {
+                Ok(written) => {
+                    to_local.drain(..written);
+                    progressed = true;
+                }
+                Err(err) if is_would_block(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if !progressed {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    let _ = channel.close();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_multi_hop_proxy_jump() {
+        let jump: ProxyJump = "admin@bastion.example.com,ops@10.1.0.5:2222,ops@[fe80::1]:22".parse().unwrap();
+        assert_eq!(jump.hosts, vec![
+            JumpHost { username: "admin".to_string(), address: "bastion.example.com".to_string(), port: 22 },
+            JumpHost { username: "ops".to_string(), address: "10.1.0.5".to_string(), port: 2222 },
+            JumpHost { username: "ops".to_string(), address: "fe80::1".to_string(), port: 22 },
+        ]);
+        assert_eq!(jump.to_string(), "admin@bastion.example.com:22,ops@10.1.0.5:2222,ops@[fe80::1]:22");
+    }
+
+    #[test]
+    fn rejects_invalid_jump_hosts() {
+        assert!("admin@bastion:ssh".parse::<ProxyJump>().is_err());
+        assert!("@bastion".parse::<ProxyJump>().is_err());
+        assert!("bastion".parse::<ProxyJump>().is_err());
+        assert!("admin@bastion,".parse::<ProxyJump>().is_err());
+        assert_eq!("none".parse::<ProxyJump>().unwrap().hosts, vec![]);
+    }
+
+    #[test]
+    fn splits_target_port() {
+        assert_eq!(split_host_port("10.2.0.7:2222"), Ok(("10.2.0.7", Some(2222))));
+        assert_eq!(split_host_port("[fe80::1]:2222"), Ok(("fe80::1", Some(2222))));
+        assert_eq!(split_host_port("fe80::1"), Ok(("fe80::1", None)));
+        assert_eq!(split_host_port("server"), Ok(("server", None)));
+        assert!(split_host_port("server:ssh").is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::remote::{Connector, Computer, Command, RemoteFileCopier, Cmd, WindowsRemoteFileHandler, FileCopier, copy_from_remote_wildcards};
+use crate::remote::{Connector, Computer, Command, CommandOutcome, RemoteFileCopier, Smb, WindowsRemoteFileHandler, FileCopier, copy_from_remote_wildcards, ProbeResult, probe_over_smb, SMB_PORT, PasswordFile, warn_secret_on_command_line, terminate_timed_out_program, run_command_from_host, Footprint, record_footprint, record_upload, footprint_removed, ShareMap};
 use std::time::Duration;
 use std::io::Error;
 use std::path::{PathBuf, Path};
@@ -10,39 +10,64 @@ pub struct PsExec {
     psexec_name: String,
     remote_temp_storage: PathBuf,
     ms_psexec: bool,
+    password_file: PasswordFile,
 }
 
 pub const PSEXEC64_NAME: &str = "PsExec64.exe";
 pub const PSEXEC32_NAME: &str = "PsExec.exe";
 
 impl PsExec {
-    pub fn paexec(computer: Computer, remote_temp_storage: PathBuf, custom_share_folder: Option<String>) -> PsExec {
+    pub fn paexec(computer: Computer, remote_temp_storage: PathBuf, custom_share_folder: Option<ShareMap>) -> PsExec {
         PsExec {
             computer: computer.clone(),
-            copier_impl: WindowsRemoteFileHandler::new(computer, Box::new(Cmd {}), custom_share_folder),
+            copier_impl: WindowsRemoteFileHandler::new(computer.clone(), Box::new(Smb::new(computer.clone())), custom_share_folder),
             psexec_name: "paexec.exe".to_string(),
             remote_temp_storage,
             ms_psexec: false,
+            password_file: PasswordFile::new(&computer),
         }
     }
 
-    pub fn psexec32(computer: Computer, remote_temp_storage: PathBuf, custom_share_folder: Option<String>) -> PsExec {
+    pub fn psexec32(computer: Computer, remote_temp_storage: PathBuf, custom_share_folder: Option<ShareMap>) -> PsExec {
         PsExec {
             computer: computer.clone(),
-            copier_impl: WindowsRemoteFileHandler::new(computer, Box::new(Cmd {}), custom_share_folder),
+            copier_impl: WindowsRemoteFileHandler::new(computer.clone(), Box::new(Smb::new(computer.clone())), custom_share_folder),
             psexec_name: PSEXEC32_NAME.to_string(),
             remote_temp_storage,
             ms_psexec: true,
+            password_file: PasswordFile::new(&computer),
         }
     }
 
-    pub fn psexec64(computer: Computer, remote_temp_storage: PathBuf, custom_share_folder: Option<String>) -> PsExec {
+    pub fn psexec64(computer: Computer, remote_temp_storage: PathBuf, custom_share_folder: Option<ShareMap>) -> PsExec {
         PsExec {
             computer: computer.clone(),
-            copier_impl: WindowsRemoteFileHandler::new(computer, Box::new(Cmd {}), custom_share_folder),
+            copier_impl: WindowsRemoteFileHandler::new(computer.clone(), Box::new(Smb::new(computer.clone())), custom_share_folder),
             psexec_name: PSEXEC64_NAME.to_string(),
             remote_temp_storage,
             ms_psexec: true,
+            password_file: PasswordFile::new(&computer),
+        }
+    }
+}
+
+impl PsExec {
+    /// PAExec reads the password from a file, Microsoft PsExec accepts it only on the command line.
+    fn password_option(&self, password: &str) -> io::Result<Vec<String>> {
+        if self.ms_psexec {
+            warn_secret_on_command_line(&self.psexec_name);
+            return Ok(vec!["-p".to_string(), password.to_string()]);
+        }
+        let path = self.password_file.path()?.unwrap_or_default();
+        Ok(vec!["-p@".to_string(), path.to_string_lossy().to_string()])
+    }
+
+    /// Service installed on the target for every command, PAExec names it after its process id and this host.
+    fn service_name(&self) -> String {
+        if self.ms_psexec {
+            "PSEXESVC".to_string()
+        } else {
+            format!("PAExec-*-{}", gethostname::gethostname().to_string_lossy())
         }
     }
 }
@@ -64,11 +89,16 @@ impl Connector for PsExec {
         self.remote_temp_storage.as_path()
     }
 
+    fn probe(&self) -> ProbeResult {
+        probe_over_smb(self.connect_method_name(), &self.computer, SMB_PORT)
+    }
+
     fn connect_and_run_local_program(&self,
                                      command_to_run: Command<'_>,
                                      timeout: Option<Duration>,
-    ) -> Result<Option<PathBuf>, Error> {
+    ) -> Result<CommandOutcome, Error> {
         let mut command = command_to_run.command;
+        let program = PathBuf::from(&command[0]);
         if self.ms_psexec {
             command.insert(0, "-accepteula".to_string());
         }
@@ -79,12 +109,35 @@ impl Connector for PsExec {
             command,
             ..command_to_run
         };
-        self.connect_and_run_command(command_to_run, timeout)
+        // `-c` copies the program into the system directory of the target, PsExec removes it once the program exits.
+        let program_on_target = PathBuf::from(format!("%SystemRoot%\\{}", program.file_name().unwrap_or_default().to_string_lossy()));
+        record_upload(self.connect_method_name(), &program_on_target, &program);
+        let outcome = self.connect_and_run_command(command_to_run, timeout)?;
+        if outcome.timed_out {
+            terminate_timed_out_program(self, &program);
+        } else {
+            footprint_removed(&Footprint::RemoteFile(program_on_target));
+        }
+        Ok(outcome)
+    }
+
+    fn connect_and_run_command(&self,
+                               command_to_run: Command<'_>,
+                               timeout: Option<Duration>,
+    ) -> Result<CommandOutcome, Error> {
+        let service = Footprint::Service(self.service_name());
+        record_footprint(self.connect_method_name(), service.clone());
+        let result = run_command_from_host(self, command_to_run, timeout);
+        // PsExec removes its service when the command ends, unless its client was killed on a timeout.
+        let timed_out = result.as_ref().map(|outcome| outcome.timed_out).unwrap_or(false);
+        if !timed_out {
+            footprint_removed(&service);
+        }
+        result
     }
 
     fn prepare_command(&self,
                        command: Vec<String>,
-                       output_file_path: Option<&str>,
                        elevated: bool,
     ) -> Vec<String> {
         let remote_computer = self.computer();
@@ -97,21 +150,20 @@ impl Connector for PsExec {
             remote_computer.domain_username(),
         ];
         if let Some(password) = &remote_computer.password {
-            prepared_command.push("-p".to_string());
-            prepared_command.push(password.clone());
+            match self.password_option(password) {
+                Ok(option) => prepared_command.extend(option),
+                Err(err) => {
+                    warn!("Cannot store password of {} in a file due to {}, passing it on the command line", remote_computer.address, err);
+                    prepared_command.push("-p".to_string());
+                    prepared_command.push(password.clone());
+                }
+            }
         }
         if elevated {
             prepared_command.push("-h".to_string());
         }
         prepared_command.extend(command.into_iter());
-        match output_file_path {
-            None => prepared_command,
-            Some(output_file_path) => {
-                prepared_command.push(">".to_string());
-                prepared_command.push(output_file_path.to_string());
-                prepared_command
-            }
-        }
+        prepared_command
     }
 }
 
@@ -129,11 +181,11 @@ impl RemoteFileCopier for PsExec {
     }
 
     fn copy_to_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
-        self.copier_impl.copy_from_remote(source, target)
+        self.copier_impl.copy_to_remote(source, target)
     }
 
     fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
-        self.connect_and_run_command(
+        let outcome = self.connect_and_run_command(
             Command {
                 command: vec![
                     "cmd".to_string(),
@@ -148,7 +200,11 @@ impl RemoteFileCopier for PsExec {
                 elevated: true,
             },
             None,
-        ).map(|_| ())
+        )?;
+        if outcome.is_failure() {
+            return Err(io::Error::new(outcome.error_kind(), format!("Deleting {} failed with {}", target.display(), outcome)));
+        }
+        Ok(())
     }
 
     fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
@@ -1,8 +1,10 @@
-use crate::remote::{Connector, Computer, FileCopier, Command, RemoteFileCopier, copy_from_remote_wildcards};
+use crate::remote::{Connector, Computer, FileCopier, Command, CommandOutcome, RemoteFileCopier, copy_from_remote_wildcards, ProbeResult, probe_over_smb, RDP_PORT, warn_secret_on_command_line, operation_timeout};
 use std::path::{Path, PathBuf};
 use std::io;
-use crate::process_runner::{run_process_blocking, create_report_path};
-use std::time::Duration;
+use crate::process_runner::{run_process_blocking, run_process_blocking_timed, run_process_blocking_maybe_timed, create_report_path};
+use std::time::{Duration, Instant, SystemTime};
+
+const OUTPUT_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Clone)]
 pub struct Rdp {
@@ -28,11 +30,24 @@ impl Connector for Rdp {
         self.remote_temp_storage.as_path()
     }
 
+    fn probe(&self) -> ProbeResult {
+        probe_over_smb(self.connect_method_name(), &self.computer, RDP_PORT)
+    }
+
+    /// SharpRDP does not return the output of commands, so the target cannot be checked.
+    fn remote_file_size(&self, _path: &Path) -> io::Result<Option<u64>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "SharpRDP does not return the output of commands"))
+    }
+
+    fn remote_process_running(&self, _program: &str) -> io::Result<bool> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "SharpRDP does not return the output of commands"))
+    }
+
     fn connect_and_run_command(
         &self,
         remote_connection: Command<'_>,
         timeout: Option<Duration>,
-    ) -> io::Result<Option<PathBuf>> {
+    ) -> io::Result<CommandOutcome> {
         debug!("Trying to run command {:?} on {}",
                remote_connection.command,
                &self.computer().address
@@ -51,27 +66,113 @@ impl Connector for Rdp {
             }
         };
 
-        let processed_command = self.prepare_command(
+        let processed_command = self.prepare_sharp_rdp_command(
             remote_connection.command,
             output_file_path.as_deref(),
             remote_connection.elevated,
         );
 
         let prepared_command = self.prepare_remote_process(processed_command);
-        let result = run_process_blocking(
-            "cmd.exe",
-            &prepared_command,
-        );
-        if let Some(timeout) = timeout {
-            std::thread::sleep(timeout);
+        let timeout = operation_timeout(timeout)?;
+        let started = SystemTime::now();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        match timeout {
+            None => run_process_blocking("cmd.exe", &prepared_command)?,
+            Some(timeout) => match run_process_blocking_timed("cmd.exe", &prepared_command, timeout) {
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    warn!("SharpRDP did not finish in time, the command may still run on {}", self.computer.address);
+                    return Ok(timed_out_outcome(output_file_path, started));
+                }
+                result => result?,
+            },
         }
-        result.map(|_| output_file_path.map(|it| PathBuf::from(it)))
+        // SharpRDP returns once the command is started in the session, its output comes through the redirected drive.
+        let mut timed_out = false;
+        if let Some(deadline) = deadline {
+            match &output_file_path {
+                Some(output_file_path) => timed_out = !wait_for_output(Path::new(output_file_path), deadline),
+                None => std::thread::sleep(deadline.saturating_duration_since(Instant::now())),
+            }
+        }
+        if timed_out {
+            warn!("Command on {} wrote no output in time, it may still run there", self.computer.address);
+        }
+        // SharpRDP does not report the exit status of the command run in the session.
+        Ok(CommandOutcome {
+            exit_status: None,
+            stdout: output_file_path.map(|it| PathBuf::from(it)),
+            stderr: None,
+            started,
+            finished: SystemTime::now(),
+            timed_out,
+        })
     }
 
     fn prepare_command(&self,
                        command: Vec<String>,
-                       output_file_path: Option<&str>,
                        elevated: bool,
+    ) -> Vec<String> {
+        self.prepare_sharp_rdp_command(command, None, elevated)
+    }
+}
+
+/// Waits until the output file of a command is written completely, i.e. it is not empty and its size stopped changing.
+fn wait_for_output(path: &Path, deadline: Instant) -> bool {
+    let mut previous = None;
+    loop {
+        let size = std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+        if size > 0 && previous == Some(size) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        previous = Some(size);
+        std::thread::sleep(OUTPUT_POLL_INTERVAL);
+    }
+}
+
+fn timed_out_outcome(output_file_path: Option<String>, started: SystemTime) -> CommandOutcome {
+    CommandOutcome {
+        exit_status: None,
+        stdout: output_file_path.map(|it| PathBuf::from(it)),
+        stderr: None,
+        started,
+        finished: SystemTime::now(),
+        timed_out: true,
+    }
+}
+
+impl Rdp {
+    fn run_command(&self, command: String) -> io::Result<()> {
+        let mut args = vec![
+            format!("computername={}", &self.computer.address),
+            "exec=cmd".to_string(),
+            "takeover=true".to_string(),
+            "connectdrive=true".to_string(),
+        ];
+        let username = self.computer.domain_username();
+        args.push(format!("username={}", username));
+        if let Some(password) = &self.computer.password {
+            warn_secret_on_command_line("SharpRDP.exe");
+            args.push(format!("password={}", password));
+        }
+        if self.nla {
+            args.push("nla=true".to_string());
+        }
+        args.push(command);
+        run_process_blocking_maybe_timed(
+            "SharpRDP.exe",
+            &args,
+            operation_timeout(None)?,
+        )
+    }
+
+    /// The output of the command is redirected to the file through the drive of this host mapped in the session.
+    fn prepare_sharp_rdp_command(&self,
+                                 command: Vec<String>,
+                                 output_file_path: Option<&str>,
+                                 elevated: bool,
     ) -> Vec<String> {
         let remote_computer = self.remote_computer();
         let program_name = "SharpRDP.exe".to_string();
@@ -89,6 +190,7 @@ impl Connector for Rdp {
         };
         prepared_command.push(format!("username={}", username));
         if let Some(password) = &remote_computer.password {
+            warn_secret_on_command_line("SharpRDP.exe");
             prepared_command.push(format!("password={}", password));
         }
 
@@ -125,30 +227,6 @@ as_remote_path
     }
 }
 
-impl Rdp {
-    fn run_command(&self, command: String) -> io::Result<()> {
-        let mut args = vec![
-            format!("computername={}", &self.computer.address),
-            "exec=cmd".to_string(),
-            "takeover=true".to_string(),
-            "connectdrive=true".to_string(),
-        ];
-        let username = self.computer.domain_username();
-        args.push(format!("username={}", username));
-        if let Some(password) = &self.computer.password {
-            args.push(format!("password={}", password));
-        }
-        if self.nla {
-            args.push("nla=true".to_string());
-        }
-        args.push(command);
-        run_process_blocking(
-            "SharpRDP.exe",
-            &args,
-        )
-    }
-}
-
 impl FileCopier for Rdp {
     fn copy_file(&self, source: &Path, target: &Path) -> io::Result<()> {
         self.run_command(format!(
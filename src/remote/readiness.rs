@@ -0,0 +1,211 @@
+use crate::remote::{Connector, Command, RemoteFileCopier, Footprint, footprint_removed};
+use std::env::temp_dir;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Upper bound of a single wait for the target when `--ready-timeout` is not given.
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Time between two checks of the target when `--ready-interval` is not given.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const CHECK_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+static READY_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+static POLL_INTERVAL: OnceLock<Duration> = OnceLock::new();
+
+/// Sets the upper bound of every wait for the target and the time between its checks,
+/// given by `--ready-timeout` and `--ready-interval`.
+pub fn set_ready_timeout(timeout: Duration, interval: Duration) {
+    let _ = READY_TIMEOUT.set(timeout);
+    let _ = POLL_INTERVAL.set(interval);
+}
+
+/// Waits for files and processes on the target by polling instead of sleeping for fixed times,
+/// so that fast targets finish fast and slow ones get up to the ready timeout.
+/// Methods that cannot check the target wait the fixed time given by the caller instead.
+pub struct Readiness<'a, C: Connector + ?Sized> {
+    connector: &'a C,
+    timeout: Duration,
+    interval: Duration,
+}
+
+impl<'a, C: Connector + ?Sized> Readiness<'a, C> {
+    pub fn new(connector: &'a C) -> Readiness<'a, C> {
+        Readiness {
+            connector,
+            timeout: *READY_TIMEOUT.get().unwrap_or(&DEFAULT_READY_TIMEOUT),
+            interval: *POLL_INTERVAL.get().unwrap_or(&DEFAULT_POLL_INTERVAL),
+        }
+    }
+
+    /// Waits until the file exists and its size stopped changing, i.e. the process writing it is done.
+    pub fn wait_for_complete_file(&self, path: &Path, fallback: Duration) -> bool {
+        self.poll(&path.display().to_string(), fallback, |previous| {
+            let size = self.connector.remote_file_size(path)?;
+            Ok((size.is_some() && size == previous, size))
+        })
+    }
+
+    /// Waits until no process of the program runs on the target, e.g. `7za.exe` started asynchronously by WMI.
+    pub fn wait_for_process_exit(&self, program: &str, fallback: Duration) -> bool {
+        self.poll(program, fallback, |_| {
+            let running = self.connector.remote_process_running(program)?;
+            Ok((!running, None))
+        })
+    }
+
+    /// Waits until the target wrote the marker file to this host once it finished a transfer,
+    /// e.g. through the reverse share with RDP, which returns before its commands finish.
+    pub fn wait_for_local_marker(&self, marker: &Path) -> bool {
+        self.poll(&marker.display().to_string(), Duration::ZERO, |_| Ok((marker.exists(), None)))
+    }
+
+    /// Deletes the file, retrying while it still exists, e.g. a program stays locked until its process exits.
+    pub fn delete_when_released(&self, copier: &dyn RemoteFileCopier, path: &Path, fallback: Duration) -> io::Result<()> {
+        let result = self.delete_when_released_impl(copier, path, fallback);
+        if result.is_ok() {
+            footprint_removed(&Footprint::RemoteFile(path.to_path_buf()));
+        }
+        result
+    }
+
+    fn delete_when_released_impl(&self, copier: &dyn RemoteFileCopier, path: &Path, fallback: Duration) -> io::Result<()> {
+        let started = Instant::now();
+        loop {
+            // Deleting a locked file may still succeed, e.g. `del` only reports it, so the file is checked either way.
+            let result = copier.delete_remote_file(path);
+            match self.connector.remote_file_size(path) {
+                Ok(None) => return Ok(()),
+                Ok(Some(_)) if started.elapsed() < self.timeout => {
+                    match result {
+                        Ok(()) => trace!("{} on {} is still in use", path.display(), self.connector.computer().address),
+                        Err(err) => trace!("{} on {} is still in use: {}", path.display(), self.connector.computer().address, err),
+                    }
+                    thread::sleep(self.interval);
+                }
+                Ok(Some(_)) => return result.and_then(|_| Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("{} is still in use", path.display()),
+                ))),
+                Err(check_err) => {
+                    debug!("Cannot check {} on {} due to {}, waiting {} seconds", path.display(), self.connector.computer().address, check_err, fallback.as_secs());
+                    thread::sleep(fallback);
+                    return copier.delete_remote_file(path);
+                }
+            }
+        }
+    }
+
+    /// Polls until `check` reports ready, `check` gets the size it returned from the previous poll.
+    fn poll<F>(&self, what: &str, fallback: Duration, check: F) -> bool
+        where F: Fn(Option<u64>) -> io::Result<(bool, Option<u64>)> {
+        let started = Instant::now();
+        let mut previous = None;
+        loop {
+            let (ready, size) = match check(previous) {
+                Ok(result) => result,
+                Err(err) => {
+                    debug!("Cannot check {} on {} due to {}, waiting {} seconds", what, self.connector.computer().address, err, fallback.as_secs());
+                    thread::sleep(fallback);
+                    return true;
+                }
+            };
+            if ready {
+                trace!("{} on {} is ready after {} ms", what, self.connector.computer().address, started.elapsed().as_millis());
+                return true;
+            }
+            if started.elapsed() >= self.timeout {
+                warn!("{} on {} is not ready after {} seconds", what, self.connector.computer().address, self.timeout.as_secs());
+                return false;
+            }
+            previous = size;
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+/// Size of a file on a Windows target listed by `dir`, `None` when it does not exist.
+pub fn file_size_windows<C: Connector + ?Sized>(connector: &C, path: &Path) -> io::Result<Option<u64>> {
+    let path = path.to_string_lossy().to_string();
+    let name = path.rsplit('\\').next().unwrap_or_default().to_string();
+    let dir = vec!["cmd.exe".to_string(), "/c".to_string(), "dir".to_string(), "/-C".to_string(), "/A-D".to_string(), path];
+    Ok(run_for_output(connector, dir)?.and_then(|output| parse_dir_size(&output, &name)))
+}
+
+/// Whether a process of the program runs on a Windows target according to `tasklist`.
+pub fn process_running_windows<C: Connector + ?Sized>(connector: &C, program: &str) -> io::Result<bool> {
+    let tasklist = vec![
+        "tasklist".to_string(),
+        "/FI".to_string(),
+        format!("IMAGENAME eq {}", program),
+        "/NH".to_string(),
+    ];
+    Ok(run_for_output(connector, tasklist)?
+        .map(|output| output.lines().any(|line| line.split_whitespace().next()
+            .map(|image| image.eq_ignore_ascii_case(program))
+            .unwrap_or(false)))
+        .unwrap_or(false))
+}
+
+/// Whether a process of the program runs on a POSIX target according to `pgrep`.
+pub fn process_running_posix<C: Connector + ?Sized>(connector: &C, program: &str) -> io::Result<bool> {
+    let pgrep = vec!["pgrep".to_string(), "-x".to_string(), program.to_string()];
+    Ok(run_for_output(connector, pgrep)?.map(|output| !output.trim().is_empty()).unwrap_or(false))
+}
+
+/// Runs an unelevated check and returns its standard output, `None` when the command failed.
+/// Errors mean the target could not be checked at all.
+fn run_for_output<C: Connector + ?Sized>(connector: &C, command: Vec<String>) -> io::Result<Option<String>> {
+    let prefix = format!("--TEMP_READY_{}", Uuid::new_v4());
+    let store_directory = temp_dir();
+    let command = Command::new(command, Some(&store_directory), &prefix, false);
+    let outcome = connector.connect_and_run_command(command, Some(CHECK_COMMAND_TIMEOUT))?;
+    if let Some(stderr) = &outcome.stderr {
+        let _ = fs::remove_file(stderr);
+    }
+    let output = match &outcome.stdout {
+        Some(stdout) => {
+            let output = fs::read(stdout).map(|bytes| String::from_utf8_lossy(&bytes).to_string());
+            let _ = fs::remove_file(stdout);
+            output?
+        }
+        None => return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} does not return the output of commands", connector.connect_method_name()),
+        )),
+    };
+    if outcome.timed_out {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, format!("Check timed out with {}", outcome)));
+    }
+    if outcome.is_failure() {
+        return Ok(None);
+    }
+    Ok(Some(output))
+}
+
+/// Reads the size of the file from `dir /-C` output, its lines end with the size and the name of the file.
+fn parse_dir_size(output: &str, name: &str) -> Option<u64> {
+    output.lines()
+        .map(|line| line.trim())
+        .filter(|line| line.len() > name.len() && line.to_lowercase().ends_with(&name.to_lowercase()))
+        .find_map(|line| line[..line.len() - name.len()].split_whitespace().last()?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_size_from_dir_listing() {
+        let output = " Volume in drive C has no label.\r\n Volume Serial Number is 5A1B-2C3D\r\n\r\n Directory of C:\\Users\\Public\r\n\r\n10/18/2026  09:12 AM           2097152 10-0-0-1_C-Windows__0e4a.7z.001\r\n               1 File(s)        2097152 bytes\r\n";
+        assert_eq!(parse_dir_size(output, "10-0-0-1_C-Windows__0e4a.7z.001"), Some(2097152));
+        assert_eq!(parse_dir_size(output, "memory.aff4"), None);
+        assert_eq!(parse_dir_size("18.10.2026  09:12    1234 Report With Spaces.txt", "report with spaces.txt"), Some(1234));
+    }
+}
@@ -0,0 +1,778 @@
+use crate::remote::{Computer, Connector, Command, CommandOutcome, FileCopier, RemoteFileCopier, ProbeResult, Access, OsInfo, OsFamily};
+use crate::process_runner::ProcessOutput;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+/// File of a fixture directory listing the interactions, one per line.
+pub const INTERACTIONS_FILE: &str = "interactions.txt";
+
+/// One interaction of a session with a target, stored as a tab separated line
+/// `kind method result fields...` where the result is `ok` or `error:<message>`.
+///
+/// Fields by kind:
+/// * `command` and `program`: elevated (0/1), exit status (or `-`), timed out (0/1), arguments...;
+///   the output is stored in `<index>.stdout` and `<index>.stderr`
+/// * `upload`, `download` and `copy`: source, target; downloaded files are stored in the `<index>` directory
+/// * `delete` and `mkdir`: path
+/// * `list`: path, dirs...
+/// * `fingerprint`: family, version, architecture, system drive, locale (empty when unknown)
+/// * `size`: path, size (or `-` when the file does not exist)
+/// * `process`: program, running (0/1)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Interaction {
+    pub kind: String,
+    pub method: String,
+    pub error: Option<String>,
+    pub fields: Vec<String>,
+}
+
+impl Interaction {
+    fn new(kind: &str, method: &str, error: Option<String>, fields: Vec<String>) -> Interaction {
+        Interaction {
+            kind: kind.to_string(),
+            method: method.to_string(),
+            error,
+            fields,
+        }
+    }
+
+    fn to_line(&self) -> String {
+        let result = match &self.error {
+            None => "ok".to_string(),
+            Some(error) => format!("error:{}", error),
+        };
+        [self.kind.clone(), self.method.clone(), result].iter()
+            .chain(self.fields.iter())
+            .map(|field| escape(field))
+            .collect::<Vec<String>>()
+            .join("\t")
+    }
+
+    fn from_line(line: &str) -> Option<Interaction> {
+        let mut fields = line.split('\t').map(unescape);
+        let kind = fields.next()?;
+        let method = fields.next()?;
+        let result = fields.next()?;
+        let error = match result.as_str() {
+            "ok" => None,
+            _ => Some(result.strip_prefix("error:")?.to_string()),
+        };
+        Some(Interaction { kind, method, error, fields: fields.collect() })
+    }
+
+    fn result(&self) -> io::Result<()> {
+        match &self.error {
+            None => Ok(()),
+            Some(error) => Err(io::Error::new(ErrorKind::Other, error.clone())),
+        }
+    }
+}
+
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Replaces UUIDs, which are new in every session (temporary names, archive names), by `{uuid}`.
+/// Path separators are unified, a session recorded on Windows joins remote paths with `\` and one replayed on Linux with `/`.
+fn normalize(field: &str) -> String {
+    let chars = field.replace('/', "\\").chars().collect::<Vec<char>>();
+    let mut result = String::with_capacity(field.len());
+    let mut index = 0;
+    while index < chars.len() {
+        let uuid_length = [36, 32].iter().cloned().find(|length| is_uuid(&chars[index..], *length));
+        match uuid_length {
+            Some(length) => {
+                result.push_str("{uuid}");
+                index += length;
+            }
+            None => {
+                result.push(chars[index]);
+                index += 1;
+            }
+        }
+    }
+    result
+}
+
+fn is_uuid(chars: &[char], length: usize) -> bool {
+    if chars.len() < length || chars.get(length).map(|c| c.is_ascii_hexdigit()).unwrap_or(false) {
+        return false;
+    }
+    chars[..length].iter().enumerate().all(|(index, c)| match (length, index) {
+        (36, 8) | (36, 13) | (36, 18) | (36, 23) => *c == '-',
+        _ => c.is_ascii_hexdigit(),
+    })
+}
+
+fn flag(value: bool) -> String {
+    if value { "1".to_string() } else { "0".to_string() }
+}
+
+/// Last segment of a path of any platform, the Windows paths of a recorded session are single components on Linux.
+fn file_name(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    path.rsplit(|c| c == '\\' || c == '/').next().unwrap_or_default().to_string()
+}
+
+/// Sizes and modification times of the files under `dir`, keyed by their path relative to it.
+fn snapshot(dir: &Path) -> HashMap<PathBuf, (u64, Option<SystemTime>)> {
+    let mut files = HashMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = match fs::read_dir(&current) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => pending.push(path),
+                Ok(metadata) => {
+                    let relative = path.strip_prefix(dir).unwrap_or(&path).to_path_buf();
+                    files.insert(relative, (metadata.len(), metadata.modified().ok()));
+                }
+                Err(_) => {}
+            }
+        }
+    }
+    files
+}
+
+/// Name of a replayed download in the target directory. A file named after the remote source was recorded under
+/// the name the recording host derived from it, it gets the name this host derives, e.g. on Linux the whole
+/// `C:\Users\Public\report.txt` is the file name of a Windows path. Archive names differ only by their UUIDs.
+fn replayed_name(source: &Path, recorded: &Path) -> PathBuf {
+    match source.file_name() {
+        Some(name) if normalize(&recorded.to_string_lossy()) == normalize(&file_name(source)) => PathBuf::from(name),
+        _ => recorded.to_path_buf(),
+    }
+}
+
+fn copy_tree(source: &Path, target: &Path, remote_source: &Path) -> io::Result<()> {
+    for (relative, _) in snapshot(source) {
+        let target_file = target.join(replayed_name(remote_source, &relative));
+        if let Some(parent) = target_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(source.join(&relative), &target_file)?;
+    }
+    Ok(())
+}
+
+/// Fixture directory a session with one target is recorded to by `Recorder`.
+pub struct Recording {
+    directory: PathBuf,
+    count: Cell<usize>,
+}
+
+impl Recording {
+    /// Creates the fixture directory, an existing recording in it is replaced.
+    pub fn create(directory: &Path) -> io::Result<Recording> {
+        fs::create_dir_all(directory)?;
+        File::create(directory.join(INTERACTIONS_FILE))?;
+        Ok(Recording {
+            directory: directory.to_path_buf(),
+            count: Cell::new(0),
+        })
+    }
+
+    /// Number of the next interaction, its blobs are named after it.
+    fn next_blob(&self) -> PathBuf {
+        self.directory.join(format!("{:04}", self.count.get() + 1))
+    }
+
+    /// Appends the interaction right away, so that an interrupted session keeps what was recorded.
+    fn record(&self, interaction: Interaction) {
+        self.count.set(self.count.get() + 1);
+        let appended = OpenOptions::new()
+            .append(true)
+            .open(self.directory.join(INTERACTIONS_FILE))
+            .and_then(|mut file| writeln!(file, "{}", interaction.to_line()));
+        if let Err(err) = appended {
+            error!("Cannot record {} interaction into {} due to {}", interaction.kind, self.directory.display(), err);
+        }
+    }
+}
+
+/// Connector decorator recording command lines, outputs and transferred files of a real session into a fixture.
+pub struct Recorder {
+    connector_impl: Box<dyn Connector>,
+    recording: Rc<Recording>,
+}
+
+impl Recorder {
+    pub fn new(connector_impl: Box<dyn Connector>, recording: Rc<Recording>) -> Recorder {
+        Recorder { connector_impl, recording }
+    }
+
+    fn method(&self) -> &'static str {
+        self.connector_impl.connect_method_name()
+    }
+
+    fn record_command(&self, kind: &str, command: &[String], elevated: bool, outcome: &io::Result<CommandOutcome>) {
+        let blob = self.recording.next_blob();
+        let (error, exit_status, timed_out) = match outcome {
+            Ok(outcome) => {
+                for (output, extension) in [(&outcome.stdout, "stdout"), (&outcome.stderr, "stderr")].iter() {
+                    if let Some(output) = output {
+                        if let Err(err) = fs::copy(output, blob.with_extension(extension)) {
+                            error!("Cannot record {} of {:?} due to {}", extension, command, err);
+                        }
+                    }
+                }
+                (None, outcome.exit_status, outcome.timed_out)
+            }
+            Err(err) => (Some(err.to_string()), None, false),
+        };
+        let fields = vec![
+            flag(elevated),
+            exit_status.map(|status| status.to_string()).unwrap_or_else(|| "-".to_string()),
+            flag(timed_out),
+        ];
+        self.recording.record(Interaction::new(kind, self.method(), error, fields.into_iter().chain(command.iter().cloned()).collect()));
+    }
+
+    fn record_transfer<F>(&self, kind: &str, source: &Path, target: &Path, transfer: F) -> io::Result<()>
+        where F: FnOnce() -> io::Result<()> {
+        let before = if target.is_dir() { Some(snapshot(target)) } else { None };
+        let result = transfer();
+        if let Some(before) = before {
+            let blob = self.recording.next_blob();
+            for (relative, state) in snapshot(target) {
+                if before.get(&relative) == Some(&state) {
+                    continue;
+                }
+                let recorded = blob.join(&relative);
+                let copied = recorded.parent()
+                    .map(|parent| fs::create_dir_all(parent))
+                    .unwrap_or(Ok(()))
+                    .and_then(|_| fs::copy(target.join(&relative), &recorded));
+                if let Err(err) = copied {
+                    error!("Cannot record downloaded {} due to {}", relative.display(), err);
+                }
+            }
+        }
+        let fields = vec![source.to_string_lossy().to_string(), target.to_string_lossy().to_string()];
+        self.recording.record(Interaction::new(kind, self.method(), result.as_ref().err().map(|err| err.to_string()), fields));
+        result
+    }
+
+    fn record_path(&self, kind: &str, path: &Path, result: io::Result<()>) -> io::Result<()> {
+        let error = result.as_ref().err().map(|err| err.to_string());
+        self.recording.record(Interaction::new(kind, self.method(), error, vec![path.to_string_lossy().to_string()]));
+        result
+    }
+}
+
+impl Connector for Recorder {
+    fn connect_method_name(&self) -> &'static str {
+        self.connector_impl.connect_method_name()
+    }
+
+    fn computer(&self) -> &Computer {
+        self.connector_impl.computer()
+    }
+
+    fn copier(&self) -> &dyn RemoteFileCopier {
+        self as &dyn RemoteFileCopier
+    }
+
+    fn remote_temp_storage(&self) -> &Path {
+        self.connector_impl.remote_temp_storage()
+    }
+
+    fn probe(&self) -> ProbeResult {
+        self.connector_impl.probe()
+    }
+
+    fn fingerprint(&self, store_directory: &Path) -> OsInfo {
+        let os = self.connector_impl.fingerprint(store_directory);
+        let fields = vec![
+            format!("{:?}", os.family),
+            os.version.clone().unwrap_or_default(),
+            os.architecture.clone().unwrap_or_default(),
+            os.system_drive.clone().unwrap_or_default(),
+            os.locale.clone().unwrap_or_default(),
+        ];
+        self.recording.record(Interaction::new("fingerprint", self.method(), None, fields));
+        os
+    }
+
+    fn remote_file_size(&self, path: &Path) -> io::Result<Option<u64>> {
+        let size = self.connector_impl.remote_file_size(path);
+        let fields = vec![
+            path.to_string_lossy().to_string(),
+            size.as_ref().ok().cloned().flatten().map(|size| size.to_string()).unwrap_or_else(|| "-".to_string()),
+        ];
+        self.recording.record(Interaction::new("size", self.method(), size.as_ref().err().map(|err| err.to_string()), fields));
+        size
+    }
+
+    fn remote_process_running(&self, program: &str) -> io::Result<bool> {
+        let running = self.connector_impl.remote_process_running(program);
+        let fields = vec![program.to_string(), flag(*running.as_ref().unwrap_or(&false))];
+        self.recording.record(Interaction::new("process", self.method(), running.as_ref().err().map(|err| err.to_string()), fields));
+        running
+    }
+
+    fn mkdir(&self, path: &Path) {
+        self.connector_impl.mkdir(path);
+        let _ = self.record_path("mkdir", path, Ok(()));
+    }
+
+    fn connect_and_run_local_program(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let command = command_to_run.command.clone();
+        let elevated = command_to_run.elevated;
+        let outcome = self.connector_impl.connect_and_run_local_program(command_to_run, timeout);
+        self.record_command("program", &command, elevated, &outcome);
+        outcome
+    }
+
+    fn connect_and_run_command(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let command = command_to_run.command.clone();
+        let elevated = command_to_run.elevated;
+        let outcome = self.connector_impl.connect_and_run_command(command_to_run, timeout);
+        self.record_command("command", &command, elevated, &outcome);
+        outcome
+    }
+
+    fn prepare_command(&self,
+                       command: Vec<String>,
+                       elevated: bool,
+    ) -> Vec<String> {
+        self.connector_impl.prepare_command(command, elevated)
+    }
+
+    fn list_dirs(&self, path: &Path, store_directory: &Path) -> Vec<String> {
+        let dirs = self.connector_impl.list_dirs(path, store_directory);
+        let fields = Some(path.to_string_lossy().to_string()).into_iter().chain(dirs.iter().cloned()).collect();
+        self.recording.record(Interaction::new("list", self.method(), None, fields));
+        dirs
+    }
+}
+
+impl FileCopier for Recorder {
+    fn copy_file(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.record_transfer("copy", source, target, || self.connector_impl.copier().copier_impl().copy_file(source, target))
+    }
+
+    fn delete_file(&self, target: &Path) -> io::Result<()> {
+        let result = self.connector_impl.copier().copier_impl().delete_file(target);
+        self.record_path("delete", target, result)
+    }
+
+    fn method_name(&self) -> &'static str {
+        self.connector_impl.copier().method_name()
+    }
+}
+
+impl RemoteFileCopier for Recorder {
+    fn remote_computer(&self) -> &Computer {
+        self.connector_impl.computer()
+    }
+
+    fn copier_impl(&self) -> &dyn FileCopier {
+        self as &dyn FileCopier
+    }
+
+    fn path_to_remote_form(&self, path: &Path) -> PathBuf {
+        self.connector_impl.copier().path_to_remote_form(path)
+    }
+
+    fn copy_to_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.record_transfer("upload", source, target, || self.connector_impl.copier().copy_to_remote(source, target))
+    }
+
+    fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
+        let result = self.connector_impl.copier().delete_remote_file(target);
+        self.record_path("delete", target, result)
+    }
+
+    fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.record_transfer("download", source, target, || self.connector_impl.copier().copy_from_remote(source, target))
+    }
+}
+
+/// Recorded session served back by `Replayer`, interactions are consumed in the recorded order.
+pub struct Fixture {
+    directory: PathBuf,
+    interactions: Vec<Interaction>,
+    next: Cell<usize>,
+}
+
+impl Fixture {
+    pub fn open(directory: &Path) -> io::Result<Fixture> {
+        let content = fs::read_to_string(directory.join(INTERACTIONS_FILE))?;
+        let interactions = content.lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| Interaction::from_line(line).ok_or_else(|| io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid interaction {} in {}", line, directory.display()),
+            )))
+            .collect::<io::Result<Vec<Interaction>>>()?;
+        Ok(Fixture {
+            directory: directory.to_path_buf(),
+            interactions,
+            next: Cell::new(0),
+        })
+    }
+
+    /// Interactions not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.interactions.len() - self.next.get()
+    }
+
+    /// Takes the next interaction, which must be of the `kind` and `method` and match the `expected` fields
+    /// regardless of UUIDs. Fields not given in `expected` are not compared.
+    fn take(&self, kind: &str, method: &str, expected: &[Option<String>]) -> io::Result<(Interaction, PathBuf)> {
+        let index = self.next.get();
+        let interaction = self.interactions.get(index).ok_or_else(|| io::Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("Replay of {} has no {} {} left", self.directory.display(), method, kind),
+        ))?;
+        let matches = interaction.kind == kind
+            && interaction.method == method
+            && interaction.fields.len() >= expected.len()
+            && expected.iter().zip(interaction.fields.iter()).all(|(expected, recorded)| match expected {
+                None => true,
+                Some(expected) => normalize(expected) == normalize(recorded),
+            });
+        if !matches {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Replay of {} expected {} but got {} {} {:?}",
+                        self.directory.display(), interaction.to_line(), method, kind, expected),
+            ));
+        }
+        self.next.set(index + 1);
+        Ok((interaction.clone(), self.directory.join(format!("{:04}", index + 1))))
+    }
+}
+
+/// Connector decorator serving a recorded session back instead of connecting to the target.
+/// The decorated connector only provides the identity of the method: its name, computer and paths.
+pub struct Replayer {
+    connector_impl: Box<dyn Connector>,
+    fixture: Rc<Fixture>,
+}
+
+impl Replayer {
+    pub fn new(connector_impl: Box<dyn Connector>, fixture: Rc<Fixture>) -> Replayer {
+        Replayer { connector_impl, fixture }
+    }
+
+    fn method(&self) -> &'static str {
+        self.connector_impl.connect_method_name()
+    }
+
+    fn replay_command(&self, kind: &str, command_to_run: Command<'_>, program_by_name: bool) -> io::Result<CommandOutcome> {
+        let mut expected = vec![Some(flag(command_to_run.elevated)), None, None];
+        expected.extend(command_to_run.command.iter().enumerate().map(|(index, arg)| match index {
+            // Local programs are launched from the current directory of the host, which differs between sessions.
+            0 if program_by_name => None,
+            _ => Some(arg.clone()),
+        }));
+        let (interaction, blob) = self.fixture.take(kind, self.method(), &expected)?;
+        if interaction.fields.len() != expected.len() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Replay expected {} but got {:?}", interaction.to_line(), command_to_run.command),
+            ));
+        }
+        if program_by_name && file_name(Path::new(&interaction.fields[3])) != file_name(Path::new(&command_to_run.command[0])) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Replay expected program {} but got {}", interaction.fields[3], command_to_run.command[0]),
+            ));
+        }
+        interaction.result()?;
+        let output = ProcessOutput {
+            exit_status: interaction.fields[1].parse().ok(),
+            stdout: fs::read(blob.with_extension("stdout")).unwrap_or_default(),
+            stderr: fs::read(blob.with_extension("stderr")).unwrap_or_default(),
+            timed_out: interaction.fields[2] == "1",
+        };
+        CommandOutcome::store(
+            self.computer(),
+            self.method(),
+            command_to_run.report_store_directory,
+            command_to_run.report_filename_prefix,
+            output,
+            SystemTime::now(),
+        )
+    }
+
+    fn replay_transfer(&self, kind: &str, source: &Path, target: &Path, compare_target: bool) -> io::Result<()> {
+        let expected = [
+            Some(source.to_string_lossy().to_string()),
+            Some(target.to_string_lossy().to_string()).filter(|_| compare_target),
+        ];
+        let (interaction, blob) = self.fixture.take(kind, self.method(), &expected)?;
+        if blob.is_dir() {
+            copy_tree(&blob, target, source)?;
+        }
+        interaction.result()
+    }
+
+    fn replay_path(&self, kind: &str, path: &Path) -> io::Result<Interaction> {
+        let (interaction, _) = self.fixture.take(kind, self.method(), &[Some(path.to_string_lossy().to_string())])?;
+        interaction.result().map(|_| interaction)
+    }
+}
+
+impl Connector for Replayer {
+    fn connect_method_name(&self) -> &'static str {
+        self.connector_impl.connect_method_name()
+    }
+
+    fn computer(&self) -> &Computer {
+        self.connector_impl.computer()
+    }
+
+    fn copier(&self) -> &dyn RemoteFileCopier {
+        self as &dyn RemoteFileCopier
+    }
+
+    fn remote_temp_storage(&self) -> &Path {
+        self.connector_impl.remote_temp_storage()
+    }
+
+    fn probe(&self) -> ProbeResult {
+        ProbeResult::new(self.method(), Access::Unknown, Some("replay".to_string()))
+    }
+
+    fn fingerprint(&self, _store_directory: &Path) -> OsInfo {
+        let interaction = match self.fixture.take("fingerprint", self.method(), &[]) {
+            Ok((interaction, _)) => interaction,
+            Err(err) => {
+                error!("{}", err);
+                return OsInfo::new(OsFamily::Unknown);
+            }
+        };
+        let field = |index: usize| interaction.fields.get(index).filter(|value| !value.is_empty()).cloned();
+        let family = match field(0).as_deref() {
+            Some("Windows") => OsFamily::Windows,
+            Some("Linux") => OsFamily::Linux,
+            Some("MacOs") => OsFamily::MacOs,
+            _ => OsFamily::Unknown,
+        };
+        OsInfo {
+            family,
+            version: field(1),
+            architecture: field(2),
+            system_drive: field(3),
+            locale: field(4),
+        }
+    }
+
+    fn remote_file_size(&self, path: &Path) -> io::Result<Option<u64>> {
+        let interaction = self.replay_path("size", path)?;
+        Ok(interaction.fields.get(1).and_then(|size| size.parse().ok()))
+    }
+
+    fn remote_process_running(&self, program: &str) -> io::Result<bool> {
+        let (interaction, _) = self.fixture.take("process", self.method(), &[Some(program.to_string())])?;
+        interaction.result()?;
+        Ok(interaction.fields.get(1).map(|running| running == "1").unwrap_or(false))
+    }
+
+    fn mkdir(&self, path: &Path) {
+        if let Err(err) = self.replay_path("mkdir", path) {
+            error!("{}", err);
+        }
+    }
+
+    fn connect_and_run_local_program(
+        &self,
+        command_to_run: Command<'_>,
+        _timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        self.replay_command("program", command_to_run, true)
+    }
+
+    fn connect_and_run_command(
+        &self,
+        command_to_run: Command<'_>,
+        _timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        self.replay_command("command", command_to_run, false)
+    }
+
+    fn prepare_command(&self,
+                       command: Vec<String>,
+                       elevated: bool,
+    ) -> Vec<String> {
+        self.connector_impl.prepare_command(command, elevated)
+    }
+
+    fn list_dirs(&self, path: &Path, _store_directory: &Path) -> Vec<String> {
+        match self.replay_path("list", path) {
+            Ok(interaction) => interaction.fields[1..].to_vec(),
+            Err(err) => {
+                error!("{}", err);
+                vec![]
+            }
+        }
+    }
+}
+
+impl FileCopier for Replayer {
+    fn copy_file(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.replay_transfer("copy", source, target, true)
+    }
+
+    fn delete_file(&self, target: &Path) -> io::Result<()> {
+        self.replay_path("delete", target).map(|_| ())
+    }
+
+    fn method_name(&self) -> &'static str {
+        self.connector_impl.copier().method_name()
+    }
+}
+
+impl RemoteFileCopier for Replayer {
+    fn remote_computer(&self) -> &Computer {
+        self.connector_impl.computer()
+    }
+
+    fn copier_impl(&self) -> &dyn FileCopier {
+        self as &dyn FileCopier
+    }
+
+    fn path_to_remote_form(&self, path: &Path) -> PathBuf {
+        self.connector_impl.copier().path_to_remote_form(path)
+    }
+
+    /// Uploaded files are matched by their name, the local path differs between sessions.
+    fn copy_to_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let (interaction, _) = self.fixture.take("upload", self.method(), &[None, Some(target.to_string_lossy().to_string())])?;
+        if file_name(Path::new(&interaction.fields[0])) != file_name(source) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Replay expected upload of {} but got {}", interaction.fields[0], source.display()),
+            ));
+        }
+        interaction.result()
+    }
+
+    fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
+        self.replay_path("delete", target).map(|_| ())
+    }
+
+    fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        // The local target differs between sessions.
+        self.replay_transfer("download", source, target, false)
+    }
+}
+
+/// Replays `fixtures/<name>` as a PsExec session with 10.0.0.1 storing temporary files in `C:\Users\Public`.
+#[cfg(test)]
+pub fn replay_fixture(name: &str) -> (Replayer, Rc<Fixture>) {
+    let computer = crate::remote::test_computer();
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name);
+    let fixture = Rc::new(Fixture::open(&directory).unwrap());
+    let psexec = crate::remote::PsExec::paexec(computer, PathBuf::from("C:\\Users\\Public"), None);
+    (Replayer::new(Box::new(psexec), fixture.clone()), fixture)
+}
+
+/// Empty directory for the reports and downloads of a replayed session.
+#[cfg(test)]
+pub fn replay_store_directory() -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("gargamel-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_interaction_lines() {
+        let interaction = Interaction::new(
+            "command",
+            "PSEXEC",
+            Some("access\tdenied".to_string()),
+            vec!["1".to_string(), "-".to_string(), "0".to_string(), "echo a\\b\nc".to_string()],
+        );
+        assert_eq!(interaction.to_line(), "command\tPSEXEC\terror:access\\tdenied\t1\t-\t0\techo a\\\\b\\nc");
+        assert_eq!(Interaction::from_line(&interaction.to_line()), Some(interaction));
+    }
+
+    #[test]
+    fn ignores_uuids_when_matching() {
+        assert_eq!(
+            normalize("C:\\Users\\Public\\10-0-0-1_C--Windows__0e4a1c5b2f3d4e6f8a9b0c1d2e3f4a5b.7z"),
+            "C:\\Users\\Public\\10-0-0-1_C--Windows__{uuid}.7z",
+        );
+        assert_eq!(normalize("--TEMP_LIST_DIR_67e55044-10b1-426f-9247-bb680e5fe0c8"), "--TEMP_LIST_DIR_{uuid}");
+        assert_eq!(normalize("deadbeef"), "deadbeef");
+    }
+
+    #[test]
+    fn replays_recorded_local_session() {
+        let session = replay_store_directory();
+        fs::create_dir_all(session.join("evidence").join("logs")).unwrap();
+        fs::write(session.join("evidence").join("report.txt"), "x".repeat(128)).unwrap();
+        let fixture_directory = session.join("fixture");
+        let run = |connector: &dyn Connector, store_directory: &Path| {
+            let command = Command::new(vec!["echo".to_string(), "recorded".to_string()], Some(store_directory), "echo", false);
+            let outcome = connector.connect_and_run_command(command, None).unwrap();
+            let dirs = connector.list_dirs(&session.join("evidence"), store_directory);
+            connector.copier().copy_from_remote(&session.join("evidence").join("report.txt"), store_directory).unwrap();
+            (fs::read_to_string(outcome.stdout.unwrap()).unwrap(), dirs, fs::read(store_directory.join("report.txt")).unwrap())
+        };
+
+        let recorded_store = session.join("recorded");
+        fs::create_dir_all(&recorded_store).unwrap();
+        let recording = Rc::new(Recording::create(&fixture_directory).unwrap());
+        let recorder = Recorder::new(Box::new(crate::remote::Local::new_default("admin".to_string())), recording);
+        let recorded = run(&recorder, &recorded_store);
+
+        // The evidence is gone, so only the fixture can serve it.
+        fs::remove_dir_all(session.join("evidence")).unwrap();
+        let replayed_store = session.join("replayed");
+        fs::create_dir_all(&replayed_store).unwrap();
+        let fixture = Rc::new(Fixture::open(&fixture_directory).unwrap());
+        let replayer = Replayer::new(Box::new(crate::remote::Local::new_default("admin".to_string())), fixture.clone());
+        assert_eq!(run(&replayer, &replayed_store), recorded);
+        assert_eq!(fixture.remaining(), 0);
+        assert!(replayer.connect_and_run_command(Command::new(vec!["hostname".to_string()], None, "", false), None).is_err());
+        let _ = fs::remove_dir_all(&session);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::remote::{Connector, Computer, Command, CommandOutcome, RemoteFileCopier, FileCopier, ProbeResult, OsInfo, operation_timeout};
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+use rand::Rng;
+
+/// Windows network errors of tools like `xcopy` or `net`: bad network path, unexpected network error,
+/// network name deleted, semaphore timeout, network and host unreachable.
+const TRANSIENT_OS_ERRORS: [i32; 6] = [53, 59, 64, 121, 1231, 1232];
+
+/// Windows network errors raised before anything reaches the target: bad network path, network and host unreachable.
+pub const UNREACHABLE_OS_ERRORS: [i32; 3] = [53, 1231, 1232];
+
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// Sets the policy of all retried operations, given by `--retry`.
+pub fn set_retry_policy(policy: RetryPolicy) {
+    let _ = RETRY_POLICY.set(policy);
+}
+
+/// How many times and how long apart operations failing with transient errors are tried.
+/// The delay doubles (by `multiplier`) after each attempt up to `max_delay`
+/// and is spread by up to `jitter` of itself, so that parallel targets do not retry at once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The policy given by `--retry`, the default one without it.
+    /// Connectors are only decorated by `Retrying` with `--retry`.
+    pub fn current() -> RetryPolicy {
+        RETRY_POLICY.get().cloned().unwrap_or_default()
+    }
+
+    /// Delay before the attempt following the given one (counted from 1), without jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        self.delay.mul_f64(factor).min(self.max_delay)
+    }
+
+    fn delay_after(&self, attempt: u32) -> Duration {
+        let backoff = self.backoff(attempt);
+        if self.jitter <= 0.0 {
+            return backoff;
+        }
+        let spread = rand::thread_rng().gen_range(-self.jitter..=self.jitter);
+        backoff.mul_f64(1.0 + spread)
+    }
+
+    /// Runs `operation` until it succeeds, fails with an error that is not transient or the attempts run out.
+    /// No attempt is started once the time budget of the target is spent.
+    pub fn run<T, F>(&self, what: &str, operation: F) -> io::Result<T>
+        where F: FnMut() -> io::Result<T> {
+        self.run_when(what, &is_retryable, operation)
+    }
+
+    /// Like `run`, with the errors worth another attempt told by `retryable`.
+    pub fn run_when<T, F>(&self, what: &str, retryable: &dyn Fn(&io::Error) -> bool, mut operation: F) -> io::Result<T>
+        where F: FnMut() -> io::Result<T> {
+        let mut attempt = 1;
+        loop {
+            let err = match operation() {
+                Ok(result) => return Ok(result),
+                Err(err) => err,
+            };
+            if attempt >= self.attempts || !retryable(&err) || operation_timeout(None).is_err() {
+                return Err(err);
+            }
+            let delay = self.delay_after(attempt);
+            debug!("{} failed due to {} (attempt {} of {}), retrying in {} ms", what, err, attempt, self.attempts, delay.as_millis());
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+impl FromStr for RetryPolicy {
+    type Err = String;
+
+    /// Parses comma separated `attempts=N`, `delay=SECONDS`, `max-delay=SECONDS`, `multiplier=X` and `jitter=FRACTION`
+    /// items or `file:PATH` with the same items one per line, `#` starts a comment. Items not given keep their defaults.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let items = match spec.strip_prefix("file:") {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|err| format!("Cannot read retry policy from {} due to {}", path, err))?
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim().to_string())
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<String>>(),
+            None => spec.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect(),
+        };
+        let mut policy = RetryPolicy::default();
+        for item in items.iter() {
+            let (key, value) = item.split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| format!("Retry policy item {} is not in the form key=value", item))?;
+            let number = value.parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite() && *number >= 0.0)
+                .ok_or_else(|| format!("Value of retry policy item {} is not a non-negative number", item))?;
+            match key {
+                "attempts" if number >= 1.0 => policy.attempts = number as u32,
+                "delay" => policy.delay = Duration::from_secs_f64(number),
+                "max-delay" => policy.max_delay = Duration::from_secs_f64(number),
+                "multiplier" if number >= 1.0 => policy.multiplier = number,
+                "jitter" if number <= 1.0 => policy.jitter = number,
+                "attempts" | "multiplier" => return Err(format!("Retry policy item {} must be at least 1", item)),
+                "jitter" => return Err(format!("Retry policy item {} must be at most 1", item)),
+                _ => return Err(format!("Unknown retry policy item {}, expected attempts, delay, max-delay, multiplier or jitter", key)),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+/// Tells whether the error is transient, i.e. the connection dropped or the target was briefly unreachable.
+/// Missing files, denied access, invalid input and failed commands are not retried.
+/// Only transfers, which can be repeated, are retried on errors that may come after they started.
+pub fn is_retryable(err: &io::Error) -> bool {
+    match err.kind() {
+        ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::NotConnected
+        | ErrorKind::AddrNotAvailable
+        | ErrorKind::BrokenPipe
+        | ErrorKind::UnexpectedEof
+        | ErrorKind::Interrupted
+        | ErrorKind::WouldBlock
+        | ErrorKind::TimedOut => true,
+        _ => err.raw_os_error().map(|code| TRANSIENT_OS_ERRORS.contains(&code)).unwrap_or(false),
+    }
+}
+
+/// Tells whether the error was raised before the command reached the target, i.e. the connection could not be made.
+/// Commands failing later may have run already, e.g. made a shadow copy, and are not run again.
+pub fn failed_before_submission(err: &io::Error) -> bool {
+    match err.kind() {
+        ErrorKind::ConnectionRefused
+        | ErrorKind::NotConnected
+        | ErrorKind::AddrNotAvailable => true,
+        _ => err.raw_os_error().map(|code| UNREACHABLE_OS_ERRORS.contains(&code)).unwrap_or(false),
+    }
+}
+
+/// Connector decorator retrying commands, uploads, downloads and deletes that fail with transient errors.
+/// Local programs are run once, running them again is not safe after they may have started.
+pub struct Retrying {
+    connector_impl: Box<dyn Connector>,
+    policy: RetryPolicy,
+}
+
+impl Retrying {
+    pub fn new(connector_impl: Box<dyn Connector>, policy: RetryPolicy) -> Retrying {
+        Retrying { connector_impl, policy }
+    }
+
+    fn what(&self, operation: &str, path: &Path) -> String {
+        format!("{} of {} on {} using {}", operation, path.display(), self.computer().address, self.connect_method_name())
+    }
+}
+
+impl Connector for Retrying {
+    fn connect_method_name(&self) -> &'static str {
+        self.connector_impl.connect_method_name()
+    }
+
+    fn computer(&self) -> &Computer {
+        self.connector_impl.computer()
+    }
+
+    fn copier(&self) -> &dyn RemoteFileCopier {
+        self as &dyn RemoteFileCopier
+    }
+
+    fn remote_temp_storage(&self) -> &Path {
+        self.connector_impl.remote_temp_storage()
+    }
+
+    fn probe(&self) -> ProbeResult {
+        self.connector_impl.probe()
+    }
+
+    fn fingerprint(&self, store_directory: &Path) -> OsInfo {
+        self.connector_impl.fingerprint(store_directory)
+    }
+
+    fn remote_file_size(&self, path: &Path) -> io::Result<Option<u64>> {
+        self.connector_impl.remote_file_size(path)
+    }
+
+    fn remote_process_running(&self, program: &str) -> io::Result<bool> {
+        self.connector_impl.remote_process_running(program)
+    }
+
+    fn terminate_remote_process(&self, program: &str) -> io::Result<()> {
+        self.connector_impl.terminate_remote_process(program)
+    }
+
+    fn mkdir(&self, path: &Path) {
+        self.connector_impl.mkdir(path)
+    }
+
+    fn connect_and_run_local_program(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        self.connector_impl.connect_and_run_local_program(command_to_run, timeout)
+    }
+
+    /// Only commands that could not be submitted are retried, commands that may have started are not.
+    fn connect_and_run_command(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let what = format!("Command {:?} on {} using {}", command_to_run.command, self.computer().address, self.connect_method_name());
+        self.policy.run_when(&what, &failed_before_submission, || self.connector_impl.connect_and_run_command(
+            Command { command: command_to_run.command.clone(), ..command_to_run },
+            timeout,
+        ))
+    }
+
+    fn prepare_command(&self,
+                       command: Vec<String>,
+                       elevated: bool,
+    ) -> Vec<String> {
+        self.connector_impl.prepare_command(command, elevated)
+    }
+
+    fn list_dirs(&self, path: &Path, store_directory: &Path) -> Vec<String> {
+        self.connector_impl.list_dirs(path, store_directory)
+    }
+
+    fn acquire_perms(&self, path: &Path) {
+        self.connector_impl.acquire_perms(path)
+    }
+
+    fn release_perms(&self, path: &Path) {
+        self.connector_impl.release_perms(path)
+    }
+}
+
+impl FileCopier for Retrying {
+    fn copy_file(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.policy.run(&self.what("Copy", source), || self.connector_impl.copier().copier_impl().copy_file(source, target))
+    }
+
+    fn delete_file(&self, target: &Path) -> io::Result<()> {
+        self.policy.run(&self.what("Delete", target), || self.connector_impl.copier().copier_impl().delete_file(target))
+    }
+
+    fn method_name(&self) -> &'static str {
+        self.connector_impl.copier().copier_impl().method_name()
+    }
+}
+
+impl RemoteFileCopier for Retrying {
+    fn remote_computer(&self) -> &Computer {
+        self.connector_impl.computer()
+    }
+
+    fn copier_impl(&self) -> &dyn FileCopier {
+        self as &dyn FileCopier
+    }
+
+    fn path_to_remote_form(&self, path: &Path) -> PathBuf {
+        self.connector_impl.copier().path_to_remote_form(path)
+    }
+
+    fn copy_to_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.policy.run(&self.what("Upload", source), || self.connector_impl.copier().copy_to_remote(source, target))
+    }
+
+    fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
+        self.policy.run(&self.what("Delete", target), || self.connector_impl.copier().delete_remote_file(target))
+    }
+
+    fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.policy.run(&self.what("Download", source), || self.connector_impl.copier().copy_from_remote(source, target))
+    }
+
+    fn method_name(&self) -> &'static str {
+        self.connector_impl.copier().method_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::ProcessOutput;
+    use crate::remote::client_connection_error;
+    use std::cell::Cell;
+
+    #[test]
+    fn parses_policy_and_backs_off() {
+        let policy: RetryPolicy = "attempts=5, delay=0.5,max-delay=3".parse().unwrap();
+        assert_eq!(policy.attempts, 5);
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(4), Duration::from_secs(3));
+        assert_eq!(policy.jitter, RetryPolicy::default().jitter);
+        assert!("attempts=0".parse::<RetryPolicy>().is_err());
+        assert!("retries=3".parse::<RetryPolicy>().is_err());
+    }
+
+    #[test]
+    fn retries_only_transient_errors() {
+        let policy: RetryPolicy = "attempts=3,delay=0".parse().unwrap();
+        let calls = Cell::new(0);
+        let result = policy.run("download", || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 { Err(io::Error::new(ErrorKind::ConnectionReset, "reset")) } else { Ok(calls.get()) }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        calls.set(0);
+        let result: io::Result<()> = policy.run("download", || {
+            calls.set(calls.get() + 1);
+            Err(io::Error::new(ErrorKind::NotFound, "missing"))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(calls.get(), 1);
+
+        // A command whose connection dropped may have run, only refused connections are tried again.
+        assert!(failed_before_submission(&io::Error::new(ErrorKind::ConnectionRefused, "refused")));
+        assert!(!failed_before_submission(&io::Error::new(ErrorKind::ConnectionReset, "reset")));
+        assert!(!failed_before_submission(&io::Error::new(ErrorKind::TimedOut, "timed out")));
+    }
+
+    #[test]
+    fn retries_clients_that_did_not_reach_the_target() {
+        let output = |exit_status: i32, stderr: &str| ProcessOutput {
+            exit_status: Some(exit_status),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+            timed_out: false,
+        };
+        let unreachable = client_connection_error(&output(53, "Couldn't access 10.0.0.1:\r\nThe network path was not found.\r\n")).unwrap();
+        assert!(failed_before_submission(&unreachable));
+        let host_unreachable = client_connection_error(&output(1232, "")).unwrap();
+        assert!(failed_before_submission(&host_unreachable));
+        let logon_failure = client_connection_error(&output(1326, "The user name or password is incorrect.\r\n")).unwrap();
+        assert_eq!(logon_failure.kind(), ErrorKind::PermissionDenied);
+        assert!(!failed_before_submission(&logon_failure));
+        // The exit status of a command that ran on the target is its outcome.
+        assert!(client_connection_error(&output(1, "")).is_none());
+    }
+}
@@ -1,36 +1,81 @@
-use crate::remote::{Connector, Computer, Command, RemoteFileCopier, FileCopier, GARGAMEL_SHARED_FOLDER_NAME, RemoteCmd};
+use crate::remote::{Readiness, Connector, Computer, Command, CommandOutcome, RemoteFileCopier, FileCopier, GARGAMEL_SHARED_FOLDER_NAME, RemoteCmd, ProbeResult, OsInfo, Footprint, HOST_METHOD, terminate_timed_out_program, beyond_budget, record_footprint_with_detail, record_upload, footprint_removed};
 use std::time::Duration;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
 use std::path::{PathBuf, Path};
-use std::io;
-use crate::process_runner::run_process_blocking;
+use std::{env, fs, io};
+use uuid::Uuid;
+use crate::process_runner::run_process_capturing;
 
 
+/// Lets the target copy files from and to this host through a share of this host.
+/// The share is hidden, randomly named and limited to a staging directory of its own,
+/// which only the accounts the target connects with can use.
 pub struct RevShareConnector {
-    connector_impl: Box<dyn Connector>
+    connector_impl: Box<dyn Connector>,
+    share_name: String,
+    staging_directory: PathBuf,
 }
 
 impl RevShareConnector {
     pub fn new(connector_impl: Box<dyn Connector>) -> RevShareConnector {
-        let result = RevShareConnector { connector_impl };
-        result.open_connection();
+        let name = format!("{}-{}", GARGAMEL_SHARED_FOLDER_NAME, &Uuid::new_v4().to_string().replace("-", "")[..12]);
+        let result = RevShareConnector {
+            connector_impl,
+            share_name: format!("{}$", name),
+            staging_directory: env::temp_dir().join(name),
+        };
+        // Dropping the connector while panicking removes whatever part of the share was made.
+        if let Err(err) = result.open_connection() {
+            panic!("Cannot establish share {} using \"net share\" due to {}", result.share_name, err);
+        }
         result
     }
 
     fn open_connection(
         &self
-    ) {
-        let args = vec![
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.staging_directory)?;
+        let staging_directory = self.staging_directory.to_string_lossy().to_string();
+        let accounts = reverse_share_accounts(self.computer());
+        for account in accounts.iter() {
+            run_on_host("icacls", &[
+                staging_directory.clone(),
+                "/grant".to_string(),
+                format!("{}:(OI)(CI)M", account),
+            ])?;
+        }
+        let mut args = vec![
             "share".to_string(),
-            format!("{}=C:", GARGAMEL_SHARED_FOLDER_NAME),
-            "/GRANT:Everyone,FULL".to_string()
+            format!("{}={}", self.share_name, staging_directory),
         ];
-        run_process_blocking(
-            "NET",
-            &args,
-        ).expect(&format!(
-            "Cannot establish share using \"net share\" for {}=C:", GARGAMEL_SHARED_FOLDER_NAME
-        ));
+        args.extend(accounts.iter().map(|account| format!("/GRANT:{},CHANGE", account)));
+        run_on_host("NET", &args)?;
+        record_footprint_with_detail(HOST_METHOD, Footprint::Share(self.share_name.clone()), &staging_directory);
+        Ok(())
+    }
+
+    /// A new directory in the staging directory for a single transfer.
+    fn transfer_directory(&self) -> io::Result<PathBuf> {
+        let directory = self.staging_directory.join(Uuid::new_v4().to_string().replace("-", ""));
+        fs::create_dir(&directory)?;
+        Ok(directory)
+    }
+
+    /// Copies on the target and waits for the marker the target writes after the copy,
+    /// the staged files must stay until then and the copied ones are complete only then.
+    fn copy_and_wait(&self, source: &Path, target: &Path, transfer: &Path) -> io::Result<()> {
+        let marker = transfer.with_extension("done");
+        let outcome = RemoteCmd::new(self).copy_and_mark(source, target, &self.path_to_remote_form(&marker));
+        let confirmed = outcome.map(|outcome| outcome.has_finished() || Readiness::new(self).wait_for_local_marker(&marker));
+        let _ = fs::remove_file(&marker);
+        match confirmed? {
+            true => Ok(()),
+            false => Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("Copy of {} to {} through share {} was not confirmed", source.display(), target.display(), self.share_name),
+            )),
+        }
     }
 }
 
@@ -65,16 +110,40 @@ impl Connector for RevShareConnector {
         self.connector_impl.remote_temp_storage()
     }
 
+    fn probe(&self) -> ProbeResult {
+        self.connector_impl.probe()
+    }
+
+    fn fingerprint(&self, store_directory: &Path) -> OsInfo {
+        self.connector_impl.fingerprint(store_directory)
+    }
+
+    fn remote_file_size(&self, path: &Path) -> Result<Option<u64>, Error> {
+        self.connector_impl.remote_file_size(path)
+    }
+
+    fn remote_process_running(&self, program: &str) -> Result<bool, Error> {
+        self.connector_impl.remote_process_running(program)
+    }
+
+    fn connect_and_run_command(&self,
+                               command_to_run: Command<'_>,
+                               timeout: Option<Duration>,
+    ) -> Result<CommandOutcome, Error> {
+        self.connector_impl.connect_and_run_command(command_to_run, timeout)
+    }
+
     fn connect_and_run_local_program(&self,
                                      command_to_run: Command<'_>,
                                      timeout: Option<Duration>,
-    ) -> Result<Option<PathBuf>, Error> {
+    ) -> Result<CommandOutcome, Error> {
         let local_program = &command_to_run.command[0];
         let local_program_path = Path::new(local_program);
-        if let Err(err) = self.copy_to_remote(local_program_path, self.remote_temp_storage()){
-            error!("{}", err);
-        }
         let local_program_on_target_path = self.remote_temp_storage().join(local_program_path.file_name().unwrap());
+        match self.copy_to_remote(local_program_path, self.remote_temp_storage()) {
+            Ok(()) => record_upload(self.connect_method_name(), &local_program_on_target_path, local_program_path),
+            Err(err) => error!("{}", err),
+        }
         let mut command = command_to_run.command;
         command[0] = local_program_on_target_path.to_string_lossy().to_string();
 
@@ -83,16 +152,22 @@ impl Connector for RevShareConnector {
             ..command_to_run
         };
         let result = self.connect_and_run_command(command_to_run, timeout);
-        let _ = self.delete_remote_file(&local_program_on_target_path);
+        if let Ok(outcome) = &result {
+            if outcome.timed_out {
+                terminate_timed_out_program(self, &local_program_on_target_path);
+            }
+        }
+        if self.delete_remote_file(&local_program_on_target_path).is_ok() {
+            footprint_removed(&Footprint::RemoteFile(local_program_on_target_path));
+        }
         result
     }
 
     fn prepare_command(&self,
                        command: Vec<String>,
-                       output_file_path: Option<&str>,
                        elevated: bool,
     ) -> Vec<String> {
-        self.connector_impl.prepare_command(command, output_file_path, elevated)
+        self.connector_impl.prepare_command(command, elevated)
     }
 }
 
@@ -105,14 +180,20 @@ impl RemoteFileCopier for RevShareConnector {
         self as &dyn FileCopier
     }
 
+    /// Maps a path in the staging directory to the share, other paths are not shared.
     fn path_to_remote_form(
         &self,
         path: &Path,
     ) -> PathBuf {
+        let relative = path.strip_prefix(&self.staging_directory).unwrap_or_else(|_| {
+            error!("{} is not in the staging directory {}", path.display(), self.staging_directory.display());
+            Path::new("")
+        });
         PathBuf::from(format!(
-            "\\\\{}\\{}",
+            "\\\\{}\\{}\\{}",
             gethostname::gethostname().to_string_lossy(),
-            path.to_str().unwrap().replace("C:", GARGAMEL_SHARED_FOLDER_NAME)
+            self.share_name,
+            relative.to_string_lossy()
         ))
     }
 
@@ -121,7 +202,12 @@ impl RemoteFileCopier for RevShareConnector {
         source: &Path,
         target: &Path,
     ) -> io::Result<()> {
-        self.copier_impl().copy_file(&self.path_to_remote_form(source), target)
+        let transfer = self.transfer_directory()?;
+        let staged = transfer.join(source.file_name().unwrap_or_default());
+        let result = fs::copy(source, &staged)
+            .and_then(|_| self.copy_and_wait(&self.path_to_remote_form(&staged), target, &transfer));
+        let _ = fs::remove_dir_all(&transfer);
+        result
     }
 
     fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
@@ -133,22 +219,135 @@ impl RemoteFileCopier for RevShareConnector {
         source: &Path,
         target: &Path,
     ) -> io::Result<()> {
-        self.copier_impl().copy_file(source, &self.path_to_remote_form(target))
+        let transfer = self.transfer_directory()?;
+        let result = self.copy_and_wait(source, &self.path_to_remote_form(&transfer), &transfer)
+            .and_then(|_| collect_transfer(&transfer, target));
+        let _ = fs::remove_dir_all(&transfer);
+        result
     }
 }
 
 impl Drop for RevShareConnector {
     fn drop(&mut self) {
-        run_process_blocking(
-            "NET",
-            &[
-                "share".to_string(),
-                "/Y".to_string(),
-                "/D".to_string(),
-                GARGAMEL_SHARED_FOLDER_NAME.to_string()
-            ],
-        ).expect(&format!(
-            "Cannot drop connection using \"net share\" to {}", GARGAMEL_SHARED_FOLDER_NAME
-        ));
+        // The share must be removed even when the time budget of the target is spent.
+        match beyond_budget(|| remove_host_share(&self.share_name, Some(&self.staging_directory))) {
+            Ok(()) => footprint_removed(&Footprint::Share(self.share_name.clone())),
+            Err(err) => error!("Cannot drop connection using \"net share\" to {} due to {}", self.share_name, err),
+        }
+    }
+}
+
+/// The accounts the target uses the share with: the supplied user and, as commands may run as SYSTEM,
+/// the computer account of the target, which is only known for a target in a domain given by its name.
+pub fn reverse_share_accounts(computer: &Computer) -> Vec<String> {
+    let mut accounts = vec![computer.domain_username()];
+    if let (Some(domain), Err(_)) = (&computer.domain, computer.address.parse::<IpAddr>()) {
+        let host = computer.address.split('.').next().unwrap_or_default();
+        accounts.push(format!("{}\\{}$", domain, host));
+    }
+    accounts
+}
+
+/// Removes the share of this host created for the target together with its staging directory.
+pub fn remove_host_share(name: &str, staging_directory: Option<&Path>) -> io::Result<()> {
+    run_on_host(
+        "NET",
+        &[
+            "share".to_string(),
+            "/Y".to_string(),
+            "/D".to_string(),
+            name.to_string()
+        ],
+    )?;
+    let staging_directory = match staging_directory {
+        None => return Ok(()),
+        Some(staging_directory) => staging_directory,
+    };
+    // The directory comes from the footprint ledger, so only a staging directory is ever removed.
+    let is_staging = staging_directory.file_name()
+        .map_or(false, |name| name.to_string_lossy().starts_with(GARGAMEL_SHARED_FOLDER_NAME));
+    if !is_staging {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{} is not a staging directory", staging_directory.display())));
+    }
+    match fs::remove_dir_all(staging_directory) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn run_on_host(command_name: &str, args: &[String]) -> io::Result<()> {
+    let output = run_process_capturing(command_name, args, Some(Duration::from_secs(30)))?;
+    match output.exit_status {
+        Some(0) => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::Other,
+            format!("{} failed: {}", command_name, String::from_utf8_lossy(&output.stderr).trim()),
+        )),
+    }
+}
+
+/// Moves what the target copied into the transfer directory to the target directory or file.
+fn collect_transfer(transfer: &Path, target: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(transfer)? {
+        let entry = entry?;
+        let destination = if target.is_dir() { target.join(entry.file_name()) } else { target.to_path_buf() };
+        move_path(&entry.path(), &destination)?;
+    }
+    Ok(())
+}
+
+fn move_path(source: &Path, target: &Path) -> io::Result<()> {
+    if fs::rename(source, target).is_ok() {
+        return Ok(());
+    }
+    // The staging directory may be on another volume, or the target directory may exist already.
+    if source.is_dir() {
+        fs::create_dir_all(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            move_path(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(source, target).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::test_computer;
+
+    fn computer(address: &str, domain: Option<&str>) -> Computer {
+        Computer {
+            address: address.to_string(),
+            domain: domain.map(|domain| domain.to_string()),
+            ..test_computer()
+        }
+    }
+
+    #[test]
+    fn grants_share_to_user_and_computer_account() {
+        assert_eq!(reverse_share_accounts(&computer("192.168.42.47", Some("CORP"))), vec!["CORP\\admin"]);
+        assert_eq!(reverse_share_accounts(&computer("ws042.corp.local", None)), vec!["admin"]);
+        assert_eq!(
+            reverse_share_accounts(&computer("ws042.corp.local", Some("CORP"))),
+            vec!["CORP\\admin", "CORP\\ws042$"]
+        );
+    }
+
+    #[test]
+    fn collects_transfer_into_target_directory() {
+        let root = env::temp_dir().join(format!("gargamel-transfer-{}", Uuid::new_v4()));
+        let transfer = root.join("transfer");
+        let target = root.join("target");
+        fs::create_dir_all(transfer.join("logs")).unwrap();
+        fs::create_dir_all(target.join("logs")).unwrap();
+        fs::write(transfer.join("SAM"), b"regf").unwrap();
+        fs::write(transfer.join("logs").join("System.evtx"), b"ElfFile").unwrap();
+        collect_transfer(&transfer, &target).unwrap();
+        assert_eq!(fs::read(target.join("SAM")).unwrap(), b"regf");
+        assert_eq!(fs::read(target.join("logs").join("System.evtx")).unwrap(), b"ElfFile");
+        let _ = fs::remove_dir_all(&root);
     }
 }
@@ -1,16 +1,80 @@
-use crate::remote::{Connector, Computer, Command, RemoteFileCopier, FileCopier};
+use crate::remote::{Connector, Computer, Command, CommandOutcome, RemoteFileCopier, FileCopier, Footprint, beyond_budget, record_footprint, footprint_removed};
+use std::cell::RefCell;
 use std::path::{Path, PathBuf};
 use std::{io, fs};
+use std::str::FromStr;
 use std::time::Duration;
 use std::io::ErrorKind;
 use uuid::Uuid;
-use rev_lines::RevLines;
 
 
+/// Copies files from shadow copies of their volumes, so that files locked on the live volumes can be read.
+/// The shadow copy of a volume is made when a file of it is first copied and deleted when the copier is dropped.
+/// A copier of an existing shadow copy only links it and copies the files of its volume.
 pub struct ShadowCopier<'a> {
     connector_impl: &'a dyn Connector,
     pub copier_impl: &'a dyn RemoteFileCopier,
-    shadow_drive: PathBuf,
+    local_store_directory: PathBuf,
+    existing: Option<ExistingShadowCopy>,
+    shadow_volumes: RefCell<Vec<ShadowVolume>>,
+}
+
+/// Shadow copy found on the target, e.g. made by Windows before an attacker deleted files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExistingShadowCopy {
+    pub id: String,
+    pub created: String,
+    pub volume: char,
+    pub device: String,
+}
+
+/// Which existing shadow copies the search list is collected from, given by `--shadow-history`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShadowHistory {
+    All,
+    Only(String),
+}
+
+impl ShadowHistory {
+    pub fn selects(&self, shadow_copy: &ExistingShadowCopy) -> bool {
+        match self {
+            ShadowHistory::All => true,
+            ShadowHistory::Only(id) => shadow_copy.id.eq_ignore_ascii_case(id),
+        }
+    }
+}
+
+impl FromStr for ShadowHistory {
+    type Err = String;
+
+    /// Parses `all` or the ID of a shadow copy as listed by `vssadmin list shadows`, with or without braces.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        if spec.eq_ignore_ascii_case("all") {
+            return Ok(ShadowHistory::All);
+        }
+        let id = spec.trim_start_matches('{').trim_end_matches('}');
+        match Uuid::parse_str(id) {
+            Ok(_) => Ok(ShadowHistory::Only(format!("{{{}}}", id))),
+            Err(_) => Err(format!("{} is neither all nor the ID of a shadow copy", spec)),
+        }
+    }
+}
+
+/// Shadow copy of a volume of the target linked to a directory on the system volume.
+struct ShadowVolume {
+    volume: char,
+    snapshot_id: Option<String>,
+    /// `None` when the shadow copy could not be made or linked, the live volume is used then.
+    link: Option<PathBuf>,
+}
+
+impl ShadowVolume {
+    fn root(&self) -> PathBuf {
+        match &self.link {
+            Some(link) => link.clone(),
+            None => PathBuf::from(format!("{}:\\", self.volume)),
+        }
+    }
 }
 
 impl<'a> ShadowCopier<'a> {
@@ -19,124 +83,270 @@ impl<'a> ShadowCopier<'a> {
         local_store_directory: &Path,
         copier_impl: Option<&'a dyn RemoteFileCopier>,
     ) -> ShadowCopier<'a> {
-        let shadow_drive = ShadowCopier::make_shadow_copy(connector_impl, local_store_directory);
-        let result = ShadowCopier {
+        ShadowCopier {
             connector_impl,
-            shadow_drive,
             copier_impl: match copier_impl {
                 None => connector_impl.copier(),
                 Some(copier_impl) => copier_impl
             },
-        };
-        result
+            local_store_directory: local_store_directory.to_path_buf(),
+            existing: None,
+            shadow_volumes: RefCell::new(Vec::new()),
+        }
     }
 
-    pub fn make_shadow_copy(
+    /// Copier of the existing shadow copy, which is linked on the first use and left on the target.
+    pub fn existing(
+        connector_impl: &'a dyn Connector,
+        local_store_directory: &Path,
+        copier_impl: Option<&'a dyn RemoteFileCopier>,
+        shadow_copy: ExistingShadowCopy,
+    ) -> ShadowCopier<'a> {
+        let mut shadow_copier = ShadowCopier::new(connector_impl, local_store_directory, copier_impl);
+        shadow_copier.existing = Some(shadow_copy);
+        shadow_copier
+    }
+
+    /// Root of the shadow copy of the volume, which is made on the first use of the volume.
+    fn shadow_root(&self, volume: char) -> io::Result<PathBuf> {
+        let volume = volume.to_ascii_uppercase();
+        if !self.shadow_volumes.borrow().iter().any(|it| it.volume == volume) {
+            let shadow_volume = match &self.existing {
+                None => ShadowCopier::make_shadow_copy(self.connector_impl, &self.local_store_directory, volume),
+                Some(existing) if existing.volume == volume => ShadowVolume {
+                    volume,
+                    snapshot_id: None,
+                    link: link_shadow_copy(self.connector_impl, &self.local_store_directory, &existing.device),
+                },
+                Some(existing) => return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("Shadow copy {} is of volume {}: and not of {}:", existing.id, existing.volume, volume),
+                )),
+            };
+            self.shadow_volumes.borrow_mut().push(shadow_volume);
+        }
+        let shadow_volumes = self.shadow_volumes.borrow();
+        let shadow_volume = shadow_volumes.iter().find(|it| it.volume == volume).unwrap();
+        match (&self.existing, &shadow_volume.link) {
+            // The live volume must not pass for an earlier state of it.
+            (Some(existing), None) => Err(io::Error::new(
+                ErrorKind::Other,
+                format!("Cannot link shadow copy {}", existing.id),
+            )),
+            _ => Ok(shadow_volume.root()),
+        }
+    }
+
+    fn make_shadow_copy(
         connector: &dyn Connector,
         local_store_directory: &Path,
-    ) -> PathBuf {
+        volume: char,
+    ) -> ShadowVolume {
+        let mut shadow_volume = ShadowVolume {
+            volume,
+            snapshot_id: None,
+            link: None,
+        };
         let create_vss_command = Command {
             command: vec![
                 "cmd.exe".to_string(),
                 "/c".to_string(),
                 "powershell.exe".to_string(),
                 "-Command".to_string(),
-                "(gwmi -list win32_shadowcopy).Create('C:\\','ClientAccessible')".to_string()
+                format!("(gwmi -list win32_shadowcopy).Create('{}:\\','ClientAccessible')", volume)
             ],
-            report_store_directory: None,
+            report_store_directory: Some(local_store_directory),
             report_filename_prefix: "VSS_RESULT",
             elevated: true,
         };
 
         let timeout = Some(Duration::from_secs(20));
-        if let Err(err) = connector.connect_and_run_command(
-            create_vss_command,
-            timeout.clone(),
-        ) {
-            error!("{}", err);
-            return PathBuf::from("C:\\");
-        }
+        let create_output = match connector.connect_and_run_command(create_vss_command, timeout.clone()) {
+            Err(err) => {
+                error!("{}", err);
+                return shadow_volume;
+            }
+            Ok(outcome) => read_report(&outcome),
+        };
+        // Only the shadow copy reported by this call is ours, any other one of the volume may be evidence.
+        let vss_shadow_id = match create_output.as_deref().map(parse_created_shadow_id) {
+            Some(Ok(vss_shadow_id)) => vss_shadow_id,
+            Some(Err(err)) => {
+                error!("Cannot make shadow copy of {}: on {}: {}", volume, connector.computer().address, err);
+                return shadow_volume;
+            }
+            None => {
+                error!("{}", io::Error::new(ErrorKind::InvalidData, "No output from VSS shadow create"));
+                return shadow_volume;
+            }
+        };
+        record_footprint(connector.connect_method_name(), Footprint::Snapshot(vss_shadow_id.clone()));
+        shadow_volume.snapshot_id = Some(vss_shadow_id.clone());
+
         let list_vss_command = Command {
             command: vec![
                 "cmd.exe".to_string(),
                 "/c".to_string(),
                 "vssadmin".to_string(),
                 "list".to_string(),
-                "shadows".to_string()
+                "shadows".to_string(),
+                format!("/Shadow={}", vss_shadow_id),
             ],
             report_store_directory: Some(local_store_directory),
             report_filename_prefix: "VSS_RESULT",
             elevated: true,
         };
-
-        let vss_list_output_path = connector.connect_and_run_command(
-            list_vss_command,
-            timeout.clone(),
-        );
-        if let Err(err) = vss_list_output_path {
-            error!("{}", err);
-            return PathBuf::from("C:\\");
-        }
-        if let None = vss_list_output_path.as_ref().unwrap() {
-            error!("{}", io::Error::new(ErrorKind::InvalidData, "No output from VSS shadow create"));
-            return PathBuf::from("C:\\");
-        }
-
-        let vss_list_output_path = vss_list_output_path.unwrap().unwrap();
-
-        let vss_shadow_volume_path = {
-            let vss_list_output_file = fs::File::open(&vss_list_output_path);
-            if let Err(err) = vss_list_output_file {
+        let vss_shadow_volume_path = match connector.connect_and_run_command(list_vss_command, timeout) {
+            Err(err) => {
                 error!("{}", err);
-                return PathBuf::from("C:\\");
+                return shadow_volume;
             }
-            RevLines::new(io::BufReader::new(&vss_list_output_file.unwrap()))
-                .unwrap()
-                .find(|it| it.contains("Shadow Copy Volume"))
-                .map(|shadow_volume_line| {
-                    let id_start_idx = shadow_volume_line.find(':').unwrap_or_default();
-                    (shadow_volume_line[id_start_idx + 2..]).to_string()
-                })
+            Ok(outcome) => read_report(&outcome)
+                .and_then(|output| parse_shadow_copies(&output).into_iter().find(|it| it.id.eq_ignore_ascii_case(&vss_shadow_id)))
+                .map(|shadow_copy| shadow_copy.device),
         };
-        let _ = fs::remove_file(&vss_list_output_path);
-        if let None = vss_shadow_volume_path {
-            error!("{}", io::Error::new(ErrorKind::InvalidData, "No output from VSS shadow create"));
-            return PathBuf::from("C:\\");
+        match vss_shadow_volume_path {
+            None => error!("{}", io::Error::new(ErrorKind::InvalidData, format!("No device of shadow copy {}", vss_shadow_id))),
+            Some(vss_shadow_volume_path) => {
+                shadow_volume.link = link_shadow_copy(connector, local_store_directory, &vss_shadow_volume_path);
+            }
         }
-        let vss_shadow_volume_path = vss_shadow_volume_path.unwrap();
-        let vss_link_path = connector
-            .remote_temp_storage()
-            .join(Uuid::new_v4().to_string().replace("-", ""));
-        // self.acquire_perms(&vss_link_path);
-        let link_vss_command = Command {
-            command: vec![
-                "cmd.exe".to_string(),
-                "/c".to_string(),
-                "mklink".to_string(),
-                "/d".to_string(),
-                vss_link_path.to_string_lossy().to_string(),
-                format!("{}\\", vss_shadow_volume_path.to_string()),
-            ],
-            report_store_directory: Some(local_store_directory),
-            report_filename_prefix: "",
-            elevated: true,
-        };
+        shadow_volume
+    }
+}
+
+/// Links the shadow copy device, e.g. `\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopy3`, to a directory of the target.
+fn link_shadow_copy(
+    connector: &dyn Connector,
+    local_store_directory: &Path,
+    device: &str,
+) -> Option<PathBuf> {
+    let vss_link_path = connector
+        .remote_temp_storage()
+        .join(Uuid::new_v4().to_string().replace("-", ""));
+    let link_vss_command = Command {
+        command: vec![
+            "cmd.exe".to_string(),
+            "/c".to_string(),
+            "mklink".to_string(),
+            "/d".to_string(),
+            vss_link_path.to_string_lossy().to_string(),
+            format!("{}\\", device),
+        ],
+        report_store_directory: Some(local_store_directory),
+        report_filename_prefix: "",
+        elevated: true,
+    };
 
-        connector.acquire_perms(&vss_link_path);
-        if let Err(err) = connector.connect_and_run_command(
-            link_vss_command,
-            timeout.clone(),
-        ) {
+    connector.acquire_perms(&vss_link_path);
+    match connector.connect_and_run_command(
+        link_vss_command,
+        Some(Duration::from_secs(20)),
+    ) {
+        Ok(outcome) if !outcome.is_failure() => record_footprint(connector.connect_method_name(), Footprint::VssLink(vss_link_path.clone())),
+        Ok(_) => {}
+        Err(err) => {
             error!("{}", err);
-            return PathBuf::from("C:\\");
+            return None;
+        }
+    }
+    Some(vss_link_path)
+}
+
+/// Lists the shadow copies on the target, the output of `vssadmin` is kept in the store directory as their record.
+pub fn list_shadow_copies(
+    connector: &dyn Connector,
+    local_store_directory: &Path,
+) -> io::Result<Vec<ExistingShadowCopy>> {
+    let list_vss_command = Command {
+        command: vec![
+            "cmd.exe".to_string(),
+            "/c".to_string(),
+            "vssadmin".to_string(),
+            "list".to_string(),
+            "shadows".to_string(),
+        ],
+        report_store_directory: Some(local_store_directory),
+        report_filename_prefix: "VSS_HISTORY",
+        elevated: true,
+    };
+    let outcome = connector.connect_and_run_command(list_vss_command, Some(Duration::from_secs(20)))?;
+    let output_path = outcome.stdout
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "No output from VSS shadow list"))?;
+    let shadow_copies = parse_shadow_copies(&fs::read_to_string(&output_path)?);
+    for shadow_copy in shadow_copies.iter() {
+        info!("Shadow copy {} of volume {}: made {} is on {}", shadow_copy.id, shadow_copy.volume, shadow_copy.created, connector.computer().address);
+    }
+    Ok(shadow_copies)
+}
+
+/// Reads the output of the command stored in the report, removing the report.
+fn read_report(outcome: &CommandOutcome) -> Option<String> {
+    let path = outcome.stdout.as_ref()?;
+    let output = fs::read(path);
+    let _ = fs::remove_file(path);
+    output.ok().map(|it| String::from_utf8_lossy(&it).to_string())
+}
+
+/// Parses the ID of the shadow copy from the output of `Win32_ShadowCopy.Create`, failing on a non-zero `ReturnValue`.
+fn parse_created_shadow_id(output: &str) -> io::Result<String> {
+    let value = |name: &str| output.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim() == name)
+        .map(|(_, value)| value.trim().to_string());
+    match value("ReturnValue").as_deref() {
+        Some("0") => value("ShadowID")
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "No ShadowID in output")),
+        Some(return_value) => Err(io::Error::new(ErrorKind::Other, format!("ReturnValue {}", return_value))),
+        None => Err(io::Error::new(ErrorKind::InvalidData, "No ReturnValue in output")),
+    }
+}
+
+/// Reads the shadow copies from `vssadmin list shadows` output, where a set of shadow copies
+/// starts with its creation time followed by the ID, original volume and device of each copy.
+fn parse_shadow_copies(output: &str) -> Vec<ExistingShadowCopy> {
+    let value = |line: &str| line[line.find(':').unwrap_or_default() + 1..].trim().to_string();
+    let mut shadow_copies = Vec::new();
+    let mut created = String::new();
+    let mut id = None;
+    let mut volume = None;
+    for line in output.lines().map(|line| line.trim()) {
+        if let Some(index) = line.find("creation time:") {
+            created = line[index + "creation time:".len()..].trim().to_string();
+        } else if line.starts_with("Shadow Copy ID:") {
+            id = Some(value(line));
+            volume = None;
+        } else if line.starts_with("Original Volume:") {
+            // e.g. `(C:)\\?\Volume{...}\`, volumes without a drive letter cannot be searched by path.
+            volume = value(line).strip_prefix('(').and_then(|rest| rest.chars().next()).filter(|it| it.is_ascii_alphabetic());
+        } else if line.starts_with("Shadow Copy Volume:") {
+            if let (Some(id), Some(volume)) = (id.take(), volume.take()) {
+                shadow_copies.push(ExistingShadowCopy {
+                    id,
+                    created: created.clone(),
+                    volume: volume.to_ascii_uppercase(),
+                    device: value(line),
+                });
+            }
         }
-        vss_link_path
     }
+    shadow_copies
 }
 
 impl Drop for ShadowCopier<'_> {
     fn drop(&mut self) {
-        delete_shadow_copy(self.connector_impl, self.shadow_drive.as_path())
+        // The shadow copies must be removed even when the time budget of the target is spent.
+        beyond_budget(|| {
+            for shadow_volume in self.shadow_volumes.borrow().iter().rev() {
+                if let Some(link) = &shadow_volume.link {
+                    delete_shadow_copy(self.connector_impl, link);
+                }
+                if let Some(snapshot_id) = &shadow_volume.snapshot_id {
+                    delete_shadow_snapshot(self.connector_impl, snapshot_id);
+                }
+            }
+        })
     }
 }
 
@@ -173,11 +383,15 @@ impl<'a> RemoteFileCopier for ShadowCopier<'a> {
         source: &Path,
         target: &Path,
     ) -> io::Result<()> {
-        let without_c_drive = source.strip_prefix("C:\\");
-        let new_source = match without_c_drive {
-            Ok(without_c_drive) => self.shadow_drive.join(without_c_drive),
-            Err(err) => {
-                error!("{}", err);
+        // The source is a Windows path whatever the host is, so it is not split by `Path`.
+        let source_path = source.to_string_lossy();
+        let mut chars = source_path.chars();
+        let new_source = match (chars.next(), chars.next(), chars.next()) {
+            (Some(volume), Some(':'), Some('\\')) if volume.is_ascii_alphabetic() => {
+                self.shadow_root(volume)?.join(&source_path[3..])
+            }
+            _ => {
+                error!("{} is not on a volume with a drive letter", source.display());
                 source.to_path_buf()
             }
         };
@@ -185,7 +399,8 @@ impl<'a> RemoteFileCopier for ShadowCopier<'a> {
     }
 }
 
-fn delete_shadow_copy(
+/// Removes the link to the shadow copy and releases the permissions granted on it.
+pub fn delete_shadow_copy(
     connector: &dyn Connector,
     shadow_path: &Path,
 ) {
@@ -201,10 +416,113 @@ fn delete_shadow_copy(
         report_filename_prefix: "",
         elevated: true,
     };
-    if let Err(err) = connector.connect_and_run_command(
+    // The link stays in the footprint ledger unless its removal is confirmed in time.
+    match connector.connect_and_run_command(
         unlink_vss_command,
-        Some(Duration::from_secs(1)),
+        Some(Duration::from_secs(10)),
+    ) {
+        Ok(outcome) if !outcome.is_failure() => footprint_removed(&Footprint::VssLink(shadow_path.to_path_buf())),
+        Ok(_) => {}
+        Err(err) => error!("{}", err),
+    }
+}
+
+/// Deletes the shadow copy with the given ID.
+pub fn delete_shadow_snapshot(
+    connector: &dyn Connector,
+    shadow_id: &str,
+) {
+    let delete_vss_command = Command {
+        command: vec![
+            "cmd.exe".to_string(),
+            "/c".to_string(),
+            "vssadmin".to_string(),
+            "delete".to_string(),
+            "shadows".to_string(),
+            format!("/Shadow={}", shadow_id),
+            "/Quiet".to_string(),
+        ],
+        report_store_directory: None,
+        report_filename_prefix: "",
+        elevated: true,
+    };
+    match connector.connect_and_run_command(
+        delete_vss_command,
+        Some(Duration::from_secs(20)),
     ) {
-        error!("{}", err)
+        Ok(outcome) if !outcome.is_failure() => footprint_removed(&Footprint::Snapshot(shadow_id.to_string())),
+        Ok(outcome) => error!("Cannot delete shadow copy {} with {}", shadow_id, outcome),
+        Err(err) => error!("{}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{replay_fixture, replay_store_directory};
+
+    #[test]
+    fn replays_download_from_shadow_copy() {
+        let (replayer, fixture) = replay_fixture("shadow-copy-psexec");
+        let store_directory = replay_store_directory();
+        {
+            let shadow_copier = ShadowCopier::new(&replayer, &store_directory, None);
+            // No shadow copy is made before a file of its volume is copied.
+            assert!(shadow_copier.shadow_volumes.borrow().is_empty());
+            shadow_copier.copy_from_remote(Path::new("C:\\Windows\\System32\\config\\SAM"), &store_directory).unwrap();
+            assert!(shadow_copier.shadow_volumes.borrow()[0].link.is_some());
+        }
+        // Dropping the copier removes the link to the shadow copy and the shadow copy.
+        assert_eq!(fixture.remaining(), 0);
+        let downloaded = fs::read_dir(&store_directory).unwrap()
+            .filter_map(|entry| fs::read(entry.unwrap().path()).ok())
+            .any(|content| content.starts_with(b"regf"));
+        assert!(downloaded);
+        let _ = fs::remove_dir_all(&store_directory);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parses_existing_shadow_copies() {
+        let output = "vssadmin 1.1 - Volume Shadow Copy Service administrative command-line tool
+(C) Copyright 2001-2013 Microsoft Corp.
+
+Contents of shadow copy set ID: {2f4e6a8c-0b1d-4f3e-8a5c-7e9b1d3f5a7c}
+   Contained 2 shadow copies at creation time: 9/30/2026 3:00:12 AM
+      Shadow Copy ID: {8b1f6d2e-3c4a-4e5b-9a7d-1c2e3f4a5b6c}
+         Original Volume: (C:)\\\\?\\Volume{0f1e2d3c-4b5a-4968-8776-655443322110}\\
+         Shadow Copy Volume: \\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1
+         Type: ClientAccessibleWriters
+      Shadow Copy ID: {5a6b7c8d-9e0f-4a1b-8c2d-3e4f5a6b7c8d}
+         Original Volume: (D:)\\\\?\\Volume{1a2b3c4d-5e6f-4a7b-8c9d-0e1f2a3b4c5d}\\
+         Shadow Copy Volume: \\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy2
+
+Contents of shadow copy set ID: {7c8d9e0f-1a2b-4c3d-9e4f-5a6b7c8d9e0f}
+   Contained 1 shadow copies at creation time: 10/17/2026 3:00:09 AM
+      Shadow Copy ID: {0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d}
+         Original Volume: \\\\?\\Volume{2b3c4d5e-6f7a-4b8c-9d0e-1f2a3b4c5d6e}\\
+         Shadow Copy Volume: \\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy3
+";
+        let shadow_copies = parse_shadow_copies(output);
+        // The last one is of a volume without a drive letter.
+        assert_eq!(shadow_copies.len(), 2);
+        assert_eq!(shadow_copies[1], ExistingShadowCopy {
+            id: "{5a6b7c8d-9e0f-4a1b-8c2d-3e4f5a6b7c8d}".to_string(),
+            created: "9/30/2026 3:00:12 AM".to_string(),
+            volume: 'D',
+            device: "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy2".to_string(),
+        });
+        let history = "5A6B7C8D-9E0F-4A1B-8C2D-3E4F5A6B7C8D".parse::<ShadowHistory>().unwrap();
+        assert!(!history.selects(&shadow_copies[0]));
+        assert!(history.selects(&shadow_copies[1]));
+        assert!("latest".parse::<ShadowHistory>().is_err());
+    }
+
+    #[test]
+    fn parses_only_created_shadow_copy() {
+        let created = "__GENUS          : 2\r\nReturnValue      : 0\r\nShadowID         : {8b1f6d2e-3c4a-4e5b-9a7d-1c2e3f4a5b6c}\r\n";
+        assert_eq!(parse_created_shadow_id(created).unwrap(), "{8b1f6d2e-3c4a-4e5b-9a7d-1c2e3f4a5b6c}");
+        // A failed call reports an empty ShadowID, no shadow copy is ours then.
+        assert!(parse_created_shadow_id("ReturnValue      : 5\r\nShadowID         : \r\n").is_err());
+        assert!(parse_created_shadow_id("").is_err());
+    }
+}
@@ -0,0 +1,435 @@
+use crate::remote::{Computer, FileCopier};
+use crate::remote::smb2::{Smb2Client, OpenMode, SMB_PORT, is_connection_error};
+use crate::host;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use wildmatch::WildMatch;
+
+/// Attempts to finish a single file transfer when the connection drops.
+const MAX_TRANSFER_ATTEMPTS: usize = 3;
+const PARTIAL_DOWNLOAD_EXTENSION: &str = "part";
+
+/// Copies files to and from `\\host\share\path` locations over SMB2/3 without mounting the share.
+/// Paths that are not UNC paths are copied locally.
+pub struct Smb {
+    computer: Computer,
+    connection: RefCell<Option<SmbConnection>>,
+}
+
+struct SmbConnection {
+    host: String,
+    client: Smb2Client,
+    trees: HashMap<String, u32>,
+}
+
+impl Drop for SmbConnection {
+    fn drop(&mut self) {
+        for (share, tree_id) in self.trees.drain() {
+            if let Err(err) = self.client.tree_disconnect(tree_id) {
+                debug!("Cannot disconnect from \\\\{}\\{} due to {}", self.host, share, err);
+            }
+        }
+        if let Err(err) = self.client.logoff() {
+            debug!("Cannot log off SMB session to {} due to {}", self.host, err);
+        }
+    }
+}
+
+/// Location of a file on a SMB share.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UncPath {
+    pub host: String,
+    pub share: String,
+    /// Path relative to the share root separated by `\`, empty for the root itself.
+    pub path: String,
+}
+
+impl UncPath {
+    pub fn parse(path: &Path) -> Option<UncPath> {
+        let path = path.to_string_lossy().replace('/', "\\");
+        if !path.starts_with("\\\\") {
+            return None;
+        }
+        let mut components = path.split('\\').filter(|component| !component.is_empty());
+        let host = components.next()?.to_string();
+        let share = components.next()?.to_string();
+        Some(UncPath {
+            host,
+            share,
+            path: components.collect::<Vec<&str>>().join("\\"),
+        })
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('\\').next().unwrap_or_default()
+    }
+
+    pub fn parent(&self) -> UncPath {
+        UncPath {
+            path: self.path.rsplitn(2, '\\').nth(1).unwrap_or_default().to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn join(&self, name: &str) -> UncPath {
+        UncPath {
+            path: if self.path.is_empty() { name.to_string() } else { format!("{}\\{}", self.path, name) },
+            ..self.clone()
+        }
+    }
+
+    fn has_wildcards(&self) -> bool {
+        self.file_name().contains('*') || self.file_name().contains('?')
+    }
+}
+
+impl Smb {
+    pub fn new(computer: Computer) -> Smb {
+        Smb {
+            computer,
+            connection: RefCell::new(None),
+        }
+    }
+
+    fn connect(&self, host: &str) -> io::Result<SmbConnection> {
+        debug!("Opening SMB session to {}", host);
+        let mut client = Smb2Client::connect(host, SMB_PORT)?;
+        client.login(
+            &self.computer.username,
+            self.computer.domain.as_deref().unwrap_or_default(),
+            self.computer.password.as_deref().unwrap_or_default(),
+        )?;
+        Ok(SmbConnection {
+            host: host.to_string(),
+            client,
+            trees: HashMap::new(),
+        })
+    }
+
+    /// Drops the current session so the next operation opens a new one.
+    fn disconnect(&self) {
+        self.connection.borrow_mut().take();
+    }
+
+    fn with_share<T, F>(&self, location: &UncPath, action: F) -> io::Result<T>
+        where F: FnOnce(&mut Smb2Client, u32) -> io::Result<T> {
+        let mut connection = self.connection.borrow_mut();
+        if connection.as_ref().map(|it| it.host != location.host).unwrap_or(true) {
+            connection.take();
+            *connection = Some(self.connect(&location.host)?);
+        }
+        let connection = connection.as_mut().unwrap();
+        let tree_id = match connection.trees.get(&location.share) {
+            Some(tree_id) => *tree_id,
+            None => {
+                let share = format!("\\\\{}\\{}", location.host, location.share);
+                let tree_id = connection.client.tree_connect(&share)?;
+                connection.trees.insert(location.share.clone(), tree_id);
+                tree_id
+            }
+        };
+        action(&mut connection.client, tree_id)
+    }
+
+    /// Runs `action` again on a fresh session when the connection breaks.
+    fn with_reconnect<T, F>(&self, description: &str, mut action: F) -> io::Result<T>
+        where F: FnMut() -> io::Result<T> {
+        let mut attempt = 1;
+        loop {
+            match action() {
+                Err(err) if attempt < MAX_TRANSFER_ATTEMPTS && is_connection_error(&err) => {
+                    warn!("SMB connection broken while {} due to {}, reconnecting", description, err);
+                    self.disconnect();
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn stat(&self, location: &UncPath) -> io::Result<bool> {
+        self.with_reconnect(&format!("reading attributes of {}", location.path), || {
+            self.with_share(location, |client, tree_id| {
+                let file = client.create(tree_id, &location.path, OpenMode::Stat)?;
+                client.close(tree_id, &file.id)?;
+                Ok(file.is_directory)
+            })
+        })
+    }
+
+    fn list(&self, directory: &UncPath, pattern: &str) -> io::Result<Vec<(String, bool)>> {
+        self.with_reconnect(&format!("listing {}", directory.path), || {
+            self.with_share(directory, |client, tree_id| {
+                let dir = client.create(tree_id, &directory.path, OpenMode::List)?;
+                let entries = client.query_directory(tree_id, &dir.id, pattern);
+                client.close(tree_id, &dir.id)?;
+                Ok(entries?.into_iter().map(|entry| (entry.name, entry.is_directory)).collect())
+            })
+        })
+    }
+
+    fn download(&self, source: &UncPath, target: &Path) -> io::Result<()> {
+        fs::create_dir_all(target)?;
+        if source.has_wildcards() {
+            let parent = source.parent();
+            for (name, is_directory) in self.list(&parent, source.file_name())? {
+                if let Err(err) = self.download_entry(&parent.join(&name), is_directory, target) {
+                    error!("Cannot download {} due to {}", name, err);
+                }
+            }
+            Ok(())
+        } else {
+            let is_directory = self.stat(source)?;
+            self.download_entry(source, is_directory, target)
+        }
+    }
+
+    fn download_entry(&self, source: &UncPath, is_directory: bool, target: &Path) -> io::Result<()> {
+        let local_path = target.join(source.file_name());
+        if !is_directory {
+            return self.download_file(source, &local_path);
+        }
+        fs::create_dir_all(&local_path)?;
+        for (name, is_directory) in self.list(source, "*")? {
+            self.download_entry(&source.join(&name), is_directory, &local_path)?;
+        }
+        Ok(())
+    }
+
+    /// Streams the file into a `.part` file renamed once complete.
+    /// An existing `.part` file left by an interrupted run is resumed only when the remote file kept the size
+    /// and last write time recorded next to it, otherwise the download starts over.
+    fn download_file(&self, source: &UncPath, target: &Path) -> io::Result<()> {
+        let partial_path = partial_download_path(target);
+        let source_path = partial_source_path(&partial_path);
+        let mut local_file = OpenOptions::new().create(true).append(true).open(&partial_path)?;
+        let mut offset = local_file.metadata()?.len();
+        self.with_reconnect(&format!("downloading {}", source.path), || {
+            self.with_share(source, |client, tree_id| {
+                let file = client.create(tree_id, &source.path, OpenMode::Read)?;
+                let identity = format!("{} {}", file.size, file.last_write_time);
+                if offset > 0 {
+                    if offset <= file.size && fs::read_to_string(&source_path).ok().as_deref() == Some(identity.as_str()) {
+                        debug!("Resuming download of {} at {} bytes", source.path, offset);
+                    } else {
+                        debug!("{} changed since its partial download, downloading it again", source.path);
+                        local_file.set_len(0)?;
+                        offset = 0;
+                    }
+                }
+                if offset == 0 {
+                    fs::write(&source_path, &identity)?;
+                }
+                let result = (|| {
+                    loop {
+                        let data = client.read(tree_id, &file.id, offset)?;
+                        if data.is_empty() {
+                            return Ok(());
+                        }
+                        local_file.write_all(&data)?;
+                        offset += data.len() as u64;
+                    }
+                })();
+                if let Err(err) = client.close(tree_id, &file.id) {
+                    debug!("Cannot close {} due to {}", source.path, err);
+                }
+                result
+            })
+        })?;
+        drop(local_file);
+        fs::rename(&partial_path, target)?;
+        let _ = fs::remove_file(&source_path);
+        Ok(())
+    }
+
+    fn upload(&self, source: &Path, target: &UncPath) -> io::Result<()> {
+        self.create_directories(target)?;
+        let file_name = source.file_name()
+            .map(|it| it.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if !file_name.contains('*') && !file_name.contains('?') {
+            return self.upload_entry(source, target);
+        }
+        let parent = source.parent().unwrap_or_else(|| Path::new("."));
+        let pattern = WildMatch::new(&file_name);
+        for entry in parent.read_dir()?.filter_map(|entry| entry.ok()) {
+            if pattern.matches(&entry.file_name().to_string_lossy()) {
+                if let Err(err) = self.upload_entry(&entry.path(), target) {
+                    error!("Cannot upload {} due to {}", entry.path().display(), err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn upload_entry(&self, source: &Path, target: &UncPath) -> io::Result<()> {
+        let file_name = source.file_name()
+            .map(|it| it.to_string_lossy().to_string())
+            .ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no file name", source.display()),
+            ))?;
+        let remote_path = target.join(&file_name);
+        if !source.is_dir() {
+            return self.upload_file(source, &remote_path);
+        }
+        self.create_directories(&remote_path)?;
+        for entry in source.read_dir()? {
+            self.upload_entry(&entry?.path(), &remote_path)?;
+        }
+        Ok(())
+    }
+
+    /// Streams the file to the share, continuing at the last written offset after a reconnect.
+    fn upload_file(&self, source: &Path, target: &UncPath) -> io::Result<()> {
+        let mut local_file = fs::File::open(source)?;
+        let mut offset = 0u64;
+        let mut mode = OpenMode::Overwrite;
+        self.with_reconnect(&format!("uploading {}", source.display()), || {
+            self.with_share(target, |client, tree_id| {
+                let file = client.create(tree_id, &target.path, mode)?;
+                mode = OpenMode::Append;
+                let result = (|| {
+                    local_file.seek(SeekFrom::Start(offset))?;
+                    let mut buffer = vec![0u8; 1024 * 1024];
+                    loop {
+                        let length = local_file.read(&mut buffer)?;
+                        if length == 0 {
+                            return Ok(());
+                        }
+                        let mut written = 0;
+                        while written < length {
+                            written += client.write(tree_id, &file.id, offset + written as u64, &buffer[written..length])?;
+                        }
+                        offset += length as u64;
+                    }
+                })();
+                if let Err(err) = client.close(tree_id, &file.id) {
+                    debug!("Cannot close {} due to {}", target.path, err);
+                }
+                result
+            })
+        })
+    }
+
+    fn create_directories(&self, target: &UncPath) -> io::Result<()> {
+        let mut directory = UncPath { path: String::new(), ..target.clone() };
+        for component in target.path.split('\\').filter(|it| !it.is_empty()) {
+            directory = directory.join(component);
+            self.with_reconnect(&format!("creating {}", directory.path), || {
+                self.with_share(&directory, |client, tree_id| {
+                    let dir = client.create(tree_id, &directory.path, OpenMode::CreateDirectory)?;
+                    client.close(tree_id, &dir.id)
+                })
+            })?;
+        }
+        Ok(())
+    }
+
+    fn delete(&self, target: &UncPath) -> io::Result<()> {
+        let names = if target.has_wildcards() {
+            self.list(&target.parent(), target.file_name())?
+                .into_iter()
+                .filter(|(_, is_directory)| !is_directory)
+                .map(|(name, _)| target.parent().join(&name))
+                .collect()
+        } else {
+            vec![target.clone()]
+        };
+        for file in names {
+            self.with_reconnect(&format!("deleting {}", file.path), || {
+                self.with_share(&file, |client, tree_id| {
+                    let handle = client.create(tree_id, &file.path, OpenMode::Delete)?;
+                    client.close(tree_id, &handle.id)
+                })
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl FileCopier for Smb {
+    fn copy_file(&self, source: &Path, target: &Path) -> io::Result<()> {
+        match (UncPath::parse(source), UncPath::parse(target)) {
+            (Some(source), None) => {
+                trace!("Downloading {:?} to {}", source, target.display());
+                self.download(&source, target)
+            }
+            (None, Some(target)) => {
+                trace!("Uploading {} to {:?}", source.display(), target);
+                self.upload(source, &target)
+            }
+            (Some(_), Some(_)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Cannot copy between two shares {} and {}", source.display(), target.display()),
+            )),
+            (None, None) => host::copy_path(source, target),
+        }
+    }
+
+    fn delete_file(&self, target: &Path) -> io::Result<()> {
+        match UncPath::parse(target) {
+            Some(target) => self.delete(&target),
+            None => host::delete_path(target),
+        }
+    }
+
+    fn method_name(&self) -> &'static str {
+        "SMB"
+    }
+}
+
+fn partial_download_path(target: &Path) -> PathBuf {
+    let mut file_name = target.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(PARTIAL_DOWNLOAD_EXTENSION);
+    target.with_file_name(file_name)
+}
+
+/// File next to the `.part` file holding the size and last write time of the remote file it is downloaded from.
+fn partial_source_path(partial_path: &Path) -> PathBuf {
+    let mut file_name = partial_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".source");
+    partial_path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_admin_share_path() {
+        let path = UncPath::parse(Path::new("\\\\192.168.0.5\\C$\\Windows\\Temp\\out.txt")).unwrap();
+        assert_eq!(path, UncPath {
+            host: "192.168.0.5".to_string(),
+            share: "C$".to_string(),
+            path: "Windows\\Temp\\out.txt".to_string(),
+        });
+        assert_eq!(path.file_name(), "out.txt");
+        assert_eq!(path.parent().path, "Windows\\Temp");
+        assert_eq!(path.parent().join("*.log").path, "Windows\\Temp\\*.log");
+    }
+
+    #[test]
+    fn parses_share_root_and_forward_slashes() {
+        let path = UncPath::parse(Path::new("//host/D$")).unwrap();
+        assert_eq!(path.share, "D$");
+        assert_eq!(path.path, "");
+        assert_eq!(path.join("a").path, "a");
+        assert_eq!(UncPath::parse(Path::new("C:\\Windows")), None);
+        assert_eq!(UncPath::parse(Path::new("\\\\host")), None);
+    }
+
+    #[test]
+    fn partial_download_keeps_directory() {
+        assert_eq!(
+            partial_download_path(Path::new("out/SYSTEM")),
+            PathBuf::from("out/SYSTEM.part")
+        );
+    }
+}
@@ -0,0 +1,832 @@
+use std::io;
+use std::io::{Read, Write, ErrorKind};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use aes::Aes128;
+use cmac::Cmac;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use crate::remote::ntlm;
+
+pub const SMB_PORT: u16 = 445;
+
+const HEADER_SIZE: usize = 64;
+const PROTOCOL_ID: &[u8; 4] = b"\xfeSMB";
+const DIALECTS: [u16; 4] = [0x0202, 0x0210, 0x0300, 0x0302];
+const DIALECT_SMB_2_1: u16 = 0x0210;
+const DIALECT_SMB_3_0: u16 = 0x0300;
+
+const NEGOTIATE: u16 = 0x00;
+const SESSION_SETUP: u16 = 0x01;
+const LOGOFF: u16 = 0x02;
+const TREE_CONNECT: u16 = 0x03;
+const TREE_DISCONNECT: u16 = 0x04;
+const CREATE: u16 = 0x05;
+const CLOSE: u16 = 0x06;
+const READ: u16 = 0x08;
+const WRITE: u16 = 0x09;
+const QUERY_DIRECTORY: u16 = 0x0e;
+
+const FLAGS_ASYNC_COMMAND: u32 = 0x02;
+const FLAGS_SIGNED: u32 = 0x08;
+
+const STATUS_SUCCESS: u32 = 0x0000_0000;
+const STATUS_PENDING: u32 = 0x0000_0103;
+const STATUS_NO_MORE_FILES: u32 = 0x8000_0006;
+const STATUS_NO_SUCH_FILE: u32 = 0xc000_000f;
+const STATUS_END_OF_FILE: u32 = 0xc000_0011;
+const STATUS_MORE_PROCESSING_REQUIRED: u32 = 0xc000_0016;
+const STATUS_ACCESS_DENIED: u32 = 0xc000_0022;
+const STATUS_OBJECT_NAME_NOT_FOUND: u32 = 0xc000_0034;
+const STATUS_OBJECT_NAME_COLLISION: u32 = 0xc000_0035;
+const STATUS_OBJECT_PATH_NOT_FOUND: u32 = 0xc000_003a;
+const STATUS_LOGON_FAILURE: u32 = 0xc000_006d;
+const STATUS_NETWORK_NAME_DELETED: u32 = 0xc000_00c9;
+const STATUS_BAD_NETWORK_NAME: u32 = 0xc000_00cc;
+const STATUS_USER_SESSION_DELETED: u32 = 0xc000_0203;
+
+const NEGOTIATE_SIGNING_ENABLED: u16 = 0x01;
+const GLOBAL_CAP_LARGE_MTU: u32 = 0x04;
+const SESSION_FLAG_IS_GUEST: u16 = 0x01;
+const SESSION_FLAG_IS_NULL: u16 = 0x02;
+const SESSION_FLAG_ENCRYPT_DATA: u16 = 0x04;
+const SHAREFLAG_ENCRYPT_DATA: u32 = 0x8000;
+
+const FILE_READ_DATA: u32 = 0x0000_0001;
+const FILE_WRITE_DATA: u32 = 0x0000_0002;
+const FILE_READ_ATTRIBUTES: u32 = 0x0000_0080;
+const FILE_WRITE_ATTRIBUTES: u32 = 0x0000_0100;
+const DELETE: u32 = 0x0001_0000;
+const SYNCHRONIZE: u32 = 0x0010_0000;
+const FILE_SHARE_ALL: u32 = 0x07;
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+
+const FILE_OPEN: u32 = 1;
+const FILE_OPEN_IF: u32 = 3;
+const FILE_OVERWRITE_IF: u32 = 5;
+const FILE_DIRECTORY_FILE: u32 = 0x0000_0001;
+const FILE_NON_DIRECTORY_FILE: u32 = 0x0000_0040;
+const FILE_DELETE_ON_CLOSE: u32 = 0x0000_1000;
+
+const FILE_DIRECTORY_INFORMATION: u8 = 0x01;
+const RESTART_SCANS: u8 = 0x01;
+
+/// Credits asked for with every request so large reads and writes do not starve.
+const CREDITS_REQUESTED: u16 = 64;
+const CREDIT_SIZE: usize = 64 * 1024;
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+const QUERY_DIRECTORY_BUFFER: u32 = 64 * 1024;
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(60);
+
+const SPNEGO_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+const NTLMSSP_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a];
+const NTLMSSP_SIGNATURE: &[u8] = b"NTLMSSP\0";
+
+const SIGNING_LABEL: &[u8] = b"SMB2AESCMAC\0";
+const SIGNING_CONTEXT: &[u8] = b"SmbSign\0";
+
+/// How a file is opened by `Smb2Client::create`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpenMode {
+    Read,
+    Stat,
+    List,
+    Overwrite,
+    Append,
+    CreateDirectory,
+    Delete,
+}
+
+impl OpenMode {
+    /// Desired access, file attributes, create disposition and create options.
+    fn parameters(self) -> (u32, u32, u32, u32) {
+        match self {
+            OpenMode::Read => (
+                FILE_READ_DATA | FILE_READ_ATTRIBUTES | SYNCHRONIZE,
+                0,
+                FILE_OPEN,
+                FILE_NON_DIRECTORY_FILE,
+            ),
+            OpenMode::Stat => (FILE_READ_ATTRIBUTES | SYNCHRONIZE, 0, FILE_OPEN, 0),
+            OpenMode::List => (
+                FILE_READ_DATA | FILE_READ_ATTRIBUTES | SYNCHRONIZE,
+                0,
+                FILE_OPEN,
+                FILE_DIRECTORY_FILE,
+            ),
+            OpenMode::Overwrite => (
+                FILE_WRITE_DATA | FILE_READ_ATTRIBUTES | FILE_WRITE_ATTRIBUTES | SYNCHRONIZE,
+                FILE_ATTRIBUTE_NORMAL,
+                FILE_OVERWRITE_IF,
+                FILE_NON_DIRECTORY_FILE,
+            ),
+            OpenMode::Append => (
+                FILE_WRITE_DATA | FILE_READ_ATTRIBUTES | FILE_WRITE_ATTRIBUTES | SYNCHRONIZE,
+                FILE_ATTRIBUTE_NORMAL,
+                FILE_OPEN_IF,
+                FILE_NON_DIRECTORY_FILE,
+            ),
+            OpenMode::CreateDirectory => (
+                FILE_READ_DATA | FILE_READ_ATTRIBUTES | SYNCHRONIZE,
+                0,
+                FILE_OPEN_IF,
+                FILE_DIRECTORY_FILE,
+            ),
+            OpenMode::Delete => (
+                DELETE | FILE_READ_ATTRIBUTES,
+                0,
+                FILE_OPEN,
+                FILE_NON_DIRECTORY_FILE | FILE_DELETE_ON_CLOSE,
+            ),
+        }
+    }
+}
+
+pub struct OpenFile {
+    pub id: [u8; 16],
+    pub size: u64,
+    /// Last write time as a Windows `FILETIME`.
+    pub last_write_time: u64,
+    pub is_directory: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_directory: bool,
+}
+
+enum SigningKey {
+    HmacSha256([u8; 16]),
+    AesCmac([u8; 16]),
+}
+
+impl SigningKey {
+    fn signature(&self, message: &[u8]) -> [u8; 16] {
+        let mut unsigned = message.to_vec();
+        unsigned[48..HEADER_SIZE].copy_from_slice(&[0u8; 16]);
+        let mut signature = [0u8; 16];
+        match self {
+            SigningKey::HmacSha256(key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+                mac.update(&unsigned);
+                signature.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+            }
+            SigningKey::AesCmac(key) => {
+                let mut mac = Cmac::<Aes128>::new_from_slice(key).expect("AES-128 key has 16 bytes");
+                mac.update(&unsigned);
+                signature.copy_from_slice(&mac.finalize().into_bytes());
+            }
+        }
+        signature
+    }
+}
+
+struct Response {
+    status: u32,
+    message: Vec<u8>,
+}
+
+impl Response {
+    fn u16_at(&self, offset: usize) -> u16 {
+        u16_at(&self.message, offset)
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32_at(&self.message, offset)
+    }
+
+    fn u64_at(&self, offset: usize) -> u64 {
+        u64_at(&self.message, offset)
+    }
+
+    /// Buffer described by an offset (from the header start) and a length.
+    fn buffer(&self, offset: usize, length: usize) -> io::Result<&[u8]> {
+        self.message.get(offset..offset + length)
+            .ok_or_else(|| invalid_data("SMB2 buffer out of bounds"))
+    }
+}
+
+/// Minimal SMB 2.0.2 - 3.0.2 client authenticating with NTLM and signing every request.
+pub struct Smb2Client {
+    address: String,
+    stream: TcpStream,
+    dialect: u16,
+    large_mtu: bool,
+    max_read_size: usize,
+    max_write_size: usize,
+    message_id: u64,
+    credits: usize,
+    session_id: u64,
+    signing_key: Option<SigningKey>,
+}
+
+impl Smb2Client {
+    /// Opens the TCP connection and negotiates the dialect.
+    pub fn connect(address: &str, port: u16) -> io::Result<Smb2Client> {
+        let socket_address = (address, port).to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("Cannot resolve {}", address)))?;
+        let stream = TcpStream::connect_timeout(&socket_address, SOCKET_TIMEOUT)?;
+        stream.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+        stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut client = Smb2Client {
+            address: address.to_string(),
+            stream,
+            dialect: 0,
+            large_mtu: false,
+            max_read_size: CREDIT_SIZE,
+            max_write_size: CREDIT_SIZE,
+            message_id: 0,
+            credits: 1,
+            session_id: 0,
+            signing_key: None,
+        };
+        client.negotiate()?;
+        Ok(client)
+    }
+
+    fn negotiate(&mut self) -> io::Result<()> {
+        let mut client_guid = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut client_guid);
+        let mut body = Vec::with_capacity(36 + DIALECTS.len() * 2);
+        push_u16(&mut body, 36);
+        push_u16(&mut body, DIALECTS.len() as u16);
+        push_u16(&mut body, NEGOTIATE_SIGNING_ENABLED);
+        push_u16(&mut body, 0);
+        push_u32(&mut body, GLOBAL_CAP_LARGE_MTU);
+        body.extend_from_slice(&client_guid);
+        push_u64(&mut body, 0);
+        for dialect in DIALECTS.iter() {
+            push_u16(&mut body, *dialect);
+        }
+        let response = self.call(NEGOTIATE, 0, &body, 1)?;
+        self.dialect = response.u16_at(HEADER_SIZE + 4);
+        if !DIALECTS.contains(&self.dialect) {
+            return Err(invalid_data(&format!("Unsupported SMB dialect 0x{:04x}", self.dialect)));
+        }
+        let capabilities = response.u32_at(HEADER_SIZE + 24);
+        self.large_mtu = self.dialect >= DIALECT_SMB_2_1 && capabilities & GLOBAL_CAP_LARGE_MTU != 0;
+        self.max_read_size = response.u32_at(HEADER_SIZE + 32) as usize;
+        self.max_write_size = response.u32_at(HEADER_SIZE + 36) as usize;
+        debug!("Negotiated SMB dialect 0x{:04x} with {}", self.dialect, self.address);
+        Ok(())
+    }
+
+    /// Authenticates using NTLM wrapped in SPNEGO and derives the signing key.
+    pub fn login(&mut self, username: &str, domain: &str, password: &str) -> io::Result<()> {
+        let response = self.session_setup(&spnego_init(&ntlm::negotiate_message()))?;
+        if response.status != STATUS_MORE_PROCESSING_REQUIRED {
+            return Err(status_error("SESSION_SETUP", response.status));
+        }
+        self.session_id = response.u64_at(40);
+        let challenge = ntlm::ChallengeMessage::parse(&spnego_token(session_security_buffer(&response)?)?)?;
+        let authentication = ntlm::authenticate(username, domain, password, &challenge);
+
+        let response = self.session_setup(&spnego_response(&authentication.message))?;
+        if response.status != STATUS_SUCCESS {
+            return Err(status_error("SESSION_SETUP", response.status));
+        }
+        let session_flags = response.u16_at(HEADER_SIZE + 2);
+        if session_flags & SESSION_FLAG_ENCRYPT_DATA != 0 {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!("{} requires SMB encryption which is not supported", self.address),
+            ));
+        }
+        if session_flags & (SESSION_FLAG_IS_GUEST | SESSION_FLAG_IS_NULL) != 0 {
+            warn!("SMB session to {} was established as guest", self.address);
+        } else if self.dialect >= DIALECT_SMB_3_0 {
+            self.signing_key = Some(SigningKey::AesCmac(
+                derive_key(&authentication.session_key, SIGNING_LABEL, SIGNING_CONTEXT)
+            ));
+        } else {
+            self.signing_key = Some(SigningKey::HmacSha256(authentication.session_key));
+        }
+        Ok(())
+    }
+
+    fn session_setup(&mut self, token: &[u8]) -> io::Result<Response> {
+        let mut body = Vec::with_capacity(24 + token.len());
+        push_u16(&mut body, 25);
+        body.push(0);
+        body.push(NEGOTIATE_SIGNING_ENABLED as u8);
+        push_u32(&mut body, 0);
+        push_u32(&mut body, 0);
+        push_u16(&mut body, (HEADER_SIZE + 24) as u16);
+        push_u16(&mut body, token.len() as u16);
+        push_u64(&mut body, 0);
+        body.extend_from_slice(token);
+        self.send_request(SESSION_SETUP, 0, &body, 1)
+    }
+
+    /// Connects to a share given as `\\host\share` and returns its tree id.
+    pub fn tree_connect(&mut self, unc: &str) -> io::Result<u32> {
+        let path = ntlm::utf16le(unc);
+        let mut body = Vec::with_capacity(8 + path.len());
+        push_u16(&mut body, 9);
+        push_u16(&mut body, 0);
+        push_u16(&mut body, (HEADER_SIZE + 8) as u16);
+        push_u16(&mut body, path.len() as u16);
+        body.extend_from_slice(&path);
+        let response = self.call(TREE_CONNECT, 0, &body, 1)?;
+        if response.u32_at(HEADER_SIZE + 4) & SHAREFLAG_ENCRYPT_DATA != 0 {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!("{} requires SMB encryption which is not supported", unc),
+            ));
+        }
+        Ok(response.u32_at(36))
+    }
+
+    pub fn tree_disconnect(&mut self, tree_id: u32) -> io::Result<()> {
+        self.call(TREE_DISCONNECT, tree_id, &[4, 0, 0, 0], 1).map(|_| ())
+    }
+
+    pub fn logoff(&mut self) -> io::Result<()> {
+        self.call(LOGOFF, 0, &[4, 0, 0, 0], 1).map(|_| ())
+    }
+
+    /// Opens `path` relative to the share root.
+    pub fn create(&mut self, tree_id: u32, path: &str, mode: OpenMode) -> io::Result<OpenFile> {
+        let (access, attributes, disposition, options) = mode.parameters();
+        let name = ntlm::utf16le(path);
+        let mut body = Vec::with_capacity(56 + name.len().max(1));
+        push_u16(&mut body, 57);
+        body.push(0);
+        body.push(0);
+        push_u32(&mut body, 2);
+        push_u64(&mut body, 0);
+        push_u64(&mut body, 0);
+        push_u32(&mut body, access);
+        push_u32(&mut body, attributes);
+        push_u32(&mut body, FILE_SHARE_ALL);
+        push_u32(&mut body, disposition);
+        push_u32(&mut body, options);
+        push_u16(&mut body, (HEADER_SIZE + 56) as u16);
+        push_u16(&mut body, name.len() as u16);
+        push_u32(&mut body, 0);
+        push_u32(&mut body, 0);
+        body.extend_from_slice(&name);
+        if name.is_empty() {
+            body.push(0);
+        }
+        let response = self.call(CREATE, tree_id, &body, 1)?;
+        let mut id = [0u8; 16];
+        id.copy_from_slice(response.buffer(HEADER_SIZE + 64, 16)?);
+        Ok(OpenFile {
+            id,
+            size: response.u64_at(HEADER_SIZE + 48),
+            last_write_time: response.u64_at(HEADER_SIZE + 24),
+            is_directory: response.u32_at(HEADER_SIZE + 56) & FILE_ATTRIBUTE_DIRECTORY != 0,
+        })
+    }
+
+    pub fn close(&mut self, tree_id: u32, file_id: &[u8; 16]) -> io::Result<()> {
+        let mut body = Vec::with_capacity(24);
+        push_u16(&mut body, 24);
+        push_u16(&mut body, 0);
+        push_u32(&mut body, 0);
+        body.extend_from_slice(file_id);
+        self.call(CLOSE, tree_id, &body, 1).map(|_| ())
+    }
+
+    /// Reads the next chunk at `offset`, an empty result means end of file.
+    pub fn read(&mut self, tree_id: u32, file_id: &[u8; 16], offset: u64) -> io::Result<Vec<u8>> {
+        let length = self.chunk_size(self.max_read_size);
+        let mut body = Vec::with_capacity(49);
+        push_u16(&mut body, 49);
+        body.push(0x50);
+        body.push(0);
+        push_u32(&mut body, length as u32);
+        push_u64(&mut body, offset);
+        body.extend_from_slice(file_id);
+        push_u32(&mut body, 0);
+        push_u32(&mut body, 0);
+        push_u32(&mut body, 0);
+        push_u16(&mut body, 0);
+        push_u16(&mut body, 0);
+        body.push(0);
+        let response = self.send_request(READ, tree_id, &body, self.credit_charge(length))?;
+        match response.status {
+            STATUS_SUCCESS => {
+                let data_offset = response.message[HEADER_SIZE + 2] as usize;
+                let data_length = response.u32_at(HEADER_SIZE + 4) as usize;
+                Ok(response.buffer(data_offset, data_length)?.to_vec())
+            }
+            STATUS_END_OF_FILE => Ok(Vec::new()),
+            status => Err(status_error("READ", status)),
+        }
+    }
+
+    /// Writes as much of `data` as fits into one request and returns the number of bytes written.
+    pub fn write(&mut self, tree_id: u32, file_id: &[u8; 16], offset: u64, data: &[u8]) -> io::Result<usize> {
+        let length = data.len().min(self.chunk_size(self.max_write_size));
+        let mut body = Vec::with_capacity(48 + length);
+        push_u16(&mut body, 49);
+        push_u16(&mut body, (HEADER_SIZE + 48) as u16);
+        push_u32(&mut body, length as u32);
+        push_u64(&mut body, offset);
+        body.extend_from_slice(file_id);
+        push_u32(&mut body, 0);
+        push_u32(&mut body, 0);
+        push_u16(&mut body, 0);
+        push_u16(&mut body, 0);
+        push_u32(&mut body, 0);
+        body.extend_from_slice(&data[..length]);
+        let response = self.call(WRITE, tree_id, &body, self.credit_charge(length))?;
+        Ok(response.u32_at(HEADER_SIZE + 4) as usize)
+    }
+
+    /// Lists entries of an open directory matching `pattern`, skipping `.` and `..`.
+    pub fn query_directory(&mut self, tree_id: u32, file_id: &[u8; 16], pattern: &str) -> io::Result<Vec<DirectoryEntry>> {
+        let name = ntlm::utf16le(pattern);
+        let mut entries = Vec::new();
+        let mut flags = RESTART_SCANS;
+        loop {
+            let mut body = Vec::with_capacity(32 + name.len());
+            push_u16(&mut body, 33);
+            body.push(FILE_DIRECTORY_INFORMATION);
+            body.push(flags);
+            push_u32(&mut body, 0);
+            body.extend_from_slice(file_id);
+            push_u16(&mut body, (HEADER_SIZE + 32) as u16);
+            push_u16(&mut body, name.len() as u16);
+            push_u32(&mut body, QUERY_DIRECTORY_BUFFER);
+            body.extend_from_slice(&name);
+            let charge = self.credit_charge(QUERY_DIRECTORY_BUFFER as usize);
+            let response = self.send_request(QUERY_DIRECTORY, tree_id, &body, charge)?;
+            match response.status {
+                STATUS_SUCCESS => {
+                    let offset = response.u16_at(HEADER_SIZE + 2) as usize;
+                    let length = response.u32_at(HEADER_SIZE + 4) as usize;
+                    entries.extend(parse_directory_information(response.buffer(offset, length)?)?);
+                }
+                STATUS_NO_MORE_FILES => break,
+                status => return Err(status_error("QUERY_DIRECTORY", status)),
+            }
+            flags = 0;
+        }
+        entries.retain(|entry| entry.name != "." && entry.name != "..");
+        Ok(entries)
+    }
+
+    /// Largest read or write the negotiated dialect and the current credits allow.
+    fn chunk_size(&self, max_size: usize) -> usize {
+        if self.large_mtu {
+            max_size.min(MAX_CHUNK_SIZE).min(self.credits.max(1) * CREDIT_SIZE)
+        } else {
+            max_size.min(CREDIT_SIZE)
+        }
+    }
+
+    fn credit_charge(&self, length: usize) -> u16 {
+        if self.large_mtu {
+            (1 + length.saturating_sub(1) / CREDIT_SIZE) as u16
+        } else {
+            1
+        }
+    }
+
+    fn call(&mut self, command: u16, tree_id: u32, body: &[u8], credit_charge: u16) -> io::Result<Response> {
+        let response = self.send_request(command, tree_id, body, credit_charge)?;
+        if response.status != STATUS_SUCCESS {
+            return Err(status_error(command_name(command), response.status));
+        }
+        Ok(response)
+    }
+
+    fn send_request(&mut self, command: u16, tree_id: u32, body: &[u8], credit_charge: u16) -> io::Result<Response> {
+        let message_id = self.message_id;
+        let mut message = Vec::with_capacity(HEADER_SIZE + body.len());
+        message.extend_from_slice(PROTOCOL_ID);
+        push_u16(&mut message, HEADER_SIZE as u16);
+        push_u16(&mut message, if self.dialect >= DIALECT_SMB_2_1 { credit_charge } else { 0 });
+        push_u32(&mut message, 0);
+        push_u16(&mut message, command);
+        push_u16(&mut message, CREDITS_REQUESTED);
+        push_u32(&mut message, if self.signing_key.is_some() { FLAGS_SIGNED } else { 0 });
+        push_u32(&mut message, 0);
+        push_u64(&mut message, message_id);
+        push_u32(&mut message, 0);
+        push_u32(&mut message, tree_id);
+        push_u64(&mut message, self.session_id);
+        message.extend_from_slice(&[0u8; 16]);
+        message.extend_from_slice(body);
+        if let Some(key) = &self.signing_key {
+            let signature = key.signature(&message);
+            message[48..HEADER_SIZE].copy_from_slice(&signature);
+        }
+        trace!("Sending SMB2 {} (message {}) to {}", command_name(command), message_id, self.address);
+        self.message_id += credit_charge.max(1) as u64;
+        self.credits = self.credits.saturating_sub(credit_charge.max(1) as usize);
+        self.send_frame(&message)?;
+
+        loop {
+            let message = self.receive_frame()?;
+            if message.len() < HEADER_SIZE || &message[..4] != PROTOCOL_ID {
+                return Err(invalid_data("Not a SMB2 message"));
+            }
+            let response = Response { status: u32_at(&message, 8), message };
+            self.credits += response.u16_at(14) as usize;
+            let flags = response.u32_at(16);
+            if response.u64_at(24) != message_id {
+                trace!("Ignoring unsolicited SMB2 message from {}", self.address);
+                continue;
+            }
+            if response.status == STATUS_PENDING && flags & FLAGS_ASYNC_COMMAND != 0 {
+                continue;
+            }
+            if command != SESSION_SETUP && flags & FLAGS_SIGNED != 0 {
+                if let Some(key) = &self.signing_key {
+                    if key.signature(&response.message)[..] != response.message[48..HEADER_SIZE] {
+                        return Err(io::Error::new(
+                            ErrorKind::PermissionDenied,
+                            format!("Invalid SMB2 signature received from {}", self.address),
+                        ));
+                    }
+                }
+            }
+            return Ok(response);
+        }
+    }
+
+    fn send_frame(&mut self, message: &[u8]) -> io::Result<()> {
+        let length = message.len() as u32;
+        let mut frame = Vec::with_capacity(4 + message.len());
+        frame.extend_from_slice(&length.to_be_bytes());
+        frame[0] = 0;
+        frame.extend_from_slice(message);
+        self.stream.write_all(&frame)?;
+        self.stream.flush()
+    }
+
+    fn receive_frame(&mut self) -> io::Result<Vec<u8>> {
+        let mut length = [0u8; 4];
+        self.stream.read_exact(&mut length)?;
+        length[0] = 0;
+        let mut message = vec![0u8; u32::from_be_bytes(length) as usize];
+        self.stream.read_exact(&mut message)?;
+        Ok(message)
+    }
+}
+
+/// Tells whether the error means the connection or session is gone and reconnecting may help.
+pub fn is_connection_error(err: &io::Error) -> bool {
+    match err.kind() {
+        ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::BrokenPipe
+        | ErrorKind::UnexpectedEof
+        | ErrorKind::NotConnected
+        | ErrorKind::TimedOut
+        | ErrorKind::WouldBlock => true,
+        _ => false,
+    }
+}
+
+fn status_error(command: &str, status: u32) -> io::Error {
+    let kind = match status {
+        STATUS_NO_SUCH_FILE
+        | STATUS_OBJECT_NAME_NOT_FOUND
+        | STATUS_OBJECT_PATH_NOT_FOUND
+        | STATUS_BAD_NETWORK_NAME => ErrorKind::NotFound,
+        STATUS_ACCESS_DENIED | STATUS_LOGON_FAILURE => ErrorKind::PermissionDenied,
+        STATUS_OBJECT_NAME_COLLISION => ErrorKind::AlreadyExists,
+        STATUS_NETWORK_NAME_DELETED | STATUS_USER_SESSION_DELETED => ErrorKind::ConnectionAborted,
+        _ => ErrorKind::Other,
+    };
+    io::Error::new(kind, format!("SMB2 {} failed with status 0x{:08x}", command, status))
+}
+
+fn command_name(command: u16) -> &'static str {
+    match command {
+        NEGOTIATE => "NEGOTIATE",
+        SESSION_SETUP => "SESSION_SETUP",
+        LOGOFF => "LOGOFF",
+        TREE_CONNECT => "TREE_CONNECT",
+        TREE_DISCONNECT => "TREE_DISCONNECT",
+        CREATE => "CREATE",
+        CLOSE => "CLOSE",
+        READ => "READ",
+        WRITE => "WRITE",
+        QUERY_DIRECTORY => "QUERY_DIRECTORY",
+        _ => "request",
+    }
+}
+
+fn session_security_buffer(response: &Response) -> io::Result<&[u8]> {
+    let offset = response.u16_at(HEADER_SIZE + 4) as usize;
+    let length = response.u16_at(HEADER_SIZE + 6) as usize;
+    response.buffer(offset, length)
+}
+
+fn parse_directory_information(buffer: &[u8]) -> io::Result<Vec<DirectoryEntry>> {
+    let mut entries = Vec::new();
+    let mut position = 0;
+    loop {
+        let entry = buffer.get(position..)
+            .filter(|entry| entry.len() >= 64)
+            .ok_or_else(|| invalid_data("Truncated SMB2 directory entry"))?;
+        let name_length = u32_at(entry, 60) as usize;
+        let name = entry.get(64..64 + name_length)
+            .ok_or_else(|| invalid_data("Truncated SMB2 directory entry"))?;
+        let name = name.chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect::<Vec<u16>>();
+        entries.push(DirectoryEntry {
+            name: String::from_utf16_lossy(&name),
+            size: u64_at(entry, 40),
+            is_directory: u32_at(entry, 56) & FILE_ATTRIBUTE_DIRECTORY != 0,
+        });
+        let next = u32_at(entry, 0) as usize;
+        if next == 0 {
+            break;
+        }
+        position += next;
+    }
+    Ok(entries)
+}
+
+/// SP800-108 counter mode KDF with HMAC-SHA256 producing a 128 bit key.
+fn derive_key(key: &[u8], label: &[u8], context: &[u8]) -> [u8; 16] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&1u32.to_be_bytes());
+    mac.update(label);
+    mac.update(&[0]);
+    mac.update(context);
+    mac.update(&128u32.to_be_bytes());
+    let mut derived = [0u8; 16];
+    derived.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+    derived
+}
+
+/// SPNEGO NegTokenInit offering NTLM with the given mechanism token.
+fn spnego_init(token: &[u8]) -> Vec<u8> {
+    let mech_types = der(0xa0, &der(0x30, &der(0x06, NTLMSSP_OID)));
+    let mech_token = der(0xa2, &der(0x04, token));
+    let neg_token_init = der(0xa0, &der(0x30, &[mech_types, mech_token].concat()));
+    der(0x60, &[der(0x06, SPNEGO_OID), neg_token_init].concat())
+}
+
+/// SPNEGO NegTokenResp carrying the given response token.
+fn spnego_response(token: &[u8]) -> Vec<u8> {
+    der(0xa1, &der(0x30, &der(0xa2, &der(0x04, token))))
+}
+
+/// Extracts the mechanism token from a SPNEGO NegTokenResp, raw NTLM messages are passed through.
+fn spnego_token(blob: &[u8]) -> io::Result<Vec<u8>> {
+    if blob.starts_with(NTLMSSP_SIGNATURE) {
+        return Ok(blob.to_vec());
+    }
+    let malformed = || invalid_data("Malformed SPNEGO token");
+    let (tag, neg_token_resp, _) = der_read(blob).ok_or_else(malformed)?;
+    if tag != 0xa1 {
+        return Err(malformed());
+    }
+    let (_, mut fields, _) = der_read(neg_token_resp).ok_or_else(malformed)?;
+    while let Some((tag, content, rest)) = der_read(fields) {
+        if tag == 0xa2 {
+            let (_, token, _) = der_read(content).ok_or_else(malformed)?;
+            return Ok(token.to_vec());
+        }
+        fields = rest;
+    }
+    Err(invalid_data("SPNEGO token carries no NTLM message"))
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let length = content.len();
+    let mut encoded = vec![tag];
+    if length < 0x80 {
+        encoded.push(length as u8);
+    } else if length <= 0xff {
+        encoded.extend_from_slice(&[0x81, length as u8]);
+    } else {
+        encoded.extend_from_slice(&[0x82, (length >> 8) as u8, length as u8]);
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+/// Splits a DER element into its tag, content and the bytes following it.
+fn der_read(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.get(0)?;
+    let first = *data.get(1)? as usize;
+    let (length, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let count = first & 0x7f;
+        let length = data.get(2..2 + count)?
+            .iter()
+            .fold(0usize, |length, byte| (length << 8) | *byte as usize);
+        (length, 2 + count)
+    };
+    let content = data.get(header..header + length)?;
+    Some((tag, content, &data[header + length..]))
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn u16_at(message: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([message[offset], message[offset + 1]])
+}
+
+fn u32_at(message: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&message[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn u64_at(message: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&message[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spnego_response_round_trips_token() {
+        let token = vec![0x42u8; 300];
+        assert_eq!(spnego_token(&spnego_response(&token)).unwrap(), token);
+    }
+
+    #[test]
+    fn spnego_init_wraps_ntlm_negotiate() {
+        let token = ntlm::negotiate_message();
+        let init = spnego_init(&token);
+        let (tag, content, rest) = der_read(&init).unwrap();
+        assert_eq!(tag, 0x60);
+        assert!(rest.is_empty());
+        assert!(content.starts_with(&der(0x06, SPNEGO_OID)));
+        assert!(init.ends_with(&token));
+    }
+
+    #[test]
+    fn extracts_token_from_server_response() {
+        // negState accept-incomplete, supportedMech NTLMSSP, responseToken.
+        let fields = [
+            der(0xa0, &der(0x0a, &[1])),
+            der(0xa1, &der(0x06, NTLMSSP_OID)),
+            der(0xa2, &der(0x04, b"NTLMSSP\0challenge")),
+        ].concat();
+        let blob = der(0xa1, &der(0x30, &fields));
+        assert_eq!(spnego_token(&blob).unwrap(), b"NTLMSSP\0challenge".to_vec());
+    }
+
+    #[test]
+    fn parses_directory_information() {
+        let mut buffer = Vec::new();
+        for (index, (name, attributes)) in [("Windows", FILE_ATTRIBUTE_DIRECTORY), ("a.txt", 0x20)].iter().enumerate() {
+            let name = ntlm::utf16le(name);
+            let mut entry = vec![0u8; 64];
+            entry[40..48].copy_from_slice(&(index as u64 * 10).to_le_bytes());
+            entry[56..60].copy_from_slice(&attributes.to_le_bytes());
+            entry[60..64].copy_from_slice(&(name.len() as u32).to_le_bytes());
+            entry.extend(name);
+            while entry.len() % 8 != 0 {
+                entry.push(0);
+            }
+            if index == 0 {
+                let length = entry.len() as u32;
+                entry[0..4].copy_from_slice(&length.to_le_bytes());
+            }
+            buffer.extend(entry);
+        }
+        assert_eq!(parse_directory_information(&buffer).unwrap(), vec![
+            DirectoryEntry { name: "Windows".to_string(), size: 0, is_directory: true },
+            DirectoryEntry { name: "a.txt".to_string(), size: 10, is_directory: false },
+        ]);
+    }
+
+    #[test]
+    fn signs_with_hmac_sha256_over_zeroed_signature() {
+        let mut message = vec![0u8; HEADER_SIZE + 8];
+        message[48..HEADER_SIZE].copy_from_slice(&[0xff; 16]);
+        let key = SigningKey::HmacSha256([7u8; 16]);
+        let mut zeroed = message.clone();
+        zeroed[48..HEADER_SIZE].copy_from_slice(&[0u8; 16]);
+        assert_eq!(key.signature(&message), key.signature(&zeroed));
+    }
+}
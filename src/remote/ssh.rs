@@ -1,13 +1,450 @@
-use crate::remote::{Connector, Computer, Command, FileCopier, RemoteFileCopier};
+use crate::remote::{Connector, Computer, Command, CommandOutcome, FileCopier, RemoteFileCopier, ProbeResult, Access, probe_with_command, is_port_open, JumpHost, Tunnel, split_host_port, HostKeyVerifier, user_known_hosts, RECORDED_HOSTS_FILE, Escalation, ESCALATION_CANDIDATES, refused_before_password, ends_with_prompt, strip_prompt, OsInfo, fingerprint_posix, process_running_posix, terminate_posix, operation_timeout, beyond_budget};
+use crate::arg_parser::Opts;
 use std::io;
-use crate::process_runner::{create_report_path, run_piped_processes_blocking};
+use std::io::{Read, Write, ErrorKind};
+use crate::process_runner::ProcessOutput;
 use std::fs::File;
 use std::path::{PathBuf, Path};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
+use std::cell::RefCell;
+use std::net::TcpStream;
+use std::thread;
+use ssh2::{ErrorCode, PtyModeOpcode, PtyModes, Session, Sftp};
+use wildmatch::WildMatch;
+use uuid::Uuid;
 
+pub const SSH_PORT: u16 = 22;
+
+const ESCALATION_CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(30);
+const PTY_COLUMNS: u32 = 4096;
+const PTY_ROWS: u32 = 24;
+
+/// Password typed into an escalated command, either right away or after its prompt.
+struct PasswordInput<'a> {
+    password: &'a str,
+    after_prompt: bool,
+}
+
+/// Connector to Linux targets using an in-process SSH session.
+/// Commands are run over an exec channel and files are transferred over SFTP,
+/// so no external SSH client is needed on the host.
+#[derive(Clone)]
+pub struct SshSettings {
+    pub key_file: Option<PathBuf>,
+    pub host_keys: HostKeyVerifier,
+    /// Forced way to elevate, detected on the target when `None`.
+    pub escalation: Option<Escalation>,
+}
+
+impl From<&Opts> for SshSettings {
+    fn from(opts: &Opts) -> Self {
+        SshSettings {
+            key_file: opts.ssh_key.clone().map(|key_file| PathBuf::from(key_file)),
+            host_keys: HostKeyVerifier {
+                known_hosts: opts.known_hosts.clone().map(|known_hosts| PathBuf::from(known_hosts))
+                    .or_else(user_known_hosts),
+                recorded_hosts: Path::new(&opts.local_store_directory).join(RECORDED_HOSTS_FILE),
+                policy: opts.ssh_host_key_policy,
+            },
+            escalation: opts.ssh_escalation,
+        }
+    }
+}
+
+/// Targets behind jump hosts are reached through tunnels of SSH sessions to the jump hosts.
 pub struct Ssh {
     pub computer: Computer,
-    pub key_file: Option<PathBuf>
+    settings: SshSettings,
+    session: RefCell<Option<Session>>,
+    tunnels: RefCell<Vec<Tunnel>>,
+    escalation: RefCell<Option<Escalation>>,
+}
+
+impl Ssh {
+    pub fn new(computer: Computer, settings: SshSettings) -> Ssh {
+        Ssh {
+            computer,
+            settings,
+            session: RefCell::new(None),
+            tunnels: RefCell::new(Vec::new()),
+            escalation: RefCell::new(None),
+        }
+    }
+
+    fn jump_hosts(&self) -> &[JumpHost] {
+        self.computer.ssh_jump.as_ref()
+            .map(|jump| jump.hosts.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns the session to the target, connecting on the first use.
+    fn session(&self) -> io::Result<Session> {
+        if let Some(session) = self.session.borrow().as_ref() {
+            return Ok(session.clone());
+        }
+        let session = self.connect()?;
+        *self.session.borrow_mut() = Some(session.clone());
+        Ok(session)
+    }
+
+    /// The target may be given as `host:port` to reach SSH on another port.
+    fn target(&self) -> io::Result<(&str, u16)> {
+        let (address, port) = split_host_port(&self.computer.address)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        Ok((address, port.unwrap_or(SSH_PORT)))
+    }
+
+    fn connect(&self) -> io::Result<Session> {
+        let (address, port) = self.target()?;
+        let jump_hosts = self.jump_hosts();
+        let mut tcp = match jump_hosts.first() {
+            None => TcpStream::connect((address, port))?,
+            Some(first) => TcpStream::connect((first.address.as_str(), first.port))?,
+        };
+        for (index, jump_host) in jump_hosts.iter().enumerate() {
+            debug!("Opening SSH session to jump host {} for {}", jump_host, self.computer.address);
+            let session = self.handshake(tcp, &jump_host.username, None, &jump_host.address, jump_host.port, None)?;
+            let (tunnel, stream) = match jump_hosts.get(index + 1) {
+                Some(next) => Tunnel::open(session, &next.address, next.port)?,
+                None => Tunnel::open(session, address, port)?,
+            };
+            self.tunnels.borrow_mut().push(tunnel);
+            tcp = stream;
+        }
+        debug!("Opening SSH session to {}", self.computer.address);
+        let pinned_host_key = self.computer.ssh_host_key.as_deref();
+        let password = self.computer.password.as_deref();
+        self.handshake(tcp, &self.computer.username, password, address, port, pinned_host_key)
+    }
+
+    /// Verifies the host key before sending any credentials.
+    fn handshake(
+        &self,
+        tcp: TcpStream,
+        username: &str,
+        password: Option<&str>,
+        address: &str,
+        port: u16,
+        pinned_host_key: Option<&str>,
+    ) -> io::Result<Session> {
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+        self.settings.host_keys.verify(&session, address, port, pinned_host_key)?;
+        self.authenticate(&session, username, password, address)?;
+        Ok(session)
+    }
+
+    /// Tries the key file, then the password or the SSH agent.
+    /// Jump hosts get no password, so the one of the target never leaves for them.
+    fn authenticate(&self, session: &Session, username: &str, password: Option<&str>, address: &str) -> io::Result<()> {
+        if let Some(key_file) = &self.settings.key_file {
+            if let Err(err) = session.userauth_pubkey_file(username, None, key_file, None) {
+                debug!("Public key authentication of {} on {} failed: {}", username, address, err);
+            }
+        }
+        if !session.authenticated() {
+            let result = match password {
+                Some(password) => session.userauth_password(username, password),
+                None => session.userauth_agent(username),
+            };
+            if let Err(err) = result {
+                debug!("Authentication of {} on {} failed: {}", username, address, err);
+            }
+        }
+        if session.authenticated() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("Cannot authenticate {} on {}", username, address),
+            ))
+        }
+    }
+
+    fn sftp(&self) -> io::Result<Sftp> {
+        Ok(self.session()?.sftp()?)
+    }
+
+    /// Runs the command line on the target and collects its output.
+    /// With a `terminal` the command runs on a pseudo-terminal without echo, which merges stderr into stdout.
+    /// Stdout and stderr are read alternately so that neither of them can fill the channel window
+    /// and stall the other.
+    fn exec(
+        &self,
+        command_line: &str,
+        terminal: bool,
+        password: Option<PasswordInput<'_>>,
+        timeout: Option<Duration>,
+    ) -> io::Result<ProcessOutput> {
+        let session = self.session()?;
+        let mut channel = session.channel_session()?;
+        if terminal {
+            let mut modes = PtyModes::new();
+            modes.set_boolean(PtyModeOpcode::ECHO, false);
+            modes.set_boolean(PtyModeOpcode::ONLCR, false);
+            channel.request_pty("dumb", Some(modes), Some((PTY_COLUMNS, PTY_ROWS, 0, 0)))?;
+        }
+        channel.exec(command_line)?;
+        let mut pending_password = None;
+        if let Some(password) = password {
+            if password.after_prompt {
+                pending_password = Some(password.password);
+            } else {
+                channel.write_all(format!("{}\n", password.password).as_bytes())?;
+                if !terminal {
+                    channel.send_eof()?;
+                }
+            }
+        }
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut stdout = Vec::<u8>::new();
+        let mut stderr = Vec::<u8>::new();
+        let mut buffer = [0u8; 32 * 1024];
+        let mut stdout_finished = false;
+        let mut stderr_finished = false;
+        let mut timed_out = false;
+        let mut prompt_end = None;
+        session.set_blocking(false);
+        let pumped: io::Result<()> = (|| {
+            while !stdout_finished || !stderr_finished {
+                let mut progressed = false;
+                if !stdout_finished {
+                    match channel.read(&mut buffer) {
+                        Ok(0) => stdout_finished = true,
+                        Ok(read) => {
+                            stdout.extend_from_slice(&buffer[..read]);
+                            progressed = true;
+                        }
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                if !stderr_finished {
+                    match channel.stderr().read(&mut buffer) {
+                        Ok(0) => stderr_finished = true,
+                        Ok(read) => {
+                            stderr.extend_from_slice(&buffer[..read]);
+                            progressed = true;
+                        }
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                if let Some(password) = pending_password {
+                    if ends_with_prompt(&stdout) {
+                        session.set_blocking(true);
+                        let written = channel.write_all(format!("{}\n", password).as_bytes());
+                        session.set_blocking(false);
+                        written?;
+                        pending_password = None;
+                        prompt_end = Some(stdout.len());
+                    }
+                }
+                if !progressed {
+                    if deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+                        timed_out = true;
+                        return Ok(());
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+            Ok(())
+        })();
+        session.set_blocking(true);
+        pumped?;
+        if let Some(prompt_end) = prompt_end {
+            strip_prompt(&mut stdout, prompt_end);
+        }
+
+        if timed_out {
+            warn!("Command \"{}\" on {} reached time out", command_line, self.computer.address);
+            channel.close()?;
+            return Ok(ProcessOutput { exit_status: None, stdout, stderr, timed_out });
+        }
+        channel.wait_close()?;
+        let exit_status = channel.exit_status().ok();
+        Ok(ProcessOutput { exit_status, stdout, stderr, timed_out })
+    }
+
+    /// Runs the command line elevated, the password never appears in the command line.
+    fn exec_escalated(
+        &self,
+        command_line: &str,
+        escalation: Escalation,
+        timeout: Option<Duration>,
+    ) -> io::Result<ProcessOutput> {
+        let password = match (escalation.needs_password(), &self.computer.password) {
+            (true, Some(password)) => Some(PasswordInput {
+                password,
+                after_prompt: escalation.waits_for_prompt(),
+            }),
+            _ => None,
+        };
+        self.exec(&escalation.wrap(command_line), escalation.needs_pty(), password, timeout)
+    }
+
+    /// Kills the shell of a timed out command and its children by the process id stored by `tracked_command_line`.
+    fn kill_tracked(&self, pid_file: &str, escalation: Option<Escalation>) {
+        let kill_line = format!(
+            "if [ -f {0} ]; then pid=$(cat {0}); pkill -KILL -P $pid; kill -KILL $pid; rm -f {0}; fi",
+            pid_file
+        );
+        let killed = beyond_budget(|| match escalation {
+            Some(escalation) => self.exec_escalated(&kill_line, escalation, Some(TERMINATE_TIMEOUT)),
+            None => self.exec(&kill_line, false, None, Some(TERMINATE_TIMEOUT)),
+        });
+        match killed {
+            Ok(output) if output.exit_status == Some(0) => debug!("Timed out command on {} was killed", self.computer.address),
+            Ok(output) => warn!("Cannot kill timed out command on {}, exit status {:?}", self.computer.address, output.exit_status),
+            Err(err) => warn!("Cannot kill timed out command on {} due to {}", self.computer.address, err),
+        }
+    }
+
+    /// The way elevated commands run on the target, detected on the first use unless forced by the settings.
+    fn escalation(&self) -> io::Result<Escalation> {
+        if let Some(escalation) = *self.escalation.borrow() {
+            return Ok(escalation);
+        }
+        let escalation = match self.settings.escalation {
+            Some(escalation) => escalation,
+            None => self.detect_escalation()?,
+        };
+        if escalation == Escalation::Unavailable {
+            warn!("Cannot elevate on {}, commands run with the rights of {}", self.computer.address, self.computer.username);
+        } else {
+            info!("Elevated commands on {} run using {}", self.computer.address, escalation);
+        }
+        *self.escalation.borrow_mut() = Some(escalation);
+        Ok(escalation)
+    }
+
+    fn detect_escalation(&self) -> io::Result<Escalation> {
+        let id = self.exec("id -u", false, None, Some(ESCALATION_CHECK_TIMEOUT))?;
+        if String::from_utf8_lossy(&id.stdout).trim() == "0" {
+            return Ok(Escalation::AlreadyRoot);
+        }
+        for candidate in ESCALATION_CANDIDATES.iter() {
+            if candidate.needs_password() && self.computer.password.is_none() {
+                continue;
+            }
+            let output = self.exec_escalated("true", *candidate, Some(ESCALATION_CHECK_TIMEOUT))?;
+            debug!("Checking {} on {} finished with {:?}", candidate, self.computer.address, output.exit_status);
+            if output.exit_status == Some(0) {
+                return Ok(*candidate);
+            }
+            // Trying the same password again with another program could lock the account.
+            if candidate.needs_password() && !refused_before_password(output.exit_status, &output.stderr) {
+                warn!("Password for {} was rejected by {} on {}, no other escalation is tried", self.computer.username, candidate, self.computer.address);
+                break;
+            }
+        }
+        Ok(Escalation::Unavailable)
+    }
+
+    /// Strips the `address:` prefix added by `path_to_remote_form`.
+    fn remote_path(&self, path: &Path) -> Option<PathBuf> {
+        path.to_str()
+            .and_then(|path| path.strip_prefix(&format!("{}:", self.computer.address)))
+            .map(|path| PathBuf::from(path))
+    }
+
+    fn download(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let sftp = self.sftp()?;
+        let file_name = file_name_of(source);
+        if !file_name.contains('*') && !file_name.contains('?') {
+            return download_entry(&sftp, source, target);
+        }
+        let parent = source.parent().unwrap_or_else(|| Path::new("/"));
+        let pattern = WildMatch::new(&file_name);
+        for (entry, _) in sftp.readdir(parent)? {
+            let entry_name = file_name_of(&entry);
+            if pattern.matches(&entry_name) {
+                let entry = remote_join(parent, &entry_name);
+                if let Err(err) = download_entry(&sftp, &entry, target) {
+                    error!("Cannot download {} from {} due to {}", entry.display(), self.computer.address, err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn upload(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let sftp = self.sftp()?;
+        upload_entry(&sftp, source, target)
+    }
+}
+
+impl Drop for Ssh {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.get_mut().take() {
+            if let Err(err) = session.disconnect(None, "Gargamel finished", None) {
+                debug!("Cannot disconnect SSH session to {}: {}", self.computer.address, err);
+            }
+        }
+        // The innermost tunnel runs over the outer ones, so it is closed first.
+        for tunnel in self.tunnels.get_mut().drain(..).rev() {
+            drop(tunnel);
+        }
+    }
+}
+
+/// Runs the command line in a shell that stores its process id in the `pid_file` while the command runs.
+/// Lines keep the command apart, so it may end with `&` or a comment.
+fn tracked_command_line(command_line: &str, pid_file: &str) -> String {
+    format!("echo $$ > {0}\n{1}\nstatus=$?; rm -f {0}; exit $status", pid_file, command_line)
+}
+
+/// Joins remote paths with `/` regardless of the host platform.
+fn remote_join(dir: &Path, name: &str) -> PathBuf {
+    PathBuf::from(format!("{}/{}", dir.to_string_lossy().trim_end_matches('/'), name))
+}
+
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|it| it.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn download_entry(sftp: &Sftp, source: &Path, target: &Path) -> io::Result<()> {
+    let local_path = target.join(file_name_of(source));
+    if sftp.stat(source)?.is_dir() {
+        std::fs::create_dir_all(&local_path)?;
+        for (entry, _) in sftp.readdir(source)? {
+            let entry = remote_join(source, &file_name_of(&entry));
+            if let Err(err) = download_entry(sftp, &entry, &local_path) {
+                error!("Cannot download {} due to {}", entry.display(), err);
+            }
+        }
+        Ok(())
+    } else {
+        trace!("Downloading {} to {}", source.display(), local_path.display());
+        let mut remote_file = sftp.open(source)?;
+        let mut local_file = File::create(&local_path)?;
+        io::copy(&mut remote_file, &mut local_file).map(|_| ())
+    }
+}
+
+fn upload_entry(sftp: &Sftp, source: &Path, target: &Path) -> io::Result<()> {
+    let remote_path = remote_join(target, &file_name_of(source));
+    if source.is_dir() {
+        if sftp.stat(&remote_path).is_err() {
+            sftp.mkdir(&remote_path, 0o700)?;
+        }
+        for entry in source.read_dir()?.filter_map(|entry| entry.ok()) {
+            if let Err(err) = upload_entry(sftp, &entry.path(), &remote_path) {
+                error!("Cannot upload {} due to {}", entry.path().display(), err);
+            }
+        }
+        Ok(())
+    } else {
+        trace!("Uploading {} to {}", source.display(), remote_path.display());
+        let mut local_file = File::open(source)?;
+        let mut remote_file = sftp.create(&remote_path)?;
+        io::copy(&mut local_file, &mut remote_file).map(|_| ())
+    }
 }
 
 impl Connector for Ssh {
@@ -27,157 +464,161 @@ impl Connector for Ssh {
         Path::new("/tmp")
     }
 
+    fn fingerprint(&self, store_directory: &Path) -> OsInfo {
+        fingerprint_posix(self, store_directory)
+    }
+
+    fn remote_file_size(&self, path: &Path) -> io::Result<Option<u64>> {
+        // LIBSSH2_FX_NO_SUCH_FILE
+        match self.sftp()?.stat(path) {
+            Ok(stat) => Ok(Some(stat.size.unwrap_or_default())),
+            Err(err) if err.code() == ErrorCode::SFTP(2) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn remote_process_running(&self, program: &str) -> io::Result<bool> {
+        process_running_posix(self, program)
+    }
+
+    fn terminate_remote_process(&self, program: &str) -> io::Result<()> {
+        terminate_posix(self, program)
+    }
+
+    /// Admin rights mean that the user is root or some way to elevate works, which is reported in the detail.
+    fn probe(&self) -> ProbeResult {
+        // Behind jump hosts the SSH port of the target is not reachable from this host.
+        if self.jump_hosts().is_empty() {
+            if let Ok((address, port)) = self.target() {
+                if !is_port_open(address, port) {
+                    return ProbeResult::new(self.connect_method_name(), Access::Unreachable, Some(format!("port {} is closed", port)));
+                }
+            }
+        }
+        let mut result = probe_with_command(self, None, vec!["true".to_string()], true);
+        if let Some(escalation) = *self.escalation.borrow() {
+            if escalation == Escalation::Unavailable && result.access == Access::Admin {
+                result.access = Access::User;
+            }
+            result.detail = Some(match result.detail {
+                None => format!("escalation: {}", escalation),
+                Some(detail) => format!("escalation: {}, {}", escalation, detail),
+            });
+        }
+        result
+    }
+
+    fn mkdir(&self, path: &Path) {
+        let command = Command::new(
+            vec![
+                "mkdir".to_string(),
+                "-p".to_string(),
+                path.to_str().unwrap_or_default().to_string(),
+            ],
+            None,
+            "",
+            false,
+        );
+        if let Err(err) = self.connect_and_run_command(command, Some(Duration::from_secs(10))) {
+            error!("{}", err);
+        }
+    }
+
     fn connect_and_run_command(&self,
                                remote_connection: Command<'_>,
-                               _timeout: Option<Duration>
-    ) -> io::Result<Option<PathBuf>> {
+                               timeout: Option<Duration>
+    ) -> io::Result<CommandOutcome> {
         debug!("Trying to run command {:?} on {}",
                remote_connection.command,
                &self.computer().address
         );
-        let output_file_path = match remote_connection.report_store_directory {
-            None => None,
-            Some(store_directory) => {
-                let file_path = create_report_path(
-                    &self.computer(),
-                    store_directory,
-                    &remote_connection.report_filename_prefix,
-                    self.connect_method_name(),
-                    "txt"
-                );
-                {
-                    File::create(&file_path)?;
-                }
-                Some(dunce::canonicalize(file_path)?.to_str().unwrap().to_string())
-            }
+        let command_line = remote_connection.command.join(" ");
+        let timeout = operation_timeout(timeout)?;
+        let escalation = if remote_connection.elevated { Some(self.escalation()?) } else { None };
+        // Closing the channel of a timed out command does not stop it, so it is killed by the process id it stored.
+        let pid_file = timeout.map(|_| format!("/tmp/.gargamel-{}.pid", Uuid::new_v4()));
+        let tracked_line = match &pid_file {
+            None => command_line.clone(),
+            Some(pid_file) => tracked_command_line(&command_line, pid_file),
         };
-
-        let echo = vec!["echo".to_string(), "n".to_string()];
-        let prepared_echo = self.prepare_remote_process(echo);
-
-        let processed_command = self.prepare_command(
-            remote_connection.command,
-            output_file_path.as_deref(),
-            false
-        );
-        let prepared_command = self.prepare_remote_process(processed_command);
-        run_piped_processes_blocking(
-            "cmd.exe",
-            &prepared_echo,
-            "cmd.exe",
-            &prepared_command)?;
-        Ok(output_file_path.map(|it| PathBuf::from(it)))
+        let started = SystemTime::now();
+        let output = match escalation {
+            Some(escalation) => self.exec_escalated(&tracked_line, escalation, timeout)?,
+            None => self.exec(&tracked_line, false, None, timeout)?,
+        };
+        if let (true, Some(pid_file)) = (output.timed_out, &pid_file) {
+            self.kill_tracked(pid_file, escalation);
+        }
+        let outcome = CommandOutcome::store(
+            self.computer(),
+            self.connect_method_name(),
+            remote_connection.report_store_directory,
+            remote_connection.report_filename_prefix,
+            output,
+            started,
+        )?;
+        trace!("Command \"{}\" on {} finished with {}", command_line, self.computer.address, outcome);
+        Ok(outcome)
     }
 
     fn prepare_command(&self,
                        command: Vec<String>,
-                       output_file_path: Option<&str>,
                        elevated: bool,
     ) -> Vec<String> {
-        let remote_computer = self.remote_computer();
-        let program_name = "plink.exe".to_string();
-        let mut prepared_command = vec![
-            program_name,
-            "-ssh".to_string(),
-            remote_computer.address.clone(),
-            "-l".to_string(),
-            remote_computer.username.clone(),
-            "-no-antispoof".to_string()
-        ];
-        if let Some(password) = &remote_computer.password {
-            prepared_command.push("-pw".to_string());
-            prepared_command.push(password.clone());
-        }
-
-        if let Some(key_file) = &self.key_file {
-            prepared_command.push("-i".to_string());
-            prepared_command.push(key_file.to_string_lossy().to_string())
-        }
-        if elevated {
-            if let Some(password) = &remote_computer.password {
-                prepared_command.push(format!("echo {} | sudo -S {}", password, command.join(" ")));
-            } else {
-                prepared_command.push(format!("sudo -S {}", command.join(" ")));
-            }
-        }else {
-            prepared_command.push(command.join(" "));
-        }
-        match output_file_path {
-            None => prepared_command,
-            Some(output_file_path) => {
-                prepared_command.push(">".to_string());
-                prepared_command.push(output_file_path.to_string());
-                prepared_command
-            }
+        let command_line = command.join(" ");
+        if !elevated {
+            return vec![command_line];
         }
+        let escalation = self.escalation().unwrap_or_else(|err| {
+            error!("Cannot detect how to elevate on {} due to {}", self.computer.address, err);
+            Escalation::Unavailable
+        });
+        vec![escalation.wrap(&command_line)]
+    }
+
+    fn list_dirs(&self, path: &Path, _store_directory: &Path) -> Vec<String> {
+        debug!("Listing dirs in remote path {}", path.display());
+        let entries = self.sftp().and_then(|sftp| Ok(sftp.readdir(path)?));
+        let result = match entries {
+            Ok(entries) => entries.iter()
+                .filter(|(_, stat)| stat.is_dir())
+                .map(|(entry, _)| file_name_of(entry))
+                .collect(),
+            Err(err) => {
+                error!("Cannot list {} on {} due to {}", path.display(), self.computer.address, err);
+                vec![]
+            }
+        };
+        debug!("Remote path {} has dirs {:?}", path.display(), result);
+        result
     }
 }
 
 impl FileCopier for Ssh {
+    /// Either `source` or `target` must be in the remote form (`address:path`)
+    /// to decide the direction of the copy.
     fn copy_file(
         &self,
         source: &Path,
         target: &Path,
     ) -> io::Result<()> {
-        let mut scp = vec![
-            "-l".to_string(),
-            self.computer.username.clone(),
-        ];
-        if let Some(password) = &self.computer.password {
-            scp.push("-pw".to_string());
-            scp.push(password.clone());
-        }
-        if let Some(key_file) = &self.key_file {
-            scp.push("-i".to_string());
-            scp.push(key_file.to_string_lossy().to_string())
-        }
-        scp.push(format!("{}", source.to_string_lossy()));
-        scp.push(format!("{}", target.to_string_lossy()));
-        run_piped_processes_blocking(
-            "cmd",
-            &[
-                "/c".to_string(),
-                "echo".to_string(),
-                "n".to_string()
-            ],
-            "pscp.exe",
-            &scp,
-        )
+        match (self.remote_path(source), self.remote_path(target)) {
+            (Some(source), _) => self.download(&source, target),
+            (None, Some(target)) => self.upload(source, &target),
+            (None, None) => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Neither {} nor {} is a remote path", source.display(), target.display()),
+            )),
+        }
     }
 
     fn delete_file(&self, target: &Path) -> io::Result<()> {
-        let mut params = vec![
-            "-ssh".to_string(),
-            self.computer.address.clone(),
-            "-l".to_string(),
-            self.computer.username.clone(),
-            "-no-antispoof".to_string()
-        ];
-        if let Some(password) = &self.computer.password {
-            params.push("-pw".to_string());
-            params.push(password.clone());
-        }
-        if let Some(key_file) = &self.key_file {
-            params.push("-i".to_string());
-            params.push(key_file.to_string_lossy().to_string())
-        }
-        params.push("rm".to_string());
-        params.push("-f".to_string());
-        params.push(target.to_string_lossy().to_string());
-        run_piped_processes_blocking(
-            "cmd",
-            &[
-                "/c".to_string(),
-                "echo".to_string(),
-                "n".to_string()
-            ],
-            "plink.exe",
-            &params,
-        )
+        let target = self.remote_path(target).unwrap_or_else(|| target.to_path_buf());
+        Ok(self.sftp()?.unlink(&target)?)
     }
 
     fn method_name(&self) -> &'static str {
-        "SCP"
+        "SFTP"
     }
 }
 
@@ -0,0 +1,511 @@
+use crate::remote::{Connector, Computer, Command, CommandOutcome, FileCopier, RemoteFileCopier, WsManClient, MAX_INPUT_CHUNK, ProbeResult, probe_with_command, operation_timeout, Footprint, record_upload, footprint_removed};
+use crate::process_runner::ProcessOutput;
+use crate::arg_parser::Opts;
+use std::io;
+use std::io::{Read, Write, ErrorKind};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use std::cell::RefCell;
+
+pub const WINRM_HTTP_PORT: u16 = 5985;
+pub const WINRM_HTTPS_PORT: u16 = 5986;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WinRmAuth {
+    Ntlm,
+    Basic,
+}
+
+#[derive(Clone)]
+pub struct WinRmSettings {
+    pub https: bool,
+    pub port: Option<u16>,
+    pub auth: WinRmAuth,
+    pub accept_invalid_certs: bool,
+}
+
+impl WinRmSettings {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.https { WINRM_HTTPS_PORT } else { WINRM_HTTP_PORT })
+    }
+}
+
+impl From<&Opts> for WinRmSettings {
+    fn from(opts: &Opts) -> Self {
+        WinRmSettings {
+            https: opts.winrm_https,
+            port: opts.winrm_port,
+            auth: if opts.winrm_basic { WinRmAuth::Basic } else { WinRmAuth::Ntlm },
+            accept_invalid_certs: opts.winrm_insecure,
+        }
+    }
+}
+
+/// Connector to Windows targets using WinRM (PowerShell remoting).
+/// Commands run in a remote shell and files are transferred through the standard streams
+/// of a remote PowerShell process, so neither PowerShell nor shared folders are needed on the host.
+pub struct WinRm {
+    pub computer: Computer,
+    settings: WinRmSettings,
+    remote_temp_storage: PathBuf,
+    shell: RefCell<Option<(WsManClient, String)>>,
+}
+
+/// Result of a command whose standard output was consumed while it ran.
+struct RunResult {
+    exit_status: Option<i32>,
+    stderr: Vec<u8>,
+    timed_out: bool,
+}
+
+impl WinRm {
+    pub fn new(computer: Computer, remote_temp_storage: PathBuf, settings: WinRmSettings) -> WinRm {
+        WinRm {
+            computer,
+            settings,
+            remote_temp_storage,
+            shell: RefCell::new(None),
+        }
+    }
+
+    /// Calls `f` with the remote shell, opening it on the first use.
+    fn with_shell<T, F>(&self, f: F) -> io::Result<T>
+        where F: FnOnce(&mut WsManClient, &str) -> io::Result<T> {
+        let mut shell = self.shell.borrow_mut();
+        if shell.is_none() {
+            let mut client = WsManClient::new(self.computer.clone(), self.settings.clone());
+            let shell_id = client.open_shell()?;
+            *shell = Some((client, shell_id));
+        }
+        let (client, shell_id) = shell.as_mut().unwrap();
+        f(client, shell_id)
+    }
+
+    /// Runs the command line in the remote shell.
+    /// Standard input is streamed from `input` and standard output is passed to `on_stdout` as it arrives.
+    fn run(
+        &self,
+        command_line: &str,
+        input: Option<&mut dyn Read>,
+        on_stdout: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+        timeout: Option<Duration>,
+    ) -> io::Result<RunResult> {
+        let timeout = operation_timeout(timeout)?;
+        self.with_shell(|client, shell_id| {
+            let command_id = client.start_command(shell_id, command_line)?;
+            if let Some(input) = input {
+                let mut buffer = vec![0u8; MAX_INPUT_CHUNK];
+                loop {
+                    let read = input.read(&mut buffer)?;
+                    client.send_input(shell_id, &command_id, &buffer[..read], read == 0)?;
+                    if read == 0 {
+                        break;
+                    }
+                }
+            }
+
+            let deadline = timeout.map(|timeout| Instant::now() + timeout);
+            let mut stderr = Vec::new();
+            loop {
+                let received = client.receive(shell_id, &command_id)?;
+                if !received.stdout.is_empty() {
+                    if let Err(err) = on_stdout(&received.stdout) {
+                        let _ = client.terminate_command(shell_id, &command_id);
+                        return Err(err);
+                    }
+                }
+                stderr.extend(received.stderr);
+                if received.done {
+                    let _ = client.terminate_command(shell_id, &command_id);
+                    return Ok(RunResult { exit_status: received.exit_code, stderr, timed_out: false });
+                }
+                if deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+                    warn!("Command \"{}\" on {} reached time out", command_line, self.computer.address);
+                    client.terminate_command(shell_id, &command_id)?;
+                    return Ok(RunResult { exit_status: None, stderr, timed_out: true });
+                }
+            }
+        })
+    }
+
+    /// Strips the `address:` prefix added by `path_to_remote_form`.
+    fn remote_path(&self, path: &Path) -> Option<PathBuf> {
+        path.to_str()
+            .and_then(|path| path.strip_prefix(&format!("{}:", self.computer.address)))
+            .map(|path| PathBuf::from(path))
+    }
+
+    fn upload(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let remote_path = remote_join(target, &file_name_of(source));
+        if source.is_dir() {
+            self.mkdir(Path::new(&remote_path));
+            for entry in source.read_dir()?.filter_map(|entry| entry.ok()) {
+                if let Err(err) = self.upload(&entry.path(), Path::new(&remote_path)) {
+                    error!("Cannot upload {} due to {}", entry.path().display(), err);
+                }
+            }
+            return Ok(());
+        }
+        trace!("Uploading {} to {}", source.display(), remote_path);
+        let script = format!(
+            "$ErrorActionPreference = 'Stop'\n\
+            $stdin = [Console]::OpenStandardInput()\n\
+            $file = [IO.File]::Create({})\n\
+            try {{\n\
+                $buffer = New-Object byte[] 65536\n\
+                while (($read = $stdin.Read($buffer, 0, $buffer.Length)) -gt 0) {{\n\
+                    $file.Write($buffer, 0, $read)\n\
+                }}\n\
+            }} finally {{\n\
+                $file.Close()\n\
+            }}",
+            powershell_quote(&remote_path)
+        );
+        let mut file = File::open(source)?;
+        let result = self.run(&powershell(&script), Some(&mut file), &mut |_| Ok(()), None)?;
+        self.check_transfer(result, &format!("upload {} to {}", source.display(), remote_path))
+    }
+
+    /// Downloads files matching the `source` into the `target` directory.
+    /// Wildcards are supported in any component of the `source` and directories are downloaded recursively.
+    fn download(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let source = windows_path(source);
+        let script = format!(
+            "$ErrorActionPreference = 'SilentlyContinue'\n\
+            Get-Item -Path {} -Force | ForEach-Object {{\n\
+                if ($_.PSIsContainer) {{\n\
+                    Get-ChildItem -LiteralPath $_.FullName -Recurse -Force | Where-Object {{ -not $_.PSIsContainer }}\n\
+                }} else {{\n\
+                    $_\n\
+                }}\n\
+            }} | ForEach-Object {{\n\
+                [Console]::Out.WriteLine([Convert]::ToBase64String([Text.Encoding]::UTF8.GetBytes($_.FullName)))\n\
+            }}",
+            powershell_quote(&source)
+        );
+        let mut listing = Vec::new();
+        self.run(&powershell(&script), None, &mut |data| {
+            listing.extend_from_slice(data);
+            Ok(())
+        }, None)?;
+        let files = String::from_utf8_lossy(&listing)
+            .lines()
+            .filter_map(|line| base64::decode(line.trim()).ok())
+            .map(|file| String::from_utf8_lossy(&file).to_string())
+            .filter(|file| !file.is_empty())
+            .collect::<Vec<String>>();
+        if files.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Cannot find {} on {}", source, self.computer.address),
+            ));
+        }
+        let base = transfer_base(&source);
+        for file in files {
+            let local_path = local_path_of(&file, &base, target);
+            if let Some(parent) = local_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            if let Err(err) = self.download_file(&file, &local_path) {
+                error!("Cannot download {} from {} due to {}", file, self.computer.address, err);
+            }
+        }
+        Ok(())
+    }
+
+    fn download_file(&self, source: &str, target: &Path) -> io::Result<()> {
+        trace!("Downloading {} to {}", source, target.display());
+        let script = format!(
+            "$ErrorActionPreference = 'Stop'\n\
+            $file = [IO.File]::Open({}, 'Open', 'Read', 'ReadWrite')\n\
+            $stdout = [Console]::OpenStandardOutput()\n\
+            try {{\n\
+                $buffer = New-Object byte[] 65536\n\
+                while (($read = $file.Read($buffer, 0, $buffer.Length)) -gt 0) {{\n\
+                    $stdout.Write($buffer, 0, $read)\n\
+                }}\n\
+                $stdout.Flush()\n\
+            }} finally {{\n\
+                $file.Close()\n\
+            }}",
+            powershell_quote(source)
+        );
+        let mut file = File::create(target)?;
+        let result = self.run(&powershell(&script), None, &mut |data| file.write_all(data), None)?;
+        self.check_transfer(result, &format!("download {}", source))
+    }
+
+    fn delete(&self, target: &Path) -> io::Result<()> {
+        let command = Command::new(
+            vec![
+                "cmd.exe".to_string(),
+                "/c".to_string(),
+                "del".to_string(),
+                "/F".to_string(),
+                "/Q".to_string(),
+                windows_path(target),
+            ],
+            None,
+            "",
+            true,
+        );
+        let outcome = self.connect_and_run_command(command, None)?;
+        if outcome.is_failure() {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!("Cannot delete {} on {}: {}", target.display(), self.computer.address, outcome),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_transfer(&self, result: RunResult, operation: &str) -> io::Result<()> {
+        if result.exit_status == Some(0) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Cannot {} on {} (exit status {:?}): {}",
+                    operation,
+                    self.computer.address,
+                    result.exit_status,
+                    String::from_utf8_lossy(&result.stderr).trim()
+                ),
+            ))
+        }
+    }
+}
+
+impl Drop for WinRm {
+    fn drop(&mut self) {
+        if let Some((client, shell_id)) = self.shell.get_mut().as_mut() {
+            if let Err(err) = client.close_shell(shell_id) {
+                debug!("Cannot close WinRM shell on {}: {}", self.computer.address, err);
+            }
+        }
+    }
+}
+
+/// Command line running the script in PowerShell without exposing it to cmd.exe quoting rules.
+fn powershell(script: &str) -> String {
+    let encoded = script.encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes().to_vec())
+        .collect::<Vec<u8>>();
+    format!(
+        "powershell.exe -NoProfile -NonInteractive -ExecutionPolicy Bypass -EncodedCommand {}",
+        base64::encode(encoded)
+    )
+}
+
+pub(crate) fn powershell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// Quotes an argument for a Windows command line unless it is already quoted.
+fn quote_windows(arg: &str) -> String {
+    let already_quoted = arg.len() >= 2 && arg.starts_with('"') && arg.ends_with('"');
+    if already_quoted || (!arg.is_empty() && !arg.contains(|c: char| c == ' ' || c == '\t' || c == '"')) {
+        arg.to_string()
+    } else {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    }
+}
+
+/// Converts the path to Windows separators. Paths built on non-Windows hosts may mix both.
+fn windows_path(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('/', "\\");
+    let (prefix, rest) = if path.starts_with("\\\\") {
+        ("\\\\", &path[2..])
+    } else {
+        ("", &path[..])
+    };
+    let mut normalized = prefix.to_string();
+    for c in rest.chars() {
+        if c != '\\' || !normalized[prefix.len()..].ends_with('\\') {
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
+fn remote_join(dir: &Path, name: &str) -> String {
+    format!("{}\\{}", windows_path(dir).trim_end_matches('\\'), name)
+}
+
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|it| it.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Directory of the `source` pattern to which downloaded files keep their relative paths.
+fn transfer_base(source: &str) -> String {
+    let components = source.split('\\').collect::<Vec<&str>>();
+    let last = components.len().saturating_sub(1);
+    let end = components.iter()
+        .position(|component| component.contains('*') || component.contains('?'))
+        .filter(|wildcard| *wildcard < last)
+        .unwrap_or(last);
+    components[..end].join("\\")
+}
+
+fn local_path_of(remote_file: &str, base: &str, target: &Path) -> PathBuf {
+    let relative = remote_file.get(..base.len())
+        .filter(|prefix| !base.is_empty() && prefix.eq_ignore_ascii_case(base))
+        .and_then(|_| remote_file[base.len()..].strip_prefix('\\'));
+    match relative {
+        Some(relative) => relative.split('\\')
+            .filter(|component| !component.is_empty())
+            .fold(target.to_path_buf(), |path, component| path.join(component)),
+        None => target.join(remote_file.rsplit('\\').next().unwrap_or(remote_file)),
+    }
+}
+
+impl Connector for WinRm {
+    fn connect_method_name(&self) -> &'static str {
+        "WINRM"
+    }
+
+    fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    fn copier(&self) -> &dyn RemoteFileCopier {
+        self as &dyn RemoteFileCopier
+    }
+
+    fn remote_temp_storage(&self) -> &Path {
+        self.remote_temp_storage.as_path()
+    }
+
+    fn probe(&self) -> ProbeResult {
+        probe_with_command(self, Some(self.settings.port()), vec!["net".to_string(), "session".to_string()], false)
+    }
+
+    fn connect_and_run_local_program(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let local_program_path = PathBuf::from(&command_to_run.command[0]);
+        self.upload(&local_program_path, self.remote_temp_storage())?;
+        let remote_program_path = remote_join(self.remote_temp_storage(), &file_name_of(&local_program_path));
+        record_upload(self.connect_method_name(), Path::new(&remote_program_path), &local_program_path);
+        let mut command = command_to_run.command;
+        command[0] = remote_program_path.clone();
+
+        let command_to_run = Command {
+            command,
+            ..command_to_run
+        };
+        let result = self.connect_and_run_command(command_to_run, timeout);
+        match self.delete(Path::new(&remote_program_path)) {
+            Ok(()) => footprint_removed(&Footprint::RemoteFile(PathBuf::from(&remote_program_path))),
+            Err(err) => error!("{}", err),
+        }
+        result
+    }
+
+    fn connect_and_run_command(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        debug!("Trying to run command {:?} on {}",
+               command_to_run.command,
+               &self.computer().address
+        );
+        let command_line = self.prepare_command(
+            command_to_run.command,
+            command_to_run.elevated,
+        ).iter()
+            .map(|arg| quote_windows(arg))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let started = SystemTime::now();
+        let mut stdout = Vec::new();
+        let result = self.run(&command_line, None, &mut |data| {
+            stdout.extend_from_slice(data);
+            Ok(())
+        }, timeout)?;
+        let outcome = CommandOutcome::store(
+            self.computer(),
+            self.connect_method_name(),
+            command_to_run.report_store_directory,
+            command_to_run.report_filename_prefix,
+            ProcessOutput {
+                exit_status: result.exit_status,
+                stdout,
+                stderr: result.stderr,
+                timed_out: result.timed_out,
+            },
+            started,
+        )?;
+        trace!("Command \"{}\" on {} finished with {}", command_line, self.computer.address, outcome);
+        Ok(outcome)
+    }
+
+    /// WinRM runs commands with the full token of the connected user,
+    /// so there is nothing to add for elevated commands.
+    fn prepare_command(&self,
+                       command: Vec<String>,
+                       _elevated: bool,
+    ) -> Vec<String> {
+        command
+    }
+}
+
+impl FileCopier for WinRm {
+    fn copy_file(&self, source: &Path, target: &Path) -> io::Result<()> {
+        match (self.remote_path(source), self.remote_path(target)) {
+            (Some(source), None) => self.download(&source, target),
+            (None, Some(target)) => self.upload(source, &target),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Exactly one of {} and {} must be remote", source.display(), target.display()),
+            )),
+        }
+    }
+
+    fn delete_file(&self, target: &Path) -> io::Result<()> {
+        match self.remote_path(target) {
+            Some(target) => self.delete(&target),
+            None => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not a remote path", target.display()),
+            )),
+        }
+    }
+
+    fn method_name(&self) -> &'static str {
+        "WinRM"
+    }
+}
+
+impl RemoteFileCopier for WinRm {
+    fn remote_computer(&self) -> &Computer {
+        self.computer()
+    }
+
+    fn copier_impl(&self) -> &dyn FileCopier {
+        self as &dyn FileCopier
+    }
+
+    fn path_to_remote_form(&self, path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}:{}", self.computer.address, path.display()))
+    }
+
+    fn copy_to_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.upload(source, target)
+    }
+
+    fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
+        self.delete(target)
+    }
+
+    fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.download(source, target)
+    }
+}
@@ -1,4 +1,5 @@
-use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, copy_from_remote_wildcards};
+use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, copy_from_remote_wildcards, ProbeResult, probe_over_smb, RPC_PORT, PasswordFile, operation_timeout};
+use crate::remote::winrm::powershell_quote;
 use std::path::{Path, PathBuf};
 use std::io;
 use crate::process_runner::{run_process_blocking_maybe_timed, run_process_blocking_timed};
@@ -6,7 +7,8 @@ use std::time::Duration;
 
 pub struct Wmi {
     pub computer: Computer,
-    pub remote_temp_storage: PathBuf
+    pub remote_temp_storage: PathBuf,
+    password_file: PasswordFile,
 }
 
 impl Connector for Wmi {
@@ -26,43 +28,62 @@ impl Connector for Wmi {
         self.remote_temp_storage.as_path()
     }
 
+    fn probe(&self) -> ProbeResult {
+        probe_over_smb(self.connect_method_name(), &self.computer, RPC_PORT)
+    }
+
     fn prepare_command(&self,
                        command: Vec<String>,
-                       output_file_path: Option<&str>,
                        _elevated: bool,
     ) -> Vec<String> {
         let remote_computer = self.remote_computer();
-        let program_name = "powershell.exe".to_string();
         let command_joined: String = command.join(" ");
-        let mut prepared_command = vec![
-            program_name,
-            "-File".to_string(),
-            "WMImplant.ps1".to_string(),
+        let mut prepared_command = vec!["powershell.exe".to_string()];
+        prepared_command.extend(self.wmimplant_args(vec![
             "-ComputerName".to_string(),
             remote_computer.address.clone(),
             "-RemoteUser".to_string(),
             remote_computer.domain_username(),
             "-CommandExec".to_string(),
             "-RemoteCommand".to_string(),
-            format!("{}", command_joined),
-        ];
+            command_joined,
+        ]));
+        prepared_command
+    }
+}
 
-        if let Some(password) = &remote_computer.password {
-            prepared_command.push("-RemotePass".to_string());
-            prepared_command.push(password.clone());
+impl Wmi {
+    pub fn new(computer: Computer, remote_temp_storage: PathBuf) -> Wmi {
+        Wmi {
+            password_file: PasswordFile::new(&computer),
+            computer,
+            remote_temp_storage,
         }
-        match output_file_path {
-            None => prepared_command,
-            Some(output_file_path) => {
-                prepared_command.push(">".to_string());
-                prepared_command.push(output_file_path.to_string());
-                prepared_command
+    }
+
+    /// Arguments of `powershell.exe` running WMImplant with the given parameters.
+    /// WMImplant reads the password from a temporary file, so it does not appear on the command line.
+    fn wmimplant_args(&self, parameters: Vec<String>) -> Vec<String> {
+        let mut script = vec!["&".to_string(), powershell_quote(".\\WMImplant.ps1")];
+        script.extend(parameters.iter().map(|parameter| {
+            let is_switch = parameter.starts_with('-') && parameter[1..].chars().all(|c| c.is_ascii_alphabetic());
+            if is_switch { parameter.clone() } else { powershell_quote(parameter) }
+        }));
+        match self.password_file.path() {
+            Ok(None) => {}
+            Ok(Some(path)) => {
+                script.push("-RemotePass".to_string());
+                script.push(format!("(Get-Content -LiteralPath {} -Raw)", powershell_quote(&path.to_string_lossy())));
+            }
+            Err(err) => {
+                warn!("Cannot store password of {} in a file due to {}, passing it on the command line", self.computer.address, err);
+                script.push("-RemotePass".to_string());
+                script.push(powershell_quote(self.computer.password.as_deref().unwrap_or_default()));
             }
         }
+        vec!["-NoProfile".to_string(), "-Command".to_string(), script.join(" ")]
     }
-}
 
-impl Wmi {
     fn copy_impl(&self,
                  source: &Path,
                  target: &Path,
@@ -77,8 +98,6 @@ impl Wmi {
         };
 
         let mut prepared_command = vec![
-            "-File".to_string(),
-            "WMImplant.ps1".to_string(),
             method_name.to_string(),
             "-ComputerName".to_string(),
             remote_computer.address.clone(),
@@ -98,15 +117,10 @@ impl Wmi {
             prepared_command.push(target.to_string_lossy().to_string());
         }
 
-        if let Some(password) = &remote_computer.password {
-            prepared_command.push("-RemotePass".to_string());
-            prepared_command.push(password.clone());
-        }
-
         run_process_blocking_maybe_timed(
             "powershell.exe",
-            &prepared_command,
-            None
+            &self.wmimplant_args(prepared_command),
+            operation_timeout(None)?
         )
     }
 }
@@ -119,9 +133,7 @@ impl FileCopier for Wmi {
     fn delete_file(&self, target: &Path) -> io::Result<()> {
         let remote_computer = self.remote_computer();
 
-        let mut prepared_command = vec![
-            "-File".to_string(),
-            "WMImplant.ps1".to_string(),
+        let prepared_command = vec![
             "-Delete".to_string(),
             "-LocalFile".to_string(),
             target.to_string_lossy().to_string(),
@@ -131,15 +143,11 @@ impl FileCopier for Wmi {
             remote_computer.domain_username()
         ];
 
-        if let Some(password) = &remote_computer.password {
-            prepared_command.push("-RemotePass".to_string());
-            prepared_command.push(password.clone());
-        }
-
+        let timeout = Duration::from_secs(10);
         run_process_blocking_timed(
             "powershell.exe",
-            &prepared_command,
-            Duration::from_secs(10)
+            &self.wmimplant_args(prepared_command),
+            operation_timeout(Some(timeout))?.unwrap_or(timeout)
         )
     }
 
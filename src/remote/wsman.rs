@@ -0,0 +1,746 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write, ErrorKind};
+use std::net::TcpStream;
+use std::time::Duration;
+use native_tls::TlsConnector;
+use uuid::Uuid;
+use crate::remote::{Computer, WinRmAuth, WinRmSettings};
+use crate::remote::ntlm;
+use crate::remote::ntlm::{ChallengeMessage, SessionSecurity};
+
+const SHELL_RESOURCE_URI: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/cmd";
+const ACTION_CREATE: &str = "http://schemas.xmlsoap.org/ws/2004/09/transfer/Create";
+const ACTION_DELETE: &str = "http://schemas.xmlsoap.org/ws/2004/09/transfer/Delete";
+const ACTION_COMMAND: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Command";
+const ACTION_SEND: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Send";
+const ACTION_RECEIVE: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Receive";
+const ACTION_SIGNAL: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Signal";
+const SIGNAL_TERMINATE: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/signal/terminate";
+const COMMAND_STATE_DONE: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/CommandState/Done";
+
+/// WS-Management fault code returned when a Receive request finds no output within the operation timeout.
+const OPERATION_TIMEOUT_FAULT: &str = "2150858793";
+
+const SOAP_CONTENT_TYPE: &str = "application/soap+xml;charset=UTF-8";
+const ENCRYPTED_BOUNDARY: &str = "Encrypted Boundary";
+const ENCRYPTED_CONTENT_TYPE: &str =
+    "multipart/encrypted;protocol=\"application/HTTP-SPNEGO-session-encrypted\";boundary=\"Encrypted Boundary\"";
+
+const MAX_ENVELOPE_SIZE: usize = 153_600;
+const OPERATION_TIMEOUT: &str = "PT20S";
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Maximal number of standard input bytes sent in a single message.
+/// Base64 encoded they must fit into `MAX_ENVELOPE_SIZE`.
+pub const MAX_INPUT_CHUNK: usize = 64 * 1024;
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+type Connection = BufReader<Box<dyn Stream>>;
+
+/// Output of a single Receive request.
+#[derive(Default)]
+pub struct Received {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: Option<i32>,
+    pub done: bool,
+}
+
+/// Client of the WinRS shell over WS-Management.
+/// The HTTP connection is kept alive because NTLM authenticates the connection, not the requests.
+/// Over plain HTTP the messages are sealed with the NTLM session key as Windows does not accept
+/// unencrypted traffic by default.
+pub struct WsManClient {
+    computer: Computer,
+    settings: WinRmSettings,
+    endpoint: String,
+    connection: Option<Connection>,
+    session_security: Option<SessionSecurity>,
+}
+
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn headers<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a str> + 'a {
+        self.headers.iter()
+            .filter(move |(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn header<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        self.headers(name).next()
+    }
+}
+
+impl WsManClient {
+    pub fn new(computer: Computer, settings: WinRmSettings) -> WsManClient {
+        let scheme = if settings.https { "https" } else { "http" };
+        let endpoint = format!("{}://{}:{}/wsman", scheme, computer.address, settings.port());
+        WsManClient {
+            computer,
+            settings,
+            endpoint,
+            connection: None,
+            session_security: None,
+        }
+    }
+
+    pub fn open_shell(&mut self) -> io::Result<String> {
+        let envelope = self.envelope(
+            ACTION_CREATE,
+            None,
+            &[("WINRS_NOPROFILE", "FALSE"), ("WINRS_CODEPAGE", "65001")],
+            "<rsp:Shell><rsp:InputStreams>stdin</rsp:InputStreams>\
+            <rsp:OutputStreams>stdout stderr</rsp:OutputStreams></rsp:Shell>",
+        );
+        let response = self.post(&envelope)?;
+        let shell_id = element_text(&response, "ShellId")
+            .or_else(|| elements(&response, "Selector").iter()
+                .find(|selector| selector.attribute("Name") == Some("ShellId"))
+                .map(|selector| unescape(selector.content)))
+            .ok_or_else(|| invalid_data("WinRM did not return a shell id"))?;
+        debug!("Opened WinRM shell {} on {}", shell_id, self.computer.address);
+        Ok(shell_id)
+    }
+
+    pub fn start_command(&mut self, shell_id: &str, command_line: &str) -> io::Result<String> {
+        let envelope = self.envelope(
+            ACTION_COMMAND,
+            Some(shell_id),
+            &[("WINRS_CONSOLEMODE_STDIN", "FALSE"), ("WINRS_SKIP_CMD_SHELL", "FALSE")],
+            &format!("<rsp:CommandLine><rsp:Command>{}</rsp:Command></rsp:CommandLine>", escape(command_line)),
+        );
+        let response = self.post(&envelope)?;
+        element_text(&response, "CommandId")
+            .ok_or_else(|| invalid_data("WinRM did not return a command id"))
+    }
+
+    pub fn send_input(&mut self, shell_id: &str, command_id: &str, data: &[u8], end: bool) -> io::Result<()> {
+        let envelope = self.envelope(
+            ACTION_SEND,
+            Some(shell_id),
+            &[],
+            &format!(
+                "<rsp:Send><rsp:Stream Name=\"stdin\" CommandId=\"{}\"{}>{}</rsp:Stream></rsp:Send>",
+                escape(command_id),
+                if end { " End=\"true\"" } else { "" },
+                base64::encode(data),
+            ),
+        );
+        self.post(&envelope).map(|_| ())
+    }
+
+    pub fn receive(&mut self, shell_id: &str, command_id: &str) -> io::Result<Received> {
+        let envelope = self.envelope(
+            ACTION_RECEIVE,
+            Some(shell_id),
+            &[("WSMAN_CMDSHELL_OPTION_KEEPALIVE", "TRUE")],
+            &format!(
+                "<rsp:Receive><rsp:DesiredStream CommandId=\"{}\">stdout stderr</rsp:DesiredStream></rsp:Receive>",
+                escape(command_id),
+            ),
+        );
+        let response = match self.post(&envelope) {
+            Ok(response) => response,
+            Err(err) if err.kind() == ErrorKind::TimedOut => return Ok(Received::default()),
+            Err(err) => return Err(err),
+        };
+        let mut received = Received::default();
+        for stream in elements(&response, "Stream") {
+            let data = base64::decode(stream.content.trim())
+                .map_err(|err| invalid_data(&format!("Invalid WinRM stream data: {}", err)))?;
+            match stream.attribute("Name") {
+                Some("stdout") => received.stdout.extend(data),
+                Some("stderr") => received.stderr.extend(data),
+                _ => {}
+            }
+        }
+        if let Some(state) = elements(&response, "CommandState").first() {
+            received.done = state.attribute("State") == Some(COMMAND_STATE_DONE);
+            received.exit_code = element_text(state.content, "ExitCode")
+                .and_then(|exit_code| exit_code.trim().parse().ok());
+        }
+        Ok(received)
+    }
+
+    pub fn terminate_command(&mut self, shell_id: &str, command_id: &str) -> io::Result<()> {
+        let envelope = self.envelope(
+            ACTION_SIGNAL,
+            Some(shell_id),
+            &[],
+            &format!(
+                "<rsp:Signal CommandId=\"{}\"><rsp:Code>{}</rsp:Code></rsp:Signal>",
+                escape(command_id),
+                SIGNAL_TERMINATE,
+            ),
+        );
+        self.post(&envelope).map(|_| ())
+    }
+
+    pub fn close_shell(&mut self, shell_id: &str) -> io::Result<()> {
+        let envelope = self.envelope(ACTION_DELETE, Some(shell_id), &[], "");
+        self.post(&envelope).map(|_| ())
+    }
+
+    fn envelope(&self, action: &str, shell_id: Option<&str>, options: &[(&str, &str)], body: &str) -> String {
+        let selector = shell_id
+            .map(|shell_id| format!(
+                "<w:SelectorSet><w:Selector Name=\"ShellId\">{}</w:Selector></w:SelectorSet>",
+                escape(shell_id)
+            ))
+            .unwrap_or_default();
+        let option_set = if options.is_empty() {
+            String::new()
+        } else {
+            let options = options.iter()
+                .map(|(name, value)| format!("<w:Option Name=\"{}\">{}</w:Option>", name, value))
+                .collect::<String>();
+            format!("<w:OptionSet>{}</w:OptionSet>", options)
+        };
+        format!(
+            "<s:Envelope xmlns:s=\"http://www.w3.org/2003/05/soap-envelope\" \
+            xmlns:a=\"http://schemas.xmlsoap.org/ws/2004/08/addressing\" \
+            xmlns:w=\"http://schemas.dmtf.org/wbem/wsman/1/wsman.xsd\" \
+            xmlns:p=\"http://schemas.microsoft.com/wbem/wsman/1/wsman.xsd\" \
+            xmlns:rsp=\"http://schemas.microsoft.com/wbem/wsman/1/windows/shell\">\
+            <s:Header>\
+            <a:To>{endpoint}</a:To>\
+            <a:ReplyTo><a:Address s:mustUnderstand=\"true\">http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</a:Address></a:ReplyTo>\
+            <w:ResourceURI s:mustUnderstand=\"true\">{resource}</w:ResourceURI>\
+            <a:Action s:mustUnderstand=\"true\">{action}</a:Action>\
+            <w:MaxEnvelopeSize s:mustUnderstand=\"true\">{max_size}</w:MaxEnvelopeSize>\
+            <a:MessageID>uuid:{message_id}</a:MessageID>\
+            <w:Locale xml:lang=\"en-US\" s:mustUnderstand=\"false\"/>\
+            <p:DataLocale xml:lang=\"en-US\" s:mustUnderstand=\"false\"/>\
+            <w:OperationTimeout>{timeout}</w:OperationTimeout>\
+            {selector}{option_set}\
+            </s:Header>\
+            <s:Body>{body}</s:Body>\
+            </s:Envelope>",
+            endpoint = escape(&self.endpoint),
+            resource = SHELL_RESOURCE_URI,
+            action = action,
+            max_size = MAX_ENVELOPE_SIZE,
+            message_id = Uuid::new_v4().to_string().to_uppercase(),
+            timeout = OPERATION_TIMEOUT,
+            selector = selector,
+            option_set = option_set,
+            body = body,
+        )
+    }
+
+    /// Sends the envelope and returns the response envelope.
+    /// A connection closed by the server is reopened once.
+    fn post(&mut self, envelope: &str) -> io::Result<String> {
+        match self.try_post(envelope) {
+            Err(err) if is_connection_error(&err) => {
+                debug!("WinRM connection to {} was closed ({}), reconnecting", self.computer.address, err);
+                self.connection = None;
+                self.try_post(envelope)
+            }
+            result => result,
+        }
+    }
+
+    fn try_post(&mut self, envelope: &str) -> io::Result<String> {
+        if self.connection.is_none() {
+            self.connect()?;
+        }
+        let (content_type, body) = match &mut self.session_security {
+            None => (SOAP_CONTENT_TYPE, envelope.as_bytes().to_vec()),
+            Some(session_security) => (ENCRYPTED_CONTENT_TYPE, encrypt(session_security, envelope.as_bytes())),
+        };
+        let mut headers = vec![("Content-Type", content_type.to_string())];
+        if self.settings.auth == WinRmAuth::Basic {
+            headers.push(("Authorization", self.basic_authorization()));
+        }
+        let host = self.host();
+        let response = {
+            let connection = self.connection.as_mut().unwrap();
+            exchange(connection, &host, &headers, &body)
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                self.connection = None;
+                return Err(err);
+            }
+        };
+        if response.header("Connection").map(|value| value.eq_ignore_ascii_case("close")).unwrap_or(false) {
+            self.connection = None;
+        }
+        if response.status == 401 {
+            self.connection = None;
+            return Err(self.authentication_error());
+        }
+        let is_encrypted = response.header("Content-Type")
+            .map(|value| value.starts_with("multipart/encrypted"))
+            .unwrap_or(false);
+        let body = match (&mut self.session_security, is_encrypted) {
+            (Some(session_security), true) => decrypt(session_security, &response.body)?,
+            _ => response.body,
+        };
+        let text = String::from_utf8_lossy(&body).to_string();
+        if response.status == 200 {
+            Ok(text)
+        } else {
+            Err(fault_error(response.status, &text))
+        }
+    }
+
+    fn connect(&mut self) -> io::Result<()> {
+        debug!("Opening WinRM connection to {}", self.endpoint);
+        let mut connection = self.open_connection()?;
+        self.session_security = None;
+        if self.settings.auth == WinRmAuth::Ntlm {
+            let host = self.host();
+            let negotiate = format!("Negotiate {}", base64::encode(ntlm::negotiate_message()));
+            let response = exchange(
+                &mut connection,
+                &host,
+                &[("Content-Type", SOAP_CONTENT_TYPE.to_string()), ("Authorization", negotiate)],
+                &[],
+            )?;
+            let challenge = response.headers("WWW-Authenticate")
+                .filter_map(|value| value.strip_prefix("Negotiate "))
+                .filter_map(|token| base64::decode(token.trim()).ok())
+                .next()
+                .ok_or_else(|| self.authentication_error())?;
+            let challenge = ChallengeMessage::parse(&challenge)?;
+            let authentication = ntlm::authenticate(
+                self.computer.username.trim(),
+                self.computer.domain.as_deref().unwrap_or_default().trim(),
+                self.computer.password.as_deref().unwrap_or_default(),
+                &challenge,
+            );
+            let authenticate = format!("Negotiate {}", base64::encode(&authentication.message));
+            let response = exchange(
+                &mut connection,
+                &host,
+                &[("Content-Type", SOAP_CONTENT_TYPE.to_string()), ("Authorization", authenticate)],
+                &[],
+            )?;
+            if response.status == 401 {
+                return Err(self.authentication_error());
+            }
+            if !self.settings.https {
+                self.session_security = Some(SessionSecurity::new(&authentication));
+            }
+        }
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    fn open_connection(&self) -> io::Result<Connection> {
+        let tcp = TcpStream::connect((self.computer.address.as_str(), self.settings.port()))?;
+        tcp.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+        tcp.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+        let stream: Box<dyn Stream> = if self.settings.https {
+            let connector = TlsConnector::builder()
+                .danger_accept_invalid_certs(self.settings.accept_invalid_certs)
+                .danger_accept_invalid_hostnames(self.settings.accept_invalid_certs)
+                .build()
+                .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+            let tls = connector.connect(&self.computer.address, tcp)
+                .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+            Box::new(tls)
+        } else {
+            Box::new(tcp)
+        };
+        Ok(BufReader::new(stream))
+    }
+
+    fn host(&self) -> String {
+        format!("{}:{}", self.computer.address, self.settings.port())
+    }
+
+    fn basic_authorization(&self) -> String {
+        let credentials = format!(
+            "{}:{}",
+            self.computer.domain_username().trim(),
+            self.computer.password.as_deref().unwrap_or_default()
+        );
+        format!("Basic {}", base64::encode(credentials))
+    }
+
+    fn authentication_error(&self) -> io::Error {
+        io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("Cannot authenticate {} on {} using WinRM", self.computer.domain_username(), self.endpoint),
+        )
+    }
+}
+
+fn exchange(
+    connection: &mut Connection,
+    host: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<HttpResponse> {
+    let mut request = format!(
+        "POST /wsman HTTP/1.1\r\nHost: {}\r\nUser-Agent: Gargamel\r\nConnection: Keep-Alive\r\nContent-Length: {}\r\n",
+        host,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    let stream = connection.get_mut();
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    read_response(connection)
+}
+
+fn read_response(reader: &mut impl BufRead) -> io::Result<HttpResponse> {
+    let status_line = read_line(reader)?;
+    let status = status_line.split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid_data(&format!("Invalid HTTP status line {}", status_line)))?;
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(separator) = line.find(':') {
+            headers.push((line[..separator].trim().to_string(), line[separator + 1..].trim().to_string()));
+        }
+    }
+    let mut response = HttpResponse { status, headers, body: Vec::new() };
+    let chunked = response.header("Transfer-Encoding")
+        .map(|value| value.eq_ignore_ascii_case("chunked"))
+        .unwrap_or(false);
+    if chunked {
+        loop {
+            let size_line = read_line(reader)?;
+            let size = usize::from_str_radix(size_line.split(';').next().unwrap_or_default().trim(), 16)
+                .map_err(|_| invalid_data(&format!("Invalid HTTP chunk size {}", size_line)))?;
+            if size == 0 {
+                while !read_line(reader)?.is_empty() {}
+                break;
+            }
+            let start = response.body.len();
+            response.body.resize(start + size, 0);
+            reader.read_exact(&mut response.body[start..])?;
+            read_line(reader)?;
+        }
+    } else if let Some(length) = response.header("Content-Length").and_then(|value| value.parse().ok()) {
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body)?;
+        response.body = body;
+    }
+    Ok(response)
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "WinRM connection closed"));
+    }
+    Ok(line.trim_end_matches(|c| c == '\r' || c == '\n').to_string())
+}
+
+fn is_connection_error(err: &io::Error) -> bool {
+    match err.kind() {
+        ErrorKind::UnexpectedEof
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::BrokenPipe => true,
+        _ => false,
+    }
+}
+
+/// Wraps a sealed message into the multipart body described in [MS-WSMV] 2.2.9.1.
+fn encrypt(session_security: &mut SessionSecurity, message: &[u8]) -> Vec<u8> {
+    let (sealed, signature) = session_security.seal(message);
+    let mut body = format!(
+        "--{boundary}\r\n\
+        \tContent-Type: application/HTTP-SPNEGO-session-encrypted\r\n\
+        \tOriginalContent: type={content_type};Length={length}\r\n\
+        --{boundary}\r\n\
+        \tContent-Type: application/octet-stream\r\n",
+        boundary = ENCRYPTED_BOUNDARY,
+        content_type = SOAP_CONTENT_TYPE,
+        length = message.len(),
+    ).into_bytes();
+    body.extend_from_slice(&(signature.len() as u32).to_le_bytes());
+    body.extend_from_slice(&signature);
+    body.extend(sealed);
+    body.extend_from_slice(format!("--{}--\r\n", ENCRYPTED_BOUNDARY).as_bytes());
+    body
+}
+
+fn decrypt(session_security: &mut SessionSecurity, body: &[u8]) -> io::Result<Vec<u8>> {
+    let malformed = || invalid_data("Malformed encrypted WinRM message");
+    let length_start = find(body, b"Length=").ok_or_else(malformed)? + b"Length=".len();
+    let length_end = length_start + find(&body[length_start..], b"\r\n").ok_or_else(malformed)?;
+    let length: usize = String::from_utf8_lossy(&body[length_start..length_end])
+        .trim()
+        .parse()
+        .map_err(|_| malformed())?;
+    let stream_marker = b"application/octet-stream\r\n";
+    let stream_start = find(body, stream_marker).ok_or_else(malformed)? + stream_marker.len();
+    let stream = &body[stream_start..];
+    if stream.len() < 4 {
+        return Err(malformed());
+    }
+    let signature_length = u32::from_le_bytes([stream[0], stream[1], stream[2], stream[3]]) as usize;
+    let signature = stream.get(4..4 + signature_length).ok_or_else(malformed)?;
+    let sealed = stream.get(4 + signature_length..4 + signature_length + length).ok_or_else(malformed)?;
+    session_security.unseal(sealed, signature)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn fault_error(status: u16, response: &str) -> io::Error {
+    let kind = if response.contains(OPERATION_TIMEOUT_FAULT) {
+        ErrorKind::TimedOut
+    } else {
+        ErrorKind::Other
+    };
+    let message = element_text(response, "Message")
+        .or_else(|| element_text(response, "Text"))
+        .map(|message| strip_tags(&message))
+        .unwrap_or_else(|| format!("HTTP status {}", status));
+    io::Error::new(kind, format!("WinRM request failed: {}", message.trim()))
+}
+
+struct Element<'a> {
+    start_tag: &'a str,
+    content: &'a str,
+}
+
+impl<'a> Element<'a> {
+    fn attribute(&self, name: &str) -> Option<&'a str> {
+        let pattern = format!(" {}=\"", name);
+        let start = self.start_tag.find(&pattern)? + pattern.len();
+        let end = start + self.start_tag[start..].find('"')?;
+        Some(&self.start_tag[start..end])
+    }
+}
+
+/// Finds elements by their local name regardless of the namespace prefix.
+/// Sufficient for the flat WS-Management responses, nested elements of the same name are not supported.
+fn elements<'a>(xml: &'a str, local_name: &str) -> Vec<Element<'a>> {
+    let mut result = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = xml[position..].find('<') {
+        let tag_start = position + offset;
+        let name_end = xml[tag_start + 1..]
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .map(|end| tag_start + 1 + end)
+            .unwrap_or(xml.len());
+        let name = &xml[tag_start + 1..name_end];
+        position = name_end;
+        if name.rsplit(':').next() != Some(local_name) {
+            continue;
+        }
+        let tag_end = match xml[name_end..].find('>') {
+            Some(end) => name_end + end,
+            None => break,
+        };
+        let start_tag = &xml[tag_start..=tag_end];
+        position = tag_end + 1;
+        if start_tag.ends_with("/>") {
+            result.push(Element { start_tag, content: "" });
+            continue;
+        }
+        let closing_tag = format!("</{}>", name);
+        match xml[position..].find(&closing_tag) {
+            Some(end) => {
+                result.push(Element { start_tag, content: &xml[position..position + end] });
+                position += end + closing_tag.len();
+            }
+            None => break,
+        }
+    }
+    result
+}
+
+fn element_text(xml: &str, local_name: &str) -> Option<String> {
+    elements(xml, local_name).first().map(|element| unescape(element.content))
+}
+
+fn strip_tags(xml: &str) -> String {
+    let mut result = String::new();
+    let mut in_tag = false;
+    for c in xml.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => result.push(c),
+            _ => {}
+        }
+    }
+    unescape(&result)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::remote::test_computer;
+
+    const CREATE_RESPONSE: &str = "<s:Envelope><s:Body><rsp:Shell><rsp:ShellId>11111111-2222</rsp:ShellId></rsp:Shell></s:Body></s:Envelope>";
+    const COMMAND_RESPONSE: &str = "<s:Envelope><s:Body><rsp:CommandResponse><rsp:CommandId>33333333-4444</rsp:CommandId></rsp:CommandResponse></s:Body></s:Envelope>";
+    const TIMEOUT_FAULT: &str = "<s:Envelope><s:Body><s:Fault><s:Detail><f:WSManFault Code=\"2150858793\"><f:Message>The operation timed out.</f:Message></f:WSManFault></s:Detail></s:Fault></s:Body></s:Envelope>";
+
+    fn receive_response() -> String {
+        format!(
+            "<s:Envelope><s:Body><rsp:ReceiveResponse>\
+            <rsp:Stream Name=\"stdout\" CommandId=\"33333333-4444\">{}</rsp:Stream>\
+            <rsp:Stream Name=\"stderr\" CommandId=\"33333333-4444\">{}</rsp:Stream>\
+            <rsp:Stream Name=\"stdout\" CommandId=\"33333333-4444\" End=\"true\"></rsp:Stream>\
+            <rsp:CommandState CommandId=\"33333333-4444\" State=\"{}\"><rsp:ExitCode>3</rsp:ExitCode></rsp:CommandState>\
+            </rsp:ReceiveResponse></s:Body></s:Envelope>",
+            base64::encode("Windows IP Configuration\r\n"),
+            base64::encode("warning"),
+            COMMAND_STATE_DONE,
+        )
+    }
+
+    /// Serves WS-Management requests on a single keep-alive connection and returns the received requests.
+    fn mock_server<F>(respond: F) -> (u16, thread::JoinHandle<Vec<String>>)
+        where F: Fn(&str, usize) -> (u16, String) + Send + 'static {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut requests = Vec::new();
+            while let Ok(request) = read_request(&mut reader) {
+                let action = elements(&request, "Action").first()
+                    .map(|action| action.content.to_string())
+                    .unwrap_or_default();
+                let (status, body) = respond(&action, requests.len());
+                requests.push(request);
+                let response = format!(
+                    "HTTP/1.1 {} OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    SOAP_CONTENT_TYPE,
+                    body.len(),
+                    body
+                );
+                writer.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (port, handle)
+    }
+
+    fn read_request(reader: &mut impl BufRead) -> io::Result<String> {
+        let mut head = String::new();
+        let mut content_length = 0;
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(length) = line.strip_prefix("Content-Length: ") {
+                content_length = length.parse().unwrap();
+            }
+            head.push_str(&line);
+            head.push('\n');
+        }
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body)?;
+        Ok(head + &String::from_utf8(body).unwrap())
+    }
+
+    fn client(port: u16) -> WsManClient {
+        WsManClient::new(
+            Computer {
+                address: "127.0.0.1".to_string(),
+                username: "user".to_string(),
+                password: Some("secret".to_string()),
+                ..test_computer()
+            },
+            WinRmSettings {
+                https: false,
+                port: Some(port),
+                auth: WinRmAuth::Basic,
+                accept_invalid_certs: false,
+            },
+        )
+    }
+
+    #[test]
+    fn runs_command_in_remote_shell() {
+        let (port, server) = mock_server(|action, _| match action {
+            ACTION_CREATE => (200, CREATE_RESPONSE.to_string()),
+            ACTION_COMMAND => (200, COMMAND_RESPONSE.to_string()),
+            ACTION_RECEIVE => (200, receive_response()),
+            _ => (200, "<s:Envelope><s:Body/></s:Envelope>".to_string()),
+        });
+        {
+            let mut client = client(port);
+            let shell_id = client.open_shell().unwrap();
+            assert_eq!(shell_id, "11111111-2222");
+            let command_id = client.start_command(&shell_id, "ipconfig /all > \"out.txt\"").unwrap();
+            assert_eq!(command_id, "33333333-4444");
+            let received = client.receive(&shell_id, &command_id).unwrap();
+            assert_eq!(received.stdout, b"Windows IP Configuration\r\n");
+            assert_eq!(received.stderr, b"warning");
+            assert_eq!(received.exit_code, Some(3));
+            assert!(received.done);
+            client.close_shell(&shell_id).unwrap();
+        }
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[0].contains(&format!("Authorization: Basic {}", base64::encode("user:secret"))));
+        assert!(requests[1].contains("<w:Selector Name=\"ShellId\">11111111-2222</w:Selector>"));
+        assert!(requests[1].contains("<rsp:Command>ipconfig /all &gt; &quot;out.txt&quot;</rsp:Command>"));
+        assert!(requests[3].contains(ACTION_DELETE));
+    }
+
+    #[test]
+    fn keeps_polling_after_operation_timeout() {
+        let (port, server) = mock_server(|action, _| match action {
+            ACTION_RECEIVE => (500, TIMEOUT_FAULT.to_string()),
+            _ => (500, "<s:Envelope><s:Body><s:Fault><s:Reason><s:Text>Access is denied.</s:Text></s:Reason></s:Fault></s:Body></s:Envelope>".to_string()),
+        });
+        {
+            let mut client = client(port);
+            let received = client.receive("shell", "command").unwrap();
+            assert!(!received.done);
+            assert!(received.stdout.is_empty());
+            let err = client.close_shell("shell").unwrap_err();
+            assert!(err.to_string().contains("Access is denied."));
+        }
+        server.join().unwrap();
+    }
+}
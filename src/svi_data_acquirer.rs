@@ -1,6 +1,6 @@
-use crate::remote::{Connector, Computer, PsExec, PsRemote, Rdp, Wmi, SevenZipCompressCopier, RemoteFileCopier, Compression, Local, RevShareConnector};
+use crate::remote::{Connector, Computer, PsExec, WinRm, WinRmSettings, Rdp, Wmi, SevenZipCompressCopier, RemoteFileCopier, Compression, Local, RevShareConnector, Decorate, Readiness, ShareMap};
 use std::path::{Path, PathBuf};
-use std::{io, thread};
+use std::io;
 use std::time::Duration;
 
 
@@ -18,7 +18,7 @@ impl<'a> SystemVolumeInformationAcquirer<'a> {
         local_store_directory: &'a Path,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reverse: bool,
     ) -> SystemVolumeInformationAcquirer<'a> {
         let connector = Box::new(PsExec::psexec32(remote_computer, remote_temp_storage, custom_share_folder));
@@ -50,7 +50,7 @@ impl<'a> SystemVolumeInformationAcquirer<'a> {
         local_store_directory: &'a Path,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        custom_share_folder: Option<ShareMap>,
         reverse: bool,
     ) -> SystemVolumeInformationAcquirer<'a> {
         let connector = Box::new(PsExec::psexec64(remote_computer, remote_temp_storage, custom_share_folder));
@@ -68,10 +68,10 @@ impl<'a> SystemVolumeInformationAcquirer<'a> {
         local_store_directory: &'a Path,
         _no_7zip: bool,
         remote_temp_storage: PathBuf,
-        custom_share_folder: Option<String>,
+        winrm: WinRmSettings,
         reverse: bool,
     ) -> SystemVolumeInformationAcquirer<'a> {
-        let connector = Box::new(PsRemote::new(remote_computer, remote_temp_storage, custom_share_folder));
+        let connector = Box::new(WinRm::new(remote_computer, remote_temp_storage, winrm));
         SystemVolumeInformationAcquirer {
             local_store_directory,
             connector: if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
@@ -88,13 +88,15 @@ impl<'a> SystemVolumeInformationAcquirer<'a> {
         compress_timeout: Duration,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
+        reverse: bool,
     ) -> SystemVolumeInformationAcquirer<'a> {
+        let connector = Box::new(Wmi::new(remote_computer.clone(), remote_temp_storage));
         SystemVolumeInformationAcquirer {
             local_store_directory,
-            connector: Box::new(Wmi { computer: remote_computer.clone(), remote_temp_storage }),
+            connector: if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
             image_timeout: Some(timeout),
             compress_timeout: Some(compress_timeout),
-            compression: if no_7zip { Compression::No } else { Compression::YesSplit },
+            compression: Compression::split_unless_reversed(no_7zip, reverse),
         }
     }
 
@@ -106,26 +108,31 @@ impl<'a> SystemVolumeInformationAcquirer<'a> {
         compress_timeout: Duration,
         no_7zip: bool,
         remote_temp_storage: PathBuf,
+        reverse: bool,
     ) -> SystemVolumeInformationAcquirer<'a> {
+        let connector = Box::new(Rdp {
+            nla,
+            computer: remote_computer.clone(),
+            remote_temp_storage,
+        });
         SystemVolumeInformationAcquirer {
             local_store_directory,
-            connector: Box::new(Rdp {
-                nla,
-                computer: remote_computer.clone(),
-                remote_temp_storage,
-            }),
+            connector: if reverse { Box::new(RevShareConnector::new(connector)) } else { connector },
             image_timeout: Some(image_timeout),
             compress_timeout: Some(compress_timeout),
-            compression: if no_7zip { Compression::No } else { Compression::YesSplit },
+            compression: Compression::split_unless_reversed(no_7zip, reverse),
         }
     }
 
 
+    /// Downloads the links in the System Volume Information of the `system_drive`, e.g. `C:`.
     pub fn download_data(
-        &self
+        &self,
+        system_drive: &str,
     ) -> io::Result<()> {
         let local_store_directory = self.local_store_directory;
-        self.connector.acquire_perms(Path::new("C:\\System Volume Information"));
+        let svi_directory = PathBuf::from(format!("{}\\System Volume Information", system_drive));
+        self.connector.acquire_perms(&svi_directory);
 
         let _copier = self.connector.copier();
         let _compression_split_copier = SevenZipCompressCopier::new(self.connector.as_ref(), true, self.compress_timeout, false);
@@ -135,13 +142,14 @@ impl<'a> SystemVolumeInformationAcquirer<'a> {
             Compression::Yes => &_compression_copier as &dyn RemoteFileCopier,
             Compression::YesSplit => &_compression_split_copier as &dyn RemoteFileCopier,
         };
-        let svi_path = Path::new("C:\\System Volume Information\\*.lnk");
+        let svi_path = PathBuf::from(format!("{}\\*.lnk", svi_directory.display()));
 
-        if let Err(err) = copier.copy_from_remote(
+        let download_result = copier.copy_from_remote(
             &svi_path,
             &local_store_directory,
             // &self.local_store_directory.join(target_name.file_name().unwrap()),
-        ) {
+        );
+        if let Err(err) = &download_result {
             error!("Cannot download {} from {} using method {} due to {}",
                    &svi_path.display(),
                    self.connector.computer().address,
@@ -149,9 +157,19 @@ impl<'a> SystemVolumeInformationAcquirer<'a> {
                    err
             );
         }
-        thread::sleep(Duration::from_millis(20000));
+        // 7-Zip may still read the files when started asynchronously, e.g. by WMI.
+        if !matches!(self.compression, Compression::No) {
+            Readiness::new(self.connector.as_ref()).wait_for_process_exit("7za.exe", Duration::from_secs(20));
+        }
 
-        self.connector.release_perms(Path::new("C:\\System Volume Information"));
-        Ok(())
+        self.connector.release_perms(&svi_directory);
+        download_result
     }
-}
\ No newline at end of file
+}
+
+impl<'a> Decorate for SystemVolumeInformationAcquirer<'a> {
+    fn decorate(mut self, decorator: &dyn Fn(Box<dyn Connector>) -> Box<dyn Connector>) -> Self {
+        self.connector = decorator(self.connector);
+        self
+    }
+}